@@ -0,0 +1,293 @@
+// Adaptive upstream concurrency: AIMD on throttling, errors and latency inflation
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Minimum time between two decreases, so a burst of 429s from requests sent
+/// at the old limit only counts once
+const DECREASE_COOLDOWN: Duration = Duration::from_secs(2);
+
+/// Latency samples needed before latency inflation is acted on
+const MIN_LATENCY_SAMPLES: u64 = 20;
+
+/// EWMA weight of the long-term (baseline) latency
+const BASELINE_ALPHA: f64 = 0.02;
+
+/// EWMA weight of the recent latency
+const RECENT_ALPHA: f64 = 0.2;
+
+/// Adaptive limit settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveConfig {
+    /// The limit never drops below this
+    pub min_limit: usize,
+    /// The limit never grows above this
+    pub max_limit: usize,
+    /// Factor applied to the limit on throttling, errors or inflated latency
+    pub decrease_ratio: f64,
+    /// Recent latency above this multiple of the baseline counts as inflated
+    pub latency_tolerance: f64,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self {
+            min_limit: 2,
+            max_limit: 64,
+            decrease_ratio: 0.7,
+            latency_tolerance: 2.0,
+        }
+    }
+}
+
+/// Result of an upstream call, as far as the limit is concerned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// 2xx, with the time until the response headers arrived
+    Success(Duration),
+    /// 429
+    Throttled,
+    /// 5xx
+    ServerError,
+}
+
+/// Why the limit changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdjustReason {
+    Throttled,
+    ServerError,
+    LatencyInflation,
+    Healthy,
+}
+
+impl AdjustReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdjustReason::Throttled => "throttled",
+            AdjustReason::ServerError => "server_error",
+            AdjustReason::LatencyInflation => "latency_inflation",
+            AdjustReason::Healthy => "healthy",
+        }
+    }
+}
+
+/// A change of the limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjustment {
+    pub from: usize,
+    pub to: usize,
+    pub reason: AdjustReason,
+}
+
+struct Inner {
+    limit: usize,
+    /// Successes since the last change (one limit's worth grows it by one)
+    successes: usize,
+    last_decrease: Option<Instant>,
+    /// Long-term and recent latency EWMAs (ms)
+    baseline_ms: f64,
+    recent_ms: f64,
+    samples: u64,
+}
+
+/// Additive-increase / multiplicative-decrease in-flight limit
+///
+/// - 429 or 5xx: multiply the limit by `decrease_ratio` (at most once per
+///   cooldown)
+/// - Success with the recent latency above `latency_tolerance` × baseline:
+///   decrease as well
+/// - Otherwise, after a full limit's worth of successes: increase by one
+pub struct AdaptiveLimiter {
+    config: AdaptiveConfig,
+    inner: Mutex<Inner>,
+}
+
+impl AdaptiveLimiter {
+    pub fn new(config: AdaptiveConfig, initial: usize) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner {
+                limit: initial.clamp(config.min_limit, config.max_limit),
+                successes: 0,
+                last_decrease: None,
+                baseline_ms: 0.0,
+                recent_ms: 0.0,
+                samples: 0,
+            }),
+        }
+    }
+
+    /// Current limit
+    pub fn limit(&self) -> usize {
+        self.inner.lock().unwrap().limit
+    }
+
+    /// Feed an upstream call outcome; returns the adjustment, if any
+    pub fn record(&self, outcome: Outcome, now: Instant) -> Option<Adjustment> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let reason = match outcome {
+            Outcome::Throttled => AdjustReason::Throttled,
+            Outcome::ServerError => AdjustReason::ServerError,
+            Outcome::Success(latency) => {
+                let ms = latency.as_secs_f64() * 1000.0;
+                if inner.samples == 0 {
+                    inner.baseline_ms = ms;
+                    inner.recent_ms = ms;
+                } else {
+                    inner.baseline_ms += BASELINE_ALPHA * (ms - inner.baseline_ms);
+                    inner.recent_ms += RECENT_ALPHA * (ms - inner.recent_ms);
+                }
+                inner.samples += 1;
+
+                if inner.samples >= MIN_LATENCY_SAMPLES
+                    && inner.recent_ms > inner.baseline_ms * self.config.latency_tolerance
+                {
+                    AdjustReason::LatencyInflation
+                } else {
+                    inner.successes += 1;
+                    if inner.successes < inner.limit || inner.limit >= self.config.max_limit {
+                        return None;
+                    }
+                    let from = inner.limit;
+                    inner.limit += 1;
+                    inner.successes = 0;
+                    return Some(Adjustment {
+                        from,
+                        to: inner.limit,
+                        reason: AdjustReason::Healthy,
+                    });
+                }
+            }
+        };
+
+        // Multiplicative decrease
+        if inner
+            .last_decrease
+            .is_some_and(|at| now.duration_since(at) < DECREASE_COOLDOWN)
+        {
+            return None;
+        }
+        inner.last_decrease = Some(now);
+        inner.successes = 0;
+
+        let from = inner.limit;
+        let to = ((from as f64 * self.config.decrease_ratio).floor() as usize)
+            .max(self.config.min_limit);
+        if to == from {
+            return None;
+        }
+        inner.limit = to;
+        Some(Adjustment { from, to, reason })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(initial: usize) -> AdaptiveLimiter {
+        AdaptiveLimiter::new(
+            AdaptiveConfig {
+                min_limit: 2,
+                max_limit: 12,
+                decrease_ratio: 0.5,
+                latency_tolerance: 2.0,
+            },
+            initial,
+        )
+    }
+
+    fn ok(ms: u64) -> Outcome {
+        Outcome::Success(Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn test_initial_limit_clamped() {
+        assert_eq!(limiter(0).limit(), 2);
+        assert_eq!(limiter(100).limit(), 12);
+        assert_eq!(limiter(5).limit(), 5);
+    }
+
+    #[test]
+    fn test_additive_increase_up_to_ceiling() {
+        let limiter = limiter(10);
+        let now = Instant::now();
+
+        for _ in 0..9 {
+            assert_eq!(limiter.record(ok(100), now), None);
+        }
+        assert_eq!(
+            limiter.record(ok(100), now),
+            Some(Adjustment {
+                from: 10,
+                to: 11,
+                reason: AdjustReason::Healthy
+            })
+        );
+
+        for _ in 0..100 {
+            limiter.record(ok(100), now);
+        }
+        assert_eq!(limiter.limit(), 12);
+    }
+
+    #[test]
+    fn test_multiplicative_decrease_with_cooldown() {
+        let limiter = limiter(12);
+        let now = Instant::now();
+
+        assert_eq!(
+            limiter.record(Outcome::Throttled, now),
+            Some(Adjustment {
+                from: 12,
+                to: 6,
+                reason: AdjustReason::Throttled
+            })
+        );
+        // Same burst: ignored
+        assert_eq!(limiter.record(Outcome::ServerError, now), None);
+        assert_eq!(limiter.limit(), 6);
+
+        let later = now + DECREASE_COOLDOWN;
+        assert_eq!(
+            limiter
+                .record(Outcome::ServerError, later)
+                .map(|a| a.reason),
+            Some(AdjustReason::ServerError)
+        );
+        assert_eq!(limiter.limit(), 3);
+
+        // Never below the floor
+        limiter.record(Outcome::Throttled, later + DECREASE_COOLDOWN);
+        assert_eq!(
+            limiter.record(Outcome::Throttled, later + DECREASE_COOLDOWN * 2),
+            None
+        );
+        assert_eq!(limiter.limit(), 2);
+    }
+
+    #[test]
+    fn test_latency_inflation_decreases() {
+        let limiter = limiter(12);
+        let now = Instant::now();
+
+        for _ in 0..MIN_LATENCY_SAMPLES {
+            limiter.record(ok(100), now);
+        }
+        assert_eq!(limiter.limit(), 12);
+
+        let mut adjustment = None;
+        for _ in 0..10 {
+            adjustment = adjustment.or(limiter.record(ok(1000), now));
+        }
+        assert_eq!(
+            adjustment,
+            Some(Adjustment {
+                from: 12,
+                to: 6,
+                reason: AdjustReason::LatencyInflation
+            })
+        );
+    }
+}
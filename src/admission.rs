@@ -0,0 +1,557 @@
+// Upstream admission queue: bound concurrent upstream calls, queue the rest by priority
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+use crate::adaptive_limit::{AdaptiveConfig, AdaptiveLimiter, Outcome};
+use crate::metrics::MetricsCollector;
+
+/// Request header clients may use to pick a priority class
+pub const PRIORITY_HEADER: &str = "x-kiro-priority";
+
+/// Priority class of a queued request (higher classes are admitted first)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Priority {
+    /// All classes, highest first
+    const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "low" => Some(Priority::Low),
+            "normal" => Some(Priority::Normal),
+            "high" => Some(Priority::High),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Admission settings (`max_in_flight` 0 without `adaptive` disables the queue)
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdmissionConfig {
+    /// Upstream calls allowed at once (the starting point when adaptive)
+    pub max_in_flight: usize,
+    /// Adjust the limit to upstream throttling, errors and latency
+    pub adaptive: Option<AdaptiveConfig>,
+    /// How long a request may wait for a slot before it is rejected
+    pub max_wait: Duration,
+    /// Highest priority per rate-limit tier (`normal` for other clients)
+    pub tier_priorities: HashMap<String, Priority>,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 0,
+            adaptive: None,
+            max_wait: Duration::from_secs(10),
+            tier_priorities: HashMap::new(),
+        }
+    }
+}
+
+impl AdmissionConfig {
+    /// Check whether upstream calls are limited at all
+    pub fn is_enabled(&self) -> bool {
+        self.max_in_flight > 0 || self.adaptive.is_some()
+    }
+
+    /// Priority of a request from a client in `tier`
+    ///
+    /// The tier sets the default and the ceiling: a requested priority (from
+    /// the priority header) may lower it, never raise it.
+    pub fn priority_for(&self, tier: Option<&str>, requested: Option<Priority>) -> Priority {
+        let ceiling = tier
+            .and_then(|t| self.tier_priorities.get(t))
+            .copied()
+            .unwrap_or(Priority::Normal);
+        requested.map_or(ceiling, |p| p.min(ceiling))
+    }
+}
+
+/// Request could not get a slot within the maximum wait
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueueTimeout {
+    pub waited: Duration,
+}
+
+struct Waiter {
+    id: u64,
+    grant: oneshot::Sender<()>,
+}
+
+struct Inner {
+    /// Current in-flight limit
+    limit: usize,
+    in_flight: usize,
+    /// Waiting requests per priority class, oldest first
+    waiting: [VecDeque<Waiter>; 3],
+    next_id: u64,
+}
+
+impl Inner {
+    fn depth(&self) -> usize {
+        self.waiting.iter().map(VecDeque::len).sum()
+    }
+
+    /// Free a slot, handing it straight to the oldest highest-priority waiter
+    ///
+    /// Slots above a lowered limit are retired instead.
+    fn release(&mut self) {
+        if self.in_flight <= self.limit && self.grant_next() {
+            return;
+        }
+        self.in_flight -= 1;
+    }
+
+    /// Hand a slot to the oldest highest-priority waiter still waiting
+    fn grant_next(&mut self) -> bool {
+        for priority in Priority::ALL {
+            while let Some(waiter) = self.waiting[priority.index()].pop_front() {
+                if waiter.grant.send(()).is_ok() {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Bounds concurrent upstream calls
+///
+/// Requests beyond `max_in_flight` wait in per-priority FIFO queues; a freed
+/// slot goes to the oldest waiter of the highest waiting class. Waiters that
+/// don't get a slot within `max_wait` are rejected.
+///
+/// With `adaptive` set, upstream call outcomes reported through
+/// `record_outcome` move the limit between the configured floor and ceiling.
+pub struct AdmissionQueue {
+    config: AdmissionConfig,
+    inner: Mutex<Inner>,
+    /// Adjusts the limit to upstream feedback (optional)
+    limiter: Option<AdaptiveLimiter>,
+    /// Metrics collector for queue depth and wait times (optional)
+    metrics: Option<Arc<MetricsCollector>>,
+}
+
+impl AdmissionQueue {
+    pub fn new(config: AdmissionConfig) -> Self {
+        // Adaptive limits start at the static limit, or the ceiling without one
+        let limiter = config.adaptive.map(|adaptive| {
+            let initial = match config.max_in_flight {
+                0 => adaptive.max_limit,
+                n => n,
+            };
+            AdaptiveLimiter::new(adaptive, initial)
+        });
+        let limit = limiter
+            .as_ref()
+            .map_or(config.max_in_flight, AdaptiveLimiter::limit);
+
+        Self {
+            config,
+            limiter,
+            inner: Mutex::new(Inner {
+                limit,
+                in_flight: 0,
+                waiting: Default::default(),
+                next_id: 0,
+            }),
+            metrics: None,
+        }
+    }
+
+    /// Export queue depth, in-flight count and wait times to the metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self.publish(&self.inner.lock().unwrap());
+        self
+    }
+
+    /// Feed an upstream call outcome to the adaptive limit (if enabled)
+    pub fn record_outcome(&self, outcome: Outcome) {
+        let Some(ref limiter) = self.limiter else {
+            return;
+        };
+        // Adjust under the queue lock so concurrent adjustments apply in order
+        let mut inner = self.inner.lock().unwrap();
+        let Some(adjustment) = limiter.record(outcome, Instant::now()) else {
+            return;
+        };
+
+        tracing::info!(
+            from = adjustment.from,
+            to = adjustment.to,
+            reason = adjustment.reason.as_str(),
+            "Upstream concurrency limit {} -> {} ({})",
+            adjustment.from,
+            adjustment.to,
+            adjustment.reason.as_str()
+        );
+        if let Some(ref metrics) = self.metrics {
+            metrics.record_limit_adjustment(adjustment.reason.as_str());
+        }
+
+        inner.limit = adjustment.to;
+        // A raised limit admits waiters right away
+        while inner.in_flight < inner.limit && inner.grant_next() {
+            inner.in_flight += 1;
+        }
+        self.publish(&inner);
+    }
+
+    /// Wait for an upstream slot
+    ///
+    /// Returns the permit (holding the slot until dropped) and how long the
+    /// request was queued.
+    pub async fn acquire(
+        self: &Arc<Self>,
+        priority: Priority,
+    ) -> Result<(AdmissionPermit, Duration), QueueTimeout> {
+        let started = Instant::now();
+        let (id, granted) = {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.in_flight < inner.limit {
+                inner.in_flight += 1;
+                self.publish(&inner);
+                self.record_wait(Duration::ZERO);
+                return Ok((self.permit(), Duration::ZERO));
+            }
+
+            let id = inner.next_id;
+            inner.next_id += 1;
+            let (grant, granted) = oneshot::channel();
+            inner.waiting[priority.index()].push_back(Waiter { id, grant });
+            self.publish(&inner);
+            (id, granted)
+        };
+
+        let mut waiting = Waiting {
+            queue: self,
+            id,
+            priority,
+            granted,
+            admitted: false,
+        };
+        match tokio::time::timeout(self.config.max_wait, &mut waiting.granted).await {
+            Ok(Ok(())) => {
+                waiting.admitted = true;
+                let waited = started.elapsed();
+                self.record_wait(waited);
+                Ok((self.permit(), waited))
+            }
+            _ => {
+                if let Some(ref metrics) = self.metrics {
+                    metrics.record_queue_timeout();
+                }
+                Err(QueueTimeout {
+                    waited: started.elapsed(),
+                })
+            }
+        }
+    }
+
+    /// Take a free slot without queueing; `None` when all slots are in use
+    ///
+    /// Slots are only free when nobody is waiting, so this never jumps the queue.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<AdmissionPermit> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.in_flight >= inner.limit {
+            return None;
+        }
+        inner.in_flight += 1;
+        self.publish(&inner);
+        Some(self.permit())
+    }
+
+    /// Free a slot and publish the new queue state
+    fn release(&self, inner: &mut Inner) {
+        inner.release();
+        self.publish(inner);
+    }
+
+    fn publish(&self, inner: &Inner) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.set_admission(
+                inner.depth() as u64,
+                inner.in_flight as u64,
+                inner.limit as u64,
+            );
+        }
+    }
+
+    fn record_wait(&self, waited: Duration) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.record_queue_wait(waited.as_secs_f64() * 1000.0);
+        }
+    }
+
+    fn permit(self: &Arc<Self>) -> AdmissionPermit {
+        AdmissionPermit {
+            queue: Some(Arc::clone(self)),
+        }
+    }
+
+    /// Requests currently waiting for a slot
+    #[cfg(test)]
+    pub fn depth(&self) -> usize {
+        self.inner.lock().unwrap().depth()
+    }
+
+    /// Upstream calls currently holding a slot
+    #[cfg(test)]
+    pub fn in_flight(&self) -> usize {
+        self.inner.lock().unwrap().in_flight
+    }
+
+    /// Current in-flight limit
+    #[cfg(test)]
+    pub fn limit(&self) -> usize {
+        self.inner.lock().unwrap().limit
+    }
+}
+
+/// A queued request; leaves the queue when dropped without being admitted
+///
+/// Covers timeouts and clients that disconnect while waiting. A slot granted
+/// in the meantime is passed on rather than leaked. The receiver is kept until
+/// the queue is locked, so a waiter missing from the queue was granted a slot.
+struct Waiting<'a> {
+    queue: &'a AdmissionQueue,
+    id: u64,
+    priority: Priority,
+    granted: oneshot::Receiver<()>,
+    admitted: bool,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        if self.admitted {
+            return;
+        }
+
+        let mut inner = self.queue.inner.lock().unwrap();
+        let waiting = &mut inner.waiting[self.priority.index()];
+        match waiting.iter().position(|w| w.id == self.id) {
+            Some(pos) => {
+                waiting.remove(pos);
+                self.queue.publish(&inner);
+            }
+            None => self.queue.release(&mut inner),
+        }
+    }
+}
+
+/// Slot for one upstream call, released when dropped
+///
+/// Keep it alive until the upstream response has been fully consumed.
+pub struct AdmissionPermit {
+    queue: Option<Arc<AdmissionQueue>>,
+}
+
+impl AdmissionPermit {
+    /// Permit for when no admission limit is configured
+    pub fn unbounded() -> Self {
+        Self { queue: None }
+    }
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        if let Some(ref queue) = self.queue {
+            let mut inner = queue.inner.lock().unwrap();
+            queue.release(&mut inner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(max_in_flight: usize, max_wait: Duration) -> Arc<AdmissionQueue> {
+        Arc::new(AdmissionQueue::new(AdmissionConfig {
+            max_in_flight,
+            adaptive: None,
+            max_wait,
+            tier_priorities: HashMap::new(),
+        }))
+    }
+
+    #[test]
+    fn test_priority_for_tier_and_header() {
+        let config = AdmissionConfig {
+            tier_priorities: HashMap::from([
+                ("premium".to_string(), Priority::High),
+                ("free".to_string(), Priority::Low),
+            ]),
+            ..AdmissionConfig::default()
+        };
+
+        assert_eq!(config.priority_for(None, None), Priority::Normal);
+        assert_eq!(config.priority_for(Some("premium"), None), Priority::High);
+        assert_eq!(config.priority_for(Some("free"), None), Priority::Low);
+        assert_eq!(config.priority_for(Some("unknown"), None), Priority::Normal);
+
+        // The header can lower the priority but not raise it
+        assert_eq!(
+            config.priority_for(Some("premium"), Some(Priority::Low)),
+            Priority::Low
+        );
+        assert_eq!(
+            config.priority_for(None, Some(Priority::High)),
+            Priority::Normal
+        );
+        assert_eq!(
+            config.priority_for(Some("free"), Some(Priority::High)),
+            Priority::Low
+        );
+    }
+
+    #[test]
+    fn test_parse_priority() {
+        assert_eq!(Priority::parse("HIGH"), Some(Priority::High));
+        assert_eq!(Priority::parse(" low "), Some(Priority::Low));
+        assert_eq!(Priority::parse("urgent"), None);
+    }
+
+    #[tokio::test]
+    async fn test_admits_up_to_limit_then_times_out() {
+        let queue = queue(2, Duration::from_millis(20));
+
+        let (first, waited) = queue.acquire(Priority::Normal).await.unwrap();
+        assert_eq!(waited, Duration::ZERO);
+        let _second = queue.acquire(Priority::Normal).await.unwrap();
+        assert_eq!(queue.in_flight(), 2);
+
+        let Err(timeout) = queue.acquire(Priority::High).await else {
+            panic!("expected a queue timeout");
+        };
+        assert!(timeout.waited >= Duration::from_millis(20));
+        assert_eq!(queue.depth(), 0);
+
+        // A released slot is reusable
+        drop(first);
+        assert_eq!(queue.in_flight(), 1);
+        assert!(queue.acquire(Priority::Low).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_try_acquire_does_not_wait() {
+        let queue = queue(1, Duration::from_secs(1));
+        let permit = queue.try_acquire().unwrap();
+        assert!(queue.try_acquire().is_none());
+        assert_eq!(queue.depth(), 0);
+
+        drop(permit);
+        assert!(queue.try_acquire().is_some());
+        assert_eq!(queue.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_higher_priority_admitted_first() {
+        let queue = queue(1, Duration::from_secs(5));
+        let (held, _) = queue.acquire(Priority::Normal).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut tasks = Vec::new();
+        for priority in [Priority::Low, Priority::Normal, Priority::High] {
+            let shared = Arc::clone(&queue);
+            let tx = tx.clone();
+            tasks.push(tokio::spawn(async move {
+                let (permit, _) = shared.acquire(priority).await.unwrap();
+                tx.send(priority).unwrap();
+                drop(permit);
+            }));
+            // Enqueue in a known order
+            while queue.depth() < tasks.len() {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        drop(held);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        let order: Vec<Priority> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(order, vec![Priority::High, Priority::Normal, Priority::Low]);
+        assert_eq!(queue.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_limit_follows_outcomes() {
+        let queue = Arc::new(AdmissionQueue::new(AdmissionConfig {
+            max_in_flight: 2,
+            adaptive: Some(AdaptiveConfig {
+                min_limit: 1,
+                max_limit: 4,
+                decrease_ratio: 0.5,
+                latency_tolerance: 2.0,
+            }),
+            ..AdmissionConfig::default()
+        }));
+        assert_eq!(queue.limit(), 2);
+
+        let (first, _) = queue.acquire(Priority::Normal).await.unwrap();
+        let (second, _) = queue.acquire(Priority::Normal).await.unwrap();
+
+        // Throttling halves the limit; the extra slot is retired on release
+        queue.record_outcome(Outcome::Throttled);
+        assert_eq!(queue.limit(), 1);
+        drop(first);
+        assert_eq!(queue.in_flight(), 1);
+
+        // A raised limit admits a waiter immediately
+        let waiter = {
+            let queue = Arc::clone(&queue);
+            tokio::spawn(async move { queue.acquire(Priority::Normal).await.is_ok() })
+        };
+        while queue.depth() == 0 {
+            tokio::task::yield_now().await;
+        }
+        queue.record_outcome(Outcome::Success(Duration::from_millis(100)));
+        assert_eq!(queue.limit(), 2);
+        assert_eq!(queue.depth(), 0);
+        assert!(waiter.await.unwrap());
+        drop(second);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_leaves_queue() {
+        let queue = queue(1, Duration::from_secs(5));
+        let (held, _) = queue.acquire(Priority::Normal).await.unwrap();
+
+        let waiter = {
+            let queue = Arc::clone(&queue);
+            tokio::spawn(async move { queue.acquire(Priority::Normal).await.map(|_| ()) })
+        };
+        while queue.depth() == 0 {
+            tokio::task::yield_now().await;
+        }
+        waiter.abort();
+        let _ = waiter.await;
+        assert_eq!(queue.depth(), 0);
+
+        drop(held);
+        assert_eq!(queue.in_flight(), 0);
+    }
+}
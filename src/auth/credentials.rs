@@ -2,7 +2,11 @@ use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use std::path::Path;
 
-use super::types::{AuthType, Credentials, SqliteDeviceRegistration, SqliteTokenData};
+use crate::regions::DEFAULT_REGION;
+
+use super::types::{
+    AuthType, Credentials, SqliteDeviceRegistration, SqliteProfile, SqliteTokenData,
+};
 
 /// Load credentials from SQLite database (kiro-cli)
 pub fn load_from_sqlite(path: &Path) -> Result<Credentials> {
@@ -54,15 +58,18 @@ pub fn load_from_sqlite(path: &Path) -> Result<Credentials> {
     let expires_at = token_data.expires_at.and_then(|s| parse_datetime(&s).ok());
 
     // SSO region is used for OIDC token refresh only
-    // API region stays as us-east-1 (CodeWhisperer is only available there)
+    // API region defaults to us-east-1; KIRO_REGION overrides it (AuthManager::with_region)
     let sso_region = token_data.region.or(registration.region);
 
+    // Profile selected with `kiro-cli profile` (optional, absent for Builder ID users)
+    let profile_arn = load_profile_arn(&conn);
+
     Ok(Credentials {
         refresh_token,
         access_token: token_data.access_token,
         expires_at,
-        profile_arn: None,
-        region: "us-east-1".to_string(), // CodeWhisperer API region
+        profile_arn,
+        region: DEFAULT_REGION.to_string(), // CodeWhisperer API region
         client_id: registration.client_id,
         client_secret: registration.client_secret,
         sso_region,
@@ -70,6 +77,20 @@ pub fn load_from_sqlite(path: &Path) -> Result<Credentials> {
     })
 }
 
+/// Load the selected CodeWhisperer profile ARN from the kiro-cli state table
+fn load_profile_arn(conn: &rusqlite::Connection) -> Option<String> {
+    let profile_json: String = conn
+        .query_row(
+            "SELECT value FROM state WHERE key = ?",
+            ["api.codewhisperer.profile"],
+            |row| row.get(0),
+        )
+        .ok()?;
+
+    let profile: SqliteProfile = serde_json::from_str(&profile_json).ok()?;
+    profile.arn.filter(|arn| !arn.is_empty())
+}
+
 /// Detect authentication type based on credentials
 pub fn detect_auth_type(creds: &Credentials) -> AuthType {
     if creds.client_id.is_some() && creds.client_secret.is_some() {
@@ -125,4 +146,34 @@ mod tests {
         };
         assert_eq!(detect_auth_type(&creds), AuthType::AwsSsoOidc);
     }
+
+    #[test]
+    fn test_load_profile_arn_from_state_table() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE state (key TEXT PRIMARY KEY, value TEXT)", [])
+            .unwrap();
+
+        // Missing row
+        assert_eq!(load_profile_arn(&conn), None);
+
+        conn.execute(
+            "INSERT INTO state (key, value) VALUES (?, ?)",
+            [
+                "api.codewhisperer.profile",
+                r#"{"arn":"arn:aws:codewhisperer:us-east-1:123456789012:profile/ABC","profile_name":"dev"}"#,
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            load_profile_arn(&conn).as_deref(),
+            Some("arn:aws:codewhisperer:us-east-1:123456789012:profile/ABC")
+        );
+    }
+
+    #[test]
+    fn test_load_profile_arn_without_state_table() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        assert_eq!(load_profile_arn(&conn), None);
+    }
 }
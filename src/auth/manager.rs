@@ -5,9 +5,20 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::egress::EgressConfig;
+use crate::endpoints::EndpointResolver;
+use crate::retry::Backoff;
+
 use super::credentials;
+use super::profiles;
 use super::refresh;
-use super::types::{AuthType, Credentials};
+use super::types::{AuthType, Credentials, KiroProfile};
+
+/// First retry delay when the profile ARN could not be resolved at startup
+const PROFILE_RETRY_BASE: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Longest delay between profile resolution retries
+const PROFILE_RETRY_CAP: std::time::Duration = std::time::Duration::from_secs(600);
 
 /// Authentication manager
 /// Manages token lifecycle with automatic refresh and thread-safe access
@@ -21,12 +32,22 @@ pub struct AuthManager {
     /// Token expiration time
     expires_at: Arc<RwLock<Option<DateTime<Utc>>>>,
 
+    /// Profile ARN pinned in config or discovered at startup
+    /// Takes precedence over the one carried by the credentials
+    profile_arn: Arc<RwLock<Option<String>>>,
+
     /// Authentication type
     auth_type: AuthType,
 
     /// HTTP client for refresh requests
     client: Client,
 
+    /// Upstream endpoint URLs (refresh and profile listing)
+    endpoints: Arc<EndpointResolver>,
+
+    /// API region from config, overriding the credentials default
+    region: Option<String>,
+
     /// Path to SQLite database (for reload on 400 error)
     sqlite_db: Option<PathBuf>,
 
@@ -38,6 +59,8 @@ impl AuthManager {
     /// Create a new AuthManager for testing (no SQLite required)
     /// Available in test builds and integration tests
     #[cfg(any(test, feature = "test-utils"))]
+    // The binary compiles its own copy of this module and never calls it
+    #[cfg_attr(not(test), allow(dead_code))]
     pub fn new_for_testing(
         access_token: String,
         region: String,
@@ -66,15 +89,18 @@ impl AuthManager {
             credentials: Arc::new(RwLock::new(credentials)),
             access_token: Arc::new(RwLock::new(Some(access_token))),
             expires_at: Arc::new(RwLock::new(Some(Utc::now() + Duration::hours(1)))),
+            profile_arn: Arc::new(RwLock::new(None)),
             auth_type: AuthType::AwsSsoOidc,
             client,
+            endpoints: Arc::new(EndpointResolver::default()),
+            region: None,
             sqlite_db: None,
             refresh_threshold: refresh_threshold as i64,
         })
     }
 
     /// Create a new AuthManager from SQLite database
-    pub fn new(sqlite_db: PathBuf, refresh_threshold: u64) -> Result<Self> {
+    pub fn new(sqlite_db: PathBuf, refresh_threshold: u64, egress: &EgressConfig) -> Result<Self> {
         // Load credentials from SQLite database
         tracing::info!("Loading credentials from SQLite: {}", sqlite_db.display());
         let credentials = credentials::load_from_sqlite(&sqlite_db)?;
@@ -86,8 +112,9 @@ impl AuthManager {
         let access_token = credentials.access_token.clone();
         let expires_at = credentials.expires_at;
 
-        // Create HTTP client with timeout
-        let client = Client::builder()
+        // Create HTTP client with timeout (same proxy and trust roots as the API client)
+        let client = egress
+            .client_builder()
             .timeout(std::time::Duration::from_secs(30))
             .build()
             .context("Failed to create HTTP client")?;
@@ -96,13 +123,28 @@ impl AuthManager {
             credentials: Arc::new(RwLock::new(credentials)),
             access_token: Arc::new(RwLock::new(access_token)),
             expires_at: Arc::new(RwLock::new(expires_at)),
+            profile_arn: Arc::new(RwLock::new(None)),
             auth_type,
             client,
+            endpoints: Arc::new(EndpointResolver::default()),
+            region: None,
             sqlite_db: Some(sqlite_db),
             refresh_threshold: refresh_threshold as i64,
         })
     }
 
+    /// Use the given upstream endpoints instead of the production defaults
+    pub fn with_endpoints(mut self, endpoints: Arc<EndpointResolver>) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// Use the configured API region instead of the one stored with the credentials
+    pub fn with_region(mut self, region: String) -> Self {
+        self.region = Some(region);
+        self
+    }
+
     /// Check if token is expiring soon (within threshold)
     async fn is_token_expiring_soon(&self) -> bool {
         let expires_at = self.expires_at.read().await;
@@ -137,6 +179,7 @@ impl AuthManager {
         // Perform refresh with retry logic
         let token_data = refresh::refresh_with_retry(
             &self.client,
+            &self.endpoints,
             self.auth_type.clone(),
             &mut creds,
             self.sqlite_db.as_deref(),
@@ -197,15 +240,166 @@ impl AuthManager {
 
     /// Get the region
     pub async fn get_region(&self) -> String {
+        if let Some(ref region) = self.region {
+            return region.clone();
+        }
         let creds = self.credentials.read().await;
         creds.region.clone()
     }
 
     /// Get the profile ARN
+    /// Pinned or discovered profile first, then the one from the credentials
     pub async fn get_profile_arn(&self) -> Option<String> {
+        if let Some(ref arn) = *self.profile_arn.read().await {
+            return Some(arn.clone());
+        }
         let creds = self.credentials.read().await;
         creds.profile_arn.clone()
     }
+
+    /// Set the profile ARN used for API requests
+    async fn set_profile_arn(&self, arn: String) {
+        let mut profile_arn = self.profile_arn.write().await;
+        *profile_arn = Some(arn);
+    }
+
+    /// List CodeWhisperer profiles available to the current user
+    pub async fn list_profiles(&self) -> Result<Vec<KiroProfile>> {
+        let access_token = self.get_access_token().await?;
+        let region = self.get_region().await;
+        profiles::list_available_profiles(&self.client, &self.endpoints, &access_token, &region)
+            .await
+    }
+
+    /// Resolve the profile ARN attached to API requests
+    ///
+    /// A pinned profile (ARN or profile name) always wins. Otherwise the profile
+    /// is taken from the credentials and, if missing, discovered per auth type:
+    /// - Kiro Desktop: returned by the refresh endpoint
+    /// - AWS SSO OIDC: looked up via ListAvailableProfiles
+    pub async fn initialize_profile(&self, pinned: Option<&str>) -> Result<Option<String>> {
+        if let Some(wanted) = pinned.filter(|p| !p.is_empty()) {
+            if wanted.starts_with("arn:") {
+                tracing::info!("Using pinned profile: {}", wanted);
+                self.set_profile_arn(wanted.to_string()).await;
+                return Ok(Some(wanted.to_string()));
+            }
+
+            let available = self.list_profiles().await?;
+            let profile =
+                profiles::select_profile(&available, wanted).ok_or_else(|| ProfileNotFound {
+                    wanted: wanted.to_string(),
+                    available: format_profiles(&available),
+                })?;
+
+            tracing::info!("Using pinned profile '{}': {}", wanted, profile.arn);
+            self.set_profile_arn(profile.arn.clone()).await;
+            return Ok(Some(profile.arn.clone()));
+        }
+
+        if let Some(arn) = self.get_profile_arn().await {
+            tracing::info!("Using profile from credentials: {}", arn);
+            return Ok(Some(arn));
+        }
+
+        match self.auth_type {
+            AuthType::KiroDesktop => {
+                // Kiro Desktop returns the profile ARN with every token refresh
+                self.refresh_token().await?;
+                let arn = self.get_profile_arn().await;
+                match arn {
+                    Some(ref arn) => tracing::info!("Using profile from token refresh: {}", arn),
+                    None => tracing::warn!("Kiro Desktop refresh did not return a profile ARN"),
+                }
+                Ok(arn)
+            }
+            AuthType::AwsSsoOidc => {
+                let available = self.list_profiles().await?;
+                match available.as_slice() {
+                    [] => {
+                        tracing::info!(
+                            "No CodeWhisperer profiles available, requests will be sent without profileArn"
+                        );
+                        Ok(None)
+                    }
+                    [only] => {
+                        tracing::info!("Using discovered profile: {}", only.arn);
+                        self.set_profile_arn(only.arn.clone()).await;
+                        Ok(Some(only.arn.clone()))
+                    }
+                    [first, ..] => {
+                        tracing::warn!(
+                            "{} profiles available ({}), using the first one. Set KIRO_PROFILE to choose another.",
+                            available.len(),
+                            format_profiles(&available)
+                        );
+                        self.set_profile_arn(first.arn.clone()).await;
+                        Ok(Some(first.arn.clone()))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A pinned profile the account does not have (retrying will not help)
+#[derive(Debug, thiserror::Error)]
+#[error("Profile '{wanted}' not found (available: {available})")]
+pub struct ProfileNotFound {
+    wanted: String,
+    available: String,
+}
+
+/// Keep resolving the profile ARN in the background after a failed attempt
+/// at startup
+///
+/// Retries on a backoff schedule until resolution succeeds or the pinned
+/// profile turns out not to exist.
+pub fn spawn_profile_task(
+    auth_manager: Arc<AuthManager>,
+    pinned: Option<String>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut backoff = Backoff::new(PROFILE_RETRY_BASE, PROFILE_RETRY_CAP);
+        loop {
+            let delay = backoff.next_delay();
+            tokio::time::sleep(delay).await;
+            match auth_manager.initialize_profile(pinned.as_deref()).await {
+                Ok(Some(arn)) => {
+                    tracing::info!("✅ Using profile: {}", arn);
+                    return;
+                }
+                Ok(None) => {
+                    tracing::info!("No profile ARN in use");
+                    return;
+                }
+                Err(e) if e.is::<ProfileNotFound>() => {
+                    tracing::error!("Failed to select configured profile: {:#}", e);
+                    return;
+                }
+                Err(e) => tracing::warn!(
+                    "Failed to resolve profile ARN, retrying in about {}s: {:#}",
+                    delay.as_secs(),
+                    e
+                ),
+            }
+        }
+    })
+}
+
+/// Format profiles for log and error messages
+fn format_profiles(profiles: &[KiroProfile]) -> String {
+    if profiles.is_empty() {
+        return "none".to_string();
+    }
+    profiles
+        .iter()
+        .map(|p| match p.profile_name {
+            Some(ref name) => format!("{} ({})", name, p.arn),
+            None => p.arn.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 #[cfg(test)]
@@ -230,8 +424,11 @@ mod tests {
             credentials: Arc::new(RwLock::new(creds)),
             access_token: Arc::new(RwLock::new(Some("token".to_string()))),
             expires_at: Arc::new(RwLock::new(Some(Utc::now() + Duration::seconds(600)))),
+            profile_arn: Arc::new(RwLock::new(None)),
             auth_type: AuthType::KiroDesktop,
             client: Client::new(),
+            endpoints: Arc::new(EndpointResolver::default()),
+            region: None,
             sqlite_db: None,
             refresh_threshold: 300,
         };
@@ -263,8 +460,11 @@ mod tests {
             })),
             access_token: Arc::new(RwLock::new(None)),
             expires_at: Arc::new(RwLock::new(Some(Utc::now() - Duration::seconds(60)))),
+            profile_arn: Arc::new(RwLock::new(None)),
             auth_type: AuthType::KiroDesktop,
             client: Client::new(),
+            endpoints: Arc::new(EndpointResolver::default()),
+            region: None,
             sqlite_db: None,
             refresh_threshold: 300,
         };
@@ -272,4 +472,66 @@ mod tests {
         // Token expired 1 minute ago
         assert!(manager.is_token_expired().await);
     }
+
+    #[tokio::test]
+    async fn test_initialize_profile_with_pinned_arn() {
+        let manager =
+            AuthManager::new_for_testing("token".to_string(), "us-east-1".to_string(), 300)
+                .unwrap();
+        assert_eq!(manager.get_profile_arn().await, None);
+
+        let arn = "arn:aws:codewhisperer:us-east-1:123456789012:profile/PINNED";
+        let resolved = manager.initialize_profile(Some(arn)).await.unwrap();
+
+        assert_eq!(resolved.as_deref(), Some(arn));
+        assert_eq!(manager.get_profile_arn().await.as_deref(), Some(arn));
+    }
+
+    #[tokio::test]
+    async fn test_initialize_profile_keeps_credentials_profile() {
+        let manager =
+            AuthManager::new_for_testing("token".to_string(), "us-east-1".to_string(), 300)
+                .unwrap();
+        let arn = "arn:aws:codewhisperer:us-east-1:123456789012:profile/FROM_DB";
+        manager.credentials.write().await.profile_arn = Some(arn.to_string());
+
+        let resolved = manager.initialize_profile(None).await.unwrap();
+        assert_eq!(resolved.as_deref(), Some(arn));
+    }
+
+    #[tokio::test]
+    async fn test_pinned_profile_overrides_credentials() {
+        let manager =
+            AuthManager::new_for_testing("token".to_string(), "us-east-1".to_string(), 300)
+                .unwrap();
+        manager.credentials.write().await.profile_arn =
+            Some("arn:aws:codewhisperer:us-east-1:1:profile/OLD".to_string());
+
+        manager
+            .initialize_profile(Some("arn:aws:codewhisperer:us-east-1:1:profile/NEW"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            manager.get_profile_arn().await.as_deref(),
+            Some("arn:aws:codewhisperer:us-east-1:1:profile/NEW")
+        );
+    }
+
+    #[test]
+    fn test_format_profiles() {
+        assert_eq!(format_profiles(&[]), "none");
+
+        let profiles = vec![
+            KiroProfile {
+                arn: "arn:a".to_string(),
+                profile_name: Some("team-a".to_string()),
+            },
+            KiroProfile {
+                arn: "arn:b".to_string(),
+                profile_name: None,
+            },
+        ];
+        assert_eq!(format_profiles(&profiles), "team-a (arn:a), arn:b");
+    }
 }
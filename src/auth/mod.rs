@@ -1,9 +1,7 @@
 mod credentials;
 mod manager;
+mod profiles;
 mod refresh;
 mod types;
 
-pub use manager::AuthManager;
-
-#[cfg(test)]
-pub use credentials::detect_auth_type;
+pub use manager::{spawn_profile_task, AuthManager, ProfileNotFound};
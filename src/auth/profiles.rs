@@ -0,0 +1,119 @@
+use anyhow::{Context, Result};
+use reqwest::Client;
+
+use crate::endpoints::{Endpoint, EndpointResolver};
+
+use super::types::{KiroProfile, ListAvailableProfilesResponse};
+
+/// Maximum number of pages fetched from ListAvailableProfiles
+const MAX_PROFILE_PAGES: usize = 10;
+
+/// List CodeWhisperer profiles available to the signed-in user
+///
+/// Used for AWS SSO OIDC (IAM Identity Center) users, whose credentials do not
+/// carry a profile ARN. Builder ID users have no profiles and get an empty list.
+pub async fn list_available_profiles(
+    client: &Client,
+    endpoints: &EndpointResolver,
+    access_token: &str,
+    region: &str,
+) -> Result<Vec<KiroProfile>> {
+    let url = endpoints.url(Endpoint::ListAvailableProfiles, region);
+    let mut profiles = Vec::new();
+    let mut next_token: Option<String> = None;
+
+    for _ in 0..MAX_PROFILE_PAGES {
+        let mut body = serde_json::json!({ "maxResults": 50 });
+        if let Some(ref token) = next_token {
+            body["nextToken"] = serde_json::Value::String(token.clone());
+        }
+
+        let response = client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("Content-Type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to send ListAvailableProfiles request")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            anyhow::bail!("ListAvailableProfiles failed: {} - {}", status, error_text);
+        }
+
+        let page: ListAvailableProfilesResponse = response
+            .json()
+            .await
+            .context("Failed to parse ListAvailableProfiles response")?;
+
+        profiles.extend(page.profiles);
+
+        match page.next_token {
+            Some(token) if !token.is_empty() => next_token = Some(token),
+            _ => break,
+        }
+    }
+
+    tracing::debug!("ListAvailableProfiles returned {} profiles", profiles.len());
+
+    Ok(profiles)
+}
+
+/// Select a profile by ARN or by profile name
+pub fn select_profile<'a>(profiles: &'a [KiroProfile], wanted: &str) -> Option<&'a KiroProfile> {
+    profiles.iter().find(|p| p.arn == wanted).or_else(|| {
+        profiles
+            .iter()
+            .find(|p| p.profile_name.as_deref() == Some(wanted))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(arn: &str, name: &str) -> KiroProfile {
+        KiroProfile {
+            arn: arn.to_string(),
+            profile_name: Some(name.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_select_profile_by_arn_and_name() {
+        let profiles = vec![
+            profile("arn:aws:codewhisperer:us-east-1:1:profile/A", "team-a"),
+            profile("arn:aws:codewhisperer:us-east-1:1:profile/B", "team-b"),
+        ];
+
+        let by_arn = select_profile(&profiles, "arn:aws:codewhisperer:us-east-1:1:profile/B");
+        assert_eq!(by_arn.unwrap().profile_name.as_deref(), Some("team-b"));
+
+        let by_name = select_profile(&profiles, "team-a");
+        assert_eq!(
+            by_name.unwrap().arn,
+            "arn:aws:codewhisperer:us-east-1:1:profile/A"
+        );
+
+        assert!(select_profile(&profiles, "team-c").is_none());
+    }
+
+    #[test]
+    fn test_parse_list_profiles_response() {
+        let json = r#"{
+            "profiles": [
+                {"arn": "arn:aws:codewhisperer:us-east-1:1:profile/A", "profileName": "team-a"},
+                {"arn": "arn:aws:codewhisperer:us-east-1:1:profile/B"}
+            ],
+            "nextToken": "abc"
+        }"#;
+
+        let response: ListAvailableProfilesResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.profiles.len(), 2);
+        assert_eq!(response.profiles[0].profile_name.as_deref(), Some("team-a"));
+        assert!(response.profiles[1].profile_name.is_none());
+        assert_eq!(response.next_token.as_deref(), Some("abc"));
+    }
+}
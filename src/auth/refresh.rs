@@ -2,20 +2,12 @@ use anyhow::{Context, Result};
 use chrono::{Duration, Utc};
 use reqwest::Client;
 
+use crate::endpoints::{Endpoint, EndpointResolver};
+
 use super::types::{
     AuthType, AwsSsoOidcResponse, Credentials, KiroRefreshRequest, KiroRefreshResponse, TokenData,
 };
 
-/// Get Kiro refresh URL for region
-fn get_kiro_refresh_url(region: &str) -> String {
-    format!("https://prod.{}.auth.desktop.kiro.dev/refreshToken", region)
-}
-
-/// Get AWS SSO OIDC URL for region
-fn get_aws_sso_oidc_url(region: &str) -> String {
-    format!("https://oidc.{}.amazonaws.com/token", region)
-}
-
 /// Get machine fingerprint for User-Agent
 fn get_machine_fingerprint() -> String {
     use std::collections::hash_map::DefaultHasher;
@@ -32,10 +24,14 @@ fn get_machine_fingerprint() -> String {
 }
 
 /// Refresh token using Kiro Desktop Auth
-pub async fn refresh_kiro_desktop(client: &Client, creds: &Credentials) -> Result<TokenData> {
+pub async fn refresh_kiro_desktop(
+    client: &Client,
+    endpoints: &EndpointResolver,
+    creds: &Credentials,
+) -> Result<TokenData> {
     tracing::info!("Refreshing Kiro token via Kiro Desktop Auth...");
 
-    let url = get_kiro_refresh_url(&creds.region);
+    let url = endpoints.url(Endpoint::KiroDesktopRefresh, &creds.region);
     let fingerprint = get_machine_fingerprint();
 
     let request = KiroRefreshRequest {
@@ -84,7 +80,11 @@ pub async fn refresh_kiro_desktop(client: &Client, creds: &Credentials) -> Resul
 }
 
 /// Refresh token using AWS SSO OIDC
-pub async fn refresh_aws_sso_oidc(client: &Client, creds: &Credentials) -> Result<TokenData> {
+pub async fn refresh_aws_sso_oidc(
+    client: &Client,
+    endpoints: &EndpointResolver,
+    creds: &Credentials,
+) -> Result<TokenData> {
     tracing::info!("Refreshing Kiro token via AWS SSO OIDC...");
 
     let client_id = creds
@@ -98,7 +98,7 @@ pub async fn refresh_aws_sso_oidc(client: &Client, creds: &Credentials) -> Resul
 
     // Use SSO region for OIDC endpoint (may differ from API region)
     let sso_region = creds.sso_region.as_deref().unwrap_or(&creds.region);
-    let url = get_aws_sso_oidc_url(sso_region);
+    let url = endpoints.url(Endpoint::SsoOidcToken, sso_region);
 
     tracing::debug!(
         "AWS SSO OIDC refresh request: url={}, sso_region={}, api_region={}, client_id={}...",
@@ -180,13 +180,14 @@ pub async fn refresh_aws_sso_oidc(client: &Client, creds: &Credentials) -> Resul
 /// If refresh fails with 400 error, reload credentials from SQLite and retry once
 pub async fn refresh_with_retry(
     client: &Client,
+    endpoints: &EndpointResolver,
     auth_type: AuthType,
     creds: &mut Credentials,
     sqlite_path: Option<&std::path::Path>,
 ) -> Result<TokenData> {
     let result = match auth_type {
-        AuthType::KiroDesktop => refresh_kiro_desktop(client, creds).await,
-        AuthType::AwsSsoOidc => refresh_aws_sso_oidc(client, creds).await,
+        AuthType::KiroDesktop => refresh_kiro_desktop(client, endpoints, creds).await,
+        AuthType::AwsSsoOidc => refresh_aws_sso_oidc(client, endpoints, creds).await,
     };
 
     // Handle 400 error in SQLite mode by reloading credentials
@@ -201,8 +202,8 @@ pub async fn refresh_with_retry(
 
                 // Retry refresh
                 return match auth_type {
-                    AuthType::KiroDesktop => refresh_kiro_desktop(client, creds).await,
-                    AuthType::AwsSsoOidc => refresh_aws_sso_oidc(client, creds).await,
+                    AuthType::KiroDesktop => refresh_kiro_desktop(client, endpoints, creds).await,
+                    AuthType::AwsSsoOidc => refresh_aws_sso_oidc(client, endpoints, creds).await,
                 };
             }
         }
@@ -6,11 +6,13 @@ use serde::{Deserialize, Serialize};
 pub enum AuthType {
     /// Kiro IDE credentials (default)
     /// Uses https://prod.{region}.auth.desktop.kiro.dev/refreshToken
+    /// The refresh response carries the profile ARN
     #[allow(dead_code)]
     KiroDesktop,
 
     /// AWS SSO credentials from kiro-cli
     /// Uses https://oidc.{region}.amazonaws.com/token
+    /// The profile ARN is discovered via ListAvailableProfiles
     AwsSsoOidc,
 }
 
@@ -83,3 +85,26 @@ pub struct SqliteDeviceRegistration {
     pub client_secret: Option<String>,
     pub region: Option<String>,
 }
+
+/// CodeWhisperer profile available to the signed-in user
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KiroProfile {
+    pub arn: String,
+    pub profile_name: Option<String>,
+}
+
+/// ListAvailableProfiles response
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListAvailableProfilesResponse {
+    #[serde(default)]
+    pub profiles: Vec<KiroProfile>,
+    pub next_token: Option<String>,
+}
+
+/// SQLite profile selection (written by `kiro-cli profile`)
+#[derive(Deserialize)]
+pub struct SqliteProfile {
+    pub arn: Option<String>,
+}
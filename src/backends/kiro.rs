@@ -0,0 +1,164 @@
+// Kiro (CodeWhisperer) backend
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::StreamExt;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::{UpstreamBackend, UpstreamRequest, UpstreamResponse, KIRO_BACKEND};
+use crate::auth::AuthManager;
+use crate::config::Config;
+use crate::converters::openai_to_kiro::build_kiro_payload_core;
+use crate::error::ApiError;
+use crate::http_client::KiroHttpClient;
+use crate::middleware::DEBUG_LOGGER;
+use crate::resolver::normalize_model_name;
+use crate::streaming::parse_kiro_stream;
+
+/// Sends requests to generateAssistantResponse
+///
+/// Goes through the admission queue, region failover and hedging of the
+/// shared `KiroHttpClient`.
+pub struct KiroBackend {
+    http_client: Arc<KiroHttpClient>,
+    auth_manager: Arc<AuthManager>,
+    config: Arc<Config>,
+}
+
+impl KiroBackend {
+    pub fn new(
+        http_client: Arc<KiroHttpClient>,
+        auth_manager: Arc<AuthManager>,
+        config: Arc<Config>,
+    ) -> Self {
+        Self {
+            http_client,
+            auth_manager,
+            config,
+        }
+    }
+
+    /// Convert to Kiro format
+    fn build_payload(
+        &self,
+        request: UpstreamRequest,
+        conversation_id: &str,
+        profile_arn: &str,
+    ) -> Result<Value, ApiError> {
+        build_kiro_payload_core(
+            request.messages,
+            request.system_prompt,
+            &normalize_model_name(&request.model),
+            request.tools,
+            conversation_id,
+            profile_arn,
+            true, // inject_thinking
+            &self.config,
+        )
+        .map(|result| result.payload)
+        .map_err(ApiError::ValidationError)
+    }
+
+    async fn send_request(&self, request: UpstreamRequest) -> Result<UpstreamResponse, ApiError> {
+        // Generate conversation ID
+        let conversation_id = Uuid::new_v4().to_string();
+
+        // Get profile ARN
+        let profile_arn = self
+            .auth_manager
+            .get_profile_arn()
+            .await
+            .unwrap_or_default();
+
+        let (model_id, key) = (request.model_id.clone(), request.key.clone());
+        let (conversation, priority) = (request.conversation, request.priority);
+        let kiro_payload = self.build_payload(request, &conversation_id, &profile_arn)?;
+
+        tracing::debug!(
+            "Kiro payload: {}",
+            serde_json::to_string_pretty(&kiro_payload).unwrap_or_default()
+        );
+
+        // Log Kiro request body for debugging
+        if let Ok(kiro_body_json) = serde_json::to_vec_pretty(&kiro_payload) {
+            DEBUG_LOGGER
+                .log_kiro_request_body(Bytes::from(kiro_body_json))
+                .await;
+        }
+
+        // Get access token
+        let access_token = self
+            .auth_manager
+            .get_access_token()
+            .await
+            .map_err(|e| ApiError::AuthError(format!("Failed to get access token: {}", e)))?;
+
+        // Wait for an upstream slot; held until the events have been consumed
+        let permit = self.http_client.admit(priority).await?;
+
+        // Send to the conversation's region, failing over to the next ones and
+        // hedging slow starts for latency-critical models
+        let (response, region) = self
+            .http_client
+            .send_hedged(
+                conversation,
+                &model_id,
+                &key,
+                Duration::from_secs(self.config.first_token_timeout),
+                |kiro_api_url| {
+                    self.http_client
+                        .client()
+                        .post(kiro_api_url)
+                        .header("Authorization", format!("Bearer {}", access_token))
+                        .header("Content-Type", "application/json")
+                        .json(&kiro_payload)
+                        .build()
+                        .map_err(|e| {
+                            ApiError::Internal(anyhow::anyhow!("Failed to build request: {}", e))
+                        })
+                },
+            )
+            .await?;
+
+        let events = parse_kiro_stream(response, self.config.first_token_timeout).await?;
+        let events = events
+            .map(move |event| {
+                let _permit = &permit;
+                event
+            })
+            .boxed();
+
+        Ok(UpstreamResponse {
+            events,
+            region: Some(region),
+        })
+    }
+}
+
+impl UpstreamBackend for KiroBackend {
+    fn name(&self) -> &str {
+        KIRO_BACKEND
+    }
+
+    fn payload(&self, request: &UpstreamRequest) -> Result<Value, ApiError> {
+        // The conversation ID is random per call and the profile ARN per account
+        let mut payload = self.build_payload(request.clone(), "", "")?;
+        if let Some(state) = payload
+            .get_mut("conversationState")
+            .and_then(Value::as_object_mut)
+        {
+            state.remove("conversationId");
+        }
+        if let Some(payload) = payload.as_object_mut() {
+            payload.remove("profileArn");
+        }
+        Ok(payload)
+    }
+
+    fn send(&self, request: UpstreamRequest) -> BoxFuture<'_, Result<UpstreamResponse, ApiError>> {
+        Box::pin(self.send_request(request))
+    }
+}
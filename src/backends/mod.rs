@@ -0,0 +1,496 @@
+// Upstream backends: Kiro plus OpenAI-compatible servers, routed by model name
+//
+// Handlers turn OpenAI and Anthropic requests into one `UpstreamRequest`,
+// pick a backend with `BackendRouter::route` and convert the returned
+// `KiroEvent` stream back to the client's format. Models without a routing
+// rule go to Kiro.
+
+mod kiro;
+mod openai;
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::admission::Priority;
+use crate::converters::anthropic_to_kiro::{
+    convert_anthropic_messages, convert_anthropic_tools, extract_system_prompt,
+};
+use crate::converters::core::{UnifiedMessage, UnifiedTool};
+use crate::converters::openai_to_kiro::{
+    convert_openai_messages_to_unified, convert_openai_tools_to_unified,
+};
+use crate::error::ApiError;
+use crate::keys::matches_pattern;
+use crate::models::anthropic::AnthropicMessagesRequest;
+use crate::models::openai::ChatCompletionRequest;
+use crate::streaming::KiroEventStream;
+
+pub use kiro::KiroBackend;
+pub use openai::OpenAiBackend;
+
+/// Name of the built-in Kiro backend in routing rules
+pub const KIRO_BACKEND: &str = "kiro";
+
+/// A chat request in backend-neutral form
+#[derive(Debug, Clone)]
+pub struct UpstreamRequest {
+    /// Model name sent upstream (the client's, unless a route rewrites it)
+    pub model: String,
+    /// Resolved model ID (metrics, hedging)
+    pub model_id: String,
+    pub system_prompt: String,
+    pub messages: Vec<UnifiedMessage>,
+    pub tools: Option<Vec<UnifiedTool>>,
+    pub max_tokens: Option<i32>,
+    pub temperature: Option<f32>,
+    /// Region stickiness key
+    pub conversation: Option<u64>,
+    /// Client key name (hedge budget)
+    pub key: String,
+    /// Admission queue priority
+    pub priority: Priority,
+}
+
+impl UpstreamRequest {
+    /// Build from an OpenAI chat completion request
+    pub fn from_openai(request: &ChatCompletionRequest, model_id: String) -> Self {
+        let (system_prompt, messages) = convert_openai_messages_to_unified(&request.messages);
+        Self {
+            model: request.model.clone(),
+            model_id,
+            system_prompt,
+            messages,
+            tools: convert_openai_tools_to_unified(&request.tools),
+            max_tokens: request.max_completion_tokens.or(request.max_tokens),
+            temperature: request.temperature,
+            conversation: None,
+            key: String::new(),
+            priority: Priority::Normal,
+        }
+    }
+
+    /// Build from an Anthropic messages request
+    pub fn from_anthropic(request: &AnthropicMessagesRequest, model_id: String) -> Self {
+        Self {
+            model: request.model.clone(),
+            model_id,
+            system_prompt: extract_system_prompt(&request.system),
+            messages: convert_anthropic_messages(&request.messages),
+            tools: convert_anthropic_tools(&request.tools),
+            max_tokens: Some(request.max_tokens),
+            temperature: request.temperature,
+            conversation: None,
+            key: String::new(),
+            priority: Priority::Normal,
+        }
+    }
+
+    /// Attach the client's key, conversation and priority
+    pub fn with_client(mut self, key: &str, conversation: u64, priority: Priority) -> Self {
+        self.key = key.to_string();
+        self.conversation = Some(conversation);
+        self.priority = priority;
+        self
+    }
+
+    /// SHA-256 of the payload `backend` would be sent for this request
+    ///
+    /// Per-request fields (Kiro's conversationId, the client's key, conversation
+    /// and priority) are left out, and object keys are hashed in sorted order.
+    /// Identical requests share response cache entries and in-flight upstream
+    /// calls. `None` when no payload can be built; sending reports the error.
+    pub fn fingerprint(&self, backend: &dyn UpstreamBackend) -> Option<String> {
+        let payload = backend.payload(self).ok()?;
+        let mut canonical = String::new();
+        write_canonical(&Value::String(backend.name().to_string()), &mut canonical);
+        write_canonical(&payload, &mut canonical);
+        let digest = Sha256::digest(canonical.as_bytes());
+        Some(digest.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+/// Serialize JSON with object keys sorted, whatever the map's own order
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// A backend's answer: unified events, ready to be converted for the client
+pub struct UpstreamResponse {
+    pub events: KiroEventStream,
+    /// Region that served the request (Kiro only)
+    pub region: Option<String>,
+}
+
+/// A model provider the gateway can forward chat requests to
+pub trait UpstreamBackend: Send + Sync {
+    /// Name used in routing rules and logs
+    fn name(&self) -> &str;
+
+    /// Request body sent upstream, without per-request fields (for fingerprints)
+    fn payload(&self, request: &UpstreamRequest) -> Result<Value, ApiError>;
+
+    /// Send a request and wait until the response starts streaming
+    fn send(&self, request: UpstreamRequest) -> BoxFuture<'_, Result<UpstreamResponse, ApiError>>;
+}
+
+/// Backend chosen for a model
+pub struct Route<'a> {
+    pub backend: &'a Arc<dyn UpstreamBackend>,
+    /// Model name to send instead of the client's
+    pub model: Option<&'a str>,
+}
+
+struct BackendRoute {
+    /// Model patterns (trailing `*` wildcard, case-insensitive)
+    models: Vec<String>,
+    backend: Arc<dyn UpstreamBackend>,
+    model: Option<String>,
+}
+
+/// Maps requested model names to backends; first matching rule wins
+pub struct BackendRouter {
+    default: Arc<dyn UpstreamBackend>,
+    routes: Vec<BackendRoute>,
+}
+
+/// Backends file (`BACKENDS_FILE`)
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BackendsFile {
+    #[serde(default)]
+    backends: HashMap<String, BackendSpec>,
+    #[serde(default)]
+    routes: Vec<RouteSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackendSpec {
+    /// OpenAI-compatible `/chat/completions` server
+    Openai {
+        base_url: String,
+        #[serde(default)]
+        api_key: Option<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RouteSpec {
+    models: Vec<String>,
+    backend: String,
+    #[serde(default)]
+    model: Option<String>,
+}
+
+impl BackendRouter {
+    /// Send everything to one backend
+    pub fn new(default: Arc<dyn UpstreamBackend>) -> Self {
+        Self {
+            default,
+            routes: Vec::new(),
+        }
+    }
+
+    /// Add a routing rule, checked after the existing ones
+    pub fn with_route(
+        mut self,
+        models: Vec<String>,
+        backend: Arc<dyn UpstreamBackend>,
+        model: Option<String>,
+    ) -> Self {
+        self.routes.push(BackendRoute {
+            models: models.iter().map(|m| m.to_lowercase()).collect(),
+            backend,
+            model,
+        });
+        self
+    }
+
+    /// Load backends and routing rules from a JSON file
+    ///
+    /// `kiro` names the default backend; OpenAI-compatible backends share
+    /// `client` (and so the outbound proxy settings).
+    pub fn load(
+        path: &Path,
+        kiro: Arc<dyn UpstreamBackend>,
+        client: reqwest::Client,
+        first_token_timeout: Duration,
+    ) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read backends file: {}", path.display()))?;
+        Self::from_json(&content, kiro, client, first_token_timeout)
+            .with_context(|| format!("Failed to parse backends file: {}", path.display()))
+    }
+
+    fn from_json(
+        content: &str,
+        kiro: Arc<dyn UpstreamBackend>,
+        client: reqwest::Client,
+        first_token_timeout: Duration,
+    ) -> Result<Self> {
+        let file: BackendsFile = serde_json::from_str(content)?;
+
+        let mut backends: HashMap<String, Arc<dyn UpstreamBackend>> = HashMap::new();
+        for (name, spec) in file.backends {
+            if name == KIRO_BACKEND {
+                anyhow::bail!("backend name '{}' is reserved", KIRO_BACKEND);
+            }
+            let backend: Arc<dyn UpstreamBackend> = match spec {
+                BackendSpec::Openai { base_url, api_key } => Arc::new(OpenAiBackend::new(
+                    name.clone(),
+                    base_url,
+                    api_key,
+                    client.clone(),
+                    first_token_timeout,
+                )),
+            };
+            backends.insert(name, backend);
+        }
+
+        let mut router = Self::new(Arc::clone(&kiro));
+        for route in file.routes {
+            let backend = if route.backend == KIRO_BACKEND {
+                Arc::clone(&kiro)
+            } else {
+                backends
+                    .get(&route.backend)
+                    .cloned()
+                    .with_context(|| format!("route uses unknown backend '{}'", route.backend))?
+            };
+            if route.models.is_empty() {
+                anyhow::bail!("route to '{}' lists no models", route.backend);
+            }
+            router = router.with_route(route.models, backend, route.model);
+        }
+        Ok(router)
+    }
+
+    /// Pick the backend for a requested model
+    pub fn route(&self, model: &str) -> Route<'_> {
+        let model = model.to_lowercase();
+        self.routes
+            .iter()
+            .find(|route| route.models.iter().any(|p| matches_pattern(p, &model)))
+            .map(|route| Route {
+                backend: &route.backend,
+                model: route.model.as_deref(),
+            })
+            .unwrap_or(Route {
+                backend: &self.default,
+                model: None,
+            })
+    }
+
+    /// Exact model names from routing rules, listed in `/v1/models`
+    pub fn listed_models(&self) -> Vec<String> {
+        self.routes
+            .iter()
+            .flat_map(|route| route.models.iter())
+            .filter(|pattern| !pattern.ends_with('*'))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converters::core::MessageContent;
+    use futures::StreamExt;
+
+    struct NamedBackend(&'static str);
+
+    impl UpstreamBackend for NamedBackend {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn payload(&self, request: &UpstreamRequest) -> Result<Value, ApiError> {
+            Ok(serde_json::json!({ "model": request.model_id }))
+        }
+
+        /// Empty response; routing tests never read it
+        fn send(
+            &self,
+            _request: UpstreamRequest,
+        ) -> BoxFuture<'_, Result<UpstreamResponse, ApiError>> {
+            Box::pin(futures::future::ready(Ok(UpstreamResponse {
+                events: futures::stream::empty().boxed(),
+                region: None,
+            })))
+        }
+    }
+
+    fn request(prompt: &str) -> UpstreamRequest {
+        UpstreamRequest {
+            model: "claude-sonnet-4".to_string(),
+            model_id: "claude-sonnet-4".to_string(),
+            system_prompt: String::new(),
+            messages: vec![UnifiedMessage {
+                role: "user".to_string(),
+                content: MessageContent::Text(prompt.to_string()),
+                tool_calls: None,
+                tool_results: None,
+                images: None,
+            }],
+            tools: None,
+            max_tokens: None,
+            temperature: Some(0.0),
+            conversation: None,
+            key: String::new(),
+            priority: Priority::Normal,
+        }
+    }
+
+    fn load(json: &str) -> Result<BackendRouter> {
+        BackendRouter::from_json(
+            json,
+            Arc::new(NamedBackend(KIRO_BACKEND)),
+            reqwest::Client::new(),
+            Duration::from_secs(15),
+        )
+    }
+
+    #[test]
+    fn test_routes_by_model_pattern() {
+        let router = load(
+            r#"{
+                "backends": {"local": {"type": "openai", "base_url": "http://127.0.0.1:8080/v1"}},
+                "routes": [
+                    {"models": ["local-*", "Cheap"], "backend": "local", "model": "qwen2.5-coder"},
+                    {"models": ["fast"], "backend": "kiro", "model": "claude-haiku-4.5"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let route = router.route("local-llama");
+        assert_eq!(route.backend.name(), "local");
+        assert_eq!(route.model, Some("qwen2.5-coder"));
+        assert_eq!(router.route("cheap").backend.name(), "local");
+
+        let route = router.route("fast");
+        assert_eq!(route.backend.name(), KIRO_BACKEND);
+        assert_eq!(route.model, Some("claude-haiku-4.5"));
+
+        let route = router.route("claude-sonnet-4");
+        assert_eq!(route.backend.name(), KIRO_BACKEND);
+        assert_eq!(route.model, None);
+
+        assert_eq!(router.listed_models(), vec!["cheap", "fast"]);
+    }
+
+    fn openai_backend(name: &str) -> OpenAiBackend {
+        OpenAiBackend::new(
+            name.to_string(),
+            "http://127.0.0.1:8080/v1".to_string(),
+            None,
+            reqwest::Client::new(),
+            Duration::from_secs(15),
+        )
+    }
+
+    #[test]
+    fn test_fingerprint_covers_request_content() {
+        let local = openai_backend("local");
+        let fingerprint = request("Hi").fingerprint(&local).unwrap();
+        assert_eq!(fingerprint.len(), 64);
+
+        // Client attribution doesn't change the fingerprint
+        let mut other_client = request("Hi");
+        other_client.key = "ci".to_string();
+        other_client.conversation = Some(42);
+        other_client.priority = Priority::High;
+        assert_eq!(other_client.fingerprint(&local), Some(fingerprint.clone()));
+
+        assert_ne!(
+            request("Hello").fingerprint(&local),
+            Some(fingerprint.clone())
+        );
+        assert_ne!(
+            request("Hi").fingerprint(&openai_backend("other")),
+            Some(fingerprint.clone())
+        );
+        let mut warmer = request("Hi");
+        warmer.temperature = Some(0.7);
+        assert_ne!(warmer.fingerprint(&local), Some(fingerprint));
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_key_order() {
+        let with_schema = |schema: &str| {
+            let mut request = request("Hi");
+            request.tools = Some(vec![UnifiedTool {
+                name: "search".to_string(),
+                description: Some("Search the web".to_string()),
+                input_schema: Some(serde_json::from_str(schema).unwrap()),
+            }]);
+            request
+        };
+        let a = with_schema(
+            r#"{"type": "object", "properties": {"q": {"type": "string"}, "n": {"type": "integer"}}}"#,
+        );
+        let b = with_schema(
+            r#"{"properties": {"n": {"type": "integer"}, "q": {"type": "string"}}, "type": "object"}"#,
+        );
+
+        let local = openai_backend("local");
+        assert_eq!(a.fingerprint(&local), b.fingerprint(&local));
+        assert_ne!(a.fingerprint(&local), request("Hi").fingerprint(&local));
+    }
+
+    #[test]
+    fn test_write_canonical_sorts_keys() {
+        let mut out = String::new();
+        write_canonical(
+            &serde_json::json!({"b": [1, {"d": null, "c": "x"}], "a": true}),
+            &mut out,
+        );
+        assert_eq!(out, r#"{"a":true,"b":[1,{"c":"x","d":null}]}"#);
+    }
+
+    #[test]
+    fn test_rejects_invalid_files() {
+        assert!(load(r#"{"routes": [{"models": ["x"], "backend": "missing"}]}"#).is_err());
+        assert!(load(r#"{"routes": [{"models": [], "backend": "kiro"}]}"#).is_err());
+        assert!(load(
+            r#"{"backends": {"kiro": {"type": "openai", "base_url": "http://localhost"}}}"#
+        )
+        .is_err());
+        assert!(load(r#"{"backends": {"x": {"type": "grpc"}}}"#).is_err());
+    }
+}
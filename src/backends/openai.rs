@@ -0,0 +1,490 @@
+// OpenAI-compatible backend (llama.cpp, vLLM, Ollama, LM Studio, ...)
+
+use futures::future::BoxFuture;
+use futures::stream::StreamExt;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+use super::{UpstreamBackend, UpstreamRequest, UpstreamResponse};
+use crate::converters::core::{
+    extract_images_from_content, ContentBlock, MessageContent, UnifiedMessage,
+};
+use crate::error::ApiError;
+use crate::streaming::{wait_for_first_chunk, ByteStream, KiroEvent, ToolUse, Usage};
+
+/// Streams `/chat/completions` from an OpenAI-compatible server
+pub struct OpenAiBackend {
+    name: String,
+    /// Base URL including the version prefix, e.g. `http://127.0.0.1:8080/v1`
+    base_url: String,
+    api_key: Option<String>,
+    client: reqwest::Client,
+    first_token_timeout: Duration,
+}
+
+impl OpenAiBackend {
+    pub fn new(
+        name: String,
+        base_url: String,
+        api_key: Option<String>,
+        client: reqwest::Client,
+        first_token_timeout: Duration,
+    ) -> Self {
+        Self {
+            name,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            client,
+            first_token_timeout,
+        }
+    }
+
+    async fn send_request(&self, request: UpstreamRequest) -> Result<UpstreamResponse, ApiError> {
+        let url = format!("{}/chat/completions", self.base_url);
+        let body = build_body(&request);
+        tracing::debug!(
+            "Backend '{}' request: {}",
+            self.name,
+            serde_json::to_string_pretty(&body).unwrap_or_default()
+        );
+
+        let mut req_builder = self.client.post(&url).json(&body);
+        if let Some(ref api_key) = self.api_key {
+            req_builder = req_builder.bearer_auth(api_key);
+        }
+
+        let response = req_builder
+            .send()
+            .await
+            .map_err(|e| ApiError::KiroApiError {
+                status: 502,
+                message: format!("Backend '{}' unreachable: {}", self.name, e),
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(ApiError::KiroApiError {
+                status: status.as_u16(),
+                message: format!("Backend '{}' error: {}", self.name, error_text),
+            });
+        }
+
+        let (first_chunk, rest) = wait_for_first_chunk(response, self.first_token_timeout).await?;
+        Ok(UpstreamResponse {
+            events: parse_sse(first_chunk, rest),
+            region: None,
+        })
+    }
+}
+
+impl UpstreamBackend for OpenAiBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn payload(&self, request: &UpstreamRequest) -> Result<Value, ApiError> {
+        Ok(build_body(request))
+    }
+
+    fn send(&self, request: UpstreamRequest) -> BoxFuture<'_, Result<UpstreamResponse, ApiError>> {
+        Box::pin(self.send_request(request))
+    }
+}
+
+/// Build a streaming chat completion request body
+fn build_body(request: &UpstreamRequest) -> Value {
+    let mut messages = Vec::new();
+    if !request.system_prompt.is_empty() {
+        messages.push(json!({"role": "system", "content": request.system_prompt}));
+    }
+    for message in &request.messages {
+        push_message(&mut messages, message);
+    }
+
+    let mut body = json!({
+        "model": request.model,
+        "messages": messages,
+        "stream": true,
+        "stream_options": {"include_usage": true},
+    });
+
+    if let Some(ref tools) = request.tools {
+        body["tools"] = tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.input_schema.clone().unwrap_or_else(|| json!({"type": "object"})),
+                    }
+                })
+            })
+            .collect();
+    }
+    if let Some(max_tokens) = request.max_tokens {
+        body["max_tokens"] = json!(max_tokens);
+    }
+    if let Some(temperature) = request.temperature {
+        body["temperature"] = json!(temperature);
+    }
+    body
+}
+
+/// Append a unified message in OpenAI form (tool results become `tool` messages)
+fn push_message(messages: &mut Vec<Value>, message: &UnifiedMessage) {
+    for result in message.tool_results.iter().flatten() {
+        messages.push(json!({
+            "role": "tool",
+            "tool_call_id": result.tool_use_id,
+            "content": result.content,
+        }));
+    }
+
+    let text = text_of(&message.content);
+    let images = match message.images {
+        Some(ref images) => images.clone(),
+        None => extract_images_from_content(&message.content),
+    };
+
+    if message.role == "assistant" {
+        let mut assistant = json!({"role": "assistant", "content": text});
+        if let Some(ref calls) = message.tool_calls {
+            assistant["tool_calls"] = json!(calls);
+            if text.is_empty() {
+                assistant["content"] = Value::Null;
+            }
+        }
+        messages.push(assistant);
+    } else if !images.is_empty() {
+        let mut parts = vec![json!({"type": "text", "text": text})];
+        parts.extend(images.iter().map(|image| {
+            json!({
+                "type": "image_url",
+                "image_url": {"url": format!("data:{};base64,{}", image.media_type, image.data)}
+            })
+        }));
+        messages.push(json!({"role": message.role, "content": parts}));
+    } else if !text.is_empty() || message.tool_results.is_none() {
+        messages.push(json!({"role": message.role, "content": text}));
+    }
+}
+
+/// Text blocks only (tool results are sent as their own messages)
+fn text_of(content: &MessageContent) -> String {
+    match content {
+        MessageContent::Text(text) => text.clone(),
+        MessageContent::Blocks(blocks) => blocks
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect(),
+    }
+}
+
+fn event(event_type: &str) -> KiroEvent {
+    KiroEvent {
+        event_type: event_type.to_string(),
+        content: None,
+        thinking_content: None,
+        tool_use: None,
+        usage: None,
+        context_usage_percentage: None,
+        is_first_thinking_chunk: false,
+        is_last_thinking_chunk: false,
+    }
+}
+
+/// Tool call assembled from streamed deltas
+#[derive(Default)]
+struct PendingTool {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Incremental parser for `data:` lines of a chat completion stream
+#[derive(Default)]
+struct SseState {
+    buffer: Vec<u8>,
+    /// Tool calls by index, emitted when the stream ends
+    tools: BTreeMap<u64, PendingTool>,
+    events: VecDeque<Result<KiroEvent, ApiError>>,
+    done: bool,
+}
+
+impl SseState {
+    fn feed(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            self.line(String::from_utf8_lossy(&line).trim());
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            return;
+        };
+        if data == "[DONE]" {
+            self.finish();
+            return;
+        }
+        let json: Value = match serde_json::from_str(data) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::warn!("Skipping unparseable backend chunk: {}", e);
+                return;
+            }
+        };
+
+        if let Some(error) = json.get("error") {
+            let message = error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .map_or_else(|| error.to_string(), str::to_string);
+            self.events.push_back(Err(ApiError::KiroApiError {
+                status: 502,
+                message,
+            }));
+            return;
+        }
+
+        if let Some(delta) = json.pointer("/choices/0/delta") {
+            let reasoning = delta
+                .get("reasoning_content")
+                .or_else(|| delta.get("reasoning"))
+                .and_then(|v| v.as_str());
+            if let Some(thinking) = reasoning.filter(|s| !s.is_empty()) {
+                let mut e = event("thinking");
+                e.thinking_content = Some(thinking.to_string());
+                self.events.push_back(Ok(e));
+            }
+            if let Some(content) = delta
+                .get("content")
+                .and_then(|v| v.as_str())
+                .filter(|s| !s.is_empty())
+            {
+                let mut e = event("content");
+                e.content = Some(content.to_string());
+                self.events.push_back(Ok(e));
+            }
+            for call in delta
+                .get("tool_calls")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+            {
+                let index = call.get("index").and_then(|v| v.as_u64()).unwrap_or(0);
+                let tool = self.tools.entry(index).or_default();
+                if let Some(id) = call.get("id").and_then(|v| v.as_str()) {
+                    tool.id = id.to_string();
+                }
+                if let Some(function) = call.get("function") {
+                    if let Some(name) = function.get("name").and_then(|v| v.as_str()) {
+                        tool.name.push_str(name);
+                    }
+                    if let Some(args) = function.get("arguments").and_then(|v| v.as_str()) {
+                        tool.arguments.push_str(args);
+                    }
+                }
+            }
+        }
+
+        if let Some(usage) = json.get("usage").filter(|u| u.is_object()) {
+            let tokens = |field: &str| usage.get(field).and_then(|v| v.as_i64()).unwrap_or(0);
+            let mut e = event("usage");
+            e.usage = Some(Usage {
+                input_tokens: tokens("prompt_tokens") as i32,
+                output_tokens: tokens("completion_tokens") as i32,
+                credits: None,
+            });
+            self.events.push_back(Ok(e));
+        }
+    }
+
+    /// Emit the assembled tool calls; nothing is parsed after this
+    fn finish(&mut self) {
+        if self.done {
+            return;
+        }
+        self.done = true;
+        for (_, tool) in std::mem::take(&mut self.tools) {
+            let input = if tool.arguments.trim().is_empty() {
+                json!({})
+            } else {
+                serde_json::from_str(&tool.arguments).unwrap_or_else(|e| {
+                    tracing::warn!("Invalid arguments for tool '{}': {}", tool.name, e);
+                    json!({})
+                })
+            };
+            let mut e = event("tool_use");
+            e.tool_use = Some(ToolUse {
+                tool_use_id: tool.id,
+                name: tool.name,
+                input,
+            });
+            self.events.push_back(Ok(e));
+        }
+    }
+}
+
+/// Turn a chat completion SSE body into unified events
+fn parse_sse(
+    first_chunk: Option<bytes::Bytes>,
+    body: ByteStream,
+) -> crate::streaming::KiroEventStream {
+    let mut state = SseState::default();
+    if let Some(chunk) = first_chunk {
+        state.feed(&chunk);
+    }
+
+    futures::stream::unfold((state, body), |(mut state, mut body)| async move {
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Some((event, (state, body)));
+            }
+            if state.done {
+                return None;
+            }
+            match body.next().await {
+                Some(Ok(chunk)) => state.feed(&chunk),
+                Some(Err(e)) => {
+                    state.finish();
+                    state
+                        .events
+                        .push_back(Err(ApiError::Internal(anyhow::anyhow!(
+                            "Stream error: {}",
+                            e
+                        ))));
+                }
+                None => {
+                    let rest = std::mem::take(&mut state.buffer);
+                    state.line(String::from_utf8_lossy(&rest).trim());
+                    state.finish();
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admission::Priority;
+    use crate::converters::core::{ToolCall, ToolFunction, ToolResult, UnifiedTool};
+
+    fn request(messages: Vec<UnifiedMessage>) -> UpstreamRequest {
+        UpstreamRequest {
+            model: "qwen2.5-coder".to_string(),
+            model_id: "local-coder".to_string(),
+            system_prompt: "Be brief.".to_string(),
+            messages,
+            tools: None,
+            max_tokens: Some(256),
+            temperature: None,
+            conversation: None,
+            key: "default".to_string(),
+            priority: Priority::Normal,
+        }
+    }
+
+    fn message(role: &str, text: &str) -> UnifiedMessage {
+        UnifiedMessage {
+            role: role.to_string(),
+            content: MessageContent::Text(text.to_string()),
+            tool_calls: None,
+            tool_results: None,
+            images: None,
+        }
+    }
+
+    #[test]
+    fn test_build_body_with_tools() {
+        let mut assistant = message("assistant", "");
+        assistant.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            call_type: "function".to_string(),
+            function: ToolFunction {
+                name: "get_weather".to_string(),
+                arguments: r#"{"city":"Oslo"}"#.to_string(),
+            },
+        }]);
+        let mut result = message("user", "");
+        result.tool_results = Some(vec![ToolResult {
+            result_type: "tool_result".to_string(),
+            tool_use_id: "call_1".to_string(),
+            content: "-3C".to_string(),
+        }]);
+
+        let mut request = request(vec![message("user", "Weather?"), assistant, result]);
+        request.tools = Some(vec![UnifiedTool {
+            name: "get_weather".to_string(),
+            description: Some("Current weather".to_string()),
+            input_schema: None,
+        }]);
+
+        let body = build_body(&request);
+        assert_eq!(body["model"], "qwen2.5-coder");
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["max_tokens"], 256);
+        assert_eq!(
+            body["messages"],
+            json!([
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Weather?"},
+                {"role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Oslo\"}"}
+                }]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "-3C"}
+            ])
+        );
+        assert_eq!(body["tools"][0]["function"]["name"], "get_weather");
+        assert_eq!(body["tools"][0]["function"]["parameters"]["type"], "object");
+    }
+
+    #[tokio::test]
+    async fn test_parse_sse_events() {
+        let chunks: &[&'static str] = &[
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"reasoning_content\":\"Hmm\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\ndata: {\"choices\":[{\"delta\":",
+            "{\"content\":\"lo\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"function\":{\"name\":\"get_weather\",\"arguments\":\"{\\\"city\\\"\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\":\\\"Oslo\\\"}\"}}]}}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":5}}\n\n",
+            "data: [DONE]\n\n",
+        ];
+        let body: ByteStream = futures::stream::iter(
+            chunks[1..]
+                .iter()
+                .map(|c| Ok::<_, reqwest::Error>(bytes::Bytes::from_static(c.as_bytes()))),
+        )
+        .boxed();
+
+        let events: Vec<KiroEvent> =
+            parse_sse(Some(bytes::Bytes::from_static(chunks[0].as_bytes())), body)
+                .map(|e| e.unwrap())
+                .collect()
+                .await;
+
+        let types: Vec<&str> = events.iter().map(|e| e.event_type.as_str()).collect();
+        assert_eq!(
+            types,
+            ["thinking", "content", "content", "usage", "tool_use"]
+        );
+        assert_eq!(events[1].content.as_deref(), Some("Hel"));
+        assert_eq!(events[2].content.as_deref(), Some("lo"));
+        assert_eq!(events[3].usage.as_ref().unwrap().input_tokens, 12);
+        let tool = events[4].tool_use.as_ref().unwrap();
+        assert_eq!(tool.tool_use_id, "call_1");
+        assert_eq!(tool.input, json!({"city": "Oslo"}));
+    }
+}
@@ -5,7 +5,7 @@ use axum::{
     extract::State,
     http::{header, StatusCode},
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{get, post},
     Router,
 };
 use bytes::{BufMut, BytesMut};
@@ -46,6 +46,7 @@ impl MockKiroServer {
 
         let app = Router::new()
             .route("/generateAssistantResponse", post(handle_generate))
+            .route("/ListAvailableModels", get(handle_list_models))
             .with_state(config);
 
         tokio::spawn(async move {
@@ -128,6 +129,24 @@ async fn handle_generate(State(config): State<Arc<MockServerConfig>>) -> Respons
     }
 }
 
+/// Handle ListAvailableModels requests, so a gateway can start against the mock
+/// (KIRO_UPSTREAM_BASE_URL=<mock url>)
+async fn handle_list_models() -> Response {
+    let response = serde_json::json!({
+        "models": [
+            {"modelId": "claude-sonnet-4", "tokenLimits": {"maxInputTokens": 200000}},
+            {"modelId": "claude-haiku-4.5", "tokenLimits": {"maxInputTokens": 200000}}
+        ]
+    });
+
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        serde_json::to_string(&response).unwrap(),
+    )
+        .into_response()
+}
+
 /// Generate a streaming response using AWS Event Stream format
 fn generate_stream(
     config: Arc<MockServerConfig>,
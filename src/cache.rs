@@ -1,10 +1,28 @@
 use dashmap::DashMap;
+use serde::Serialize;
 use serde_json::Value;
+use std::collections::HashSet;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const DEFAULT_MAX_INPUT_TOKENS: i32 = 200_000;
 
+/// Changes between two model catalogues (hidden models excluded)
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ModelDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Models whose metadata (e.g. token limits) changed
+    pub updated: Vec<String>,
+}
+
+impl ModelDiff {
+    /// Check whether the catalogue changed at all
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+}
+
 /// Thread-safe cache for storing model metadata
 pub struct ModelCache {
     /// Model data indexed by model ID
@@ -28,25 +46,67 @@ impl ModelCache {
     }
 
     /// Update the cache with new model data
-    pub fn update(&self, models_data: Vec<Value>) {
+    ///
+    /// Models missing from the new data are removed, hidden models are kept.
+    /// The cache is never empty in between, so concurrent requests keep
+    /// resolving models during a refresh.
+    pub fn update(&self, models_data: Vec<Value>) -> ModelDiff {
         tracing::info!("Updating model cache. Found {} models.", models_data.len());
 
-        // Clear existing cache
-        self.cache.clear();
+        let mut diff = ModelDiff::default();
+        let mut seen = HashSet::new();
 
-        // Add new models
+        // Add or replace new models
         for model in models_data {
             if let Some(model_id) = model.get("modelId").and_then(|v| v.as_str()) {
-                self.cache.insert(model_id.to_string(), model);
+                let model_id = model_id.to_string();
+                match self.cache.insert(model_id.clone(), model.clone()) {
+                    None => diff.added.push(model_id.clone()),
+                    Some(old) if is_hidden(&old) => diff.added.push(model_id.clone()),
+                    Some(old) if old != model => diff.updated.push(model_id.clone()),
+                    Some(_) => {}
+                }
+                seen.insert(model_id);
             }
         }
 
+        // Drop models that are gone
+        self.cache.retain(|model_id, model| {
+            let keep = seen.contains(model_id) || is_hidden(model);
+            if !keep {
+                diff.removed.push(model_id.clone());
+            }
+            keep
+        });
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.updated.sort();
+
         // Update timestamp
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
         self.last_update.insert((), now);
+        diff
+    }
+
+    /// Seed the cache from a saved catalogue snapshot
+    ///
+    /// The timestamp is left alone, so the cache stays stale until the next
+    /// successful `update`.
+    pub fn restore(&self, models_data: Vec<Value>) {
+        for model in models_data {
+            if let Some(model_id) = model.get("modelId").and_then(|v| v.as_str()) {
+                self.cache.insert(model_id.to_string(), model);
+            }
+        }
+    }
+
+    /// How long the catalogue is considered fresh
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.cache_ttl)
     }
 
     /// Get model information by ID
@@ -101,7 +161,6 @@ impl ModelCache {
     }
 
     /// Check if the cache is stale
-    #[allow(dead_code)]
     pub fn is_stale(&self) -> bool {
         if let Some(entry) = self.last_update.get(&()) {
             let now = SystemTime::now()
@@ -130,6 +189,14 @@ impl ModelCache {
     }
 }
 
+/// Check whether a cache entry is a hidden model alias
+fn is_hidden(model: &Value) -> bool {
+    model
+        .get("_is_hidden")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
 impl Clone for ModelCache {
     fn clone(&self) -> Self {
         Self {
@@ -201,4 +268,43 @@ mod tests {
         assert_eq!(model["_is_hidden"], true);
         assert_eq!(model["_internal_id"], "CLAUDE_3_7_SONNET_20250219_V1_0");
     }
+
+    #[test]
+    fn test_update_diff_keeps_hidden_models() {
+        let cache = ModelCache::new(3600);
+        let model = |id: &str, limit: i64| serde_json::json!({"modelId": id, "tokenLimits": {"maxInputTokens": limit}});
+
+        let diff = cache.update(vec![model("claude-a", 100), model("claude-b", 100)]);
+        assert_eq!(diff.added, vec!["claude-a", "claude-b"]);
+        cache.add_hidden_model("claude-old", "CLAUDE_OLD_V1_0");
+
+        let diff = cache.update(vec![model("claude-b", 200), model("claude-c", 100)]);
+        assert_eq!(
+            diff,
+            ModelDiff {
+                added: vec!["claude-c".to_string()],
+                removed: vec!["claude-a".to_string()],
+                updated: vec!["claude-b".to_string()],
+            }
+        );
+        assert!(cache.is_valid_model("claude-old"));
+        assert!(!cache.is_valid_model("claude-a"));
+        assert_eq!(cache.get_max_input_tokens("claude-b"), 200);
+
+        assert!(cache
+            .update(vec![model("claude-b", 200), model("claude-c", 100)])
+            .is_empty());
+    }
+
+    #[test]
+    fn test_restore_keeps_cache_stale() {
+        let cache = ModelCache::new(3600);
+        cache.restore(vec![serde_json::json!({"modelId": "claude-sonnet-4"})]);
+
+        assert!(cache.is_valid_model("claude-sonnet-4"));
+        assert!(cache.is_stale());
+
+        cache.update(vec![serde_json::json!({"modelId": "claude-sonnet-4"})]);
+        assert!(!cache.is_stale());
+    }
 }
@@ -0,0 +1,315 @@
+// Model catalogue: load ListAvailableModels at startup and keep the cache fresh
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::auth::AuthManager;
+use crate::cache::{ModelCache, ModelDiff};
+use crate::endpoints::Endpoint;
+use crate::http_client::KiroHttpClient;
+use crate::retry::Backoff;
+
+/// First retry delay after a failed background refresh
+const REFRESH_RETRY_BASE: Duration = Duration::from_secs(30);
+
+/// Serializes refreshes, so the background task and the admin endpoint don't
+/// race each other
+static REFRESH_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Load models from Kiro API (no retries - callers decide how to retry)
+pub async fn fetch_models(
+    http_client: &KiroHttpClient,
+    auth_manager: &AuthManager,
+) -> Result<Vec<Value>> {
+    // Get access token
+    let access_token = auth_manager.get_access_token().await?;
+    let region = auth_manager.get_region().await;
+
+    // Build request to list models - use Q API endpoint, not CodeWhisperer
+    let url = http_client.endpoint_url(Endpoint::ListAvailableModels, &region);
+
+    // Build request with query parameters
+    let mut req_builder = http_client
+        .client()
+        .get(&url)
+        .query(&[("origin", "AI_EDITOR")])
+        .header("Authorization", format!("Bearer {}", access_token))
+        .header("Content-Type", "application/json");
+
+    // Attach the same profileArn that generateAssistantResponse uses
+    // (resolved per auth type by AuthManager::initialize_profile)
+    if let Some(profile_arn) = auth_manager.get_profile_arn().await {
+        req_builder = req_builder.query(&[("profileArn", profile_arn)]);
+    }
+
+    let req = req_builder.build()?;
+
+    // Execute request WITHOUT retries
+    let response = http_client.request_no_retry(req).await?;
+
+    // Parse response
+    let body = response.text().await?;
+    let json: Value = serde_json::from_str(&body)?;
+
+    // Extract models from response
+    if let Some(models) = json.get("models").and_then(|v| v.as_array()) {
+        Ok(models.clone())
+    } else {
+        Ok(vec![])
+    }
+}
+
+/// Add hidden models to cache
+pub fn add_hidden_models(cache: &ModelCache) {
+    // Add commonly used model aliases that may not be in the API response
+    let hidden_models = vec![
+        (
+            "claude-3-5-sonnet-20241022",
+            "CLAUDE_3_5_SONNET_20241022_V2_0",
+        ),
+        (
+            "claude-3-5-sonnet-20240620",
+            "CLAUDE_3_5_SONNET_20240620_V1_0",
+        ),
+        (
+            "claude-3-5-haiku-20241022",
+            "CLAUDE_3_5_HAIKU_20241022_V1_0",
+        ),
+        ("claude-3-opus-20240229", "CLAUDE_3_OPUS_20240229_V1_0"),
+        ("claude-3-sonnet-20240229", "CLAUDE_3_SONNET_20240229_V1_0"),
+        ("claude-3-haiku-20240307", "CLAUDE_3_HAIKU_20240307_V1_0"),
+        ("claude-sonnet-4", "CLAUDE_SONNET_4_20250514_V1_0"),
+        ("claude-sonnet-4-20250514", "CLAUDE_SONNET_4_20250514_V1_0"),
+        (
+            "anthropic.claude-sonnet-4-v1",
+            "CLAUDE_SONNET_4_20250514_V1_0",
+        ),
+    ];
+
+    for (display_name, internal_id) in hidden_models {
+        cache.add_hidden_model(display_name, internal_id);
+    }
+}
+
+/// Write the catalogue to disk, so a later start can serve it if the
+/// Kiro API is unreachable
+///
+/// Written to a temporary file first, so a crash never leaves a torn snapshot.
+pub fn save_snapshot(path: &Path, models: &[Value]) -> Result<()> {
+    let body = serde_json::to_vec_pretty(&json!({
+        "saved_at": chrono::Utc::now().to_rfc3339(),
+        "models": models,
+    }))?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, body).with_context(|| format!("writing {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("replacing {}", path.display()))?;
+    Ok(())
+}
+
+/// Read a catalogue written by `save_snapshot`
+pub fn load_snapshot(path: &Path) -> Result<Vec<Value>> {
+    let body = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    let snapshot: Value =
+        serde_json::from_slice(&body).with_context(|| format!("parsing {}", path.display()))?;
+    match snapshot.get("models").and_then(|v| v.as_array()) {
+        Some(models) if !models.is_empty() => Ok(models.clone()),
+        _ => anyhow::bail!("{} contains no models", path.display()),
+    }
+}
+
+/// Load a freshly fetched catalogue into the cache and persist the snapshot
+///
+/// A snapshot that can't be written is logged, not fatal: the catalogue in
+/// memory is still good.
+pub fn apply(cache: &ModelCache, models: Vec<Value>, snapshot: Option<&Path>) -> ModelDiff {
+    if let Some(path) = snapshot {
+        if let Err(e) = save_snapshot(path, &models) {
+            tracing::warn!("Failed to save model catalogue snapshot: {:#}", e);
+        }
+    }
+    cache.update(models)
+}
+
+/// Reload the model catalogue into the cache
+///
+/// On failure the cache is left untouched, so the last good catalogue keeps
+/// being served. Changes are logged and returned.
+pub async fn refresh(
+    http_client: &KiroHttpClient,
+    auth_manager: &AuthManager,
+    cache: &ModelCache,
+    snapshot: Option<&Path>,
+) -> Result<ModelDiff> {
+    let _guard = REFRESH_LOCK.lock().await;
+
+    let models = fetch_models(http_client, auth_manager).await?;
+    // An empty list is far more likely an upstream glitch than a real catalogue
+    if models.is_empty() {
+        anyhow::bail!("ListAvailableModels returned no models");
+    }
+
+    let diff = apply(cache, models, snapshot);
+    log_diff(&diff, cache.get_all_model_ids().len());
+    Ok(diff)
+}
+
+/// Log what a refresh changed
+fn log_diff(diff: &ModelDiff, total: usize) {
+    if diff.is_empty() {
+        tracing::debug!("Model catalogue unchanged ({} models)", total);
+        return;
+    }
+
+    tracing::info!(
+        "🔄 Model catalogue refreshed ({} models): {} added, {} removed, {} updated",
+        total,
+        diff.added.len(),
+        diff.removed.len(),
+        diff.updated.len()
+    );
+    for model in &diff.added {
+        tracing::info!("  + {}", model);
+    }
+    for model in &diff.removed {
+        tracing::info!("  - {}", model);
+    }
+    for model in &diff.updated {
+        tracing::info!("  ~ {}", model);
+    }
+}
+
+/// Refresh the catalogue every cache TTL in the background
+///
+/// Failed refreshes are retried with jittered backoff (30s up to the TTL)
+/// while the last good catalogue stays in place. A cache that is already
+/// stale (degraded startup) is retried on the backoff schedule right away.
+pub fn spawn_refresh_task(
+    http_client: Arc<KiroHttpClient>,
+    auth_manager: Arc<AuthManager>,
+    cache: ModelCache,
+    snapshot: Option<PathBuf>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let ttl = cache.ttl();
+        let new_backoff = || Backoff::new(REFRESH_RETRY_BASE.min(ttl), ttl);
+        let mut backoff = new_backoff();
+        let mut delay = if cache.is_stale() {
+            backoff.next_delay()
+        } else {
+            ttl
+        };
+
+        loop {
+            tokio::time::sleep(delay).await;
+            match refresh(&http_client, &auth_manager, &cache, snapshot.as_deref()).await {
+                Ok(_) => {
+                    backoff = new_backoff();
+                    delay = ttl;
+                }
+                Err(e) => {
+                    delay = backoff.next_delay();
+                    tracing::warn!(
+                        "Model catalogue refresh failed, keeping {} cached models; retrying in {}s: {:#}",
+                        cache.get_all_model_ids().len(),
+                        delay.as_secs(),
+                        e
+                    );
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::egress::EgressConfig;
+    use crate::endpoints::EndpointResolver;
+    use std::collections::HashMap;
+
+    fn test_client(server: &mockito::Server) -> (KiroHttpClient, Arc<AuthManager>) {
+        let auth_manager = Arc::new(
+            AuthManager::new_for_testing("test-token".to_string(), "us-east-1".to_string(), 300)
+                .unwrap(),
+        );
+        let endpoints = EndpointResolver::new(
+            None,
+            HashMap::from([(
+                Endpoint::ListAvailableModels,
+                format!("{}/ListAvailableModels", server.url()),
+            )]),
+        )
+        .unwrap();
+        let http_client = KiroHttpClient::new(
+            Arc::clone(&auth_manager),
+            20,
+            30,
+            300,
+            0,
+            &EgressConfig::default(),
+        )
+        .unwrap()
+        .with_endpoints(Arc::new(endpoints));
+        (http_client, auth_manager)
+    }
+
+    #[tokio::test]
+    async fn test_refresh_keeps_last_good_catalogue() {
+        let mut server = mockito::Server::new_async().await;
+        let (http_client, auth_manager) = test_client(&server);
+        let cache = ModelCache::new(3600);
+        cache.update(vec![serde_json::json!({"modelId": "claude-old"})]);
+        add_hidden_models(&cache);
+
+        let ok = server
+            .mock("GET", "/ListAvailableModels")
+            .match_query(mockito::Matcher::Any)
+            .with_status(200)
+            .with_body(r#"{"models": [{"modelId": "claude-new"}]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let diff = refresh(&http_client, &auth_manager, &cache, None)
+            .await
+            .unwrap();
+        ok.assert_async().await;
+        assert_eq!(diff.added, vec!["claude-new"]);
+        assert_eq!(diff.removed, vec!["claude-old"]);
+        assert!(cache.is_valid_model("claude-sonnet-4"));
+
+        // Upstream failure: the catalogue stays as it was
+        server.reset();
+        server
+            .mock("GET", "/ListAvailableModels")
+            .match_query(mockito::Matcher::Any)
+            .with_status(500)
+            .create_async()
+            .await;
+        assert!(refresh(&http_client, &auth_manager, &cache, None)
+            .await
+            .is_err());
+        assert!(cache.is_valid_model("claude-new"));
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let dir = std::env::temp_dir().join(format!("kiro-catalog-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("models.json");
+        let models = vec![serde_json::json!({"modelId": "claude-sonnet-4"})];
+
+        assert!(load_snapshot(&path).is_err());
+
+        let cache = ModelCache::new(3600);
+        apply(&cache, models.clone(), Some(&path));
+        assert_eq!(load_snapshot(&path).unwrap(), models);
+
+        std::fs::write(&path, r#"{"models": []}"#).unwrap();
+        assert!(load_snapshot(&path).is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,366 @@
+// Upstream circuit breaker: fail fast while CodeWhisperer is down
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sliding window for the error-rate threshold
+const ERROR_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Breaker state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// Upstream considered down: requests fail fast
+    Open,
+    /// Cool-down elapsed: a single probe request is let through
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// Thresholds (0 disables the respective trip condition)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BreakerConfig {
+    /// Trip after this many consecutive failures
+    pub consecutive_failures: u32,
+    /// Trip when the failure rate over the last minute reaches this fraction...
+    pub error_rate: f64,
+    /// ...and at least this many requests were made in that minute
+    pub min_requests: u32,
+    /// How long the breaker stays open before letting a probe through
+    pub open_duration: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            consecutive_failures: 5,
+            error_rate: 0.5,
+            min_requests: 20,
+            open_duration: Duration::from_secs(30),
+        }
+    }
+}
+
+impl BreakerConfig {
+    /// Check whether any trip condition is configured
+    pub fn is_enabled(&self) -> bool {
+        self.consecutive_failures > 0 || self.error_rate > 0.0
+    }
+}
+
+/// Point-in-time view of the breaker, for /health and the dashboard
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerSnapshot {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub error_rate: f64,
+    pub trips: u64,
+    /// Seconds until a probe is allowed (open state only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+}
+
+struct Inner {
+    state: CircuitState,
+    consecutive_failures: u32,
+    /// Recent outcomes: (finished at, failed)
+    outcomes: VecDeque<(Instant, bool)>,
+    failures: usize,
+    opened_at: Option<Instant>,
+    /// When the current half-open probe was let through
+    probe_started: Option<Instant>,
+    trips: u64,
+}
+
+impl Inner {
+    fn prune(&mut self, now: Instant) {
+        while let Some(&(at, failed)) = self.outcomes.front() {
+            if now.duration_since(at) < ERROR_RATE_WINDOW {
+                break;
+            }
+            self.outcomes.pop_front();
+            if failed {
+                self.failures -= 1;
+            }
+        }
+    }
+
+    fn error_rate(&self) -> f64 {
+        if self.outcomes.is_empty() {
+            0.0
+        } else {
+            self.failures as f64 / self.outcomes.len() as f64
+        }
+    }
+
+    fn open(&mut self, now: Instant) {
+        self.state = CircuitState::Open;
+        self.opened_at = Some(now);
+        self.probe_started = None;
+        self.trips += 1;
+    }
+}
+
+/// Circuit breaker around upstream calls
+///
+/// - Closed: requests pass; trips to open on `consecutive_failures` in a row
+///   or an `error_rate` over the last minute (with `min_requests`)
+/// - Open: requests are rejected until `open_duration` has passed
+/// - Half-open: one probe is let through; success closes the breaker, failure
+///   re-opens it. A probe that never reports back is replaced after
+///   `open_duration`.
+pub struct CircuitBreaker {
+    config: BreakerConfig,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: BreakerConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                outcomes: VecDeque::new(),
+                failures: 0,
+                opened_at: None,
+                probe_started: None,
+                trips: 0,
+            }),
+        }
+    }
+
+    /// Ask to send a request upstream
+    ///
+    /// Returns the time until a probe is allowed if the request must fail fast.
+    pub fn try_acquire(&self, now: Instant) -> Result<(), Duration> {
+        if !self.config.is_enabled() {
+            return Ok(());
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::Open => {
+                let opened_at = inner.opened_at.unwrap_or(now);
+                let elapsed = now.duration_since(opened_at);
+                if elapsed < self.config.open_duration {
+                    return Err(self.config.open_duration - elapsed);
+                }
+                tracing::info!("Circuit breaker half-open, sending probe request");
+                inner.state = CircuitState::HalfOpen;
+                inner.probe_started = Some(now);
+                Ok(())
+            }
+            CircuitState::HalfOpen => match inner.probe_started {
+                Some(started) if now.duration_since(started) < self.config.open_duration => {
+                    Err(self.config.open_duration - now.duration_since(started))
+                }
+                _ => {
+                    inner.probe_started = Some(now);
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    /// Record a successful upstream call (any response that isn't an outage)
+    pub fn record_success(&self, now: Instant) {
+        self.record(now, false);
+    }
+
+    /// Record a failed upstream call (5xx or network error)
+    pub fn record_failure(&self, now: Instant) {
+        self.record(now, true);
+    }
+
+    fn record(&self, now: Instant, failed: bool) {
+        if !self.config.is_enabled() {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.prune(now);
+        inner.outcomes.push_back((now, failed));
+        if failed {
+            inner.failures += 1;
+            inner.consecutive_failures += 1;
+        } else {
+            inner.consecutive_failures = 0;
+        }
+
+        match inner.state {
+            CircuitState::HalfOpen if failed => {
+                tracing::warn!("Circuit breaker probe failed, re-opening");
+                inner.open(now);
+            }
+            CircuitState::HalfOpen => {
+                tracing::info!("Circuit breaker probe succeeded, closing");
+                inner.state = CircuitState::Closed;
+                inner.opened_at = None;
+                inner.probe_started = None;
+                // Start the error-rate window afresh
+                inner.outcomes.clear();
+                inner.failures = 0;
+            }
+            CircuitState::Closed if failed => {
+                let by_count = self.config.consecutive_failures > 0
+                    && inner.consecutive_failures >= self.config.consecutive_failures;
+                let by_rate = self.config.error_rate > 0.0
+                    && inner.outcomes.len() >= self.config.min_requests as usize
+                    && inner.error_rate() >= self.config.error_rate;
+                if by_count || by_rate {
+                    tracing::error!(
+                        consecutive_failures = inner.consecutive_failures,
+                        error_rate = inner.error_rate(),
+                        "Circuit breaker open: failing upstream requests fast for {}s",
+                        self.config.open_duration.as_secs()
+                    );
+                    inner.open(now);
+                }
+            }
+            // Late results from requests started before the breaker opened
+            _ => {}
+        }
+    }
+
+    /// Current state
+    pub fn state(&self) -> CircuitState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Snapshot for /health and the dashboard
+    pub fn snapshot(&self, now: Instant) -> BreakerSnapshot {
+        let mut inner = self.inner.lock().unwrap();
+        inner.prune(now);
+
+        let retry_after = match (inner.state, inner.opened_at) {
+            (CircuitState::Open, Some(opened_at)) => Some(
+                self.config
+                    .open_duration
+                    .saturating_sub(now.duration_since(opened_at))
+                    .as_secs_f64()
+                    .ceil() as u64,
+            ),
+            _ => None,
+        };
+
+        BreakerSnapshot {
+            state: inner.state,
+            consecutive_failures: inner.consecutive_failures,
+            error_rate: inner.error_rate(),
+            trips: inner.trips,
+            retry_after,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BreakerConfig {
+        BreakerConfig {
+            consecutive_failures: 3,
+            error_rate: 0.5,
+            min_requests: 10,
+            open_duration: Duration::from_secs(30),
+        }
+    }
+
+    #[test]
+    fn test_trips_on_consecutive_failures() {
+        let breaker = CircuitBreaker::new(config());
+        let now = Instant::now();
+
+        breaker.record_failure(now);
+        breaker.record_failure(now);
+        breaker.record_success(now);
+        breaker.record_failure(now);
+        breaker.record_failure(now);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        breaker.record_failure(now);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_eq!(breaker.try_acquire(now), Err(Duration::from_secs(30)));
+        assert_eq!(breaker.snapshot(now).trips, 1);
+        assert_eq!(breaker.snapshot(now).retry_after, Some(30));
+    }
+
+    #[test]
+    fn test_trips_on_error_rate() {
+        let breaker = CircuitBreaker::new(BreakerConfig {
+            consecutive_failures: 0,
+            ..config()
+        });
+        let now = Instant::now();
+
+        // Alternating failures never hit a consecutive threshold
+        for _ in 0..4 {
+            breaker.record_success(now);
+            breaker.record_failure(now);
+        }
+        // Below min_requests
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        breaker.record_success(now);
+        breaker.record_failure(now);
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_half_open_probe() {
+        let breaker = CircuitBreaker::new(config());
+        let now = Instant::now();
+        for _ in 0..3 {
+            breaker.record_failure(now);
+        }
+
+        // After the cool-down a single probe goes through
+        let later = now + Duration::from_secs(30);
+        assert!(breaker.try_acquire(later).is_ok());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.try_acquire(later).is_err());
+
+        // Failed probe re-opens
+        breaker.record_failure(later);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.try_acquire(later).is_err());
+
+        // Successful probe closes
+        let much_later = later + Duration::from_secs(30);
+        assert!(breaker.try_acquire(much_later).is_ok());
+        breaker.record_success(much_later);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire(much_later).is_ok());
+        assert_eq!(breaker.snapshot(much_later).trips, 2);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let breaker = CircuitBreaker::new(BreakerConfig {
+            consecutive_failures: 0,
+            error_rate: 0.0,
+            ..config()
+        });
+        let now = Instant::now();
+        for _ in 0..100 {
+            breaker.record_failure(now);
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire(now).is_ok());
+    }
+}
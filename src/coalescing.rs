@@ -0,0 +1,476 @@
+// Coalescing of identical in-flight upstream requests
+//
+// The first request with a given fingerprint starts the upstream call in a
+// background task; identical requests arriving while it runs join that call
+// instead of starting their own. Events are buffered, so every client -
+// including late joiners - receives the full stream from its start. The
+// upstream call is abandoned once all clients have gone.
+
+use futures::future::Future;
+use futures::stream::{self, StreamExt};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+use crate::backends::UpstreamResponse;
+use crate::error::ApiError;
+use crate::keys::matches_pattern;
+use crate::streaming::{KiroEvent, KiroEventStream};
+
+type Flights = Arc<Mutex<HashMap<String, Arc<Flight>>>>;
+
+enum Start {
+    Pending,
+    /// Upstream responded (serving region)
+    Started(Option<String>),
+    Failed(ApiError),
+}
+
+struct FlightState {
+    start: Start,
+    events: Vec<Result<KiroEvent, ApiError>>,
+    done: bool,
+}
+
+/// One upstream call shared by identical requests
+struct Flight {
+    state: Mutex<FlightState>,
+    /// Bumped on every change of `state`
+    version: watch::Sender<u64>,
+    /// Clients still reading the flight
+    subscribers: AtomicUsize,
+}
+
+impl Flight {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(FlightState {
+                start: Start::Pending,
+                events: Vec::new(),
+                done: false,
+            }),
+            version: watch::channel(0).0,
+            subscribers: AtomicUsize::new(0),
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut FlightState)) {
+        if let Ok(mut state) = self.state.lock() {
+            f(&mut state);
+        }
+        self.version.send_modify(|version| *version += 1);
+    }
+}
+
+/// A client reading a flight from its first event
+struct Subscription {
+    flight: Arc<Flight>,
+    version: watch::Receiver<u64>,
+    next: usize,
+}
+
+impl Subscription {
+    fn new(flight: &Arc<Flight>) -> Self {
+        flight.subscribers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            flight: Arc::clone(flight),
+            version: flight.version.subscribe(),
+            next: 0,
+        }
+    }
+
+    /// Wait until the upstream call has responded
+    async fn started(&mut self) -> Result<Option<String>, ApiError> {
+        loop {
+            if let Ok(state) = self.flight.state.lock() {
+                match state.start {
+                    Start::Pending => {}
+                    Start::Started(ref region) => return Ok(region.clone()),
+                    Start::Failed(ref e) => return Err(duplicate_error(e)),
+                }
+            }
+            if self.version.changed().await.is_err() {
+                return Err(ApiError::Internal(anyhow::anyhow!(
+                    "Coalesced upstream request was dropped"
+                )));
+            }
+        }
+    }
+
+    async fn next_event(&mut self) -> Option<Result<KiroEvent, ApiError>> {
+        loop {
+            if let Ok(state) = self.flight.state.lock() {
+                if let Some(item) = state.events.get(self.next) {
+                    self.next += 1;
+                    return Some(item.as_ref().cloned().map_err(duplicate_error));
+                }
+                if state.done {
+                    return None;
+                }
+            }
+            if self.version.changed().await.is_err() {
+                return None;
+            }
+        }
+    }
+
+    fn into_stream(self) -> KiroEventStream {
+        stream::unfold(self, |mut subscription| async move {
+            let item = subscription.next_event().await?;
+            Some((item, subscription))
+        })
+        .boxed()
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.flight.subscribers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Shares upstream calls between identical concurrent requests
+///
+/// Only requests to the configured routes (`COALESCE_ROUTES`) are coalesced.
+pub struct Coalescer {
+    /// Route path patterns (trailing `*` wildcard)
+    routes: Vec<String>,
+    flights: Flights,
+}
+
+impl Coalescer {
+    pub fn new(routes: Vec<String>) -> Self {
+        Self {
+            routes,
+            flights: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Check whether requests to this route path are coalesced
+    pub fn applies_to(&self, path: &str) -> bool {
+        self.routes
+            .iter()
+            .any(|pattern| matches_pattern(pattern, path))
+    }
+
+    /// Run `send` once for all concurrent requests with this fingerprint
+    ///
+    /// `send` is only polled (in a background task) if no identical request is
+    /// in flight. Returns the response and whether it joined another request.
+    pub async fn run<F>(
+        &self,
+        fingerprint: String,
+        send: F,
+    ) -> Result<(UpstreamResponse, bool), ApiError>
+    where
+        F: Future<Output = Result<UpstreamResponse, ApiError>> + Send + 'static,
+    {
+        let (mut subscription, joined) = {
+            let mut flights = self
+                .flights
+                .lock()
+                .map_err(|_| ApiError::Internal(anyhow::anyhow!("Coalescing lock poisoned")))?;
+            match flights.get(&fingerprint) {
+                Some(flight) => (Subscription::new(flight), true),
+                None => {
+                    let flight = Arc::new(Flight::new());
+                    flights.insert(fingerprint.clone(), Arc::clone(&flight));
+                    (Subscription::new(&flight), false)
+                }
+            }
+        };
+
+        if !joined {
+            tokio::spawn(drive(
+                Arc::clone(&self.flights),
+                fingerprint,
+                Arc::clone(&subscription.flight),
+                send,
+            ));
+        }
+
+        let region = subscription.started().await?;
+        let response = UpstreamResponse {
+            events: subscription.into_stream(),
+            region,
+        };
+        Ok((response, joined))
+    }
+}
+
+/// Make the upstream call and buffer its events for every subscriber
+async fn drive<F>(flights: Flights, fingerprint: String, flight: Arc<Flight>, send: F)
+where
+    F: Future<Output = Result<UpstreamResponse, ApiError>>,
+{
+    let response = tokio::select! {
+        response = send => response,
+        _ = abandoned(&flights, &fingerprint, &flight) => {
+            tracing::debug!("Coalesced upstream request abandoned by all clients before it started");
+            return;
+        }
+    };
+    let mut events = match response {
+        Ok(response) => {
+            flight.update(|state| state.start = Start::Started(response.region));
+            response.events
+        }
+        Err(e) => {
+            remove(&flights, &fingerprint, &flight);
+            flight.update(|state| state.start = Start::Failed(e));
+            return;
+        }
+    };
+
+    loop {
+        let item = tokio::select! {
+            item = events.next() => item,
+            _ = abandoned(&flights, &fingerprint, &flight) => {
+                tracing::debug!("Coalesced upstream request abandoned by all clients");
+                return;
+            }
+        };
+        let Some(item) = item else {
+            break;
+        };
+        flight.update(|state| state.events.push(item));
+    }
+
+    remove(&flights, &fingerprint, &flight);
+    flight.update(|state| state.done = true);
+}
+
+/// Resolve once every client has gone, taking the flight out of the map
+///
+/// Checked under the lock so no request can join in between; a request that
+/// joined just before keeps the flight going.
+async fn abandoned(flights: &Flights, fingerprint: &str, flight: &Arc<Flight>) {
+    loop {
+        // Each subscription holds a receiver of `version`
+        flight.version.closed().await;
+        let Ok(mut map) = flights.lock() else {
+            return;
+        };
+        if flight.subscribers.load(Ordering::Relaxed) == 0 {
+            if map.get(fingerprint).is_some_and(|f| Arc::ptr_eq(f, flight)) {
+                map.remove(fingerprint);
+            }
+            return;
+        }
+    }
+}
+
+fn remove(flights: &Flights, fingerprint: &str, flight: &Arc<Flight>) {
+    if let Ok(mut map) = flights.lock() {
+        if map.get(fingerprint).is_some_and(|f| Arc::ptr_eq(f, flight)) {
+            map.remove(fingerprint);
+        }
+    }
+}
+
+/// Copy of an error for each client of a flight
+fn duplicate_error(e: &ApiError) -> ApiError {
+    match e {
+        ApiError::AuthError(msg) => ApiError::AuthError(msg.clone()),
+        ApiError::Forbidden(msg) => ApiError::Forbidden(msg.clone()),
+        ApiError::RateLimited {
+            message,
+            retry_after,
+        } => ApiError::RateLimited {
+            message: message.clone(),
+            retry_after: *retry_after,
+        },
+        ApiError::NotFound(msg) => ApiError::NotFound(msg.clone()),
+        ApiError::BudgetExceeded {
+            message,
+            retry_after,
+        } => ApiError::BudgetExceeded {
+            message: message.clone(),
+            retry_after: *retry_after,
+        },
+        ApiError::InvalidModel(msg) => ApiError::InvalidModel(msg.clone()),
+        ApiError::KiroApiError { status, message } => ApiError::KiroApiError {
+            status: *status,
+            message: message.clone(),
+        },
+        ApiError::Overloaded {
+            message,
+            retry_after,
+        } => ApiError::Overloaded {
+            message: message.clone(),
+            retry_after: *retry_after,
+        },
+        ApiError::ConfigError(msg) => ApiError::ConfigError(msg.clone()),
+        ApiError::ValidationError(msg) => ApiError::ValidationError(msg.clone()),
+        ApiError::PayloadTooLarge(msg) => ApiError::PayloadTooLarge(msg.clone()),
+        ApiError::Internal(err) => ApiError::Internal(anyhow::anyhow!("{:#}", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::{mpsc, oneshot};
+    use std::time::Duration;
+
+    fn content(text: &str) -> KiroEvent {
+        KiroEvent {
+            event_type: "content".to_string(),
+            content: Some(text.to_string()),
+            thinking_content: None,
+            tool_use: None,
+            usage: None,
+            context_usage_percentage: None,
+            is_first_thinking_chunk: false,
+            is_last_thinking_chunk: false,
+        }
+    }
+
+    /// Upstream call that must not be made
+    fn unused() -> impl Future<Output = Result<UpstreamResponse, ApiError>> + Send + 'static {
+        futures::future::pending()
+    }
+
+    async fn texts(events: KiroEventStream) -> Vec<String> {
+        events
+            .map(|e| e.unwrap().content.unwrap_or_default())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_late_joiner_sees_full_stream() {
+        let coalescer = Coalescer::new(vec!["/v1/*".to_string()]);
+        assert!(coalescer.applies_to("/v1/messages"));
+        assert!(!coalescer.applies_to("/admin/usage"));
+
+        let (tx, rx) = mpsc::unbounded();
+        let send = async move {
+            Ok(UpstreamResponse {
+                events: rx.map(Ok).boxed(),
+                region: Some("us-east-1".to_string()),
+            })
+        };
+        let (first, joined) = coalescer.run("abc".to_string(), send).await.unwrap();
+        assert!(!joined);
+        assert_eq!(first.region.as_deref(), Some("us-east-1"));
+
+        let mut first_events = first.events;
+        tx.unbounded_send(content("Hel")).unwrap();
+        let event = first_events.next().await.unwrap().unwrap();
+        assert_eq!(event.content.as_deref(), Some("Hel"));
+
+        // Joins after the first event went out
+        let (late, joined) = coalescer.run("abc".to_string(), unused()).await.unwrap();
+        assert!(joined);
+        assert_eq!(late.region.as_deref(), Some("us-east-1"));
+
+        tx.unbounded_send(content("lo")).unwrap();
+        drop(tx);
+        assert_eq!(texts(first_events).await, ["lo"]);
+        assert_eq!(texts(late.events).await, ["Hel", "lo"]);
+        assert!(coalescer.flights.lock().unwrap().is_empty());
+
+        // Finished flights aren't joined
+        let send = async {
+            Ok(UpstreamResponse {
+                events: stream::iter(vec![Ok(content("again"))]).boxed(),
+                region: None,
+            })
+        };
+        let (response, joined) = coalescer.run("abc".to_string(), send).await.unwrap();
+        assert!(!joined);
+        assert_eq!(texts(response.events).await, ["again"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_start_reaches_every_waiter() {
+        let coalescer = Arc::new(Coalescer::new(vec!["*".to_string()]));
+        let (fail, failed) = oneshot::channel::<()>();
+        let send = async move {
+            failed.await.ok();
+            Err(ApiError::KiroApiError {
+                status: 429,
+                message: "Too many requests".to_string(),
+            })
+        };
+
+        let leader = tokio::spawn({
+            let coalescer = Arc::clone(&coalescer);
+            async move { coalescer.run("abc".to_string(), send).await.map(|_| ()) }
+        });
+        let follower = tokio::spawn({
+            let coalescer = Arc::clone(&coalescer);
+            async move {
+                while coalescer.flights.lock().unwrap().is_empty() {
+                    tokio::task::yield_now().await;
+                }
+                coalescer
+                    .run("abc".to_string(), unused())
+                    .await
+                    .map(|(_, joined)| assert!(joined))
+            }
+        });
+        while coalescer
+            .flights
+            .lock()
+            .unwrap()
+            .get("abc")
+            .is_none_or(|f| f.subscribers.load(Ordering::Relaxed) < 2)
+        {
+            tokio::task::yield_now().await;
+        }
+        fail.send(()).unwrap();
+
+        for result in [leader.await.unwrap(), follower.await.unwrap()] {
+            assert!(matches!(
+                result,
+                Err(ApiError::KiroApiError { status: 429, .. })
+            ));
+        }
+        assert!(coalescer.flights.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_when_all_clients_leave() {
+        let coalescer = Coalescer::new(vec!["*".to_string()]);
+        let (tx, rx) = mpsc::unbounded();
+        let send = async move {
+            Ok(UpstreamResponse {
+                events: rx.map(Ok).boxed(),
+                region: None,
+            })
+        };
+        let (response, _) = coalescer.run("abc".to_string(), send).await.unwrap();
+        drop(response);
+
+        // Dropped without waiting for another event
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !tx.is_closed() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("upstream stream should be dropped");
+        assert!(coalescer.flights.lock().unwrap().is_empty());
+
+        // Clients gone before the upstream responded: the call is cancelled
+        let (guard, cancelled) = oneshot::channel::<()>();
+        let send = async move {
+            let _guard = guard;
+            futures::future::pending().await
+        };
+        let waited = tokio::time::timeout(
+            Duration::from_millis(20),
+            coalescer.run("def".to_string(), send),
+        )
+        .await;
+        assert!(waited.is_err());
+        tokio::time::timeout(Duration::from_secs(5), cancelled)
+            .await
+            .expect("upstream call should be dropped")
+            .unwrap_err();
+        assert!(coalescer.flights.lock().unwrap().is_empty());
+    }
+}
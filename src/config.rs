@@ -1,9 +1,13 @@
 use anyhow::{Context, Result};
 use clap::Parser;
 use dialoguer::{Confirm, Input, Password, Select};
+use std::collections::HashMap;
 use std::io::{IsTerminal, Write};
 use std::path::PathBuf;
 
+use crate::admission::Priority;
+use crate::endpoints::Endpoint;
+
 /// Kiro Gateway - Rust Implementation
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -20,6 +24,10 @@ pub struct CliArgs {
     #[arg(short = 'k', long, env = "PROXY_API_KEY")]
     pub api_key: Option<String>,
 
+    /// Path to client API key store (JSON file or SQLite database)
+    #[arg(long, env = "API_KEYS_FILE")]
+    pub api_keys_file: Option<String>,
+
     /// Path to kiro-cli SQLite database
     #[arg(short = 'd', long, env = "KIRO_CLI_DB_FILE")]
     pub db_file: Option<String>,
@@ -28,6 +36,18 @@ pub struct CliArgs {
     #[arg(short = 'r', long, env = "KIRO_REGION", default_value = "us-east-1")]
     pub region: String,
 
+    /// Regions to fail over to, in order, when the primary region is failing
+    #[arg(long, env = "KIRO_FAILOVER_REGIONS", value_delimiter = ',')]
+    pub failover_regions: Vec<String>,
+
+    /// CodeWhisperer profile to use (profile ARN or profile name)
+    #[arg(long, env = "KIRO_PROFILE")]
+    pub profile: Option<String>,
+
+    /// List available CodeWhisperer profiles and exit
+    #[arg(long, default_value = "false")]
+    pub list_profiles: bool,
+
     /// Log level (trace, debug, info, warn, error)
     #[arg(long, env = "LOG_LEVEL", default_value = "info")]
     pub log_level: String,
@@ -69,10 +89,61 @@ pub struct Config {
 
     // Authentication
     pub proxy_api_key: String,
+    pub api_keys_file: Option<PathBuf>,
+    pub virtual_keys_db_file: Option<PathBuf>,
+
+    // JWT authentication (enabled by a secret or JWKS file)
+    pub jwt_secret: Option<String>,
+    pub jwt_jwks_file: Option<PathBuf>,
+    pub jwt_issuer: Option<String>,
+    pub jwt_audience: Vec<String>,
+    pub jwt_claims_file: Option<PathBuf>,
+    pub jwt_api_key_fallback: bool,
+
+    // TLS (HTTPS when cert and key are set; client CA enables mTLS)
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+    pub tls_client_ca_file: Option<PathBuf>,
+    pub tls_client_cert_required: bool,
+
+    // Network ACLs and CORS policy (allow all when unset)
+    pub network_policy_file: Option<PathBuf>,
+
+    // Upstream backends and model routing rules (everything goes to Kiro when unset)
+    pub backends_file: Option<PathBuf>,
+
+    // Rate limiting (per client, 0 = unlimited)
+    pub rate_limit_rpm: u32,
+    pub rate_limit_input_tpm: u64,
+    pub rate_limit_concurrent_streams: u32,
+    pub rate_limit_by: RateLimitKey,
+
+    // Largest request body accepted on the API routes
+    pub max_request_body_bytes: usize,
+
+    // Usage accounting
+    pub usage_db_file: Option<PathBuf>,
+
+    // Admin API (disabled when unset)
+    pub admin_api_key: Option<String>,
 
     // Kiro credentials
     pub kiro_region: String,
+    pub kiro_failover_regions: Vec<String>,
+    pub region_sticky_ttl: u64,
     pub kiro_cli_db_file: PathBuf,
+    pub kiro_profile: Option<String>,
+
+    // Upstream endpoints (production URLs when unset, may contain {region})
+    pub upstream_base_url: Option<String>,
+    pub upstream_endpoints: HashMap<Endpoint, String>,
+
+    // Outbound proxy and extra trusted CAs (system proxy env when unset)
+    pub kiro_proxy_url: Option<String>,
+    pub kiro_proxy_username: Option<String>,
+    pub kiro_proxy_password: Option<String>,
+    pub no_proxy: Option<String>,
+    pub kiro_ca_bundle: Option<PathBuf>,
 
     // Timeouts
     #[allow(dead_code)]
@@ -85,6 +156,71 @@ pub struct Config {
     pub http_connect_timeout: u64,
     pub http_request_timeout: u64,
     pub http_max_retries: u32,
+    pub http_retry_budget_ratio: f64,
+    pub http_retry_budget_min_per_sec: u32,
+
+    // Upstream circuit breaker (0 disables a trip condition)
+    pub circuit_breaker_failures: u32,
+    pub circuit_breaker_error_rate: f64,
+    pub circuit_breaker_min_requests: u32,
+    pub circuit_breaker_open_secs: u64,
+
+    // Hedged requests (disabled when no models are listed)
+    pub hedge_models: Vec<String>,
+    pub hedge_percentile: f64,
+    pub hedge_min_delay_ms: u64,
+    pub hedge_budget_per_min: u32,
+
+    // Upstream admission queue (unlimited when max in flight is 0)
+    pub upstream_max_in_flight: usize,
+    pub upstream_queue_max_wait_ms: u64,
+    pub upstream_tier_priorities: HashMap<String, Priority>,
+
+    // Adaptive upstream concurrency (AIMD between the min and max in flight)
+    pub adaptive_concurrency: bool,
+    pub adaptive_min_in_flight: usize,
+    pub adaptive_max_in_flight: usize,
+    pub adaptive_decrease_ratio: f64,
+    pub adaptive_latency_tolerance: f64,
+
+    // Model catalogue (refreshed in the background every TTL seconds)
+    pub model_cache_ttl: u64,
+    pub model_snapshot_file: Option<PathBuf>,
+    pub degraded_startup: bool,
+
+    // Response cache (exact match; off unless enabled)
+    pub response_cache: bool,
+    pub response_cache_ttl: u64,
+    pub response_cache_max_entries: usize,
+    pub response_cache_max_bytes: usize,
+    pub response_cache_db_file: Option<PathBuf>,
+    pub response_cache_db_max_entries: usize,
+    pub response_cache_db_max_bytes: usize,
+    pub response_cache_keys: Vec<String>,
+
+    // Coalescing of identical in-flight requests (off when no routes are listed)
+    pub coalesce_routes: Vec<String>,
+
+    // Remote image URLs (fetched and inlined before conversion)
+    pub image_fetch_allowed_hosts: Vec<String>,
+    pub image_fetch_max_bytes: usize,
+    pub image_fetch_timeout: u64,
+    pub image_fetch_cache_entries: usize,
+    pub image_fetch_max_total_bytes: usize,
+
+    // Image normalization (applied before images are sent upstream)
+    pub image_max_dimension: u32,
+    pub image_max_bytes: usize,
+    pub image_max_count: usize,
+
+    // Document blocks (rendered as text before conversion)
+    pub document_max_bytes: usize,
+    pub document_max_pages: usize,
+    pub document_max_chars: usize,
+
+    // Prompt cache accounting (cache token usage; off when the TTL is 0)
+    pub prompt_cache_ttl: u64,
+    pub prompt_cache_max_entries: usize,
 
     // Debug
     pub debug_mode: DebugMode,
@@ -99,6 +235,9 @@ pub struct Config {
 
     // Dashboard
     pub dashboard: bool,
+
+    // Profile listing mode
+    pub list_profiles: bool,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -109,6 +248,12 @@ pub enum FakeReasoningHandling {
     StripTags,          // Remove tags but keep content
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLimitKey {
+    ApiKey,   // Bucket by client API key name
+    ClientIp, // Bucket by client IP address
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum DebugMode {
     Off,
@@ -125,21 +270,235 @@ impl Config {
         // Parse CLI arguments
         let args = CliArgs::parse();
 
+        let api_keys_file = args
+            .api_keys_file
+            .filter(|s| !s.is_empty())
+            .map(|s| expand_tilde(&s));
+
+        let jwt_secret = std::env::var("JWT_SECRET").ok().filter(|s| !s.is_empty());
+        let jwt_jwks_file = std::env::var("JWT_JWKS_FILE")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|s| expand_tilde(&s));
+        let jwt_enabled = jwt_secret.is_some() || jwt_jwks_file.is_some();
+
+        let tls_cert_file = std::env::var("TLS_CERT_FILE")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|s| expand_tilde(&s));
+        let tls_key_file = std::env::var("TLS_KEY_FILE")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|s| expand_tilde(&s));
+        if tls_cert_file.is_some() != tls_key_file.is_some() {
+            anyhow::bail!("TLS_CERT_FILE and TLS_KEY_FILE must be set together");
+        }
+        let tls_client_ca_file = std::env::var("TLS_CLIENT_CA_FILE")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|s| expand_tilde(&s));
+        let tls_client_cert_required = std::env::var("TLS_CLIENT_CERT_REQUIRED")
+            .map(|s| matches!(s.to_lowercase().as_str(), "true" | "1" | "yes"))
+            .unwrap_or(false);
+        if tls_client_ca_file.is_some() && tls_cert_file.is_none() {
+            anyhow::bail!("TLS_CLIENT_CA_FILE requires TLS_CERT_FILE and TLS_KEY_FILE");
+        }
+        // Every client presents a verified certificate, so no key is needed
+        let mtls_required = tls_client_ca_file.is_some() && tls_client_cert_required;
+
+        // Adaptive upstream concurrency bounds
+        let adaptive_min_in_flight: usize = std::env::var("ADAPTIVE_MIN_IN_FLIGHT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(2);
+        let adaptive_max_in_flight: usize = std::env::var("ADAPTIVE_MAX_IN_FLIGHT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(64);
+        if adaptive_min_in_flight == 0 || adaptive_min_in_flight > adaptive_max_in_flight {
+            anyhow::bail!(
+                "ADAPTIVE_MIN_IN_FLIGHT must be at least 1 and not above ADAPTIVE_MAX_IN_FLIGHT"
+            );
+        }
+        let adaptive_decrease_ratio: f64 = std::env::var("ADAPTIVE_DECREASE_RATIO")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0.7);
+        if !(adaptive_decrease_ratio > 0.0 && adaptive_decrease_ratio < 1.0) {
+            anyhow::bail!("ADAPTIVE_DECREASE_RATIO must be between 0 and 1");
+        }
+
+        let max_request_body_bytes: usize = std::env::var("MAX_REQUEST_BODY_BYTES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(32 * 1024 * 1024);
+        if max_request_body_bytes == 0 {
+            anyhow::bail!("MAX_REQUEST_BODY_BYTES must be at least 1");
+        }
+
+        let model_cache_ttl: u64 = std::env::var("MODEL_CACHE_TTL")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(3600);
+        if model_cache_ttl == 0 {
+            anyhow::bail!("MODEL_CACHE_TTL must be at least 1 second");
+        }
+
+        let response_cache_ttl: u64 = std::env::var("RESPONSE_CACHE_TTL")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(3600);
+        if response_cache_ttl == 0 {
+            anyhow::bail!("RESPONSE_CACHE_TTL must be at least 1 second");
+        }
+        let response_cache_max_entries: usize = std::env::var("RESPONSE_CACHE_MAX_ENTRIES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(1000);
+        if response_cache_max_entries == 0 {
+            anyhow::bail!("RESPONSE_CACHE_MAX_ENTRIES must be at least 1");
+        }
+        let prompt_cache_max_entries: usize = std::env::var("PROMPT_CACHE_MAX_ENTRIES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(10_000);
+        if prompt_cache_max_entries == 0 {
+            anyhow::bail!("PROMPT_CACHE_MAX_ENTRIES must be at least 1");
+        }
+        let image_fetch_max_bytes: usize = std::env::var("IMAGE_FETCH_MAX_BYTES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(5 * 1024 * 1024);
+        if image_fetch_max_bytes == 0 {
+            anyhow::bail!("IMAGE_FETCH_MAX_BYTES must be at least 1");
+        }
+        let image_max_dimension: u32 = std::env::var("IMAGE_MAX_DIMENSION")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(1568);
+        if image_max_dimension < 64 {
+            anyhow::bail!("IMAGE_MAX_DIMENSION must be at least 64");
+        }
+        let image_max_bytes: usize = std::env::var("IMAGE_MAX_BYTES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(3_750_000);
+        if image_max_bytes == 0 {
+            anyhow::bail!("IMAGE_MAX_BYTES must be at least 1");
+        }
+
         // Build config with priority handling
         let config = Config {
             // Server settings (from CLI with defaults)
             server_host: args.host,
             server_port: args.port,
 
-            // Authentication (CLI > ENV, required)
-            proxy_api_key: args
+            // Authentication (CLI > ENV, required unless a key store, JWT or required mTLS is configured)
+            proxy_api_key: match args
                 .api_key
                 .or_else(|| std::env::var("PROXY_API_KEY").ok())
-                .context("PROXY_API_KEY is required (use -k or set PROXY_API_KEY env var)")?,
+            {
+                Some(key) => key,
+                None if api_keys_file.is_some() || jwt_enabled || mtls_required => String::new(),
+                None => anyhow::bail!(
+                    "PROXY_API_KEY is required (use -k or set PROXY_API_KEY env var, or set API_KEYS_FILE, JWT_SECRET or JWT_JWKS_FILE)"
+                ),
+            },
+
+            api_keys_file,
+
+            virtual_keys_db_file: std::env::var("VIRTUAL_KEYS_DB_FILE")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| expand_tilde(&s)),
+
+            // JWT authentication
+            jwt_secret,
+            jwt_jwks_file,
+
+            jwt_issuer: std::env::var("JWT_ISSUER").ok().filter(|s| !s.is_empty()),
+
+            jwt_audience: std::env::var("JWT_AUDIENCE")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+
+            jwt_claims_file: std::env::var("JWT_CLAIMS_FILE")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| expand_tilde(&s)),
+
+            jwt_api_key_fallback: std::env::var("JWT_API_KEY_FALLBACK")
+                .map(|s| matches!(s.to_lowercase().as_str(), "true" | "1" | "yes"))
+                .unwrap_or(false),
+
+            // TLS
+            tls_cert_file,
+            tls_key_file,
+            tls_client_ca_file,
+            tls_client_cert_required,
+
+            // Network policy
+            network_policy_file: std::env::var("NETWORK_POLICY_FILE")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| expand_tilde(&s)),
+
+            // Upstream backends
+            backends_file: std::env::var("BACKENDS_FILE")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| expand_tilde(&s)),
+
+            // Rate limiting
+            rate_limit_rpm: std::env::var("RATE_LIMIT_RPM")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+
+            rate_limit_input_tpm: std::env::var("RATE_LIMIT_INPUT_TPM")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+
+            rate_limit_concurrent_streams: std::env::var("RATE_LIMIT_CONCURRENT_STREAMS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+
+            rate_limit_by: parse_rate_limit_key(
+                &std::env::var("RATE_LIMIT_BY").unwrap_or_default(),
+            ),
+            max_request_body_bytes,
+
+            // Usage accounting
+            usage_db_file: std::env::var("USAGE_DB_FILE")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| expand_tilde(&s)),
+
+            // Admin API
+            admin_api_key: std::env::var("ADMIN_API_KEY")
+                .ok()
+                .filter(|s| !s.is_empty()),
 
             // Kiro credentials
             kiro_region: args.region,
 
+            kiro_failover_regions: args
+                .failover_regions
+                .into_iter()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+
+            region_sticky_ttl: std::env::var("REGION_STICKY_TTL")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(3600),
+
             kiro_cli_db_file: args
                 .db_file
                 .map(|s| expand_tilde(&s))
@@ -150,6 +509,46 @@ impl Config {
                 })
                 .context("KIRO_CLI_DB_FILE is required (use -d or set KIRO_CLI_DB_FILE env var)")?,
 
+            kiro_profile: args.profile.filter(|s| !s.is_empty()),
+
+            // Upstream endpoints
+            upstream_base_url: std::env::var("KIRO_UPSTREAM_BASE_URL")
+                .ok()
+                .filter(|s| !s.is_empty()),
+
+            upstream_endpoints: Endpoint::ALL
+                .into_iter()
+                .filter_map(|endpoint| {
+                    std::env::var(endpoint.env_var())
+                        .ok()
+                        .filter(|s| !s.is_empty())
+                        .map(|url| (endpoint, url))
+                })
+                .collect(),
+
+            // Outbound proxy
+            kiro_proxy_url: std::env::var("KIRO_PROXY_URL")
+                .ok()
+                .filter(|s| !s.is_empty()),
+
+            kiro_proxy_username: std::env::var("KIRO_PROXY_USERNAME")
+                .ok()
+                .filter(|s| !s.is_empty()),
+
+            kiro_proxy_password: std::env::var("KIRO_PROXY_PASSWORD")
+                .ok()
+                .filter(|s| !s.is_empty()),
+
+            no_proxy: std::env::var("NO_PROXY")
+                .or_else(|_| std::env::var("no_proxy"))
+                .ok()
+                .filter(|s| !s.is_empty()),
+
+            kiro_ca_bundle: std::env::var("KIRO_CA_BUNDLE")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| expand_tilde(&s)),
+
             // Timeouts
             streaming_timeout: std::env::var("STREAMING_READ_TIMEOUT")
                 .ok()
@@ -178,6 +577,184 @@ impl Config {
 
             http_max_retries: args.http_retries,
 
+            http_retry_budget_ratio: std::env::var("HTTP_RETRY_BUDGET_RATIO")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0.2),
+
+            http_retry_budget_min_per_sec: std::env::var("HTTP_RETRY_BUDGET_MIN_PER_SEC")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1),
+
+            // Circuit breaker
+            circuit_breaker_failures: std::env::var("CIRCUIT_BREAKER_FAILURES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(5),
+
+            circuit_breaker_error_rate: std::env::var("CIRCUIT_BREAKER_ERROR_RATE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0.5),
+
+            circuit_breaker_min_requests: std::env::var("CIRCUIT_BREAKER_MIN_REQUESTS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(20),
+
+            circuit_breaker_open_secs: std::env::var("CIRCUIT_BREAKER_OPEN_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(30),
+
+            // Hedged requests
+            hedge_models: std::env::var("HEDGE_MODELS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+
+            hedge_percentile: std::env::var("HEDGE_PERCENTILE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(95.0),
+
+            hedge_min_delay_ms: std::env::var("HEDGE_MIN_DELAY_MS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(500),
+
+            hedge_budget_per_min: std::env::var("HEDGE_BUDGET_PER_MIN")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10),
+
+            // Upstream admission queue
+            upstream_max_in_flight: std::env::var("UPSTREAM_MAX_IN_FLIGHT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+
+            upstream_queue_max_wait_ms: std::env::var("UPSTREAM_QUEUE_MAX_WAIT_MS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10_000),
+
+            upstream_tier_priorities: parse_tier_priorities(
+                &std::env::var("UPSTREAM_TIER_PRIORITIES").unwrap_or_default(),
+            ),
+
+            // Adaptive upstream concurrency
+            adaptive_concurrency: std::env::var("ADAPTIVE_CONCURRENCY")
+                .map(|s| matches!(s.to_lowercase().as_str(), "true" | "1" | "yes"))
+                .unwrap_or(false),
+            adaptive_min_in_flight,
+            adaptive_max_in_flight,
+            adaptive_decrease_ratio,
+            adaptive_latency_tolerance: std::env::var("ADAPTIVE_LATENCY_TOLERANCE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(2.0),
+
+            // Model catalogue
+            model_cache_ttl,
+            model_snapshot_file: std::env::var("MODEL_SNAPSHOT_FILE")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| expand_tilde(&s)),
+            degraded_startup: std::env::var("DEGRADED_STARTUP")
+                .map(|s| matches!(s.to_lowercase().as_str(), "true" | "1" | "yes"))
+                .unwrap_or(false),
+
+            // Response cache
+            response_cache: std::env::var("RESPONSE_CACHE")
+                .map(|s| matches!(s.to_lowercase().as_str(), "true" | "1" | "yes"))
+                .unwrap_or(false),
+            response_cache_ttl,
+            response_cache_max_entries,
+            response_cache_max_bytes: std::env::var("RESPONSE_CACHE_MAX_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(64 * 1024 * 1024),
+            response_cache_db_file: std::env::var("RESPONSE_CACHE_DB_FILE")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| expand_tilde(&s)),
+            response_cache_db_max_entries: std::env::var("RESPONSE_CACHE_DB_MAX_ENTRIES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10_000),
+            response_cache_db_max_bytes: std::env::var("RESPONSE_CACHE_DB_MAX_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1024 * 1024 * 1024),
+            response_cache_keys: std::env::var("RESPONSE_CACHE_KEYS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+
+            // Request coalescing
+            coalesce_routes: std::env::var("COALESCE_ROUTES")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+
+            // Remote images
+            image_fetch_allowed_hosts: std::env::var("IMAGE_FETCH_ALLOWED_HOSTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            image_fetch_max_bytes,
+            image_fetch_timeout: std::env::var("IMAGE_FETCH_TIMEOUT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10),
+            image_fetch_cache_entries: std::env::var("IMAGE_FETCH_CACHE_ENTRIES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(100),
+            image_fetch_max_total_bytes: std::env::var("IMAGE_FETCH_MAX_TOTAL_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(20 * 1024 * 1024),
+
+            // Image normalization
+            image_max_dimension,
+            image_max_bytes,
+            image_max_count: std::env::var("IMAGE_MAX_COUNT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(20),
+
+            // Documents
+            document_max_bytes: std::env::var("DOCUMENT_MAX_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(32 * 1024 * 1024),
+            document_max_pages: std::env::var("DOCUMENT_MAX_PAGES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(100),
+            document_max_chars: std::env::var("DOCUMENT_MAX_CHARS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(200_000),
+
+            // Prompt cache accounting
+            prompt_cache_ttl: std::env::var("PROMPT_CACHE_TTL")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(300),
+            prompt_cache_max_entries,
+
             // Debug
             debug_mode: parse_debug_mode(&args.debug_mode),
 
@@ -198,6 +775,8 @@ impl Config {
             ),
 
             dashboard: args.dashboard,
+
+            list_profiles: args.list_profiles,
         };
 
         Ok(config)
@@ -242,6 +821,28 @@ fn parse_debug_mode(s: &str) -> DebugMode {
     }
 }
 
+/// Parse rate limit bucketing from string
+fn parse_rate_limit_key(s: &str) -> RateLimitKey {
+    match s.to_lowercase().as_str() {
+        "ip" => RateLimitKey::ClientIp,
+        _ => RateLimitKey::ApiKey, // default
+    }
+}
+
+/// Parse `tier:priority` pairs (comma-separated), skipping invalid entries
+fn parse_tier_priorities(s: &str) -> HashMap<String, Priority> {
+    s.split(',')
+        .filter_map(|entry| {
+            let (tier, priority) = entry.split_once(':')?;
+            let tier = tier.trim();
+            if tier.is_empty() {
+                return None;
+            }
+            Some((tier.to_string(), Priority::parse(priority)?))
+        })
+        .collect()
+}
+
 /// Parse fake reasoning handling mode from string
 fn parse_fake_reasoning_handling(s: &str) -> FakeReasoningHandling {
     match s.to_lowercase().as_str() {
@@ -350,6 +951,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_rate_limit_key() {
+        assert_eq!(parse_rate_limit_key(""), RateLimitKey::ApiKey);
+        assert_eq!(parse_rate_limit_key("key"), RateLimitKey::ApiKey);
+        assert_eq!(parse_rate_limit_key("IP"), RateLimitKey::ClientIp);
+    }
+
+    #[test]
+    fn test_parse_tier_priorities() {
+        assert!(parse_tier_priorities("").is_empty());
+        assert_eq!(
+            parse_tier_priorities("premium:high, free:LOW,bogus,:high,x:urgent"),
+            HashMap::from([
+                ("premium".to_string(), Priority::High),
+                ("free".to_string(), Priority::Low),
+            ])
+        );
+    }
+
     #[test]
     fn test_debug_mode_equality() {
         assert_eq!(DebugMode::Off, DebugMode::Off);
@@ -383,7 +1003,8 @@ pub fn needs_interactive_setup() -> bool {
     let env_file_exists = std::path::Path::new(".env").exists();
 
     // Check if required env vars are set
-    let has_proxy_key = std::env::var("PROXY_API_KEY").is_ok();
+    let has_proxy_key =
+        std::env::var("PROXY_API_KEY").is_ok() || std::env::var("API_KEYS_FILE").is_ok();
     let has_db_file = std::env::var("KIRO_CLI_DB_FILE").is_ok();
 
     // Need setup if no .env and missing required values
@@ -7,15 +7,12 @@
 use serde_json::Value;
 use tracing::debug;
 
-use crate::config::Config;
-use crate::models::anthropic::{AnthropicMessage, AnthropicMessagesRequest, AnthropicTool};
-use crate::resolver::normalize_model_name;
+use crate::models::anthropic::{AnthropicMessage, AnthropicTool};
 
 use super::core::{
     extract_images_from_content, extract_text_content, ContentBlock, MessageContent, ToolCall,
     ToolFunction, ToolResult, UnifiedMessage, UnifiedTool,
 };
-use super::openai_to_kiro::build_kiro_payload_core;
 
 // ==================================================================================================
 // Anthropic-specific Content Processing
@@ -87,6 +84,31 @@ fn convert_anthropic_content(content: &Value) -> MessageContent {
                         let input = block.get("input")?.clone();
                         Some(ContentBlock::ToolUse { id, name, input })
                     }
+                    "document" => {
+                        let source = block.get("source")?;
+                        let field = |value: &Value, key: &str| {
+                            value.get(key).and_then(|v| v.as_str()).map(String::from)
+                        };
+                        let source_type = field(source, "type")?;
+                        // Custom content documents are flattened to a text source
+                        let (source_type, data) = if source_type == "content" {
+                            let content = convert_anthropic_content(source.get("content")?);
+                            ("text".to_string(), Some(extract_text_content(&content)))
+                        } else {
+                            (source_type, field(source, "data"))
+                        };
+
+                        Some(ContentBlock::Document {
+                            source: super::core::DocumentSource {
+                                source_type,
+                                media_type: field(source, "media_type"),
+                                data,
+                                url: field(source, "url"),
+                            },
+                            title: field(block, "title"),
+                            context: field(block, "context"),
+                        })
+                    }
                     _ => None,
                 }
             })
@@ -103,7 +125,7 @@ fn convert_anthropic_content(content: &Value) -> MessageContent {
 /// Anthropic API supports system in two formats:
 /// 1. String: "You are helpful"
 /// 2. List of content blocks: [{"type": "text", "text": "...", "cache_control": {...}}]
-fn extract_system_prompt(system: &Option<Value>) -> String {
+pub fn extract_system_prompt(system: &Option<Value>) -> String {
     let Some(system) = system else {
         return String::new();
     };
@@ -265,58 +287,6 @@ pub fn convert_anthropic_tools(tools: &Option<Vec<AnthropicTool>>) -> Option<Vec
     })
 }
 
-// ==================================================================================================
-// Main Entry Point
-// ==================================================================================================
-
-/// Converts Anthropic Messages API request to Kiro API payload.
-///
-/// This is the main entry point for Anthropic → Kiro conversion.
-///
-/// Key differences from OpenAI:
-/// - System prompt is a separate field (not in messages)
-/// - Content can be string or list of content blocks
-/// - Tool format uses input_schema instead of parameters
-pub fn build_kiro_payload(
-    request: &AnthropicMessagesRequest,
-    conversation_id: &str,
-    profile_arn: &str,
-    config: &Config,
-) -> Result<super::core::KiroPayloadResult, String> {
-    // Convert messages to unified format
-    let unified_messages = convert_anthropic_messages(&request.messages);
-
-    // Convert tools to unified format
-    let unified_tools = convert_anthropic_tools(&request.tools);
-
-    // System prompt is already separate in Anthropic format
-    let system_prompt = extract_system_prompt(&request.system);
-
-    // Normalize model name
-    let model_id = normalize_model_name(&request.model);
-
-    debug!(
-        "Converting Anthropic request: model={} -> {}, messages={}, tools={}, system_prompt_length={}",
-        request.model,
-        model_id,
-        unified_messages.len(),
-        unified_tools.as_ref().map_or(0, |t| t.len()),
-        system_prompt.len()
-    );
-
-    // Use core function to build payload
-    build_kiro_payload_core(
-        unified_messages,
-        system_prompt,
-        &model_id,
-        unified_tools,
-        conversation_id,
-        profile_arn,
-        true, // inject_thinking
-        config,
-    )
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -460,6 +430,61 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_convert_anthropic_content_document_blocks() {
+        let content = json!([
+            {
+                "type": "document",
+                "source": {"type": "base64", "media_type": "application/pdf", "data": "JVBERi0="},
+                "title": "Report",
+                "context": "Quarterly numbers"
+            },
+            {
+                "type": "document",
+                "source": {"type": "content", "content": [
+                    {"type": "text", "text": "First chunk. "},
+                    {"type": "text", "text": "Second chunk."}
+                ]}
+            },
+            {
+                "type": "document",
+                "source": {"type": "url", "url": "https://example.com/doc.pdf"}
+            }
+        ]);
+        let MessageContent::Blocks(blocks) = convert_anthropic_content(&content) else {
+            panic!("Expected Blocks variant");
+        };
+        assert_eq!(blocks.len(), 3);
+        match &blocks[0] {
+            ContentBlock::Document {
+                source,
+                title,
+                context,
+            } => {
+                assert_eq!(source.source_type, "base64");
+                assert_eq!(source.media_type.as_deref(), Some("application/pdf"));
+                assert_eq!(source.data.as_deref(), Some("JVBERi0="));
+                assert_eq!(title.as_deref(), Some("Report"));
+                assert_eq!(context.as_deref(), Some("Quarterly numbers"));
+            }
+            _ => panic!("Expected Document block"),
+        }
+        match &blocks[1] {
+            ContentBlock::Document { source, .. } => {
+                assert_eq!(source.source_type, "text");
+                assert_eq!(source.data.as_deref(), Some("First chunk. Second chunk."));
+            }
+            _ => panic!("Expected Document block"),
+        }
+        match &blocks[2] {
+            ContentBlock::Document { source, .. } => {
+                assert_eq!(source.source_type, "url");
+                assert_eq!(source.url.as_deref(), Some("https://example.com/doc.pdf"));
+            }
+            _ => panic!("Expected Document block"),
+        }
+    }
+
     #[test]
     fn test_convert_anthropic_content_tool_use() {
         let content = json!([
@@ -8,6 +8,8 @@
 // - Message merging
 // - Kiro history building
 
+use base64::Engine;
+use image::{DynamicImage, GenericImageView, ImageFormat};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::collections::HashMap;
@@ -22,7 +24,7 @@ use crate::config::Config;
 /// Unified message format used internally by converters.
 ///
 /// This format is API-agnostic and can be created from both OpenAI and Anthropic formats.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct UnifiedMessage {
     pub role: String,
     pub content: MessageContent,
@@ -32,7 +34,7 @@ pub struct UnifiedMessage {
 }
 
 /// Message content can be either text or structured content blocks
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum MessageContent {
     Text(String),
     Blocks(Vec<ContentBlock>),
@@ -60,6 +62,11 @@ pub enum ContentBlock {
         name: String,
         input: Value,
     },
+    Document {
+        source: DocumentSource,
+        title: Option<String>,
+        context: Option<String>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -76,6 +83,16 @@ pub struct ImageUrl {
     pub url: String,
 }
 
+/// Document source: `base64` (PDF or text file), `text`, `url` or `file` (by ID)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentSource {
+    #[serde(rename = "type")]
+    pub source_type: String,
+    pub media_type: Option<String>,
+    pub data: Option<String>,
+    pub url: Option<String>,
+}
+
 /// Tool call in unified format
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
@@ -101,14 +118,14 @@ pub struct ToolResult {
 }
 
 /// Unified image format
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct UnifiedImage {
     pub media_type: String,
     pub data: String,
 }
 
 /// Unified tool format
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct UnifiedTool {
     pub name: String,
     pub description: Option<String>,
@@ -156,6 +173,8 @@ pub fn extract_text_content(content: &MessageContent) -> String {
 /// Supports multiple image formats:
 /// - OpenAI: {"type": "image_url", "image_url": {"url": "data:image/jpeg;base64,..."}}
 /// - Anthropic: {"type": "image", "source": {"type": "base64", "media_type": "...", "data": "..."}}
+///
+/// Remote URLs are expected to have been inlined by `image_fetch` already.
 pub fn extract_images_from_content(content: &MessageContent) -> Vec<UnifiedImage> {
     let mut images = Vec::new();
 
@@ -170,7 +189,7 @@ pub fn extract_images_from_content(content: &MessageContent) -> Vec<UnifiedImage
                         }
                     } else if image_url.url.starts_with("http") {
                         warn!(
-                            "URL-based images are not supported by Kiro API, skipping: {}...",
+                            "Remote image URL was not inlined, skipping: {}...",
                             &image_url.url[..80.min(image_url.url.len())]
                         );
                     } else if !image_url.url.is_empty() {
@@ -200,7 +219,7 @@ pub fn extract_images_from_content(content: &MessageContent) -> Vec<UnifiedImage
                     } else if source.source_type == "url" {
                         if let Some(url) = &source.url {
                             warn!(
-                                "URL-based images are not supported by Kiro API, skipping: {}...",
+                                "Remote image URL was not inlined, skipping: {}...",
                                 &url[..80.min(url.len())]
                             );
                         }
@@ -219,7 +238,7 @@ pub fn extract_images_from_content(content: &MessageContent) -> Vec<UnifiedImage
 }
 
 /// Parses data URL format: data:image/jpeg;base64,/9j/...
-fn parse_data_url(url: &str) -> Option<(String, String)> {
+pub(crate) fn parse_data_url(url: &str) -> Option<(String, String)> {
     if !url.starts_with("data:") {
         return None;
     }
@@ -472,6 +491,214 @@ pub fn convert_tools_to_kiro_format(tools: &Option<Vec<UnifiedTool>>) -> Vec<Val
     kiro_tools
 }
 
+// ==================================================================================================
+// Image Normalization
+// ==================================================================================================
+
+/// Smallest long edge an image is shrunk to while trying to meet the byte limit
+const MIN_IMAGE_EDGE: u32 = 64;
+
+/// JPEG quality used when re-encoding
+const JPEG_QUALITY: u8 = 85;
+
+/// Limits applied to images before they are sent upstream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageLimits {
+    /// Longest edge in pixels
+    pub max_dimension: u32,
+    /// Encoded (not base64) size in bytes
+    pub max_bytes: usize,
+    /// Images per request, across all messages
+    pub max_count: usize,
+}
+
+impl ImageLimits {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_dimension: config.image_max_dimension,
+            max_bytes: config.image_max_bytes,
+            max_count: config.image_max_count,
+        }
+    }
+}
+
+fn encode_image(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, image::ImageError> {
+    let mut bytes = Vec::new();
+    if format == ImageFormat::Jpeg {
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY)
+            .encode_image(&image.to_rgb8())?;
+    } else {
+        image.write_to(&mut std::io::Cursor::new(&mut bytes), format)?;
+    }
+    Ok(bytes)
+}
+
+/// Brings one image within the limits and into PNG or JPEG.
+///
+/// PNG and JPEG images that already fit are passed through untouched (only
+/// a wrong media type is corrected). Anything else is decoded: WebP, GIF and
+/// BMP are transcoded (PNG when there is transparency, JPEG otherwise),
+/// images larger than `max_dimension` are downscaled, and images still above
+/// `max_bytes` are re-encoded smaller. `index` only labels the debug logs.
+pub fn normalize_image(
+    image: &UnifiedImage,
+    limits: &ImageLimits,
+    index: usize,
+) -> Result<UnifiedImage, String> {
+    let (declared, data) = match parse_data_url(&image.data) {
+        Some((media_type, data)) => (media_type, data),
+        None => (image.media_type.clone(), image.data.clone()),
+    };
+    let engine = base64::engine::general_purpose::STANDARD;
+    let bytes = engine
+        .decode(data.trim())
+        .or_else(|_| engine.decode(data.split_whitespace().collect::<String>()))
+        .map_err(|_| format!("image {} is not valid base64", index))?;
+    let format = image::guess_format(&bytes)
+        .map_err(|_| format!("image {} is not a recognised image format", index))?;
+    let source_type = format.to_mime_type();
+
+    let passthrough = matches!(format, ImageFormat::Png | ImageFormat::Jpeg);
+    if passthrough && bytes.len() <= limits.max_bytes {
+        let (width, height) = image::ImageReader::with_format(std::io::Cursor::new(&bytes), format)
+            .into_dimensions()
+            .map_err(|e| format!("image {} could not be read: {}", index, e))?;
+        if width.max(height) <= limits.max_dimension {
+            if declared != source_type {
+                debug!(
+                    "Image {}: media type corrected {} -> {}",
+                    index, declared, source_type
+                );
+            }
+            return Ok(UnifiedImage {
+                media_type: source_type.to_string(),
+                data,
+            });
+        }
+    }
+
+    let mut decoded = image::load_from_memory_with_format(&bytes, format)
+        .map_err(|e| format!("image {} could not be decoded: {}", index, e))?;
+    let has_alpha = decoded.color().has_alpha();
+    let mut target = match format {
+        ImageFormat::Png | ImageFormat::Jpeg => format,
+        _ if has_alpha => ImageFormat::Png,
+        _ => ImageFormat::Jpeg,
+    };
+    if target != format {
+        debug!(
+            "Image {}: transcoding {} -> {}",
+            index,
+            source_type,
+            target.to_mime_type()
+        );
+    }
+
+    let (width, height) = decoded.dimensions();
+    if width.max(height) > limits.max_dimension {
+        decoded = decoded.resize(
+            limits.max_dimension,
+            limits.max_dimension,
+            image::imageops::FilterType::Triangle,
+        );
+        debug!(
+            "Image {}: downscaled {}x{} -> {}x{}",
+            index,
+            width,
+            height,
+            decoded.width(),
+            decoded.height()
+        );
+    }
+
+    loop {
+        let encoded = encode_image(&decoded, target)
+            .map_err(|e| format!("image {} could not be encoded: {}", index, e))?;
+        if encoded.len() <= limits.max_bytes {
+            debug!(
+                "Image {}: {} bytes ({}) -> {} bytes ({})",
+                index,
+                bytes.len(),
+                source_type,
+                encoded.len(),
+                target.to_mime_type()
+            );
+            return Ok(UnifiedImage {
+                media_type: target.to_mime_type().to_string(),
+                data: engine.encode(&encoded),
+            });
+        }
+
+        if target == ImageFormat::Png && !has_alpha {
+            debug!(
+                "Image {}: {} bytes as PNG exceeds {}, switching to JPEG",
+                index,
+                encoded.len(),
+                limits.max_bytes
+            );
+            target = ImageFormat::Jpeg;
+            continue;
+        }
+
+        let (width, height) = decoded.dimensions();
+        let edge = width.max(height) * 3 / 4;
+        if edge < MIN_IMAGE_EDGE {
+            return Err(format!(
+                "image {} cannot be reduced below {} bytes",
+                index, limits.max_bytes
+            ));
+        }
+        decoded = decoded.resize(edge, edge, image::imageops::FilterType::Triangle);
+        debug!(
+            "Image {}: {} bytes exceeds {}, downscaled {}x{} -> {}x{}",
+            index,
+            encoded.len(),
+            limits.max_bytes,
+            width,
+            height,
+            decoded.width(),
+            decoded.height()
+        );
+    }
+}
+
+/// Normalizes every image of the user messages (see `normalize_image`).
+///
+/// Fails when the request carries more than `max_count` images or an image
+/// cannot be brought within the limits.
+pub fn normalize_images(
+    messages: &mut [UnifiedMessage],
+    limits: &ImageLimits,
+) -> Result<(), String> {
+    for message in messages.iter_mut().filter(|m| m.role == "user") {
+        if message.images.is_none() {
+            let images = extract_images_from_content(&message.content);
+            message.images = (!images.is_empty()).then_some(images);
+        }
+    }
+
+    let count: usize = messages
+        .iter()
+        .filter_map(|m| m.images.as_ref())
+        .map(Vec::len)
+        .sum();
+    if count > limits.max_count {
+        return Err(format!(
+            "too many images: {} (maximum {})",
+            count, limits.max_count
+        ));
+    }
+
+    let mut index = 0;
+    for images in messages.iter_mut().filter_map(|m| m.images.as_mut()) {
+        for image in images.iter_mut() {
+            index += 1;
+            *image = normalize_image(image, limits, index)?;
+        }
+    }
+    Ok(())
+}
+
 // ==================================================================================================
 // Image Conversion to Kiro Format
 // ==================================================================================================
@@ -1639,4 +1866,140 @@ mod tests {
         // Should keep the one with more content
         assert!(result[0]["input"]["content"].is_string());
     }
+
+    const LIMITS: ImageLimits = ImageLimits {
+        max_dimension: 1568,
+        max_bytes: 3_750_000,
+        max_count: 20,
+    };
+
+    /// Base64 of an image with a pixel pattern that does not compress away
+    fn encoded_image(width: u32, height: u32, alpha: bool, format: ImageFormat) -> String {
+        let noise = |x: u32, y: u32| ((x.wrapping_mul(7919) ^ y.wrapping_mul(104729)) % 251) as u8;
+        let image = if alpha {
+            DynamicImage::ImageRgba8(image::RgbaImage::from_fn(width, height, |x, y| {
+                image::Rgba([noise(x, y), noise(y, x), 0, 128])
+            }))
+        } else {
+            DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |x, y| {
+                image::Rgb([noise(x, y), noise(y, x), noise(x ^ y, x)])
+            }))
+        };
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut std::io::Cursor::new(&mut bytes), format)
+            .unwrap();
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    }
+
+    fn dimensions(image: &UnifiedImage) -> (u32, u32) {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(&image.data)
+            .unwrap();
+        image::load_from_memory(&bytes).unwrap().dimensions()
+    }
+
+    #[test]
+    fn test_normalize_image_passes_fitting_images_through() {
+        let data = encoded_image(32, 16, false, ImageFormat::Png);
+        // Declared as JPEG: bytes are kept, the media type is corrected
+        let image = UnifiedImage {
+            media_type: "image/jpeg".to_string(),
+            data: format!("data:image/jpeg;base64,{}", data),
+        };
+        let result = normalize_image(&image, &LIMITS, 1).unwrap();
+        assert_eq!(result.media_type, "image/png");
+        assert_eq!(result.data, data);
+    }
+
+    #[test]
+    fn test_normalize_image_transcodes_unsupported_formats() {
+        let cases = [
+            (ImageFormat::WebP, false, "image/jpeg"),
+            (ImageFormat::Bmp, false, "image/jpeg"),
+            (ImageFormat::WebP, true, "image/png"),
+            // GIF frames decode with an alpha channel
+            (ImageFormat::Gif, false, "image/png"),
+        ];
+        for (format, alpha, expected) in cases {
+            let image = UnifiedImage {
+                media_type: format.to_mime_type().to_string(),
+                data: encoded_image(40, 30, alpha, format),
+            };
+            let result = normalize_image(&image, &LIMITS, 1).unwrap();
+            assert_eq!(result.media_type, expected, "{:?}", format);
+            assert_eq!(dimensions(&result), (40, 30));
+        }
+    }
+
+    #[test]
+    fn test_normalize_image_enforces_dimension_and_byte_limits() {
+        let image = UnifiedImage {
+            media_type: "image/png".to_string(),
+            data: encoded_image(400, 100, false, ImageFormat::Png),
+        };
+        let limits = ImageLimits {
+            max_dimension: 200,
+            ..LIMITS
+        };
+        let result = normalize_image(&image, &limits, 1).unwrap();
+        assert_eq!(result.media_type, "image/png");
+        assert_eq!(dimensions(&result), (200, 50));
+
+        // Too large as PNG: switches to JPEG, then shrinks until it fits
+        let limits = ImageLimits {
+            max_bytes: 4_000,
+            ..LIMITS
+        };
+        let result = normalize_image(&image, &limits, 1).unwrap();
+        assert_eq!(result.media_type, "image/jpeg");
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(&result.data)
+            .unwrap();
+        assert!(bytes.len() <= 4_000);
+        let (width, height) = dimensions(&result);
+        assert!(width < 400);
+        assert!((width as f64 / height as f64 - 4.0).abs() < 0.1);
+
+        let limits = ImageLimits {
+            max_bytes: 10,
+            ..LIMITS
+        };
+        assert!(normalize_image(&image, &limits, 1).is_err());
+    }
+
+    #[test]
+    fn test_normalize_images_limits_and_errors() {
+        let png = encoded_image(8, 8, false, ImageFormat::Png);
+        let message = |data: &str| UnifiedMessage {
+            role: "user".to_string(),
+            content: MessageContent::Blocks(vec![ContentBlock::Image {
+                source: ImageSource {
+                    source_type: "base64".to_string(),
+                    media_type: Some("image/png".to_string()),
+                    data: Some(data.to_string()),
+                    url: None,
+                },
+            }]),
+            tool_calls: None,
+            tool_results: None,
+            images: None,
+        };
+
+        // Images are picked up from the content when not extracted yet
+        let mut messages = vec![message(&png), message(&png)];
+        normalize_images(&mut messages, &LIMITS).unwrap();
+        assert_eq!(messages[1].images.as_ref().unwrap()[0].data, png);
+
+        let limits = ImageLimits {
+            max_count: 1,
+            ..LIMITS
+        };
+        let err = normalize_images(&mut messages, &limits).unwrap_err();
+        assert_eq!(err, "too many images: 2 (maximum 1)");
+
+        let mut messages = vec![message(&png), message("bm90IGFuIGltYWdl")];
+        let err = normalize_images(&mut messages, &LIMITS).unwrap_err();
+        assert_eq!(err, "image 2 is not a recognised image format");
+    }
 }
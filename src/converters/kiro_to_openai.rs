@@ -95,6 +95,7 @@ pub fn convert_kiro_to_openai_response(
             prompt_tokens: kiro_usage.input_tokens,
             completion_tokens: kiro_usage.output_tokens,
             total_tokens: kiro_usage.input_tokens + kiro_usage.output_tokens,
+            prompt_tokens_details: None,
             credits_used: None,
         }
     } else {
@@ -102,6 +103,7 @@ pub fn convert_kiro_to_openai_response(
             prompt_tokens: 0,
             completion_tokens: 0,
             total_tokens: 0,
+            prompt_tokens_details: None,
             credits_used: None,
         }
     };
@@ -8,8 +8,7 @@ use serde_json::{json, Value};
 use tracing::debug;
 
 use crate::config::Config;
-use crate::models::openai::{ChatCompletionRequest, ChatMessage, Tool};
-use crate::resolver::normalize_model_name;
+use crate::models::openai::{ChatMessage, Tool};
 
 use super::core::{
     build_kiro_history, convert_images_to_kiro_format, convert_tool_results_to_kiro_format,
@@ -29,6 +28,8 @@ use super::core::{
 /// OpenAI content can be:
 /// - String: "Hello, world!"
 /// - List of content blocks: [{"type": "text", "text": "Hello"}, {"type": "image_url", "image_url": {"url": "..."}}]
+///
+/// `file` parts become document blocks, rendered as text by `documents`.
 fn convert_openai_content(content: &Value) -> MessageContent {
     if let Some(text) = content.as_str() {
         return MessageContent::Text(text.to_string());
@@ -51,6 +52,24 @@ fn convert_openai_content(content: &Value) -> MessageContent {
                             image_url: super::core::ImageUrl { url },
                         })
                     }
+                    "file" => {
+                        let file = block.get("file")?;
+                        let field =
+                            |key: &str| file.get(key).and_then(|v| v.as_str()).map(String::from);
+                        // Inline data (usually a data URL); otherwise an uploaded file ID
+                        let data = field("file_data");
+                        let source_type = if data.is_some() { "base64" } else { "file" };
+                        Some(ContentBlock::Document {
+                            source: super::core::DocumentSource {
+                                source_type: source_type.to_string(),
+                                media_type: None,
+                                data,
+                                url: None,
+                            },
+                            title: field("filename"),
+                            context: None,
+                        })
+                    }
                     _ => None,
                 }
             })
@@ -270,46 +289,6 @@ pub fn convert_openai_tools_to_unified(tools: &Option<Vec<Tool>>) -> Option<Vec<
 // Main Entry Point
 // ==================================================================================================
 
-/// Builds complete payload for Kiro API from OpenAI request.
-///
-/// This is the main entry point for OpenAI → Kiro conversion.
-pub fn build_kiro_payload(
-    request: &ChatCompletionRequest,
-    conversation_id: &str,
-    profile_arn: &str,
-    config: &Config,
-) -> Result<KiroPayloadResult, String> {
-    // Convert messages to unified format
-    let (system_prompt, unified_messages) = convert_openai_messages_to_unified(&request.messages);
-
-    // Convert tools to unified format
-    let unified_tools = convert_openai_tools_to_unified(&request.tools);
-
-    // Normalize model name
-    let model_id = normalize_model_name(&request.model);
-
-    debug!(
-        "Converting OpenAI request: model={} -> {}, messages={}, tools={}, system_prompt_length={}",
-        request.model,
-        model_id,
-        unified_messages.len(),
-        unified_tools.as_ref().map_or(0, |t| t.len()),
-        system_prompt.len()
-    );
-
-    // Build Kiro payload using core function
-    build_kiro_payload_core(
-        unified_messages,
-        system_prompt,
-        &model_id,
-        unified_tools,
-        conversation_id,
-        profile_arn,
-        true, // inject_thinking
-        config,
-    )
-}
-
 /// Core function to build Kiro payload from unified data.
 ///
 /// This is shared logic that can be used by all converters.
@@ -545,8 +524,51 @@ pub fn build_kiro_payload_core(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::openai::{FunctionCall, ToolCall, ToolFunction};
+    use crate::models::openai::{ChatCompletionRequest, FunctionCall, ToolCall, ToolFunction};
+    use crate::resolver::normalize_model_name;
     use serde_json::json;
+    use std::collections::HashMap;
+
+    /// Builds complete payload for Kiro API from OpenAI request.
+    ///
+    /// This is the main entry point for OpenAI → Kiro conversion.
+    fn build_kiro_payload(
+        request: &ChatCompletionRequest,
+        conversation_id: &str,
+        profile_arn: &str,
+        config: &Config,
+    ) -> Result<KiroPayloadResult, String> {
+        // Convert messages to unified format
+        let (system_prompt, unified_messages) =
+            convert_openai_messages_to_unified(&request.messages);
+
+        // Convert tools to unified format
+        let unified_tools = convert_openai_tools_to_unified(&request.tools);
+
+        // Normalize model name
+        let model_id = normalize_model_name(&request.model);
+
+        debug!(
+            "Converting OpenAI request: model={} -> {}, messages={}, tools={}, system_prompt_length={}",
+            request.model,
+            model_id,
+            unified_messages.len(),
+            unified_tools.as_ref().map_or(0, |t| t.len()),
+            system_prompt.len()
+        );
+
+        // Build Kiro payload using core function
+        build_kiro_payload_core(
+            unified_messages,
+            system_prompt,
+            &model_id,
+            unified_tools,
+            conversation_id,
+            profile_arn,
+            true, // inject_thinking
+            config,
+        )
+    }
 
     fn create_test_config() -> Config {
         Config {
@@ -569,6 +591,81 @@ mod tests {
             fake_reasoning_max_tokens: 4000,
             fake_reasoning_handling: crate::config::FakeReasoningHandling::AsReasoningContent,
             dashboard: false,
+            kiro_profile: None,
+            list_profiles: false,
+            api_keys_file: None,
+            rate_limit_rpm: 0,
+            rate_limit_input_tpm: 0,
+            rate_limit_concurrent_streams: 0,
+            rate_limit_by: crate::config::RateLimitKey::ApiKey,
+            max_request_body_bytes: 32 * 1024 * 1024,
+            usage_db_file: None,
+            admin_api_key: None,
+            virtual_keys_db_file: None,
+            jwt_secret: None,
+            jwt_jwks_file: None,
+            jwt_issuer: None,
+            jwt_audience: vec![],
+            jwt_claims_file: None,
+            jwt_api_key_fallback: false,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_client_ca_file: None,
+            tls_client_cert_required: false,
+            network_policy_file: None,
+            backends_file: None,
+            upstream_base_url: None,
+            upstream_endpoints: HashMap::new(),
+            http_retry_budget_ratio: 0.2,
+            http_retry_budget_min_per_sec: 1,
+            circuit_breaker_failures: 5,
+            circuit_breaker_error_rate: 0.5,
+            circuit_breaker_min_requests: 20,
+            circuit_breaker_open_secs: 30,
+            kiro_proxy_url: None,
+            kiro_proxy_username: None,
+            kiro_proxy_password: None,
+            no_proxy: None,
+            kiro_ca_bundle: None,
+            kiro_failover_regions: vec![],
+            region_sticky_ttl: 3600,
+            hedge_models: vec![],
+            hedge_percentile: 95.0,
+            hedge_min_delay_ms: 500,
+            hedge_budget_per_min: 10,
+            upstream_max_in_flight: 0,
+            upstream_queue_max_wait_ms: 10_000,
+            upstream_tier_priorities: HashMap::new(),
+            adaptive_concurrency: false,
+            adaptive_min_in_flight: 2,
+            adaptive_max_in_flight: 64,
+            adaptive_decrease_ratio: 0.7,
+            adaptive_latency_tolerance: 2.0,
+            model_cache_ttl: 3600,
+            model_snapshot_file: None,
+            degraded_startup: false,
+            response_cache: false,
+            response_cache_ttl: 3600,
+            response_cache_max_entries: 1000,
+            response_cache_max_bytes: 64 * 1024 * 1024,
+            response_cache_db_file: None,
+            response_cache_db_max_entries: 10_000,
+            response_cache_db_max_bytes: 1024 * 1024 * 1024,
+            response_cache_keys: Vec::new(),
+            coalesce_routes: Vec::new(),
+            image_fetch_allowed_hosts: Vec::new(),
+            image_fetch_max_bytes: 5 * 1024 * 1024,
+            image_fetch_timeout: 10,
+            image_fetch_cache_entries: 100,
+            image_fetch_max_total_bytes: 20 * 1024 * 1024,
+            image_max_dimension: 1568,
+            image_max_bytes: 3_750_000,
+            image_max_count: 20,
+            document_max_bytes: 32 * 1024 * 1024,
+            document_max_pages: 100,
+            document_max_chars: 200_000,
+            prompt_cache_ttl: 300,
+            prompt_cache_max_entries: 10_000,
         }
     }
 
@@ -754,6 +851,30 @@ mod tests {
         assert_eq!(images[0].data, "/9j/4AAQSkZJRg==");
     }
 
+    #[test]
+    fn test_convert_openai_content_file_parts() {
+        let content = json!([
+            {"type": "file", "file": {"filename": "notes.txt", "file_data": "data:text/plain;base64,aGk="}},
+            {"type": "file", "file": {"file_id": "file-abc123"}}
+        ]);
+        let MessageContent::Blocks(blocks) = convert_openai_content(&content) else {
+            panic!("Expected Blocks variant");
+        };
+        assert_eq!(blocks.len(), 2);
+        match &blocks[0] {
+            ContentBlock::Document { source, title, .. } => {
+                assert_eq!(source.source_type, "base64");
+                assert_eq!(source.data.as_deref(), Some("data:text/plain;base64,aGk="));
+                assert_eq!(title.as_deref(), Some("notes.txt"));
+            }
+            _ => panic!("Expected Document block"),
+        }
+        match &blocks[1] {
+            ContentBlock::Document { source, .. } => assert_eq!(source.source_type, "file"),
+            _ => panic!("Expected Document block"),
+        }
+    }
+
     #[test]
     fn test_build_kiro_payload_basic() {
         let config = create_test_config();
@@ -32,6 +32,7 @@ pub struct DashboardApp {
     pub search_input: Input,
     pub search_query: String,
     pub show_session_view: bool,
+    pub show_key_view: bool,
     pub middle_panel_height: u16,
     pub log_panel_height: u16,
 }
@@ -51,7 +52,8 @@ impl DashboardApp {
             search_input: Input::default(),
             search_query: String::new(),
             show_session_view: false,
-            middle_panel_height: 10,
+            show_key_view: false,
+            middle_panel_height: 13,
             log_panel_height: 15,
         }
     }
@@ -45,6 +45,9 @@ fn handle_normal_mode(app: &mut DashboardApp, key: KeyEvent) {
         KeyCode::Char('s') => {
             app.show_session_view = !app.show_session_view;
         }
+        KeyCode::Char('k') => {
+            app.show_key_view = !app.show_key_view;
+        }
         KeyCode::Char('+') | KeyCode::Char('=') => {
             app.increase_log_height();
         }
@@ -175,6 +178,17 @@ mod tests {
         assert!(app.show_session_view);
     }
 
+    #[test]
+    fn test_key_view_toggle() {
+        let mut app = create_test_app();
+        assert!(!app.show_key_view);
+
+        let key = KeyEvent::new(KeyCode::Char('k'), KeyModifiers::NONE);
+        handle_key_event(&mut app, key);
+
+        assert!(app.show_key_view);
+    }
+
     #[test]
     fn test_scroll_up() {
         let mut app = create_test_app();
@@ -61,11 +61,46 @@ fn render_middle_row(frame: &mut Frame, app: &DashboardApp, area: Rect) {
     frame.render_widget(sparkline, middle_chunks[0]);
 
     let (p50, p95, p99) = app.metrics.get_latency_percentiles();
-    let latency_info = widgets::render_latency_block(p50, p95, p99);
+    let rejected = app.metrics.get_rejections().iter().map(|(_, n)| n).sum();
+    let retries = app.metrics.get_retries();
+    let hedges = app.metrics.get_hedges();
+    let circuit_state = app.metrics.get_circuit_state();
+    let regions = app.metrics.get_regions();
+    let failovers = app.metrics.get_failovers().iter().map(|(_, n)| n).sum();
+    let (queue_depth, in_flight, limit) = app.metrics.get_admission();
+    let limit_adjustments = app.metrics.get_limit_adjustments();
+    let (_, queue_wait_p95) = app.metrics.get_queue_wait_percentiles();
+    let queue_timeouts = app.metrics.get_queue_timeouts();
+    let cache = app.metrics.get_cache();
+    let coalesced = app.metrics.get_coalesced();
+    let latency_info = widgets::render_latency_block(
+        p50,
+        p95,
+        p99,
+        rejected,
+        &retries,
+        &hedges,
+        circuit_state,
+        &regions,
+        failovers,
+        (queue_depth, in_flight, limit),
+        queue_wait_p95,
+        queue_timeouts,
+        &limit_adjustments,
+        &cache,
+        coalesced,
+    );
     frame.render_widget(latency_info, middle_chunks[1]);
 
-    let model_stats = app.metrics.get_model_stats();
-    let token_panel = widgets::render_token_usage_panel(&model_stats, app.show_session_view);
+    let (usage_stats, title) = if app.show_key_view {
+        (app.metrics.get_key_stats(), "Token Usage by Key (k toggle)")
+    } else {
+        (
+            app.metrics.get_model_stats(),
+            "Token Usage (s toggle, k keys)",
+        )
+    };
+    let token_panel = widgets::render_token_usage_panel(&usage_stats, app.show_session_view, title);
     frame.render_widget(token_panel, middle_chunks[2]);
 }
 
@@ -99,7 +134,7 @@ fn render_log_panel(frame: &mut Frame, app: &DashboardApp, area: Rect) {
     };
 
     let title = if app.search_query.is_empty() {
-        "Logs (/ search, ↑↓ scroll, s tokens)".to_string()
+        "Logs (/ search, ↑↓ scroll, s tokens, k keys)".to_string()
     } else {
         format!(
             "Logs [filter: {}] ({} matches)",
@@ -105,7 +105,58 @@ pub fn render_sparkline(title: &str, data: &[u64]) -> Sparkline<'static> {
         .style(Style::default().fg(Color::Cyan))
 }
 
-pub fn render_latency_block(p50: f64, p95: f64, p99: f64) -> Paragraph<'static> {
+#[allow(clippy::too_many_arguments)]
+pub fn render_latency_block(
+    p50: f64,
+    p95: f64,
+    p99: f64,
+    rejected: u64,
+    retries: &[(String, u64)],
+    hedges: &[(String, u64)],
+    circuit_state: &'static str,
+    regions: &[(String, u64)],
+    failovers: u64,
+    (queue_depth, in_flight, limit): (u64, u64, u64),
+    queue_wait_p95: f64,
+    queue_timeouts: u64,
+    limit_adjustments: &[(String, u64)],
+    cache: &[(String, u64)],
+    coalesced: u64,
+) -> Paragraph<'static> {
+    let circuit_color = match circuit_state {
+        "open" => Color::Red,
+        "half_open" => Color::Yellow,
+        _ => Color::Green,
+    };
+
+    let counts_text = |counts: &[(String, u64)]| {
+        if counts.is_empty() {
+            "0".to_string()
+        } else {
+            counts
+                .iter()
+                .map(|(reason, count)| format!("{}×{}", reason, count))
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+    };
+    let retries_text = counts_text(retries);
+    let hedges_text = counts_text(hedges);
+    let limit_text = match limit {
+        0 => "unlimited".to_string(),
+        n => n.to_string(),
+    };
+
+    let regions_text = if regions.is_empty() {
+        "-".to_string()
+    } else {
+        regions
+            .iter()
+            .map(|(region, count)| format!("{}×{}", region, count))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
     let text = vec![
         Line::from(vec![
             Span::styled("p50: ", Style::default().fg(Color::Gray)),
@@ -119,6 +170,64 @@ pub fn render_latency_block(p50: f64, p95: f64, p99: f64) -> Paragraph<'static>
             Span::styled("p99: ", Style::default().fg(Color::Gray)),
             Span::styled(format!("{:.1}ms", p99), Style::default().fg(Color::Red)),
         ]),
+        Line::from(vec![
+            Span::styled("rejected: ", Style::default().fg(Color::Gray)),
+            Span::styled(rejected.to_string(), Style::default().fg(Color::Magenta)),
+        ]),
+        Line::from(vec![
+            Span::styled("retries: ", Style::default().fg(Color::Gray)),
+            Span::styled(retries_text, Style::default().fg(Color::Cyan)),
+        ]),
+        Line::from(vec![
+            Span::styled("hedges: ", Style::default().fg(Color::Gray)),
+            Span::styled(hedges_text, Style::default().fg(Color::Cyan)),
+        ]),
+        Line::from(vec![
+            Span::styled("upstream: ", Style::default().fg(Color::Gray)),
+            Span::styled(circuit_state, Style::default().fg(circuit_color)),
+        ]),
+        Line::from(vec![
+            Span::styled("regions: ", Style::default().fg(Color::Gray)),
+            Span::styled(regions_text, Style::default().fg(Color::Cyan)),
+            Span::styled(
+                format!(" (failovers: {})", failovers),
+                Style::default().fg(Color::Gray),
+            ),
+        ]),
+        Line::from(vec![
+            Span::styled("queue: ", Style::default().fg(Color::Gray)),
+            Span::styled(
+                format!("{} waiting, {} in flight", queue_depth, in_flight),
+                Style::default().fg(if queue_depth > 0 {
+                    Color::Yellow
+                } else {
+                    Color::Cyan
+                }),
+            ),
+            Span::styled(
+                format!(
+                    " (p95 wait: {:.0}ms, timeouts: {})",
+                    queue_wait_p95, queue_timeouts
+                ),
+                Style::default().fg(Color::Gray),
+            ),
+        ]),
+        Line::from(vec![
+            Span::styled("limit: ", Style::default().fg(Color::Gray)),
+            Span::styled(limit_text, Style::default().fg(Color::Cyan)),
+            Span::styled(
+                format!(" (changes: {})", counts_text(limit_adjustments)),
+                Style::default().fg(Color::Gray),
+            ),
+        ]),
+        Line::from(vec![
+            Span::styled("cache: ", Style::default().fg(Color::Gray)),
+            Span::styled(counts_text(cache), Style::default().fg(Color::Cyan)),
+            Span::styled(
+                format!(" (coalesced: {})", coalesced),
+                Style::default().fg(Color::Gray),
+            ),
+        ]),
     ];
 
     Paragraph::new(text).block(Block::default().borders(Borders::ALL).title("Latency"))
@@ -165,6 +274,7 @@ pub fn render_log_panel_with_title(logs: &[LogEntry], scroll: usize, title: &str
 pub fn render_token_usage_panel(
     model_stats: &[(String, ModelStats)],
     _show_session: bool,
+    title: &str,
 ) -> Paragraph<'static> {
     let mut lines: Vec<Line> = Vec::new();
 
@@ -205,7 +315,7 @@ pub fn render_token_usage_panel(
     Paragraph::new(lines).block(
         Block::default()
             .borders(Borders::ALL)
-            .title("Token Usage (s toggle)"),
+            .title(title.to_string()),
     )
 }
 
@@ -0,0 +1,237 @@
+//! Upstream endpoint resolution
+//!
+//! Every URL the gateway calls upstream is resolved here. Each endpoint has a
+//! region-templated default that can be replaced by a base URL (all endpoints
+//! under one host, e.g. the bundled mock server or a recording proxy) or by a
+//! per-endpoint override (e.g. a VPC endpoint for the API only).
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+use crate::config::Config;
+
+/// Placeholder replaced by the AWS region in endpoint templates
+pub const REGION_PLACEHOLDER: &str = "{region}";
+
+/// Region used to check that templates form valid URLs
+const SAMPLE_REGION: &str = "us-east-1";
+
+/// Upstream endpoints called by the gateway
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// CodeWhisperer generateAssistantResponse (chat completions)
+    GenerateAssistantResponse,
+    /// Q API ListAvailableModels (model list at startup)
+    ListAvailableModels,
+    /// Q API ListAvailableProfiles (profile discovery)
+    ListAvailableProfiles,
+    /// Kiro Desktop token refresh
+    KiroDesktopRefresh,
+    /// AWS SSO OIDC token refresh
+    SsoOidcToken,
+}
+
+impl Endpoint {
+    pub const ALL: [Endpoint; 5] = [
+        Endpoint::GenerateAssistantResponse,
+        Endpoint::ListAvailableModels,
+        Endpoint::ListAvailableProfiles,
+        Endpoint::KiroDesktopRefresh,
+        Endpoint::SsoOidcToken,
+    ];
+
+    /// Name used in logs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Endpoint::GenerateAssistantResponse => "generateAssistantResponse",
+            Endpoint::ListAvailableModels => "ListAvailableModels",
+            Endpoint::ListAvailableProfiles => "ListAvailableProfiles",
+            Endpoint::KiroDesktopRefresh => "refreshToken",
+            Endpoint::SsoOidcToken => "token",
+        }
+    }
+
+    /// Environment variable holding the per-endpoint override
+    pub fn env_var(&self) -> &'static str {
+        match self {
+            Endpoint::GenerateAssistantResponse => "KIRO_GENERATE_URL",
+            Endpoint::ListAvailableModels => "KIRO_LIST_MODELS_URL",
+            Endpoint::ListAvailableProfiles => "KIRO_LIST_PROFILES_URL",
+            Endpoint::KiroDesktopRefresh => "KIRO_DESKTOP_REFRESH_URL",
+            Endpoint::SsoOidcToken => "KIRO_SSO_OIDC_URL",
+        }
+    }
+
+    /// Path appended to the base URL
+    fn path(&self) -> &'static str {
+        match self {
+            Endpoint::GenerateAssistantResponse => "/generateAssistantResponse",
+            Endpoint::ListAvailableModels => "/ListAvailableModels",
+            Endpoint::ListAvailableProfiles => "/ListAvailableProfiles",
+            Endpoint::KiroDesktopRefresh => "/refreshToken",
+            Endpoint::SsoOidcToken => "/token",
+        }
+    }
+
+    /// Production URL template
+    fn default_template(&self) -> &'static str {
+        match self {
+            Endpoint::GenerateAssistantResponse => {
+                "https://codewhisperer.{region}.amazonaws.com/generateAssistantResponse"
+            }
+            Endpoint::ListAvailableModels => "https://q.{region}.amazonaws.com/ListAvailableModels",
+            Endpoint::ListAvailableProfiles => {
+                "https://q.{region}.amazonaws.com/ListAvailableProfiles"
+            }
+            Endpoint::KiroDesktopRefresh => {
+                "https://prod.{region}.auth.desktop.kiro.dev/refreshToken"
+            }
+            Endpoint::SsoOidcToken => "https://oidc.{region}.amazonaws.com/token",
+        }
+    }
+}
+
+/// Resolves upstream endpoint URLs
+///
+/// Priority: per-endpoint override > base URL > production default.
+/// Templates may contain `{region}`, which is replaced on every lookup.
+#[derive(Debug, Clone, Default)]
+pub struct EndpointResolver {
+    base_url: Option<String>,
+    overrides: HashMap<Endpoint, String>,
+}
+
+impl EndpointResolver {
+    /// Create a resolver, checking that every configured template is a valid URL
+    pub fn new(base_url: Option<String>, overrides: HashMap<Endpoint, String>) -> Result<Self> {
+        let resolver = Self {
+            base_url: base_url.map(|url| url.trim_end_matches('/').to_string()),
+            overrides,
+        };
+
+        for endpoint in resolver.configured() {
+            let url = resolver.url(endpoint, SAMPLE_REGION);
+            let parsed = reqwest::Url::parse(&url).with_context(|| {
+                format!(
+                    "Invalid URL for {} endpoint: {}",
+                    endpoint.as_str(),
+                    resolver.template(endpoint)
+                )
+            })?;
+            if !matches!(parsed.scheme(), "http" | "https") || parsed.host().is_none() {
+                anyhow::bail!(
+                    "Invalid URL for {} endpoint (expected http(s)://host/...): {}",
+                    endpoint.as_str(),
+                    resolver.template(endpoint)
+                );
+            }
+        }
+
+        Ok(resolver)
+    }
+
+    /// Create a resolver from the upstream settings in config
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Self::new(
+            config.upstream_base_url.clone(),
+            config.upstream_endpoints.clone(),
+        )
+    }
+
+    /// URL template for an endpoint, before region substitution
+    fn template(&self, endpoint: Endpoint) -> String {
+        if let Some(url) = self.overrides.get(&endpoint) {
+            return url.clone();
+        }
+        match self.base_url {
+            Some(ref base) => format!("{}{}", base, endpoint.path()),
+            None => endpoint.default_template().to_string(),
+        }
+    }
+
+    /// Resolve the URL of an endpoint for a region
+    pub fn url(&self, endpoint: Endpoint, region: &str) -> String {
+        self.template(endpoint).replace(REGION_PLACEHOLDER, region)
+    }
+
+    /// Endpoints that don't use the production default
+    pub fn configured(&self) -> Vec<Endpoint> {
+        Endpoint::ALL
+            .into_iter()
+            .filter(|e| self.base_url.is_some() || self.overrides.contains_key(e))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_urls() {
+        let resolver = EndpointResolver::default();
+
+        assert_eq!(
+            resolver.url(Endpoint::GenerateAssistantResponse, "us-east-1"),
+            "https://codewhisperer.us-east-1.amazonaws.com/generateAssistantResponse"
+        );
+        assert_eq!(
+            resolver.url(Endpoint::ListAvailableModels, "eu-central-1"),
+            "https://q.eu-central-1.amazonaws.com/ListAvailableModels"
+        );
+        assert_eq!(
+            resolver.url(Endpoint::ListAvailableProfiles, "eu-central-1"),
+            "https://q.eu-central-1.amazonaws.com/ListAvailableProfiles"
+        );
+        assert_eq!(
+            resolver.url(Endpoint::KiroDesktopRefresh, "us-east-1"),
+            "https://prod.us-east-1.auth.desktop.kiro.dev/refreshToken"
+        );
+        assert_eq!(
+            resolver.url(Endpoint::SsoOidcToken, "us-west-2"),
+            "https://oidc.us-west-2.amazonaws.com/token"
+        );
+        assert!(resolver.configured().is_empty());
+    }
+
+    #[test]
+    fn test_base_url_and_overrides() {
+        let overrides = HashMap::from([(
+            Endpoint::GenerateAssistantResponse,
+            "https://vpce-123.codewhisperer.{region}.vpce.amazonaws.com/generateAssistantResponse"
+                .to_string(),
+        )]);
+        let resolver =
+            EndpointResolver::new(Some("http://127.0.0.1:9000/".to_string()), overrides).unwrap();
+
+        // Override wins over the base URL
+        assert_eq!(
+            resolver.url(Endpoint::GenerateAssistantResponse, "eu-west-1"),
+            "https://vpce-123.codewhisperer.eu-west-1.vpce.amazonaws.com/generateAssistantResponse"
+        );
+        // Everything else goes to the base URL, without a double slash
+        assert_eq!(
+            resolver.url(Endpoint::ListAvailableModels, "eu-west-1"),
+            "http://127.0.0.1:9000/ListAvailableModels"
+        );
+        assert_eq!(
+            resolver.url(Endpoint::SsoOidcToken, "eu-west-1"),
+            "http://127.0.0.1:9000/token"
+        );
+        assert_eq!(resolver.configured().len(), Endpoint::ALL.len());
+    }
+
+    #[test]
+    fn test_invalid_urls_rejected() {
+        let overrides = HashMap::from([(Endpoint::ListAvailableModels, "not a url".to_string())]);
+        assert!(EndpointResolver::new(None, overrides).is_err());
+
+        let overrides = HashMap::from([(
+            Endpoint::SsoOidcToken,
+            "ftp://oidc.example.com/token".to_string(),
+        )]);
+        assert!(EndpointResolver::new(None, overrides).is_err());
+
+        assert!(EndpointResolver::new(Some("127.0.0.1:9000".to_string()), HashMap::new()).is_err());
+    }
+}
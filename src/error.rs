@@ -13,6 +13,22 @@ pub enum ApiError {
     #[error("Authentication failed: {0}")]
     AuthError(String),
 
+    /// Authenticated client is not allowed to perform the request
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    /// Client exceeded its rate limit
+    #[error("Rate limited: {message}")]
+    RateLimited { message: String, retry_after: u64 },
+
+    /// Requested resource does not exist
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    /// Client exhausted its daily or monthly usage budget
+    #[error("Budget exceeded: {message}")]
+    BudgetExceeded { message: String, retry_after: u64 },
+
     /// Invalid model name
     #[error("Invalid model: {0}")]
     #[allow(dead_code)]
@@ -23,15 +39,22 @@ pub enum ApiError {
     #[allow(clippy::enum_variant_names)]
     KiroApiError { status: u16, message: String },
 
+    /// Upstream is unavailable (circuit breaker open), failing fast
+    #[error("Overloaded: {message}")]
+    Overloaded { message: String, retry_after: u64 },
+
     /// Configuration error
     #[error("Configuration error: {0}")]
-    #[allow(dead_code)]
     ConfigError(String),
 
     /// Request validation error
     #[error("Validation error: {0}")]
     ValidationError(String),
 
+    /// Request body exceeds the configured maximum size
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
     /// Internal server error
     #[error("Internal error: {0}")]
     Internal(#[from] anyhow::Error),
@@ -39,16 +62,43 @@ pub enum ApiError {
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
+        let mut retry_after = None;
         let (status, error_type, message) = match self {
             ApiError::AuthError(msg) => (StatusCode::UNAUTHORIZED, "auth_error", msg),
+            ApiError::Forbidden(msg) => (StatusCode::FORBIDDEN, "permission_error", msg),
+            ApiError::NotFound(msg) => (StatusCode::NOT_FOUND, "not_found_error", msg),
+            ApiError::RateLimited {
+                message,
+                retry_after: secs,
+            } => {
+                retry_after = Some(secs);
+                (StatusCode::TOO_MANY_REQUESTS, "rate_limit_error", message)
+            }
+            ApiError::BudgetExceeded {
+                message,
+                retry_after: secs,
+            } => {
+                retry_after = Some(secs);
+                (StatusCode::TOO_MANY_REQUESTS, "budget_exceeded", message)
+            }
             ApiError::InvalidModel(msg) => (StatusCode::BAD_REQUEST, "invalid_model", msg),
             ApiError::KiroApiError { status, message } => {
                 let status_code =
                     StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
                 (status_code, "kiro_api_error", message)
             }
+            ApiError::Overloaded {
+                message,
+                retry_after: secs,
+            } => {
+                retry_after = Some(secs);
+                (StatusCode::SERVICE_UNAVAILABLE, "overloaded", message)
+            }
             ApiError::ConfigError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, "config_error", msg),
             ApiError::ValidationError(msg) => (StatusCode::BAD_REQUEST, "validation_error", msg),
+            ApiError::PayloadTooLarge(msg) => {
+                (StatusCode::PAYLOAD_TOO_LARGE, "request_too_large", msg)
+            }
             ApiError::Internal(err) => {
                 // Log internal errors
                 tracing::error!("Internal error: {:?}", err);
@@ -67,7 +117,50 @@ impl IntoResponse for ApiError {
             }
         }));
 
-        (status, body).into_response()
+        match retry_after {
+            Some(secs) => (status, [("retry-after", secs.to_string())], body).into_response(),
+            None => (status, body).into_response(),
+        }
+    }
+}
+
+/// Error returned by Anthropic-compatible handlers
+///
+/// Overloaded errors use the Anthropic error shape
+/// (`{"type": "error", "error": {"type": "overloaded_error", ...}}`) so SDKs
+/// recognize them and back off; everything else renders like `ApiError`.
+#[derive(Debug)]
+pub struct AnthropicApiError(pub ApiError);
+
+impl From<ApiError> for AnthropicApiError {
+    fn from(err: ApiError) -> Self {
+        Self(err)
+    }
+}
+
+impl IntoResponse for AnthropicApiError {
+    fn into_response(self) -> Response {
+        match self.0 {
+            ApiError::Overloaded {
+                message,
+                retry_after,
+            } => {
+                let body = Json(json!({
+                    "type": "error",
+                    "error": {
+                        "type": "overloaded_error",
+                        "message": message,
+                    }
+                }));
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [("retry-after", retry_after.to_string())],
+                    body,
+                )
+                    .into_response()
+            }
+            err => err.into_response(),
+        }
     }
 }
 
@@ -130,6 +223,69 @@ mod tests {
         assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
     }
 
+    #[tokio::test]
+    async fn test_forbidden_error_response() {
+        let err = ApiError::Forbidden("Model not allowed".to_string());
+        assert_eq!(err.to_string(), "Forbidden: Model not allowed");
+
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_response() {
+        let err = ApiError::RateLimited {
+            message: "Too many requests".to_string(),
+            retry_after: 12,
+        };
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "12");
+    }
+
+    #[tokio::test]
+    async fn test_budget_exceeded_response() {
+        let err = ApiError::BudgetExceeded {
+            message: "Daily token budget exceeded".to_string(),
+            retry_after: 3600,
+        };
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "3600");
+    }
+
+    #[tokio::test]
+    async fn test_overloaded_response() {
+        use http_body_util::BodyExt;
+
+        let overloaded = || ApiError::Overloaded {
+            message: "Upstream unavailable".to_string(),
+            retry_after: 30,
+        };
+
+        // OpenAI shape
+        let response = overloaded().into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "30");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"]["type"], "overloaded");
+
+        // Anthropic shape
+        let response = AnthropicApiError(overloaded()).into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "30");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["type"], "error");
+        assert_eq!(json["error"]["type"], "overloaded_error");
+        assert_eq!(json["error"]["message"], "Upstream unavailable");
+
+        // Other errors are unchanged
+        let response = AnthropicApiError(ApiError::NotFound("x".to_string())).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
     #[tokio::test]
     async fn test_config_error_response() {
         let err = ApiError::ConfigError("Bad config".to_string());
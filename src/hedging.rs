@@ -0,0 +1,229 @@
+// Hedged upstream requests: race a second request when the first is slow to start
+
+use axum::http::{HeaderMap, StatusCode};
+use bytes::Bytes;
+use dashmap::DashMap;
+use futures::stream::StreamExt;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::ApiError;
+use crate::keys::matches_pattern;
+use crate::streaming::{wait_for_first_chunk, ByteStream};
+
+/// Hedge delay until enough first-chunk latencies have been observed
+pub const DEFAULT_HEDGE_DELAY: Duration = Duration::from_secs(2);
+
+/// Samples needed before the percentile replaces the default delay
+const MIN_SAMPLES: usize = 20;
+
+/// Recent first-chunk latencies kept for the percentile
+const MAX_SAMPLES: usize = 1000;
+
+/// Window of the per-key hedge budget
+const BUDGET_WINDOW: Duration = Duration::from_secs(60);
+
+/// Hedging settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct HedgeConfig {
+    /// Model patterns to hedge (trailing `*` wildcard); empty disables hedging
+    pub models: Vec<String>,
+    /// Hedge once the first chunk is later than this percentile of recent requests
+    pub percentile: f64,
+    /// Never hedge earlier than this
+    pub min_delay: Duration,
+    /// Hedges each client key may launch per minute
+    pub per_key_per_minute: u32,
+}
+
+/// Decides when to hedge and tracks the per-key hedge budget
+pub struct Hedger {
+    config: HedgeConfig,
+    /// Recent time-to-first-chunk samples
+    samples: Mutex<VecDeque<Duration>>,
+    /// Hedges launched in the last minute, per client key
+    launched: DashMap<String, VecDeque<Instant>>,
+}
+
+impl Hedger {
+    pub fn new(config: HedgeConfig) -> Self {
+        Self {
+            config,
+            samples: Mutex::new(VecDeque::with_capacity(MAX_SAMPLES)),
+            launched: DashMap::new(),
+        }
+    }
+
+    /// Check whether requests for a model are hedged
+    pub fn applies_to(&self, model: &str) -> bool {
+        let model = model.to_lowercase();
+        self.config
+            .models
+            .iter()
+            .any(|pattern| matches_pattern(&pattern.to_lowercase(), &model))
+    }
+
+    /// Record how long a request took to produce its first chunk
+    pub fn record_first_chunk(&self, latency: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() >= MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(latency);
+    }
+
+    /// How long to wait for the first chunk before hedging
+    ///
+    /// The configured percentile of recent first-chunk latencies, at least
+    /// `min_delay` and below the first token timeout.
+    pub fn delay(&self, first_token_timeout: Duration) -> Duration {
+        let samples = self.samples.lock().unwrap();
+        let delay = if samples.len() < MIN_SAMPLES {
+            DEFAULT_HEDGE_DELAY
+        } else {
+            let mut sorted: Vec<Duration> = samples.iter().copied().collect();
+            sorted.sort();
+            let rank = (self.config.percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+        delay
+            .max(self.config.min_delay)
+            .min(first_token_timeout.saturating_sub(Duration::from_millis(1)))
+    }
+
+    /// Take one hedge from a client key's budget
+    pub fn try_acquire(&self, key: &str, now: Instant) -> bool {
+        let mut launched = self.launched.entry(key.to_string()).or_default();
+        expire(&mut launched, now);
+
+        if launched.len() < self.config.per_key_per_minute as usize {
+            launched.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Forget client keys with no hedge left in the budget window
+    ///
+    /// Called as hedge-eligible requests complete, so keys that stop sending
+    /// don't stay in the map.
+    pub fn prune(&self, now: Instant) {
+        self.launched.retain(|_, launched| {
+            expire(launched, now);
+            !launched.is_empty()
+        });
+    }
+}
+
+/// Drop hedge launches older than the budget window
+fn expire(launched: &mut VecDeque<Instant>, now: Instant) {
+    while let Some(&at) = launched.front() {
+        if now.duration_since(at) < BUDGET_WINDOW {
+            break;
+        }
+        launched.pop_front();
+    }
+}
+
+/// Upstream response whose first chunk has already arrived
+pub struct PrimedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    first_chunk: Option<Bytes>,
+    rest: ByteStream,
+}
+
+impl PrimedResponse {
+    /// Wait for the first chunk of a response
+    pub async fn wait(
+        response: reqwest::Response,
+        first_token_timeout: Duration,
+    ) -> Result<Self, ApiError> {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let (first_chunk, rest) = wait_for_first_chunk(response, first_token_timeout).await?;
+        Ok(Self {
+            status,
+            headers,
+            first_chunk,
+            rest,
+        })
+    }
+
+    /// Reassemble the response, first chunk included, for the stream converters
+    pub fn into_response(self) -> reqwest::Response {
+        let body = futures::stream::iter(self.first_chunk.map(Ok)).chain(self.rest);
+        let mut response = axum::http::Response::new(reqwest::Body::wrap_stream(body));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        reqwest::Response::from(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hedger() -> Hedger {
+        Hedger::new(HedgeConfig {
+            models: vec!["claude-haiku-*".to_string()],
+            percentile: 90.0,
+            min_delay: Duration::from_millis(100),
+            per_key_per_minute: 2,
+        })
+    }
+
+    #[test]
+    fn test_applies_to_models() {
+        let hedger = hedger();
+        assert!(hedger.applies_to("claude-haiku-4.5"));
+        assert!(hedger.applies_to("CLAUDE-HAIKU-4.5"));
+        assert!(!hedger.applies_to("claude-sonnet-4.5"));
+    }
+
+    #[test]
+    fn test_percentile_delay() {
+        let hedger = hedger();
+        let timeout = Duration::from_secs(15);
+
+        // Not enough samples yet
+        assert_eq!(hedger.delay(timeout), DEFAULT_HEDGE_DELAY);
+
+        for ms in 1..=100 {
+            hedger.record_first_chunk(Duration::from_millis(ms * 10));
+        }
+        assert_eq!(hedger.delay(timeout), Duration::from_millis(900));
+
+        // Clamped to the minimum delay and below the first token timeout
+        assert_eq!(
+            hedger.delay(Duration::from_millis(500)),
+            Duration::from_millis(499)
+        );
+        for _ in 0..MAX_SAMPLES {
+            hedger.record_first_chunk(Duration::from_millis(1));
+        }
+        assert_eq!(hedger.delay(timeout), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_per_key_budget() {
+        let hedger = hedger();
+        let now = Instant::now();
+
+        assert!(hedger.try_acquire("ci", now));
+        assert!(hedger.try_acquire("ci", now));
+        assert!(!hedger.try_acquire("ci", now));
+        assert!(hedger.try_acquire("other", now));
+
+        assert!(hedger.try_acquire("ci", now + BUDGET_WINDOW));
+
+        // Keys whose launches have all expired are forgotten
+        hedger.prune(now + BUDGET_WINDOW);
+        assert_eq!(hedger.launched.len(), 1);
+        assert!(hedger.launched.contains_key("ci"));
+        hedger.prune(now + BUDGET_WINDOW * 2);
+        assert!(hedger.launched.is_empty());
+    }
+}
@@ -1,10 +1,46 @@
 use anyhow::{Context, Result};
+use chrono::Utc;
+use reqwest::header::HeaderMap;
 use reqwest::{Client, Request, Response};
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use crate::adaptive_limit::Outcome;
+use crate::admission::{AdmissionConfig, AdmissionPermit, AdmissionQueue, Priority};
 use crate::auth::AuthManager;
+use crate::circuit_breaker::{BreakerConfig, CircuitBreaker};
+use crate::egress::EgressConfig;
+use crate::endpoints::{Endpoint, EndpointResolver};
 use crate::error::ApiError;
+use crate::hedging::{HedgeConfig, Hedger, PrimedResponse};
+use crate::metrics::MetricsCollector;
+use crate::regions::RegionRouter;
+use crate::retry::{self, Backoff, RetryBudget, MAX_RETRY_DELAY};
+
+/// Default retry budget: retries may add 20% on top of recent traffic...
+pub const DEFAULT_RETRY_BUDGET_RATIO: f64 = 0.2;
+
+/// ...plus this many retries per second regardless of traffic
+pub const DEFAULT_RETRY_BUDGET_MIN_PER_SEC: u32 = 1;
+
+/// Timeout for the startup reachability check of configured endpoints
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Failed upstream call, and whether another region may succeed
+struct UpstreamFailure {
+    error: ApiError,
+    failover: bool,
+}
+
+impl From<ApiError> for UpstreamFailure {
+    fn from(error: ApiError) -> Self {
+        Self {
+            error,
+            failover: false,
+        }
+    }
+}
 
 /// HTTP client for Kiro API with retry logic
 pub struct KiroHttpClient {
@@ -14,11 +50,38 @@ pub struct KiroHttpClient {
     /// Authentication manager
     auth_manager: Arc<AuthManager>,
 
+    /// Upstream endpoint URLs
+    endpoints: Arc<EndpointResolver>,
+
+    /// Regions to send API requests to, in failover order
+    regions: RegionRouter,
+
     /// Maximum number of retries
     max_retries: u32,
 
-    /// Base delay for exponential backoff (milliseconds)
+    /// Base delay for jittered backoff (milliseconds)
     base_delay_ms: u64,
+
+    /// Process-wide cap on retries as a fraction of recent traffic
+    retry_budget: RetryBudget,
+
+    /// Thresholds for the per-region circuit breakers
+    breaker_config: BreakerConfig,
+
+    /// Fails upstream calls fast while a region is down, keyed by region
+    circuit_breakers: HashMap<String, CircuitBreaker>,
+
+    /// Races a second request when the first is slow to start (optional)
+    hedger: Option<Hedger>,
+
+    /// Limits for concurrent upstream calls (unlimited unless configured)
+    admission_config: AdmissionConfig,
+
+    /// Queues requests beyond the in-flight limit, by priority (optional)
+    admission: Option<Arc<AdmissionQueue>>,
+
+    /// Metrics collector for retry counts and breaker state (optional)
+    metrics: Option<Arc<MetricsCollector>>,
 }
 
 impl KiroHttpClient {
@@ -29,45 +92,419 @@ impl KiroHttpClient {
         connect_timeout: u64,
         request_timeout: u64,
         max_retries: u32,
+        egress: &EgressConfig,
     ) -> Result<Self> {
-        let client = Client::builder()
+        let client = egress
+            .client_builder()
             .pool_max_idle_per_host(max_connections)
             .connect_timeout(Duration::from_secs(connect_timeout))
             .timeout(Duration::from_secs(request_timeout))
             .build()
             .context("Failed to create HTTP client")?;
 
-        Ok(Self {
+        let mut http_client = Self {
             client,
             auth_manager,
+            endpoints: Arc::new(EndpointResolver::default()),
+            regions: RegionRouter::default(),
             max_retries,
             base_delay_ms: 1000, // 1 second base delay
-        })
+            retry_budget: RetryBudget::new(
+                DEFAULT_RETRY_BUDGET_RATIO,
+                DEFAULT_RETRY_BUDGET_MIN_PER_SEC,
+            ),
+            breaker_config: BreakerConfig::default(),
+            circuit_breakers: HashMap::new(),
+            hedger: None,
+            admission_config: AdmissionConfig::default(),
+            admission: None,
+            metrics: None,
+        };
+        http_client.reset_circuit_breakers();
+        Ok(http_client)
+    }
+
+    /// Use the given upstream endpoints instead of the production defaults
+    pub fn with_endpoints(mut self, endpoints: Arc<EndpointResolver>) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// Use a custom retry budget (`ratio` of recent requests plus `min_per_sec`)
+    pub fn with_retry_budget(mut self, ratio: f64, min_per_sec: u32) -> Self {
+        self.retry_budget = RetryBudget::new(ratio, min_per_sec);
+        self
+    }
+
+    /// Send API requests to these regions, in failover order
+    pub fn with_regions(mut self, regions: RegionRouter) -> Self {
+        self.regions = regions;
+        self.reset_circuit_breakers();
+        self
+    }
+
+    /// Use custom circuit breaker thresholds
+    pub fn with_circuit_breaker(mut self, config: BreakerConfig) -> Self {
+        self.breaker_config = config;
+        self.reset_circuit_breakers();
+        self
+    }
+
+    /// Hedge slow-starting requests for the configured models
+    pub fn with_hedging(mut self, config: HedgeConfig) -> Self {
+        self.hedger = (!config.models.is_empty()).then(|| Hedger::new(config));
+        self
+    }
+
+    /// Bound concurrent upstream calls, queueing the rest by priority
+    pub fn with_admission(mut self, config: AdmissionConfig) -> Self {
+        self.admission_config = config;
+        self.reset_admission_queue();
+        self
+    }
+
+    /// Admission queue for the current limits and metrics collector
+    fn reset_admission_queue(&mut self) {
+        self.admission = self.admission_config.is_enabled().then(|| {
+            let queue = AdmissionQueue::new(self.admission_config.clone());
+            Arc::new(match self.metrics {
+                Some(ref metrics) => queue.with_metrics(Arc::clone(metrics)),
+                None => queue,
+            })
+        });
+    }
+
+    /// One circuit breaker per configured region
+    fn reset_circuit_breakers(&mut self) {
+        self.circuit_breakers = self
+            .regions
+            .regions()
+            .iter()
+            .map(|region| (region.clone(), CircuitBreaker::new(self.breaker_config)))
+            .collect();
+    }
+
+    /// Regions API requests are sent to
+    pub fn regions(&self) -> &RegionRouter {
+        &self.regions
+    }
+
+    /// Circuit breaker guarding the primary region
+    pub fn circuit_breaker(&self) -> &CircuitBreaker {
+        &self.circuit_breakers[self.regions.primary()]
+    }
+
+    /// Circuit breaker guarding a region (the primary one for unknown regions)
+    pub fn circuit_breaker_for(&self, region: &str) -> &CircuitBreaker {
+        self.circuit_breakers
+            .get(region)
+            .unwrap_or_else(|| self.circuit_breaker())
+    }
+
+    /// Export retry counts and breaker state to the metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self.reset_admission_queue();
+        self
+    }
+
+    /// Priority class for a client in `tier`, optionally lowered by the client
+    pub fn admission_priority(&self, tier: Option<&str>, requested: Option<Priority>) -> Priority {
+        self.admission_config.priority_for(tier, requested)
+    }
+
+    /// Wait for an upstream slot (immediately when no limit is configured)
+    ///
+    /// Rejects with `ApiError::Overloaded` once the request has been queued for
+    /// the maximum wait. The slot is held until the permit is dropped, so keep
+    /// it alive until the upstream response has been consumed.
+    pub async fn admit(&self, priority: Priority) -> Result<AdmissionPermit, ApiError> {
+        let Some(ref queue) = self.admission else {
+            return Ok(AdmissionPermit::unbounded());
+        };
+
+        match queue.acquire(priority).await {
+            Ok((permit, waited)) => {
+                if !waited.is_zero() {
+                    tracing::debug!(
+                        priority = priority.as_str(),
+                        "Admitted upstream request after {}ms in queue",
+                        waited.as_millis()
+                    );
+                }
+                Ok(permit)
+            }
+            Err(timeout) => {
+                tracing::warn!(
+                    priority = priority.as_str(),
+                    "No upstream slot after {}ms, rejecting request",
+                    timeout.waited.as_millis()
+                );
+                Err(ApiError::Overloaded {
+                    message: "Too many concurrent upstream requests, try again later".to_string(),
+                    retry_after: (self.admission_config.max_wait.as_secs_f64().ceil() as u64)
+                        .max(1),
+                })
+            }
+        }
+    }
+
+    /// Take a free upstream slot without queueing (for hedged requests)
+    pub fn try_admit(&self) -> Option<AdmissionPermit> {
+        match self.admission {
+            Some(ref queue) => queue.try_acquire(),
+            None => Some(AdmissionPermit::unbounded()),
+        }
+    }
+
+    /// Resolve the URL of an upstream endpoint for a region
+    pub fn endpoint_url(&self, endpoint: Endpoint, region: &str) -> String {
+        self.endpoints.url(endpoint, region)
+    }
+
+    /// Check that every configured (non-default) endpoint is reachable
+    ///
+    /// Any HTTP response counts as reachable; only DNS, connection, TLS and
+    /// timeout errors fail the check. Returns the URLs that were checked.
+    pub async fn verify_endpoints(&self, region: &str) -> Result<Vec<String>> {
+        let mut checked = Vec::new();
+        let mut failures = Vec::new();
+
+        for endpoint in self.endpoints.configured() {
+            let url = self.endpoints.url(endpoint, region);
+            match self.client.head(&url).timeout(VERIFY_TIMEOUT).send().await {
+                Ok(response) => {
+                    tracing::debug!(
+                        endpoint = endpoint.as_str(),
+                        url = %url,
+                        status = response.status().as_u16(),
+                        "Upstream endpoint reachable"
+                    );
+                    checked.push(url);
+                }
+                Err(e) => failures.push(format!("{} ({}): {}", endpoint.as_str(), url, e)),
+            }
+        }
+
+        if !failures.is_empty() {
+            anyhow::bail!("Unreachable upstream endpoints: {}", failures.join("; "));
+        }
+
+        Ok(checked)
     }
 
     /// Execute a request with retry logic
+    /// Fails fast with `ApiError::Overloaded` while the circuit breaker is open.
     /// Automatically handles:
     /// - 403: refreshes token and retries
-    /// - 429: exponential backoff
-    /// - 5xx: exponential backoff
+    /// - 429/5xx: waits for Retry-After or a jittered backoff, within the retry budget
+    /// - transient network errors (timeouts, resets): jittered backoff, within the retry budget
+    #[cfg(test)]
     pub async fn request_with_retry(&self, request: Request) -> Result<Response, ApiError> {
-        self.request_with_retry_internal(request, true).await
+        let region = self.regions.primary();
+        self.request_with_retry_internal(request, true, region, false)
+            .await
+            .map_err(|failure| failure.error)
     }
 
     /// Execute a request without retries (for startup/initialization)
     /// Fails fast on any error
     pub async fn request_no_retry(&self, request: Request) -> Result<Response, ApiError> {
-        self.request_with_retry_internal(request, false).await
+        let region = self.regions.primary();
+        self.request_with_retry_internal(request, false, region, false)
+            .await
+            .map_err(|failure| failure.error)
+    }
+
+    /// Send a generateAssistantResponse request, failing over across regions
+    ///
+    /// `build` creates the request for a region's URL. Regions are tried in
+    /// order, the conversation's sticky region first. 5xx responses, timeouts,
+    /// connection errors and open circuit breakers move on to the next region
+    /// instead of being retried in place. Returns the response and the region
+    /// that served it.
+    pub async fn send_with_failover(
+        &self,
+        conversation: Option<u64>,
+        build: impl Fn(&str) -> Result<Request, ApiError>,
+    ) -> Result<(Response, String), ApiError> {
+        let candidates = self.regions.candidates(conversation, Instant::now());
+
+        for (i, region) in candidates.iter().enumerate() {
+            let next = candidates.get(i + 1);
+            let url = self.endpoint_url(Endpoint::GenerateAssistantResponse, region);
+            let request = build(&url)?;
+
+            match self
+                .request_with_retry_internal(request, true, region, next.is_some())
+                .await
+            {
+                Ok(response) => {
+                    if let Some(key) = conversation {
+                        self.regions.record(key, region, Instant::now());
+                    }
+                    if let Some(ref metrics) = self.metrics {
+                        metrics.record_region(region);
+                    }
+                    return Ok((response, region.clone()));
+                }
+                Err(failure) => match next {
+                    Some(next) if failure.failover => {
+                        tracing::warn!(
+                            "Region {} failed ({}), failing over to {}",
+                            region,
+                            failure.error,
+                            next
+                        );
+                        if let Some(ref metrics) = self.metrics {
+                            metrics.record_failover(region);
+                        }
+                    }
+                    _ => return Err(failure.error),
+                },
+            }
+        }
+
+        Err(ApiError::Internal(anyhow::anyhow!(
+            "No upstream regions configured"
+        )))
+    }
+
+    /// Send a generateAssistantResponse request, hedged if it is slow to start
+    ///
+    /// Without hedging for `model` this is `send_with_failover`. Otherwise the
+    /// first chunk is awaited here: if it hasn't arrived within the hedge delay,
+    /// an upstream slot is free and the client `key` has hedge budget left, an
+    /// identical request is sent. Whichever produces a chunk first wins; the
+    /// other is dropped, which closes its connection. The hedge's slot is held
+    /// only while both run; the caller's permit covers the winner.
+    pub async fn send_hedged(
+        &self,
+        conversation: Option<u64>,
+        model: &str,
+        key: &str,
+        first_token_timeout: Duration,
+        build: impl Fn(&str) -> Result<Request, ApiError>,
+    ) -> Result<(Response, String), ApiError> {
+        let hedger = match self.hedger {
+            Some(ref hedger) if hedger.applies_to(model) => hedger,
+            _ => return self.send_with_failover(conversation, build).await,
+        };
+
+        let attempt = || async {
+            let started = Instant::now();
+            let (response, region) = self.send_with_failover(conversation, &build).await?;
+            let primed = PrimedResponse::wait(response, first_token_timeout).await?;
+            Ok::<_, ApiError>((primed, region, started.elapsed()))
+        };
+
+        let primary_started = Instant::now();
+        let primary = attempt();
+        tokio::pin!(primary);
+
+        let delay = hedger.delay(first_token_timeout);
+        let early = tokio::select! {
+            result = &mut primary => Some(result),
+            _ = tokio::time::sleep(delay) => None,
+        };
+
+        // The hedge is a second upstream call, so it needs a slot of its own
+        let slot = match early {
+            Some(_) => None,
+            None => self.try_admit(),
+        };
+        let hedge_considered = early.is_none();
+        // The primary's time to first chunk feeds the hedge delay. When the
+        // hedge wins, the primary's wait so far is recorded as a lower bound.
+        let outcome = match early {
+            Some(result) => result.map(|(primed, region, latency)| (primed, region, Some(latency))),
+            None if slot.is_none() => {
+                self.record_hedge("no_slot");
+                primary
+                    .await
+                    .map(|(primed, region, latency)| (primed, region, Some(latency)))
+            }
+            None if !hedger.try_acquire(key, Instant::now()) => {
+                self.record_hedge("budget_exhausted");
+                primary
+                    .await
+                    .map(|(primed, region, latency)| (primed, region, Some(latency)))
+            }
+            None => {
+                let _slot = slot;
+                tracing::info!(
+                    "No first chunk after {}ms, sending hedged request",
+                    delay.as_millis()
+                );
+                self.record_hedge("launched");
+
+                let hedge = attempt();
+                tokio::pin!(hedge);
+
+                // First success wins; if one side fails, wait for the other
+                let first = tokio::select! {
+                    result = &mut primary => (result, false),
+                    result = &mut hedge => (result, true),
+                };
+                let primary_waited = primary_started.elapsed();
+                let (result, hedge_won, primary_latency) = match first {
+                    (Ok((primed, region, latency)), false) => {
+                        (Ok((primed, region)), false, Some(latency))
+                    }
+                    (Ok((primed, region, _)), true) => {
+                        (Ok((primed, region)), true, Some(primary_waited))
+                    }
+                    (Err(e), true) => {
+                        tracing::warn!("Hedged request failed: {}", e);
+                        match primary.await {
+                            Ok((primed, region, latency)) => {
+                                (Ok((primed, region)), false, Some(latency))
+                            }
+                            Err(e) => (Err(e), false, None),
+                        }
+                    }
+                    (Err(e), false) => {
+                        tracing::warn!("Request failed while hedged: {}", e);
+                        (
+                            hedge.await.map(|(primed, region, _)| (primed, region)),
+                            true,
+                            None,
+                        )
+                    }
+                };
+                if result.is_ok() {
+                    self.record_hedge(if hedge_won { "won" } else { "lost" });
+                }
+                result.map(|(primed, region)| (primed, region, primary_latency))
+            }
+        };
+        if hedge_considered {
+            hedger.prune(Instant::now());
+        }
+
+        let (primed, region, primary_latency) = outcome?;
+        if let Some(latency) = primary_latency {
+            hedger.record_first_chunk(latency);
+        }
+        Ok((primed.into_response(), region))
     }
 
     /// Internal method that handles retry logic
+    ///
+    /// With `failover` set, errors another region may not have (5xx, network
+    /// errors, open breaker) are returned right away instead of retried.
     async fn request_with_retry_internal(
         &self,
         mut request: Request,
         enable_retry: bool,
-    ) -> Result<Response, ApiError> {
+        region: &str,
+        failover: bool,
+    ) -> Result<Response, UpstreamFailure> {
+        let circuit_breaker = self.circuit_breaker_for(region);
         let max_retries = if enable_retry { self.max_retries } else { 0 };
         let mut attempt = 0;
+        let mut backoff = Backoff::new(Duration::from_millis(self.base_delay_ms), MAX_RETRY_DELAY);
+        self.retry_budget.record_request(Instant::now());
 
         // Log request details
         let method = request.method().clone();
@@ -90,7 +527,20 @@ impl KiroHttpClient {
                 "Executing request attempt"
             );
 
+            // Fail fast while the region is considered down
+            if let Err(wait) = circuit_breaker.try_acquire(Instant::now()) {
+                self.record_circuit_state();
+                return Err(UpstreamFailure {
+                    error: ApiError::Overloaded {
+                        message: "Upstream API is unavailable, try again later".to_string(),
+                        retry_after: (wait.as_secs_f64().ceil() as u64).max(1),
+                    },
+                    failover,
+                });
+            }
+
             // Execute request
+            let sent_at = Instant::now();
             let result = self.client.execute(req).await;
 
             match result {
@@ -103,6 +553,10 @@ impl KiroHttpClient {
                         "Received HTTP response"
                     );
 
+                    // Only server errors count as an outage; 4xx means the upstream is up
+                    self.record_circuit_outcome(circuit_breaker, status.is_server_error());
+                    self.record_admission_outcome(status, sent_at.elapsed());
+
                     // Success
                     if status.is_success() {
                         tracing::debug!(
@@ -122,50 +576,65 @@ impl KiroHttpClient {
                     // Handle specific error codes
                     match status.as_u16() {
                         // 403: Refresh token and retry
-                        403 => {
-                            if attempt < max_retries {
-                                tracing::warn!("Received 403, refreshing token and retrying...");
-
-                                // Refresh token
-                                if let Err(e) = self.auth_manager.get_access_token().await {
-                                    tracing::error!("Token refresh failed: {}", e);
-                                    return Err(ApiError::AuthError(format!(
-                                        "Token refresh failed: {}",
-                                        e
-                                    )));
-                                }
+                        403 if attempt < max_retries => {
+                            tracing::warn!("Received 403, refreshing token and retrying...");
 
-                                // Update Authorization header in request
-                                let token = self
-                                    .auth_manager
-                                    .get_access_token()
-                                    .await
-                                    .map_err(|e| ApiError::AuthError(e.to_string()))?;
-                                request.headers_mut().insert(
-                                    "Authorization",
-                                    format!("Bearer {}", token).parse().unwrap(),
-                                );
-
-                                attempt += 1;
-                                continue;
+                            // Refresh token
+                            if let Err(e) = self.auth_manager.get_access_token().await {
+                                tracing::error!("Token refresh failed: {}", e);
+                                return Err(ApiError::AuthError(format!(
+                                    "Token refresh failed: {}",
+                                    e
+                                ))
+                                .into());
                             }
+
+                            // Update Authorization header in request
+                            let token = self
+                                .auth_manager
+                                .get_access_token()
+                                .await
+                                .map_err(|e| ApiError::AuthError(e.to_string()))?;
+                            request.headers_mut().insert(
+                                "Authorization",
+                                format!("Bearer {}", token).parse().unwrap(),
+                            );
+
+                            self.record_retry(status.as_str());
+                            attempt += 1;
+                            continue;
                         }
 
-                        // 429 or 5xx: Exponential backoff
-                        429 | 500..=599 => {
-                            if attempt < max_retries {
-                                let delay = self.calculate_backoff_delay(attempt);
-                                tracing::warn!(
-                                    "Received {}, retrying after {}ms (attempt {}/{})",
-                                    status,
-                                    delay,
-                                    attempt + 1,
-                                    max_retries
-                                );
-
-                                tokio::time::sleep(Duration::from_millis(delay)).await;
-                                attempt += 1;
-                                continue;
+                        // 5xx with another region to try: fail over instead
+                        500..=599 if failover => {}
+
+                        // 429 or 5xx: Retry-After or jittered backoff, if the budget allows
+                        429 | 500..=599 if attempt < max_retries => {
+                            match self.retry_delay(&headers, &mut backoff) {
+                                Some(delay) if self.retry_budget.try_acquire(Instant::now()) => {
+                                    tracing::warn!(
+                                        "Received {}, retrying after {}ms (attempt {}/{})",
+                                        status,
+                                        delay.as_millis(),
+                                        attempt + 1,
+                                        max_retries
+                                    );
+                                    self.record_retry(status.as_str());
+
+                                    tokio::time::sleep(delay).await;
+                                    attempt += 1;
+                                    continue;
+                                }
+                                Some(_) => {
+                                    tracing::warn!("Received {}, retry budget exhausted", status);
+                                }
+                                None => {
+                                    tracing::warn!(
+                                        "Received {}, Retry-After exceeds {}s, not retrying",
+                                        status,
+                                        MAX_RETRY_DELAY.as_secs()
+                                    );
+                                }
                             }
                         }
 
@@ -191,26 +660,25 @@ impl KiroHttpClient {
                         attempt = attempt + 1,
                         "HTTP request failed with error response"
                     );
-                    return Err(ApiError::KiroApiError {
-                        status: status.as_u16(),
-                        message: error_text,
+                    return Err(UpstreamFailure {
+                        error: ApiError::KiroApiError {
+                            status: status.as_u16(),
+                            message: error_text,
+                        },
+                        failover: failover && status.is_server_error(),
                     });
                 }
 
                 Err(e) => {
-                    // Categorize the error for better debugging
-                    let error_kind = if e.is_timeout() {
-                        "timeout"
-                    } else if e.is_connect() {
-                        "connection_failed"
-                    } else if e.is_request() {
-                        "request_error"
-                    } else if e.is_body() {
-                        "body_error"
-                    } else if e.is_decode() {
-                        "decode_error"
-                    } else {
-                        "unknown"
+                    // Categorize the error: transient network errors are retried
+                    let retryable = retry::retryable_error_kind(&e);
+                    let error_kind = match retryable {
+                        Some(kind) => kind,
+                        None if e.is_connect() => "connection_failed",
+                        None if e.is_request() => "request_error",
+                        None if e.is_body() => "body_error",
+                        None if e.is_decode() => "decode_error",
+                        None => "unknown",
                     };
 
                     tracing::warn!(
@@ -222,20 +690,31 @@ impl KiroHttpClient {
                         "HTTP request error"
                     );
 
-                    // Network error - retry with backoff
-                    if attempt < max_retries {
-                        let delay = self.calculate_backoff_delay(attempt);
-                        tracing::warn!(
-                            "Request failed: {}, retrying after {}ms (attempt {}/{})",
-                            e,
-                            delay,
-                            attempt + 1,
-                            max_retries
-                        );
+                    let transient = retryable.is_some() || e.is_connect();
+                    if transient {
+                        self.record_circuit_outcome(circuit_breaker, true);
+                    }
+
+                    // Transient network error - retry with backoff, if the budget allows
+                    if let Some(kind) = retryable {
+                        if !failover
+                            && attempt < max_retries
+                            && self.retry_budget.try_acquire(Instant::now())
+                        {
+                            let delay = backoff.next_delay();
+                            tracing::warn!(
+                                "Request failed: {}, retrying after {}ms (attempt {}/{})",
+                                e,
+                                delay.as_millis(),
+                                attempt + 1,
+                                max_retries
+                            );
+                            self.record_retry(kind);
 
-                        tokio::time::sleep(Duration::from_millis(delay)).await;
-                        attempt += 1;
-                        continue;
+                            tokio::time::sleep(delay).await;
+                            attempt += 1;
+                            continue;
+                        }
                     }
 
                     tracing::error!(
@@ -243,7 +722,8 @@ impl KiroHttpClient {
                         error = %e,
                         url = %url,
                         total_attempts = attempt + 1,
-                        "HTTP request failed after all retries"
+                        retryable = retryable.is_some(),
+                        "HTTP request failed"
                     );
 
                     // Always print to stderr regardless of log level
@@ -255,63 +735,602 @@ impl KiroHttpClient {
                         e
                     );
 
-                    return Err(ApiError::Internal(anyhow::anyhow!(
-                        "HTTP request failed: {} (kind: {})",
-                        e,
-                        error_kind
-                    )));
+                    return Err(UpstreamFailure {
+                        error: ApiError::Internal(anyhow::anyhow!(
+                            "HTTP request failed: {} (kind: {})",
+                            e,
+                            error_kind
+                        )),
+                        failover: failover && transient,
+                    });
                 }
             }
         }
     }
 
-    /// Calculate exponential backoff delay
-    fn calculate_backoff_delay(&self, attempt: u32) -> u64 {
-        // Exponential backoff: base_delay * 2^attempt
-        // With jitter to avoid thundering herd
-        let delay = self.base_delay_ms * 2_u64.pow(attempt);
-        let jitter = (delay as f64 * 0.1 * rand::random()) as u64;
-        delay + jitter
+    /// Delay before retrying a throttled or failed response
+    ///
+    /// Retry-After is honored as a lower bound, with the jittered backoff on top
+    /// so throttled requests don't all come back at the same instant. Returns
+    /// None when the upstream asks us to wait longer than we are willing to.
+    fn retry_delay(&self, headers: &HeaderMap, backoff: &mut Backoff) -> Option<Duration> {
+        let jittered = backoff.next_delay();
+        match retry::parse_retry_after(headers, Utc::now()) {
+            Some(wait) if wait > MAX_RETRY_DELAY => None,
+            Some(wait) => Some(wait.max(jittered)),
+            None => Some(jittered),
+        }
     }
 
-    /// Get the underlying HTTP client
-    pub fn client(&self) -> &Client {
-        &self.client
+    /// Feed an upstream call outcome to a region's circuit breaker
+    fn record_circuit_outcome(&self, circuit_breaker: &CircuitBreaker, failed: bool) {
+        if failed {
+            circuit_breaker.record_failure(Instant::now());
+        } else {
+            circuit_breaker.record_success(Instant::now());
+        }
+        self.record_circuit_state();
+    }
+
+    /// Feed throttling, server errors and latency to the adaptive in-flight limit
+    fn record_admission_outcome(&self, status: reqwest::StatusCode, latency: Duration) {
+        let Some(ref queue) = self.admission else {
+            return;
+        };
+        let outcome = match status.as_u16() {
+            429 => Outcome::Throttled,
+            500..=599 => Outcome::ServerError,
+            200..=299 => Outcome::Success(latency),
+            _ => return,
+        };
+        queue.record_outcome(outcome);
+    }
+
+    /// Publish the primary region's breaker state to the dashboard
+    fn record_circuit_state(&self) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.set_circuit_state(self.circuit_breaker().state().as_str());
+        }
     }
-}
 
-// Simple random number generation for jitter
-mod rand {
-    use std::collections::hash_map::RandomState;
-    use std::hash::BuildHasher;
+    /// Count a retry by status code or network error kind
+    fn record_retry(&self, reason: &str) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.record_retry(reason);
+        }
+    }
 
-    pub fn random() -> f64 {
-        let state = RandomState::new();
-        (state.hash_one(std::time::SystemTime::now()) % 1000) as f64 / 1000.0
+    /// Count a hedging outcome (launched, won, lost, budget_exhausted, no_slot)
+    fn record_hedge(&self, outcome: &str) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.record_hedge(outcome);
+        }
+    }
+
+    /// Get the underlying HTTP client
+    pub fn client(&self) -> &Client {
+        &self.client
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::adaptive_limit::AdaptiveConfig;
+    use std::collections::HashMap;
+
+    fn test_client() -> KiroHttpClient {
+        let auth_manager = Arc::new(
+            AuthManager::new_for_testing("test-token".to_string(), "us-east-1".to_string(), 300)
+                .unwrap(),
+        );
+        let mut client =
+            KiroHttpClient::new(auth_manager, 20, 30, 300, 3, &EgressConfig::default()).unwrap();
+        client.base_delay_ms = 1;
+        client
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_and_retry_metrics() {
+        let mut server = mockito::Server::new_async().await;
+        let throttled = server
+            .mock("POST", "/generateAssistantResponse")
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .expect(1)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("POST", "/generateAssistantResponse")
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let metrics = Arc::new(MetricsCollector::new());
+        let client = test_client().with_metrics(metrics.clone());
+        let req = client
+            .client()
+            .post(format!("{}/generateAssistantResponse", server.url()))
+            .build()
+            .unwrap();
+
+        let response = client.request_with_retry(req).await.unwrap();
+        assert_eq!(response.status(), 200);
+        throttled.assert_async().await;
+        ok.assert_async().await;
+        assert_eq!(metrics.get_retries(), vec![("429".to_string(), 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_budget_exhausted() {
+        let mut server = mockito::Server::new_async().await;
+        let unavailable = server
+            .mock("POST", "/generateAssistantResponse")
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+
+        // No budget at all: the 503 is returned without retrying
+        let client = test_client().with_retry_budget(0.0, 0);
+        let req = client
+            .client()
+            .post(format!("{}/generateAssistantResponse", server.url()))
+            .build()
+            .unwrap();
+
+        let err = client.request_with_retry(req).await.unwrap_err();
+        assert!(matches!(err, ApiError::KiroApiError { status: 503, .. }));
+        unavailable.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_fails_fast() {
+        let mut server = mockito::Server::new_async().await;
+        let failing = server
+            .mock("POST", "/generateAssistantResponse")
+            .with_status(500)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let metrics = Arc::new(MetricsCollector::new());
+        let client = test_client()
+            .with_retry_budget(0.0, 0)
+            .with_circuit_breaker(BreakerConfig {
+                consecutive_failures: 2,
+                ..BreakerConfig::default()
+            })
+            .with_metrics(metrics.clone());
+        let url = format!("{}/generateAssistantResponse", server.url());
+
+        for _ in 0..2 {
+            let req = client.client().post(&url).build().unwrap();
+            let err = client.request_with_retry(req).await.unwrap_err();
+            assert!(matches!(err, ApiError::KiroApiError { status: 500, .. }));
+        }
+        assert_eq!(metrics.get_circuit_state(), "open");
+
+        // Open: rejected without reaching the upstream
+        let req = client.client().post(&url).build().unwrap();
+        let err = client.request_with_retry(req).await.unwrap_err();
+        assert!(matches!(
+            err,
+            ApiError::Overloaded {
+                retry_after: 30,
+                ..
+            }
+        ));
+        failing.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_region_failover() {
+        let mut server = mockito::Server::new_async().await;
+        let primary = server
+            .mock("POST", "/us-east-1/generateAssistantResponse")
+            .with_status(503)
+            .expect(2)
+            .create_async()
+            .await;
+        let secondary = server
+            .mock("POST", "/us-west-2/generateAssistantResponse")
+            .with_status(200)
+            .expect(3)
+            .create_async()
+            .await;
+
+        let endpoints = EndpointResolver::new(
+            None,
+            HashMap::from([(
+                Endpoint::GenerateAssistantResponse,
+                format!("{}/{{region}}/generateAssistantResponse", server.url()),
+            )]),
+        )
+        .unwrap();
+        let metrics = Arc::new(MetricsCollector::new());
+        let client = test_client()
+            .with_endpoints(Arc::new(endpoints))
+            .with_regions(RegionRouter::new(
+                vec!["us-east-1".to_string(), "us-west-2".to_string()],
+                Duration::from_secs(3600),
+            ))
+            .with_metrics(metrics.clone());
+        let build = |url: &str| Ok(client.client().post(url).build().unwrap());
+
+        // 5xx in the primary region fails over without retrying in place
+        let (_, region) = client.send_with_failover(Some(1), build).await.unwrap();
+        assert_eq!(region, "us-west-2");
+
+        // The conversation sticks to the region that served it
+        let (_, region) = client.send_with_failover(Some(1), build).await.unwrap();
+        assert_eq!(region, "us-west-2");
+
+        // Other conversations start at the primary again
+        let (_, region) = client.send_with_failover(Some(2), build).await.unwrap();
+        assert_eq!(region, "us-west-2");
+
+        primary.assert_async().await;
+        secondary.assert_async().await;
+        assert_eq!(metrics.get_regions(), vec![("us-west-2".to_string(), 3)]);
+        assert_eq!(metrics.get_failovers(), vec![("us-east-1".to_string(), 2)]);
+
+        // Client errors are not failed over
+        let mut server = mockito::Server::new_async().await;
+        let rejected = server
+            .mock("POST", "/us-east-1/generateAssistantResponse")
+            .with_status(400)
+            .expect(1)
+            .create_async()
+            .await;
+        let endpoints =
+            EndpointResolver::new(Some(format!("{}/us-east-1", server.url())), HashMap::new())
+                .unwrap();
+        let client = test_client()
+            .with_endpoints(Arc::new(endpoints))
+            .with_regions(RegionRouter::new(
+                vec!["us-east-1".to_string(), "us-west-2".to_string()],
+                Duration::from_secs(3600),
+            ));
+        let build = |url: &str| Ok(client.client().post(url).build().unwrap());
+        let err = client.send_with_failover(None, build).await.unwrap_err();
+        assert!(matches!(err, ApiError::KiroApiError { status: 400, .. }));
+        rejected.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_failover_on_open_circuit() {
+        let mut server = mockito::Server::new_async().await;
+        let secondary = server
+            .mock("POST", "/eu-west-1/generateAssistantResponse")
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let endpoints = EndpointResolver::new(
+            None,
+            HashMap::from([(
+                Endpoint::GenerateAssistantResponse,
+                format!("{}/{{region}}/generateAssistantResponse", server.url()),
+            )]),
+        )
+        .unwrap();
+        let client = test_client()
+            .with_endpoints(Arc::new(endpoints))
+            .with_regions(RegionRouter::new(
+                vec!["us-east-1".to_string(), "eu-west-1".to_string()],
+                Duration::ZERO,
+            ));
+        for _ in 0..5 {
+            client
+                .circuit_breaker_for("us-east-1")
+                .record_failure(Instant::now());
+        }
+
+        let build = |url: &str| Ok(client.client().post(url).build().unwrap());
+        let (_, region) = client.send_with_failover(None, build).await.unwrap();
+        assert_eq!(region, "eu-west-1");
+        secondary.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_admission_queue_rejects_when_full() {
+        let metrics = Arc::new(MetricsCollector::new());
+        let client = test_client()
+            .with_admission(AdmissionConfig {
+                max_in_flight: 1,
+                adaptive: None,
+                max_wait: Duration::from_millis(20),
+                tier_priorities: HashMap::from([("premium".to_string(), Priority::High)]),
+            })
+            .with_metrics(metrics.clone());
+        assert_eq!(
+            client.admission_priority(Some("premium"), None),
+            Priority::High
+        );
+
+        let permit = client.admit(Priority::Normal).await.unwrap();
+        assert_eq!(metrics.get_admission(), (0, 1, 1));
+
+        let result = client.admit(Priority::High).await;
+        assert!(matches!(
+            result,
+            Err(ApiError::Overloaded { retry_after: 1, .. })
+        ));
+        assert_eq!(metrics.get_queue_timeouts(), 1);
+
+        drop(permit);
+        assert_eq!(metrics.get_admission(), (0, 0, 1));
+        assert!(client.admit(Priority::Low).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_limit_backs_off_on_throttling() {
+        let mut server = mockito::Server::new_async().await;
+        let throttled = server
+            .mock("POST", "/generateAssistantResponse")
+            .with_status(429)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let metrics = Arc::new(MetricsCollector::new());
+        let client = test_client()
+            .with_retry_budget(0.0, 0)
+            .with_admission(AdmissionConfig {
+                max_in_flight: 8,
+                adaptive: Some(AdaptiveConfig {
+                    decrease_ratio: 0.5,
+                    ..AdaptiveConfig::default()
+                }),
+                ..AdmissionConfig::default()
+            })
+            .with_metrics(metrics.clone());
+        assert_eq!(metrics.get_admission(), (0, 0, 8));
+
+        let req = client
+            .client()
+            .post(format!("{}/generateAssistantResponse", server.url()))
+            .build()
+            .unwrap();
+        let err = client.request_with_retry(req).await.unwrap_err();
+        assert!(matches!(err, ApiError::KiroApiError { status: 429, .. }));
+        throttled.assert_async().await;
+
+        assert_eq!(metrics.get_admission(), (0, 0, 4));
+        assert_eq!(
+            metrics.get_limit_adjustments(),
+            vec![("throttled".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_admission() {
+        let client = test_client();
+        let permits: Vec<_> =
+            futures::future::join_all((0..100).map(|_| client.admit(Priority::Low))).await;
+        assert!(permits.iter().all(Result::is_ok));
+    }
+
+    /// Upstream whose first and third responses are slow to start
+    async fn start_slow_upstream() -> String {
+        use axum::{body::Body, routing::post, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new().route(
+            "/generateAssistantResponse",
+            post(move || {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    let stream = futures::stream::once(async move {
+                        if call.is_multiple_of(2) {
+                            tokio::time::sleep(Duration::from_millis(500)).await;
+                            Ok::<_, std::io::Error>("slow")
+                        } else {
+                            Ok("fast")
+                        }
+                    });
+                    Body::from_stream(stream)
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_hedged_request() {
+        let url = start_slow_upstream().await;
+        let metrics = Arc::new(MetricsCollector::new());
+        let client = test_client()
+            .with_endpoints(Arc::new(
+                EndpointResolver::new(Some(url), HashMap::new()).unwrap(),
+            ))
+            .with_hedging(HedgeConfig {
+                models: vec!["claude-haiku-*".to_string()],
+                percentile: 100.0,
+                min_delay: Duration::from_millis(50),
+                per_key_per_minute: 1,
+            })
+            .with_metrics(metrics.clone());
+        for _ in 0..20 {
+            client
+                .hedger
+                .as_ref()
+                .unwrap()
+                .record_first_chunk(Duration::from_millis(10));
+        }
+        let build = |url: &str| Ok(client.client().post(url).build().unwrap());
+        let timeout = Duration::from_secs(5);
+
+        // Slow primary: the hedge starts after 50ms and wins
+        let started = Instant::now();
+        let (response, _) = client
+            .send_hedged(None, "claude-haiku-4.5", "ci", timeout, build)
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "fast");
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert_eq!(
+            metrics.get_hedges(),
+            vec![("launched".to_string(), 1), ("won".to_string(), 1)]
+        );
+        // The slow primary's wait is sampled, not the hedge's quick start
+        let hedger = client.hedger.as_ref().unwrap();
+        assert!(hedger.delay(timeout) > Duration::from_millis(50));
+
+        // Budget used up: wait for the slow primary
+        let (response, _) = client
+            .send_hedged(None, "claude-haiku-4.5", "ci", timeout, build)
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "slow");
+        assert!(metrics
+            .get_hedges()
+            .contains(&("budget_exhausted".to_string(), 1)));
+
+        // Models without hedging are sent once
+        let (response, _) = client
+            .send_hedged(None, "claude-sonnet-4.5", "ci", timeout, build)
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "fast");
+        assert_eq!(metrics.get_hedges().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_hedge_skipped_without_upstream_slot() {
+        let url = start_slow_upstream().await;
+        let metrics = Arc::new(MetricsCollector::new());
+        let client = test_client()
+            .with_endpoints(Arc::new(
+                EndpointResolver::new(Some(url), HashMap::new()).unwrap(),
+            ))
+            .with_admission(AdmissionConfig {
+                max_in_flight: 1,
+                adaptive: None,
+                max_wait: Duration::from_millis(20),
+                tier_priorities: HashMap::new(),
+            })
+            .with_hedging(HedgeConfig {
+                models: vec!["claude-haiku-*".to_string()],
+                percentile: 95.0,
+                min_delay: Duration::from_millis(50),
+                per_key_per_minute: 10,
+            })
+            .with_metrics(metrics.clone());
+        for _ in 0..20 {
+            client
+                .hedger
+                .as_ref()
+                .unwrap()
+                .record_first_chunk(Duration::from_millis(10));
+        }
+        let build = |url: &str| Ok(client.client().post(url).build().unwrap());
+
+        // The primary holds the only slot, so the slow start is waited out
+        let _permit = client.admit(Priority::Normal).await.unwrap();
+        let (response, _) = client
+            .send_hedged(
+                None,
+                "claude-haiku-4.5",
+                "ci",
+                Duration::from_secs(5),
+                build,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "slow");
+        assert_eq!(metrics.get_hedges(), vec![("no_slot".to_string(), 1)]);
+        assert_eq!(metrics.get_admission(), (0, 1, 1));
+    }
 
     #[test]
-    fn test_backoff_calculation() {
+    fn test_retry_delay() {
+        let client = test_client();
+        let mut backoff = Backoff::new(Duration::from_millis(1), MAX_RETRY_DELAY);
+
+        // Retry-After is a lower bound
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "2".parse().unwrap());
+        let delay = client.retry_delay(&headers, &mut backoff).unwrap();
+        assert!(delay >= Duration::from_secs(2));
+
+        // Longer than we are willing to wait: give up
+        headers.insert("retry-after", "3600".parse().unwrap());
+        assert!(client.retry_delay(&headers, &mut backoff).is_none());
+
+        // No header: jittered backoff
+        let delay = client.retry_delay(&HeaderMap::new(), &mut backoff).unwrap();
+        assert!(delay <= MAX_RETRY_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_verify_endpoints() {
         let auth_manager = Arc::new(
             AuthManager::new_for_testing("test-token".to_string(), "us-east-1".to_string(), 300)
                 .unwrap(),
         );
+        let client = KiroHttpClient::new(
+            auth_manager.clone(),
+            20,
+            30,
+            300,
+            3,
+            &EgressConfig::default(),
+        )
+        .unwrap();
 
-        let client = KiroHttpClient::new(auth_manager, 20, 30, 300, 3).unwrap();
+        // Production defaults are not probed
+        assert!(client
+            .verify_endpoints("us-east-1")
+            .await
+            .unwrap()
+            .is_empty());
 
-        // Test exponential backoff
-        let delay0 = client.calculate_backoff_delay(0);
-        let delay1 = client.calculate_backoff_delay(1);
-        let delay2 = client.calculate_backoff_delay(2);
+        // Any HTTP response counts as reachable
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("HEAD", "/generateAssistantResponse")
+            .with_status(405)
+            .create_async()
+            .await;
+        let endpoints = EndpointResolver::new(Some(server.url()), HashMap::new()).unwrap();
+        let client = KiroHttpClient::new(
+            auth_manager.clone(),
+            20,
+            30,
+            300,
+            3,
+            &EgressConfig::default(),
+        )
+        .unwrap()
+        .with_endpoints(Arc::new(endpoints));
+        let checked = client.verify_endpoints("us-east-1").await.unwrap();
+        assert_eq!(checked.len(), Endpoint::ALL.len());
+        assert_eq!(
+            client.endpoint_url(Endpoint::GenerateAssistantResponse, "us-east-1"),
+            format!("{}/generateAssistantResponse", server.url())
+        );
 
-        // Each delay should be roughly double the previous (with jitter)
-        assert!(delay0 >= 1000 && delay0 <= 1200); // ~1s with jitter
-        assert!(delay1 >= 2000 && delay1 <= 2400); // ~2s with jitter
-        assert!(delay2 >= 4000 && delay2 <= 4800); // ~4s with jitter
+        // A closed port fails the check and names the endpoint
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = closed.local_addr().unwrap().port();
+        drop(closed);
+        let overrides = HashMap::from([(
+            Endpoint::SsoOidcToken,
+            format!("http://127.0.0.1:{}/token", port),
+        )]);
+        let endpoints = EndpointResolver::new(Some(server.url()), overrides).unwrap();
+        let client = KiroHttpClient::new(auth_manager, 20, 30, 300, 3, &EgressConfig::default())
+            .unwrap()
+            .with_endpoints(Arc::new(endpoints));
+        let err = client.verify_endpoints("us-east-1").await.unwrap_err();
+        assert!(err.to_string().contains("token ("));
     }
 }
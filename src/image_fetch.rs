@@ -0,0 +1,915 @@
+// Remote image and document fetching
+//
+// OpenAI `image_url` parts and Anthropic `url` image and document sources may
+// point at http(s) URLs, which neither Kiro nor the converters accept. Such
+// content is downloaded here and inlined before the request is converted. A
+// URL that cannot be fetched fails the request instead of being dropped.
+//
+// Without a host allowlist, names are resolved by `PublicResolver`, which
+// refuses internal addresses before any connection is made (redirects
+// included). A proxy would resolve names itself, so proxied fetches need the
+// allowlist.
+
+use anyhow::Result;
+use base64::Engine;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Url;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::converters::core::{
+    extract_images_from_content, ContentBlock, MessageContent, UnifiedImage, UnifiedMessage,
+};
+use crate::egress::EgressConfig;
+use crate::error::ApiError;
+
+/// How long a fetched image is served from the URL cache
+const CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// Redirects followed per fetch (each target must pass the host policy)
+const MAX_REDIRECTS: usize = 5;
+
+/// Images of one request downloaded at the same time
+const FETCH_CONCURRENCY: usize = 4;
+
+/// Whether `host_pattern` admits `host`
+///
+/// `*` admits every host and `*.example.com` admits subdomains of
+/// example.com; anything else must match exactly.
+fn host_matches(host_pattern: &str, host: &str) -> bool {
+    if host_pattern == "*" {
+        return true;
+    }
+    match host_pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.ends_with('.')),
+        None => host_pattern == host,
+    }
+}
+
+/// Addresses that are not publicly routable unicast
+///
+/// Loopback, private, shared (CGNAT), link-local, unspecified, benchmarking,
+/// documentation, reserved, multicast and broadcast ranges, and IPv6
+/// addresses embedding any of them (mapped, compatible, NAT64 and 6to4).
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_internal_v4(ip),
+        IpAddr::V6(ip) => is_internal_v6(ip),
+    }
+}
+
+fn is_internal_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || ip.is_broadcast()
+        || a == 0
+        || (a == 100 && (b & 0xc0) == 64)
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (b & 0xfe) == 18)
+        || a >= 240
+}
+
+fn is_internal_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    let embedded = |high: u16, low: u16| {
+        is_internal_v4(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)))
+    };
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || (segments[0] & 0xfe00) == 0xfc00
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] & 0xffc0) == 0xfec0
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        || ip.to_ipv4().is_some_and(is_internal_v4)
+        || (segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] && embedded(segments[6], segments[7]))
+        || (segments[0] == 0x2002 && embedded(segments[1], segments[2]))
+}
+
+/// DNS resolver that refuses names with any internal address
+///
+/// Installed when no host allowlist is configured, so a public-looking name
+/// cannot be pointed at the gateway's own network.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if addrs.iter().any(|addr| is_internal(addr.ip())) {
+                return Err(format!("{} resolves to an internal address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Host policy for image URLs
+///
+/// Only http(s) URLs are fetched. With no allowed host patterns any public
+/// host is accepted, but `localhost` and internal IP literals are refused;
+/// names are checked once resolved, by `PublicResolver`.
+fn url_allowed(allowed_hosts: &[String], url: &Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if !allowed_hosts.is_empty() {
+        return allowed_hosts
+            .iter()
+            .any(|pattern| host_matches(pattern, host));
+    }
+    if host == "localhost" || host.ends_with(".localhost") {
+        return false;
+    }
+    match host.parse::<IpAddr>() {
+        Ok(ip) => !is_internal(ip),
+        Err(_) => true,
+    }
+}
+
+/// Media type from the leading bytes (formats accepted upstream only)
+fn sniff_media_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+fn is_remote(url: &str) -> bool {
+    let lower = url.get(..8).unwrap_or(url).to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+fn fetch_error(kind: &str, url: &str, reason: impl std::fmt::Display) -> ApiError {
+    let shown: String = url.chars().take(80).collect();
+    let ellipsis = if shown.len() < url.len() { "..." } else { "" };
+    ApiError::ValidationError(format!(
+        "Failed to fetch {} {}{}: {}",
+        kind, shown, ellipsis, reason
+    ))
+}
+
+struct CachedImage {
+    image: UnifiedImage,
+    fetched_at: Instant,
+    last_used: u64,
+}
+
+/// Least recently used fetched images, keyed by URL
+#[derive(Default)]
+struct UrlCache {
+    entries: HashMap<String, CachedImage>,
+    /// `last_used` tick -> URL
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl UrlCache {
+    fn get(&mut self, url: &str) -> Option<UnifiedImage> {
+        let entry = self.entries.get_mut(url)?;
+        self.order.remove(&entry.last_used);
+        if entry.fetched_at.elapsed() >= CACHE_TTL {
+            self.entries.remove(url);
+            return None;
+        }
+        self.tick += 1;
+        entry.last_used = self.tick;
+        self.order.insert(self.tick, url.to_string());
+        Some(entry.image.clone())
+    }
+
+    fn insert(&mut self, url: String, image: UnifiedImage, max_entries: usize) {
+        if let Some(old) = self.entries.remove(&url) {
+            self.order.remove(&old.last_used);
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, url.clone());
+        self.entries.insert(
+            url,
+            CachedImage {
+                image,
+                fetched_at: Instant::now(),
+                last_used: self.tick,
+            },
+        );
+        while self.entries.len() > max_entries {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+/// Downloads remote images and documents and inlines them into request messages
+pub struct ImageFetcher {
+    client: reqwest::Client,
+    /// Host patterns that may be fetched (any public host when empty)
+    allowed_hosts: Arc<Vec<String>>,
+    /// Fetches go through the egress proxy, which resolves names itself
+    proxied: bool,
+    max_bytes: usize,
+    /// Images allowed per request, checked before anything is fetched
+    max_count: usize,
+    /// Bytes downloaded for the images of one request
+    max_total_bytes: usize,
+    cache_entries: usize,
+    cache: Mutex<UrlCache>,
+}
+
+impl ImageFetcher {
+    pub fn new(
+        egress: &EgressConfig,
+        allowed_hosts: Vec<String>,
+        max_bytes: usize,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let allowed_hosts = Arc::new(allowed_hosts);
+        let redirect_hosts = Arc::clone(&allowed_hosts);
+        let proxied = egress.proxy_display().is_some();
+        let mut builder = egress.client_builder();
+        if allowed_hosts.is_empty() {
+            if proxied {
+                tracing::warn!(
+                    "Remote image and document URLs are refused: set IMAGE_FETCH_ALLOWED_HOSTS \
+                     to fetch them through the egress proxy"
+                );
+            }
+            // Environment proxies would resolve names out of the resolver's reach
+            builder = builder.no_proxy().dns_resolver(Arc::new(PublicResolver));
+        }
+        let client = builder
+            .timeout(timeout)
+            .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.error("too many redirects")
+                } else if url_allowed(&redirect_hosts, attempt.url()) {
+                    attempt.follow()
+                } else {
+                    let message = format!("redirect to disallowed URL {}", attempt.url());
+                    attempt.error(message)
+                }
+            }))
+            .build()?;
+        Ok(Self {
+            client,
+            allowed_hosts,
+            proxied,
+            max_bytes,
+            max_count: usize::MAX,
+            max_total_bytes: usize::MAX,
+            cache_entries: 100,
+            cache: Mutex::new(UrlCache::default()),
+        })
+    }
+
+    pub fn from_config(config: &Config, egress: &EgressConfig) -> Result<Self> {
+        Ok(Self::new(
+            egress,
+            config.image_fetch_allowed_hosts.clone(),
+            config.image_fetch_max_bytes,
+            Duration::from_secs(config.image_fetch_timeout),
+        )?
+        .with_cache_entries(config.image_fetch_cache_entries)
+        .with_request_limits(config.image_max_count, config.image_fetch_max_total_bytes))
+    }
+
+    /// Images allowed per request and bytes downloaded for them in total
+    pub fn with_request_limits(mut self, max_count: usize, max_total_bytes: usize) -> Self {
+        self.max_count = max_count;
+        self.max_total_bytes = max_total_bytes;
+        self
+    }
+
+    /// Number of fetched images kept by URL (0 disables the cache)
+    pub fn with_cache_entries(mut self, entries: usize) -> Self {
+        self.cache_entries = entries;
+        self
+    }
+
+    /// Replace remote image references in user messages with inlined data
+    ///
+    /// Distinct URLs are fetched `FETCH_CONCURRENCY` at a time once the image
+    /// count is known to be within `max_count`. Returns the number of images
+    /// fetched or served from the URL cache.
+    pub async fn inline_remote_images(
+        &self,
+        messages: &mut [UnifiedMessage],
+    ) -> Result<usize, ApiError> {
+        let mut count = 0;
+        let mut urls = HashSet::new();
+        for message in messages.iter().filter(|m| m.role == "user") {
+            let MessageContent::Blocks(ref blocks) = message.content else {
+                continue;
+            };
+            for block in blocks {
+                let url = match block {
+                    ContentBlock::ImageUrl { image_url } => {
+                        Some(image_url.url.as_str()).filter(|url| is_remote(url))
+                    }
+                    ContentBlock::Image { source } => (source.source_type == "url")
+                        .then(|| source.url.as_deref().unwrap_or_default()),
+                    _ => continue,
+                };
+                count += 1;
+                urls.extend(url);
+            }
+        }
+        if urls.is_empty() {
+            return Ok(0);
+        }
+        if count > self.max_count {
+            return Err(ApiError::ValidationError(format!(
+                "too many images: {} (maximum {})",
+                count, self.max_count
+            )));
+        }
+
+        let downloaded = AtomicUsize::new(0);
+        // Collected first: a lazily mapped stream trips the Send check on handler futures
+        let fetches: Vec<_> = urls
+            .into_iter()
+            .map(|url| {
+                let downloaded = &downloaded;
+                async move {
+                    let image = self.fetch(url, Some(downloaded)).await?;
+                    Ok::<_, ApiError>((url.to_string(), image))
+                }
+            })
+            .collect();
+        let fetched: HashMap<String, UnifiedImage> = stream::iter(fetches)
+            .buffer_unordered(FETCH_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        let mut inlined = 0;
+        for message in messages.iter_mut().filter(|m| m.role == "user") {
+            let MessageContent::Blocks(ref mut blocks) = message.content else {
+                continue;
+            };
+            let mut changed = false;
+            for block in blocks.iter_mut() {
+                match block {
+                    ContentBlock::ImageUrl { image_url } if is_remote(&image_url.url) => {
+                        let image = &fetched[&image_url.url];
+                        image_url.url = format!("data:{};base64,{}", image.media_type, image.data);
+                    }
+                    ContentBlock::Image { source } if source.source_type == "url" => {
+                        let image = &fetched[source.url.as_deref().unwrap_or_default()];
+                        source.source_type = "base64".to_string();
+                        source.media_type = Some(image.media_type.clone());
+                        source.data = Some(image.data.clone());
+                        source.url = None;
+                    }
+                    _ => continue,
+                }
+                changed = true;
+                inlined += 1;
+            }
+            if changed {
+                let images = extract_images_from_content(&message.content);
+                message.images = (!images.is_empty()).then_some(images);
+            }
+        }
+        Ok(inlined)
+    }
+
+    /// Fetch one image, enforcing the host policy, size limit and format
+    ///
+    /// With `downloaded`, the bytes also count against `max_total_bytes`.
+    pub async fn fetch(
+        &self,
+        url: &str,
+        downloaded: Option<&AtomicUsize>,
+    ) -> Result<UnifiedImage, ApiError> {
+        if let Some(image) = self.cache.lock().unwrap().get(url) {
+            return Ok(image);
+        }
+
+        let total = downloaded.map(|counter| (counter, self.max_total_bytes));
+        let (bytes, declared) = self.download("image", url, self.max_bytes, total).await?;
+        let media_type = sniff_media_type(&bytes).ok_or_else(|| {
+            fetch_error(
+                "image",
+                url,
+                format!(
+                    "not a PNG, JPEG, GIF or WebP image (content-type: {})",
+                    declared
+                ),
+            )
+        })?;
+        let image = UnifiedImage {
+            media_type: media_type.to_string(),
+            data: base64::engine::general_purpose::STANDARD.encode(&bytes),
+        };
+        tracing::debug!(
+            "Fetched image {} ({}, {} bytes)",
+            url,
+            media_type,
+            bytes.len()
+        );
+
+        if self.cache_entries > 0 {
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(url.to_string(), image.clone(), self.cache_entries);
+        }
+        Ok(image)
+    }
+
+    /// Replace remote document sources with their downloaded content
+    ///
+    /// PDFs become `base64` sources and anything else must be UTF-8 text.
+    /// Returns the number of documents fetched.
+    pub async fn inline_remote_documents(
+        &self,
+        messages: &mut [UnifiedMessage],
+        max_bytes: usize,
+    ) -> Result<usize, ApiError> {
+        let mut inlined = 0;
+        for message in messages.iter_mut() {
+            let MessageContent::Blocks(ref mut blocks) = message.content else {
+                continue;
+            };
+            for block in blocks.iter_mut() {
+                let ContentBlock::Document { source, .. } = block else {
+                    continue;
+                };
+                if source.source_type != "url" {
+                    continue;
+                }
+                let url = source.url.take().unwrap_or_default();
+                let (bytes, declared) = self.download("document", &url, max_bytes, None).await?;
+                if bytes.starts_with(b"%PDF-") {
+                    source.source_type = "base64".to_string();
+                    source.media_type = Some("application/pdf".to_string());
+                    source.data = Some(base64::engine::general_purpose::STANDARD.encode(&bytes));
+                } else {
+                    let text = String::from_utf8(bytes).map_err(|_| {
+                        fetch_error(
+                            "document",
+                            &url,
+                            format!("not a PDF or text file (content-type: {})", declared),
+                        )
+                    })?;
+                    let media_type = declared.split(';').next().unwrap_or_default().trim();
+                    source.source_type = "text".to_string();
+                    source.media_type = Some(if media_type.starts_with("text/") {
+                        media_type.to_string()
+                    } else {
+                        "text/plain".to_string()
+                    });
+                    source.data = Some(text);
+                }
+                tracing::debug!("Fetched document {}", url);
+                inlined += 1;
+            }
+        }
+        Ok(inlined)
+    }
+
+    /// Download a URL allowed by the host policy, up to `max_bytes`
+    ///
+    /// With `total`, the bytes also count against a limit shared with other
+    /// downloads. Returns the body and the declared content type.
+    async fn download(
+        &self,
+        kind: &str,
+        url: &str,
+        max_bytes: usize,
+        total: Option<(&AtomicUsize, usize)>,
+    ) -> Result<(Vec<u8>, String), ApiError> {
+        let parsed = Url::parse(url).map_err(|e| fetch_error(kind, url, e))?;
+        if !url_allowed(&self.allowed_hosts, &parsed) {
+            return Err(fetch_error(kind, url, "host is not allowed"));
+        }
+        if self.proxied && self.allowed_hosts.is_empty() {
+            return Err(fetch_error(
+                kind,
+                url,
+                "IMAGE_FETCH_ALLOWED_HOSTS must be set to fetch through the egress proxy",
+            ));
+        }
+
+        let failed = |e: reqwest::Error| {
+            if e.is_timeout() {
+                fetch_error(kind, url, "timed out")
+            } else {
+                fetch_error(kind, url, format!("{:#}", anyhow::Error::from(e)))
+            }
+        };
+        let mut response = self.client.get(parsed).send().await.map_err(failed)?;
+        if !response.status().is_success() {
+            return Err(fetch_error(
+                kind,
+                url,
+                format!("HTTP {}", response.status()),
+            ));
+        }
+        let too_large = || fetch_error(kind, url, format!("larger than {} bytes", max_bytes));
+        if response
+            .content_length()
+            .is_some_and(|len| len > max_bytes as u64)
+        {
+            return Err(too_large());
+        }
+        let declared = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("none")
+            .to_string();
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(failed)? {
+            if bytes.len() + chunk.len() > max_bytes {
+                return Err(too_large());
+            }
+            if let Some((downloaded, max_total)) = total {
+                if downloaded.fetch_add(chunk.len(), Ordering::Relaxed) + chunk.len() > max_total {
+                    return Err(fetch_error(
+                        kind,
+                        url,
+                        format!("request downloads exceed {} bytes in total", max_total),
+                    ));
+                }
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok((bytes, declared))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converters::core::{DocumentSource, ImageSource, ImageUrl};
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn fetcher(max_bytes: usize) -> ImageFetcher {
+        ImageFetcher::new(
+            &EgressConfig::default(),
+            vec!["127.0.0.1".to_string()],
+            max_bytes,
+            Duration::from_secs(5),
+        )
+        .unwrap()
+    }
+
+    fn user(blocks: Vec<ContentBlock>) -> UnifiedMessage {
+        UnifiedMessage {
+            role: "user".to_string(),
+            content: MessageContent::Blocks(blocks),
+            tool_calls: None,
+            tool_results: None,
+            images: None,
+        }
+    }
+
+    #[test]
+    fn test_host_policy() {
+        let url = |s: &str| Url::parse(s).unwrap();
+        let open: Vec<String> = Vec::new();
+        assert!(url_allowed(&open, &url("https://example.com/a.png")));
+        assert!(!url_allowed(&open, &url("http://localhost/a.png")));
+        assert!(!url_allowed(&open, &url("http://127.0.0.1:8080/a.png")));
+        assert!(!url_allowed(&open, &url("http://10.1.2.3/a.png")));
+        assert!(!url_allowed(&open, &url("http://169.254.169.254/latest")));
+        assert!(!url_allowed(&open, &url("http://[::1]/a.png")));
+        assert!(!url_allowed(&open, &url("http://100.64.0.1/a.png")));
+        assert!(!url_allowed(&open, &url("http://0.0.0.0/a.png")));
+        assert!(!url_allowed(&open, &url("http://[::ffff:10.0.0.1]/a.png")));
+        assert!(!url_allowed(
+            &open,
+            &url("http://[64:ff9b::a9fe:a9fe]/a.png")
+        ));
+        assert!(!url_allowed(&open, &url("http://[fd00::1]/a.png")));
+        assert!(url_allowed(&open, &url("http://93.184.216.34/a.png")));
+        assert!(url_allowed(&open, &url("http://[2606:4700::1111]/a.png")));
+        assert!(!url_allowed(&open, &url("ftp://example.com/a.png")));
+
+        let listed = vec!["*.example.com".to_string(), "127.0.0.1".to_string()];
+        assert!(url_allowed(&listed, &url("https://cdn.example.com/a.png")));
+        assert!(!url_allowed(&listed, &url("https://example.com/a.png")));
+        assert!(!url_allowed(&listed, &url("https://badexample.com/a.png")));
+        assert!(url_allowed(&listed, &url("http://127.0.0.1:8080/a.png")));
+    }
+
+    #[test]
+    fn test_internal_ranges() {
+        for ip in [
+            "0.1.2.3",
+            "100.127.255.254",
+            "192.0.0.8",
+            "198.19.0.1",
+            "203.0.113.7",
+            "224.0.0.1",
+            "240.0.0.1",
+            "255.255.255.255",
+            "ff02::1",
+            "fe80::1",
+            "2001:db8::1",
+            "2002:c0a8:101::1",
+        ] {
+            assert!(is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["100.128.0.1", "198.20.0.1", "8.8.8.8", "2002:808:808::1"] {
+            assert!(!is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolver_refuses_internal_names() {
+        let name: Name = "localhost".parse().unwrap();
+        let err = PublicResolver.resolve(name).await.err().unwrap();
+        assert!(err.to_string().contains("internal address"), "{}", err);
+    }
+
+    #[test]
+    fn test_sniff_media_type() {
+        assert_eq!(sniff_media_type(PNG), Some("image/png"));
+        assert_eq!(
+            sniff_media_type(&[0xff, 0xd8, 0xff, 0xe0]),
+            Some("image/jpeg")
+        );
+        assert_eq!(sniff_media_type(b"GIF89a..."), Some("image/gif"));
+        assert_eq!(
+            sniff_media_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(sniff_media_type(b"<html>"), None);
+    }
+
+    #[tokio::test]
+    async fn test_inlines_and_caches_remote_images() {
+        let mut server = mockito::Server::new_async().await;
+        // Served as octet-stream: the format is sniffed from the bytes
+        let image = server
+            .mock("GET", "/cat.png")
+            .with_header("content-type", "application/octet-stream")
+            .with_body(PNG)
+            .expect(1)
+            .create_async()
+            .await;
+        let url = format!("{}/cat.png", server.url());
+        let fetcher = fetcher(1024);
+
+        let mut messages = vec![user(vec![
+            ContentBlock::Text {
+                text: "What is this?".to_string(),
+            },
+            ContentBlock::ImageUrl {
+                image_url: ImageUrl { url: url.clone() },
+            },
+            ContentBlock::Image {
+                source: ImageSource {
+                    source_type: "url".to_string(),
+                    media_type: None,
+                    data: None,
+                    url: Some(url.clone()),
+                },
+            },
+        ])];
+        assert_eq!(
+            fetcher.inline_remote_images(&mut messages).await.unwrap(),
+            2
+        );
+        image.assert_async().await;
+
+        let images = messages[0].images.as_ref().unwrap();
+        assert_eq!(images.len(), 2);
+        for image in images {
+            assert_eq!(image.media_type, "image/png");
+            assert_eq!(
+                base64::engine::general_purpose::STANDARD
+                    .decode(&image.data)
+                    .unwrap(),
+                PNG
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_failures_are_validation_errors() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/missing.png")
+            .with_status(404)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/page.html")
+            .with_header("content-type", "text/html")
+            .with_body("<html></html>")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/huge.png")
+            .with_body(vec![0u8; 2048])
+            .create_async()
+            .await;
+        let fetcher = fetcher(1024);
+
+        let reason = |err: ApiError| match err {
+            ApiError::ValidationError(message) => message,
+            other => panic!("unexpected error: {:?}", other),
+        };
+        let missing = fetcher
+            .fetch(&format!("{}/missing.png", server.url()), None)
+            .await
+            .unwrap_err();
+        assert!(reason(missing).contains("HTTP 404"));
+        let page = fetcher
+            .fetch(&format!("{}/page.html", server.url()), None)
+            .await
+            .unwrap_err();
+        assert!(reason(page).contains("content-type: text/html"));
+        let huge = fetcher
+            .fetch(&format!("{}/huge.png", server.url()), None)
+            .await
+            .unwrap_err();
+        assert!(reason(huge).contains("larger than 1024 bytes"));
+        let denied = fetcher
+            .fetch("http://203.0.113.7/a.png", None)
+            .await
+            .unwrap_err();
+        assert!(reason(denied).contains("host is not allowed"));
+
+        // A disallowed URL inside a message fails the whole request
+        let mut messages = vec![user(vec![ContentBlock::ImageUrl {
+            image_url: ImageUrl {
+                url: "https://example.com/a.png".to_string(),
+            },
+        }])];
+        assert!(fetcher.inline_remote_images(&mut messages).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_request_limits_on_remote_images() {
+        let mut server = mockito::Server::new_async().await;
+        let image = server
+            .mock("GET", mockito::Matcher::Regex(r"^/\d\.png$".to_string()))
+            .with_body(PNG)
+            .expect(2)
+            .create_async()
+            .await;
+        let images = |count: usize| {
+            let blocks = (0..count)
+                .map(|i| ContentBlock::ImageUrl {
+                    image_url: ImageUrl {
+                        url: format!("{}/{}.png", server.url(), i),
+                    },
+                })
+                .collect();
+            vec![user(blocks)]
+        };
+        let reason = |err: ApiError| match err {
+            ApiError::ValidationError(message) => message,
+            other => panic!("unexpected error: {:?}", other),
+        };
+
+        // Too many images fail before anything is downloaded
+        let limited = fetcher(1024).with_request_limits(2, PNG.len() * 2);
+        let err = limited
+            .inline_remote_images(&mut images(3))
+            .await
+            .unwrap_err();
+        assert_eq!(reason(err), "too many images: 3 (maximum 2)");
+
+        assert_eq!(
+            limited.inline_remote_images(&mut images(2)).await.unwrap(),
+            2
+        );
+        image.assert_async().await;
+
+        // Two images no longer fit once the request total is lower
+        let strict = fetcher(1024).with_request_limits(2, PNG.len() * 2 - 1);
+        let err = strict
+            .inline_remote_images(&mut images(2))
+            .await
+            .unwrap_err();
+        assert!(reason(err).contains("exceed"));
+    }
+
+    #[tokio::test]
+    async fn test_inlines_remote_documents() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/report.pdf")
+            .with_header("content-type", "application/octet-stream")
+            .with_body("%PDF-1.4 ...")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/notes.md")
+            .with_header("content-type", "text/markdown; charset=utf-8")
+            .with_body("# Notes")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/blob.bin")
+            .with_header("content-type", "application/octet-stream")
+            .with_body([0xff, 0xfe, 0x00])
+            .create_async()
+            .await;
+        let document = |path: &str| ContentBlock::Document {
+            source: DocumentSource {
+                source_type: "url".to_string(),
+                media_type: None,
+                data: None,
+                url: Some(format!("{}/{}", server.url(), path)),
+            },
+            title: None,
+            context: None,
+        };
+        let fetcher = fetcher(1024);
+
+        let mut messages = vec![user(vec![document("report.pdf"), document("notes.md")])];
+        assert_eq!(
+            fetcher
+                .inline_remote_documents(&mut messages, 1024)
+                .await
+                .unwrap(),
+            2
+        );
+        let MessageContent::Blocks(ref blocks) = messages[0].content else {
+            panic!("Expected Blocks variant");
+        };
+        let sources: Vec<_> = blocks
+            .iter()
+            .map(|block| match block {
+                ContentBlock::Document { source, .. } => source.clone(),
+                _ => panic!("Expected Document block"),
+            })
+            .collect();
+        assert_eq!(sources[0].source_type, "base64");
+        assert_eq!(sources[0].media_type.as_deref(), Some("application/pdf"));
+        assert_eq!(sources[1].source_type, "text");
+        assert_eq!(sources[1].media_type.as_deref(), Some("text/markdown"));
+        assert_eq!(sources[1].data.as_deref(), Some("# Notes"));
+
+        let mut messages = vec![user(vec![document("blob.bin")])];
+        match fetcher.inline_remote_documents(&mut messages, 1024).await {
+            Err(ApiError::ValidationError(message)) => {
+                assert!(
+                    message.starts_with("Failed to fetch document"),
+                    "{}",
+                    message
+                );
+                assert!(message.contains("not a PDF or text file"), "{}", message);
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        let mut messages = vec![user(vec![document("report.pdf")])];
+        assert!(fetcher
+            .inline_remote_documents(&mut messages, 4)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_redirects_obey_host_policy() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/moved.png")
+            .with_status(302)
+            .with_header("location", "http://198.51.100.9/a.png")
+            .create_async()
+            .await;
+        let err = fetcher(1024)
+            .fetch(&format!("{}/moved.png", server.url()), None)
+            .await
+            .unwrap_err();
+        match err {
+            ApiError::ValidationError(message) => {
+                assert!(message.contains("disallowed URL"), "{}", message)
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
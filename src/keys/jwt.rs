@@ -0,0 +1,597 @@
+use anyhow::{Context, Result};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve, JwkSet, KeyAlgorithm};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{ClientIdentity, KeyRejection};
+use crate::config::Config;
+use crate::middleware::RateLimits;
+
+/// Claim holding the caller's groups unless the policy says otherwise
+const DEFAULT_GROUPS_CLAIM: &str = "groups";
+
+/// Prefix for identities derived from a token subject
+pub(super) const JWT_NAME_PREFIX: &str = "jwt:";
+
+/// Access granted to tokens matching a rule
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ClaimGrant {
+    /// Allowed models (resolved model IDs, `*` suffix wildcard). Empty = all
+    pub allowed_models: Vec<String>,
+
+    /// Rate-limit tier (from `tiers`)
+    pub tier: Option<String>,
+}
+
+/// Maps a subject and/or group to a grant
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ClaimRule {
+    pub subject: Option<String>,
+    pub group: Option<String>,
+    #[serde(flatten)]
+    pub grant: ClaimGrant,
+}
+
+impl ClaimRule {
+    fn matches(&self, subject: &str, groups: &[String]) -> bool {
+        self.subject.as_ref().is_none_or(|s| s == subject)
+            && self
+                .group
+                .as_ref()
+                .is_none_or(|g| groups.iter().any(|group| group == g))
+    }
+}
+
+/// How token claims map to allowed models and rate-limit tiers (`JWT_CLAIMS_FILE`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClaimPolicy {
+    /// Claim holding the caller's groups (array or space-separated string)
+    pub groups_claim: String,
+
+    /// Named rate-limit tiers
+    pub tiers: HashMap<String, RateLimits>,
+
+    /// Rules in priority order; every matching rule contributes models, the
+    /// first matching rule with a tier sets the tier
+    pub rules: Vec<ClaimRule>,
+
+    /// Grant for tokens no rule matches; such tokens are rejected when unset
+    #[serde(default)]
+    pub default: Option<ClaimGrant>,
+}
+
+impl Default for ClaimPolicy {
+    /// Without a claims file every valid token gets unrestricted access
+    fn default() -> Self {
+        Self {
+            groups_claim: DEFAULT_GROUPS_CLAIM.to_string(),
+            tiers: HashMap::new(),
+            rules: Vec::new(),
+            default: Some(ClaimGrant::default()),
+        }
+    }
+}
+
+impl ClaimPolicy {
+    fn validate(&self) -> Result<()> {
+        for rule in &self.rules {
+            if rule.subject.is_none() && rule.group.is_none() {
+                anyhow::bail!("JWT claim rules must set a subject or a group");
+            }
+        }
+
+        let grants = self
+            .rules
+            .iter()
+            .map(|r| &r.grant)
+            .chain(self.default.as_ref());
+        for grant in grants {
+            if let Some(ref tier) = grant.tier {
+                if !self.tiers.contains_key(tier) {
+                    anyhow::bail!("JWT claim rule references unknown tier '{}'", tier);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve the grant for a subject and its groups
+    fn resolve(&self, subject: &str, groups: &[String]) -> Option<ClaimGrant> {
+        let matched: Vec<&ClaimGrant> = self
+            .rules
+            .iter()
+            .filter(|r| r.matches(subject, groups))
+            .map(|r| &r.grant)
+            .collect();
+
+        if matched.is_empty() {
+            return self.default.clone();
+        }
+
+        let allowed_models = if matched.iter().any(|g| g.allowed_models.is_empty()) {
+            Vec::new()
+        } else {
+            let mut models: Vec<String> = Vec::new();
+            for model in matched.iter().flat_map(|g| &g.allowed_models) {
+                if !models.contains(model) {
+                    models.push(model.clone());
+                }
+            }
+            models
+        };
+
+        let tier = matched
+            .iter()
+            .find_map(|g| g.tier.clone())
+            .or_else(|| self.default.as_ref().and_then(|d| d.tier.clone()));
+
+        Some(ClaimGrant {
+            allowed_models,
+            tier,
+        })
+    }
+}
+
+/// A key a token signature may be verified with
+struct VerificationKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+/// Validates client JWTs (RS256/ES256 via a local JWKS, HS256 via a shared secret)
+pub struct JwtValidator {
+    keys: Vec<VerificationKey>,
+    issuer: Option<String>,
+    audience: Vec<String>,
+    policy: ClaimPolicy,
+    api_key_fallback: bool,
+}
+
+impl JwtValidator {
+    /// Build the validator from `JWT_*` settings; `None` when JWT mode is off
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        if config.jwt_secret.is_none() && config.jwt_jwks_file.is_none() {
+            return Ok(None);
+        }
+
+        let jwks = match config.jwt_jwks_file {
+            Some(ref path) => Some(
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read JWKS file: {}", path.display()))?,
+            ),
+            None => None,
+        };
+
+        let policy = match config.jwt_claims_file {
+            Some(ref path) => {
+                let content = std::fs::read_to_string(path).with_context(|| {
+                    format!("Failed to read JWT claims file: {}", path.display())
+                })?;
+                serde_json::from_str(&content).with_context(|| {
+                    format!("Failed to parse JWT claims file: {}", path.display())
+                })?
+            }
+            None => ClaimPolicy::default(),
+        };
+
+        Self::new(
+            config.jwt_secret.as_deref(),
+            jwks.as_deref(),
+            config.jwt_issuer.clone(),
+            config.jwt_audience.clone(),
+            policy,
+            config.jwt_api_key_fallback,
+        )
+        .map(Some)
+    }
+
+    fn new(
+        secret: Option<&str>,
+        jwks: Option<&str>,
+        issuer: Option<String>,
+        audience: Vec<String>,
+        policy: ClaimPolicy,
+        api_key_fallback: bool,
+    ) -> Result<Self> {
+        policy.validate()?;
+
+        let mut keys = Vec::new();
+        if let Some(secret) = secret {
+            keys.push(VerificationKey {
+                kid: None,
+                algorithm: Algorithm::HS256,
+                key: DecodingKey::from_secret(secret.as_bytes()),
+            });
+        }
+        if let Some(jwks) = jwks {
+            keys.extend(parse_jwks(jwks)?);
+        }
+        if keys.is_empty() {
+            anyhow::bail!("No usable JWT verification keys (RS256, ES256 or HS256)");
+        }
+
+        Ok(Self {
+            keys,
+            issuer,
+            audience,
+            policy,
+            api_key_fallback,
+        })
+    }
+
+    /// Whether API keys are still accepted alongside JWTs
+    pub fn api_key_fallback(&self) -> bool {
+        self.api_key_fallback
+    }
+
+    /// Rate-limit tiers defined by the claims policy
+    pub fn tiers(&self) -> HashMap<String, RateLimits> {
+        self.policy.tiers.clone()
+    }
+
+    /// Number of verification keys loaded
+    pub fn key_count(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Verify a token and map its claims to a client identity
+    pub fn validate(&self, token: &str) -> Result<ClientIdentity, KeyRejection> {
+        let header = decode_header(token).map_err(|_| invalid("malformed token"))?;
+        if !matches!(
+            header.alg,
+            Algorithm::RS256 | Algorithm::ES256 | Algorithm::HS256
+        ) {
+            return Err(invalid(&format!("unsupported algorithm {:?}", header.alg)));
+        }
+
+        let mut validation = Validation::new(header.alg);
+        validation.validate_nbf = true;
+        match self.issuer {
+            Some(ref issuer) => validation.set_issuer(&[issuer]),
+            None => validation.iss = None,
+        }
+        if self.audience.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.audience);
+        }
+
+        let candidates = self.keys.iter().filter(|k| {
+            k.algorithm == header.alg
+                && match (&header.kid, &k.kid) {
+                    (Some(wanted), Some(kid)) => wanted == kid,
+                    _ => true,
+                }
+        });
+
+        let mut result = Err(invalid("no matching verification key"));
+        for candidate in candidates {
+            result = decode::<HashMap<String, Value>>(token, &candidate.key, &validation)
+                .map(|data| data.claims)
+                .map_err(|e| invalid(describe_error(e.kind())));
+            if !matches!(&result, Err(KeyRejection::InvalidToken(r)) if r == "invalid signature") {
+                break;
+            }
+        }
+        let claims = result?;
+
+        let subject = claims
+            .get("sub")
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| invalid("missing sub claim"))?;
+        let groups = claim_strings(claims.get(&self.policy.groups_claim));
+
+        let grant = self.policy.resolve(subject, &groups).ok_or_else(|| {
+            KeyRejection::NoMatchingRule(format!("{}{}", JWT_NAME_PREFIX, subject))
+        })?;
+
+        let name = format!("{}{}", JWT_NAME_PREFIX, subject);
+        Ok(ClientIdentity {
+            id: name.clone(),
+            name,
+            allowed_models: grant.allowed_models,
+            allowed_endpoints: Vec::new(),
+            rate_limit_tier: grant.tier,
+        })
+    }
+}
+
+/// Check whether a bearer token is shaped like a JWT (`eyJ...` with three segments)
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.starts_with("eyJ") && token.split('.').count() == 3
+}
+
+fn invalid(reason: &str) -> KeyRejection {
+    KeyRejection::InvalidToken(reason.to_string())
+}
+
+fn describe_error(kind: &ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::InvalidSignature => "invalid signature",
+        ErrorKind::ExpiredSignature => "token has expired",
+        ErrorKind::ImmatureSignature => "token is not valid yet",
+        ErrorKind::InvalidIssuer => "invalid issuer",
+        ErrorKind::InvalidAudience => "invalid audience",
+        ErrorKind::MissingRequiredClaim(_) => "missing required claim",
+        _ => "invalid token",
+    }
+}
+
+/// Read a claim holding either a string array or a space-separated string
+fn claim_strings(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect(),
+        Some(Value::String(s)) => s.split_whitespace().map(str::to_string).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Load RS256/ES256 verification keys from a JWKS document
+fn parse_jwks(content: &str) -> Result<Vec<VerificationKey>> {
+    let set: JwkSet = serde_json::from_str(content).context("Failed to parse JWKS")?;
+
+    let mut keys = Vec::new();
+    for jwk in &set.keys {
+        let kid = jwk.common.key_id.clone();
+        let algorithm = match (&jwk.common.key_algorithm, &jwk.algorithm) {
+            (Some(KeyAlgorithm::RS256), AlgorithmParameters::RSA(_))
+            | (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+            (Some(KeyAlgorithm::ES256), AlgorithmParameters::EllipticCurve(p))
+            | (None, AlgorithmParameters::EllipticCurve(p))
+                if p.curve == EllipticCurve::P256 =>
+            {
+                Algorithm::ES256
+            }
+            _ => {
+                tracing::warn!(
+                    "Skipping JWKS key {:?}: only RS256 and ES256 (P-256) keys are supported",
+                    kid
+                );
+                continue;
+            }
+        };
+
+        let key =
+            DecodingKey::from_jwk(jwk).with_context(|| format!("Invalid JWKS key {:?}", kid))?;
+        keys.push(VerificationKey {
+            kid,
+            algorithm,
+            key,
+        });
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    const RSA_PRIVATE_KEY: &str = include_str!("../../tests/fixtures/jwt/rsa_private.pem");
+    const EC_PRIVATE_KEY: &str = include_str!("../../tests/fixtures/jwt/ec_private.pem");
+    const JWKS: &str = include_str!("../../tests/fixtures/jwt/jwks.json");
+    const SECRET: &str = "shared-test-secret";
+
+    fn now() -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+
+    fn claims(sub: &str, groups: &[&str]) -> Value {
+        json!({
+            "sub": sub,
+            "groups": groups,
+            "iss": "https://idp.example.com",
+            "aud": "kiro-gateway",
+            "exp": now() + 600,
+            "nbf": now() - 10,
+        })
+    }
+
+    fn sign(alg: Algorithm, kid: Option<&str>, claims: &Value) -> String {
+        let mut header = Header::new(alg);
+        header.kid = kid.map(str::to_string);
+        let key = match alg {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(RSA_PRIVATE_KEY.as_bytes()).unwrap(),
+            Algorithm::ES256 => EncodingKey::from_ec_pem(EC_PRIVATE_KEY.as_bytes()).unwrap(),
+            _ => EncodingKey::from_secret(SECRET.as_bytes()),
+        };
+        encode(&header, claims, &key).unwrap()
+    }
+
+    fn policy() -> ClaimPolicy {
+        serde_json::from_value(json!({
+            "tiers": {
+                "standard": {"requests_per_minute": 60},
+                "premium": {"requests_per_minute": 600, "concurrent_streams": 10}
+            },
+            "rules": [
+                {"subject": "svc-ci", "allowed_models": ["claude-haiku-*"], "tier": "standard"},
+                {"group": "ml-research", "allowed_models": [], "tier": "premium"},
+                {"group": "support", "allowed_models": ["claude-sonnet-4.5"]}
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn validator(policy: ClaimPolicy) -> JwtValidator {
+        JwtValidator::new(
+            Some(SECRET),
+            Some(JWKS),
+            Some("https://idp.example.com".to_string()),
+            vec!["kiro-gateway".to_string()],
+            policy,
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_all_algorithms_verify() {
+        let v = validator(ClaimPolicy::default());
+        assert_eq!(v.key_count(), 3);
+
+        for (alg, kid) in [
+            (Algorithm::RS256, Some("rsa-1")),
+            (Algorithm::ES256, Some("ec-1")),
+            (Algorithm::ES256, None),
+            (Algorithm::HS256, None),
+        ] {
+            let token = sign(alg, kid, &claims("alice", &[]));
+            assert!(looks_like_jwt(&token));
+            let identity = v.validate(&token).unwrap();
+            assert_eq!(identity.name, "jwt:alice");
+            assert!(identity.allowed_models.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_rejects_bad_tokens() {
+        let v = validator(ClaimPolicy::default());
+        let reason = |token: &str| match v.validate(token) {
+            Err(KeyRejection::InvalidToken(reason)) => reason,
+            other => panic!("unexpected result: {:?}", other),
+        };
+
+        let mut expired = claims("alice", &[]);
+        expired["exp"] = json!(now() - 3600);
+        assert_eq!(
+            reason(&sign(Algorithm::HS256, None, &expired)),
+            "token has expired"
+        );
+
+        let mut early = claims("alice", &[]);
+        early["nbf"] = json!(now() + 3600);
+        assert_eq!(
+            reason(&sign(Algorithm::HS256, None, &early)),
+            "token is not valid yet"
+        );
+
+        let mut wrong_aud = claims("alice", &[]);
+        wrong_aud["aud"] = json!("someone-else");
+        assert_eq!(
+            reason(&sign(Algorithm::RS256, Some("rsa-1"), &wrong_aud)),
+            "invalid audience"
+        );
+
+        let mut wrong_iss = claims("alice", &[]);
+        wrong_iss["iss"] = json!("https://evil.example.com");
+        assert_eq!(
+            reason(&sign(Algorithm::ES256, Some("ec-1"), &wrong_iss)),
+            "invalid issuer"
+        );
+
+        // Signed with a different secret
+        let forged = encode(
+            &Header::new(Algorithm::HS256),
+            &claims("alice", &[]),
+            &EncodingKey::from_secret(b"other-secret"),
+        )
+        .unwrap();
+        assert_eq!(reason(&forged), "invalid signature");
+
+        // Unknown kid
+        assert_eq!(
+            reason(&sign(
+                Algorithm::RS256,
+                Some("rsa-2"),
+                &claims("alice", &[])
+            )),
+            "no matching verification key"
+        );
+
+        assert_eq!(reason("eyJ.not.valid"), "malformed token");
+    }
+
+    #[test]
+    fn test_claims_map_to_models_and_tiers() {
+        let v = validator(policy());
+
+        let ci = v
+            .validate(&sign(Algorithm::HS256, None, &claims("svc-ci", &[])))
+            .unwrap();
+        assert_eq!(ci.allowed_models, vec!["claude-haiku-*"]);
+        assert_eq!(ci.rate_limit_tier.as_deref(), Some("standard"));
+
+        // Any matching rule with no model list grants all models
+        let researcher = v
+            .validate(&sign(
+                Algorithm::RS256,
+                Some("rsa-1"),
+                &claims("bob", &["support", "ml-research"]),
+            ))
+            .unwrap();
+        assert!(researcher.allowed_models.is_empty());
+        assert_eq!(researcher.rate_limit_tier.as_deref(), Some("premium"));
+
+        let support = v
+            .validate(&sign(
+                Algorithm::HS256,
+                None,
+                &claims("carol", &["support"]),
+            ))
+            .unwrap();
+        assert_eq!(support.allowed_models, vec!["claude-sonnet-4.5"]);
+        assert_eq!(support.rate_limit_tier, None);
+
+        // No rule and no default grant
+        assert_eq!(
+            v.validate(&sign(
+                Algorithm::HS256,
+                None,
+                &claims("mallory", &["guests"])
+            )),
+            Err(KeyRejection::NoMatchingRule("jwt:mallory".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_groups_claim_as_string() {
+        let mut p = policy();
+        p.groups_claim = "scope".to_string();
+        let v = validator(p);
+
+        let mut c = claims("dave", &[]);
+        c["scope"] = json!("read ml-research");
+        let identity = v.validate(&sign(Algorithm::HS256, None, &c)).unwrap();
+        assert_eq!(identity.rate_limit_tier.as_deref(), Some("premium"));
+    }
+
+    #[test]
+    fn test_policy_validation() {
+        let unknown_tier: ClaimPolicy = serde_json::from_value(json!({
+            "rules": [{"group": "x", "tier": "gold"}]
+        }))
+        .unwrap();
+        assert!(unknown_tier.validate().is_err());
+
+        let empty_rule: ClaimPolicy = serde_json::from_value(json!({
+            "rules": [{"allowed_models": ["a"]}]
+        }))
+        .unwrap();
+        assert!(empty_rule.validate().is_err());
+
+        // A claims file without a default rejects unmatched tokens
+        let no_default: ClaimPolicy = serde_json::from_value(json!({"rules": []})).unwrap();
+        assert!(no_default.default.is_none());
+        assert!(ClaimPolicy::default().default.is_some());
+    }
+
+    #[test]
+    fn test_looks_like_jwt() {
+        assert!(looks_like_jwt("eyJhbGciOi.eyJzdWIi.c2ln"));
+        assert!(!looks_like_jwt("sk-static-key"));
+        assert!(!looks_like_jwt("eyJonly.two"));
+    }
+}
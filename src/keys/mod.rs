@@ -0,0 +1,272 @@
+// Client API keys: named keys with scopes, stored as hashes
+
+mod jwt;
+mod store;
+mod virtual_keys;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+pub use jwt::{looks_like_jwt, JwtValidator};
+pub use store::KeyStore;
+pub use virtual_keys::{NewVirtualKey, VirtualKeyStore};
+
+/// Prefix used for stored key hashes
+const HASH_PREFIX: &str = "sha256:";
+
+/// Name attributed to requests authenticated with `PROXY_API_KEY`
+pub const DEFAULT_KEY_NAME: &str = "default";
+
+/// Prefix for identities derived from a client certificate
+pub const MTLS_NAME_PREFIX: &str = "mtls:";
+
+/// Name prefixes owned by JWT and mTLS identities (and virtual key IDs)
+const RESERVED_NAME_PREFIXES: [&str; 3] = [
+    jwt::JWT_NAME_PREFIX,
+    MTLS_NAME_PREFIX,
+    virtual_keys::VIRTUAL_KEY_ID_PREFIX,
+];
+
+/// A named client API key as stored in the key store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientKey {
+    /// Human-readable key name (used for attribution)
+    pub name: String,
+
+    /// SHA-256 hash of the key, as `sha256:<hex>` or plain hex
+    pub key_hash: String,
+
+    /// Disabled keys are rejected
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// Keys are rejected after this instant
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Allowed models (resolved model IDs, `*` suffix wildcard). Empty = all
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+
+    /// Allowed endpoint paths (`*` suffix wildcard). Empty = all
+    #[serde(default)]
+    pub allowed_endpoints: Vec<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl ClientKey {
+    /// Check whether the key has expired
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|exp| now >= exp)
+    }
+
+    /// Build the identity attached to authenticated requests
+    pub fn identity(&self) -> ClientIdentity {
+        ClientIdentity {
+            id: self.name.clone(),
+            name: self.name.clone(),
+            allowed_models: self.allowed_models.clone(),
+            allowed_endpoints: self.allowed_endpoints.clone(),
+            rate_limit_tier: None,
+        }
+    }
+}
+
+/// Identity of an authenticated client
+///
+/// Inserted into request extensions by `auth_middleware` so handlers, metrics
+/// and debug captures can attribute traffic to a key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientIdentity {
+    /// Unique accounting ID: usage, budgets, rate limits and caps are keyed by
+    /// it. Equal to `name` except for virtual keys (`vk:<id>`)
+    pub id: String,
+    pub name: String,
+    pub allowed_models: Vec<String>,
+    pub allowed_endpoints: Vec<String>,
+    /// Named rate-limit tier (default limits when unset)
+    pub rate_limit_tier: Option<String>,
+}
+
+impl ClientIdentity {
+    /// Identity without any model or endpoint restrictions
+    pub fn unrestricted(name: &str) -> Self {
+        Self {
+            id: name.to_string(),
+            name: name.to_string(),
+            allowed_models: Vec::new(),
+            allowed_endpoints: Vec::new(),
+            rate_limit_tier: None,
+        }
+    }
+
+    /// Check whether this key may call the given endpoint path
+    pub fn allows_endpoint(&self, path: &str) -> bool {
+        self.allowed_endpoints.is_empty()
+            || self
+                .allowed_endpoints
+                .iter()
+                .any(|pattern| matches_pattern(pattern, path))
+    }
+
+    /// Check whether this key may use the given (resolved) model ID
+    pub fn allows_model(&self, model_id: &str) -> bool {
+        let model_id = model_id.to_lowercase();
+        self.allowed_models.is_empty()
+            || self
+                .allowed_models
+                .iter()
+                .any(|pattern| matches_pattern(&pattern.to_lowercase(), &model_id))
+    }
+}
+
+/// Reason a presented key was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyRejection {
+    Unknown,
+    Disabled(String),
+    Expired(String),
+    /// Virtual key used up its request or token cap
+    CapReached(String),
+    /// Bearer JWT failed verification (reason)
+    InvalidToken(String),
+    /// Valid JWT whose claims match no access rule (identity name)
+    NoMatchingRule(String),
+}
+
+impl std::fmt::Display for KeyRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyRejection::Unknown => write!(f, "unknown key"),
+            KeyRejection::Disabled(name) => write!(f, "key '{}' is disabled", name),
+            KeyRejection::Expired(name) => write!(f, "key '{}' has expired", name),
+            KeyRejection::CapReached(name) => write!(f, "key '{}' has reached its usage cap", name),
+            KeyRejection::InvalidToken(reason) => write!(f, "invalid JWT: {}", reason),
+            KeyRejection::NoMatchingRule(name) => {
+                write!(f, "token '{}' matches no claim rule", name)
+            }
+        }
+    }
+}
+
+/// Check whether a name is reserved for identities not issued as virtual keys
+pub(crate) fn is_reserved_name(name: &str) -> bool {
+    name == DEFAULT_KEY_NAME
+        || RESERVED_NAME_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+/// Hash a raw key for storage (`sha256:<hex>`)
+pub fn hash_key(key: &str) -> String {
+    format!("{}{:x}", HASH_PREFIX, Sha256::digest(key.as_bytes()))
+}
+
+/// Strip the optional `sha256:` prefix and normalize to lowercase hex
+fn normalize_hash(hash: &str) -> String {
+    hash.trim()
+        .strip_prefix(HASH_PREFIX)
+        .unwrap_or(hash.trim())
+        .to_lowercase()
+}
+
+/// Compare two secrets in constant time
+///
+/// Both sides are hashed first so the comparison does not leak length.
+pub fn secrets_equal(a: &str, b: &str) -> bool {
+    let a = Sha256::digest(a.as_bytes());
+    let b = Sha256::digest(b.as_bytes());
+    a.ct_eq(&b).into()
+}
+
+/// Match a value against a pattern with an optional trailing `*` wildcard
+pub(crate) fn matches_pattern(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => pattern == value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_key_format() {
+        let hash = hash_key("secret");
+        assert!(hash.starts_with("sha256:"));
+        assert_eq!(hash.len(), "sha256:".len() + 64);
+        assert_eq!(
+            normalize_hash(&hash),
+            "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"
+        );
+    }
+
+    #[test]
+    fn test_normalize_hash_accepts_plain_hex() {
+        assert_eq!(normalize_hash("ABCDEF"), "abcdef");
+        assert_eq!(normalize_hash("sha256:abcdef"), "abcdef");
+    }
+
+    #[test]
+    fn test_secrets_equal() {
+        assert!(secrets_equal("key-123", "key-123"));
+        assert!(!secrets_equal("key-123", "key-124"));
+        assert!(!secrets_equal("key-123", "key-1234"));
+        assert!(!secrets_equal("", "key"));
+    }
+
+    #[test]
+    fn test_identity_scopes() {
+        let identity = ClientIdentity {
+            id: "ci".to_string(),
+            name: "ci".to_string(),
+            allowed_models: vec![
+                "claude-haiku-*".to_string(),
+                "claude-sonnet-4.5".to_string(),
+            ],
+            allowed_endpoints: vec!["/v1/messages".to_string(), "/v1/models*".to_string()],
+            rate_limit_tier: None,
+        };
+
+        assert!(identity.allows_model("claude-haiku-4.5"));
+        assert!(identity.allows_model("CLAUDE-SONNET-4.5"));
+        assert!(!identity.allows_model("claude-opus-4.5"));
+
+        assert!(identity.allows_endpoint("/v1/messages"));
+        assert!(identity.allows_endpoint("/v1/models"));
+        assert!(!identity.allows_endpoint("/v1/chat/completions"));
+    }
+
+    #[test]
+    fn test_unrestricted_identity() {
+        let identity = ClientIdentity::unrestricted(DEFAULT_KEY_NAME);
+        assert!(identity.allows_model("anything"));
+        assert!(identity.allows_endpoint("/v1/chat/completions"));
+    }
+
+    #[test]
+    fn test_client_key_expiry() {
+        let now = Utc::now();
+        let mut key = ClientKey {
+            name: "temp".to_string(),
+            key_hash: hash_key("temp"),
+            enabled: true,
+            expires_at: None,
+            allowed_models: vec![],
+            allowed_endpoints: vec![],
+        };
+        assert!(!key.is_expired(now));
+
+        key.expires_at = Some(now - chrono::Duration::seconds(1));
+        assert!(key.is_expired(now));
+
+        key.expires_at = Some(now + chrono::Duration::hours(1));
+        assert!(!key.is_expired(now));
+    }
+}
@@ -0,0 +1,348 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use subtle::ConstantTimeEq;
+
+use super::{hash_key, normalize_hash, ClientIdentity, ClientKey, KeyRejection};
+
+/// Where the key store is loaded from
+#[derive(Debug, Clone)]
+enum KeySource {
+    /// JSON file: `{"keys": [ ... ]}`
+    File(PathBuf),
+
+    /// SQLite database with an `api_keys` table
+    Sqlite(PathBuf),
+}
+
+/// JSON key file layout
+#[derive(Deserialize)]
+struct KeyFile {
+    #[serde(default)]
+    keys: Vec<ClientKey>,
+}
+
+/// Store of named client API keys
+///
+/// Keys are held as SHA-256 hashes. Presented keys are hashed and compared
+/// against every stored hash in constant time.
+pub struct KeyStore {
+    keys: RwLock<Vec<ClientKey>>,
+}
+
+impl KeyStore {
+    /// Create an empty key store (only `PROXY_API_KEY` is accepted)
+    pub fn empty() -> Self {
+        Self {
+            keys: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Create a key store from in-memory keys
+    #[cfg(test)]
+    pub fn from_keys(keys: Vec<ClientKey>) -> Self {
+        Self {
+            keys: RwLock::new(keys),
+        }
+    }
+
+    /// Load a key store from a JSON file or SQLite database
+    ///
+    /// Files ending in `.db`, `.sqlite` or `.sqlite3` are opened as SQLite,
+    /// anything else is parsed as JSON.
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = if is_sqlite_path(path) {
+            KeySource::Sqlite(path.to_path_buf())
+        } else {
+            KeySource::File(path.to_path_buf())
+        };
+
+        let keys = load_keys(&source)?;
+        tracing::info!(
+            "Loaded {} client API keys from {}",
+            keys.len(),
+            path.display()
+        );
+
+        Ok(Self {
+            keys: RwLock::new(keys),
+        })
+    }
+
+    /// Number of keys in the store
+    pub fn len(&self) -> usize {
+        self.keys.read().map(|k| k.len()).unwrap_or(0)
+    }
+
+    /// Names of all keys in the store
+    pub fn names(&self) -> Vec<String> {
+        self.keys
+            .read()
+            .map(|keys| keys.iter().map(|k| k.name.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Check if the store has no keys
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Authenticate a presented key
+    ///
+    /// Every stored hash is compared (no early exit) so timing does not reveal
+    /// which key matched or how many keys exist before it.
+    pub fn authenticate(&self, presented: &str) -> Result<ClientIdentity, KeyRejection> {
+        self.authenticate_at(presented, Utc::now())
+    }
+
+    fn authenticate_at(
+        &self,
+        presented: &str,
+        now: DateTime<Utc>,
+    ) -> Result<ClientIdentity, KeyRejection> {
+        let presented_hash = normalize_hash(&hash_key(presented));
+        let keys = self.keys.read().map_err(|_| KeyRejection::Unknown)?;
+
+        let mut matched: Option<&ClientKey> = None;
+        for key in keys.iter() {
+            let stored_hash = normalize_hash(&key.key_hash);
+            let equal: bool = stored_hash
+                .as_bytes()
+                .ct_eq(presented_hash.as_bytes())
+                .into();
+            if equal && matched.is_none() {
+                matched = Some(key);
+            }
+        }
+
+        let key = matched.ok_or(KeyRejection::Unknown)?;
+        if !key.enabled {
+            return Err(KeyRejection::Disabled(key.name.clone()));
+        }
+        if key.is_expired(now) {
+            return Err(KeyRejection::Expired(key.name.clone()));
+        }
+
+        Ok(key.identity())
+    }
+}
+
+/// Check whether a key store path points to a SQLite database
+fn is_sqlite_path(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("db" | "sqlite" | "sqlite3")
+    )
+}
+
+fn load_keys(source: &KeySource) -> Result<Vec<ClientKey>> {
+    let keys = match source {
+        KeySource::File(path) => load_from_file(path)?,
+        KeySource::Sqlite(path) => load_from_sqlite(path)?,
+    };
+
+    for key in &keys {
+        if key.name.is_empty() {
+            anyhow::bail!("Client API key entries must have a name");
+        }
+    }
+
+    Ok(keys)
+}
+
+/// Load keys from a JSON file
+fn load_from_file(path: &Path) -> Result<Vec<ClientKey>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read API keys file: {}", path.display()))?;
+    let file: KeyFile = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse API keys file: {}", path.display()))?;
+    Ok(file.keys)
+}
+
+/// Load keys from a SQLite database, creating the table if missing
+fn load_from_sqlite(path: &Path) -> Result<Vec<ClientKey>> {
+    let conn = rusqlite::Connection::open(path)
+        .with_context(|| format!("Failed to open API keys database: {}", path.display()))?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS api_keys (
+            name TEXT PRIMARY KEY,
+            key_hash TEXT NOT NULL,
+            enabled INTEGER NOT NULL DEFAULT 1,
+            expires_at TEXT,
+            allowed_models TEXT NOT NULL DEFAULT '[]',
+            allowed_endpoints TEXT NOT NULL DEFAULT '[]'
+        )",
+        [],
+    )
+    .context("Failed to create api_keys table")?;
+
+    let mut stmt = conn.prepare(
+        "SELECT name, key_hash, enabled, expires_at, allowed_models, allowed_endpoints
+         FROM api_keys",
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, bool>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, String>(5)?,
+        ))
+    })?;
+
+    let mut keys = Vec::new();
+    for row in rows {
+        let (name, key_hash, enabled, expires_at, models, endpoints) = row?;
+
+        let expires_at = match expires_at.filter(|s| !s.is_empty()) {
+            Some(s) => Some(
+                DateTime::parse_from_rfc3339(&s)
+                    .with_context(|| format!("Invalid expires_at for key '{}': {}", name, s))?
+                    .with_timezone(&Utc),
+            ),
+            None => None,
+        };
+
+        let allowed_models: Vec<String> = serde_json::from_str(&models)
+            .with_context(|| format!("Invalid allowed_models for key '{}'", name))?;
+        let allowed_endpoints: Vec<String> = serde_json::from_str(&endpoints)
+            .with_context(|| format!("Invalid allowed_endpoints for key '{}'", name))?;
+
+        keys.push(ClientKey {
+            name,
+            key_hash,
+            enabled,
+            expires_at,
+            allowed_models,
+            allowed_endpoints,
+        });
+    }
+
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str, secret: &str) -> ClientKey {
+        ClientKey {
+            name: name.to_string(),
+            key_hash: hash_key(secret),
+            enabled: true,
+            expires_at: None,
+            allowed_models: vec![],
+            allowed_endpoints: vec![],
+        }
+    }
+
+    #[test]
+    fn test_authenticate_known_key() {
+        let store = KeyStore::from_keys(vec![key("alice", "alice-key"), key("ci", "ci-key")]);
+
+        let identity = store.authenticate("ci-key").unwrap();
+        assert_eq!(identity.name, "ci");
+
+        assert_eq!(store.authenticate("nope"), Err(KeyRejection::Unknown));
+    }
+
+    #[test]
+    fn test_authenticate_disabled_and_expired() {
+        let mut disabled = key("old", "old-key");
+        disabled.enabled = false;
+
+        let mut expired = key("temp", "temp-key");
+        expired.expires_at = Some(Utc::now() - chrono::Duration::minutes(5));
+
+        let store = KeyStore::from_keys(vec![disabled, expired]);
+
+        assert_eq!(
+            store.authenticate("old-key"),
+            Err(KeyRejection::Disabled("old".to_string()))
+        );
+        assert_eq!(
+            store.authenticate("temp-key"),
+            Err(KeyRejection::Expired("temp".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_plain_hex_hash_is_accepted() {
+        let mut k = key("plain", "plain-key");
+        k.key_hash = k.key_hash.trim_start_matches("sha256:").to_uppercase();
+        let store = KeyStore::from_keys(vec![k]);
+
+        assert_eq!(store.authenticate("plain-key").unwrap().name, "plain");
+    }
+
+    #[test]
+    fn test_load_from_json_file() {
+        let dir = std::env::temp_dir().join(format!("kiro-keys-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("keys.json");
+
+        let content = serde_json::json!({
+            "keys": [
+                {
+                    "name": "alice",
+                    "key_hash": hash_key("alice-key"),
+                    "allowed_models": ["claude-sonnet-*"],
+                    "allowed_endpoints": ["/v1/messages"]
+                },
+                {
+                    "name": "bob",
+                    "key_hash": hash_key("bob-key"),
+                    "enabled": false
+                }
+            ]
+        });
+        std::fs::write(&path, content.to_string()).unwrap();
+
+        let store = KeyStore::load(&path).unwrap();
+        assert_eq!(store.len(), 2);
+
+        let alice = store.authenticate("alice-key").unwrap();
+        assert_eq!(alice.allowed_models, vec!["claude-sonnet-*"]);
+        assert_eq!(alice.allowed_endpoints, vec!["/v1/messages"]);
+        assert!(store.authenticate("bob-key").is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_load_from_sqlite() {
+        let dir = std::env::temp_dir().join(format!("kiro-keys-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("keys.sqlite3");
+
+        // First load creates the table
+        let store = KeyStore::load(&path).unwrap();
+        assert!(store.is_empty());
+
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute(
+            "INSERT INTO api_keys (name, key_hash, expires_at, allowed_models)
+             VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![
+                "ci",
+                hash_key("ci-key"),
+                "2099-01-01T00:00:00Z",
+                r#"["claude-haiku-4.5"]"#
+            ],
+        )
+        .unwrap();
+
+        let store = KeyStore::load(&path).unwrap();
+        assert_eq!(store.len(), 1);
+        let identity = store.authenticate("ci-key").unwrap();
+        assert_eq!(identity.name, "ci");
+        assert_eq!(identity.allowed_models, vec!["claude-haiku-4.5"]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,772 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::{Mutex, RwLock};
+use subtle::ConstantTimeEq;
+
+use super::{hash_key, is_reserved_name, normalize_hash, ClientIdentity, KeyRejection};
+
+/// Prefix of issued virtual keys (makes them recognizable in logs and configs)
+const VIRTUAL_KEY_PREFIX: &str = "vk-";
+
+/// Prefix of the accounting ID of virtual keys (`vk:<id>`)
+pub(super) const VIRTUAL_KEY_ID_PREFIX: &str = "vk:";
+
+/// TTL used when a request does not specify one (1 hour)
+pub const DEFAULT_VIRTUAL_KEY_TTL_SECS: u64 = 3600;
+
+/// Longest TTL a virtual key may be issued with (1 year)
+pub const MAX_VIRTUAL_KEY_TTL_SECS: u64 = 365 * 24 * 3600;
+
+/// Parameters for issuing a virtual key (POST /admin/keys body)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NewVirtualKey {
+    /// Name used for attribution; defaults to `vk-<id>`
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Lifetime in seconds
+    #[serde(default)]
+    pub ttl_seconds: Option<u64>,
+
+    /// Allowed models (resolved model IDs, `*` suffix wildcard). Empty = all
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+
+    /// Allowed endpoint paths (`*` suffix wildcard). Empty = all
+    #[serde(default)]
+    pub allowed_endpoints: Vec<String>,
+
+    /// Maximum number of requests over the key's lifetime
+    #[serde(default)]
+    pub max_requests: Option<u64>,
+
+    /// Maximum input + output tokens over the key's lifetime
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+
+    /// Free-form metadata labels (e.g. `{"ci_run": "1234"}`)
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl NewVirtualKey {
+    /// Validate the requested parameters
+    pub fn validate(&self) -> Result<(), String> {
+        match self.ttl_seconds {
+            Some(0) => return Err("ttl_seconds must be greater than 0".to_string()),
+            Some(ttl) if ttl > MAX_VIRTUAL_KEY_TTL_SECS => {
+                return Err(format!(
+                    "ttl_seconds must be at most {}",
+                    MAX_VIRTUAL_KEY_TTL_SECS
+                ))
+            }
+            _ => {}
+        }
+        if self.max_requests == Some(0) || self.max_tokens == Some(0) {
+            return Err("max_requests and max_tokens must be greater than 0".to_string());
+        }
+        if let Some(name) = self.name.as_deref().filter(|n| is_reserved_name(n)) {
+            return Err(format!("Virtual key name '{}' is reserved", name));
+        }
+        Ok(())
+    }
+}
+
+/// An issued virtual key (never includes the raw key)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VirtualKey {
+    pub id: String,
+    pub name: String,
+    #[serde(skip)]
+    pub key_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub allowed_models: Vec<String>,
+    pub allowed_endpoints: Vec<String>,
+    pub max_requests: Option<u64>,
+    pub max_tokens: Option<u64>,
+    pub requests_used: u64,
+    pub tokens_used: u64,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl VirtualKey {
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now >= self.expires_at
+    }
+
+    fn identity(&self) -> ClientIdentity {
+        ClientIdentity {
+            id: format!("{}{}", VIRTUAL_KEY_ID_PREFIX, self.id),
+            name: self.name.clone(),
+            allowed_models: self.allowed_models.clone(),
+            allowed_endpoints: self.allowed_endpoints.clone(),
+            rate_limit_tier: None,
+        }
+    }
+}
+
+/// Store of short-lived keys issued through the admin API
+///
+/// Keys are persisted in SQLite so they survive restarts. Disabled (rejects
+/// nothing, issues nothing) unless `VIRTUAL_KEYS_DB_FILE` is configured.
+/// Usage counters are kept in memory and written by `flush_counters`.
+pub struct VirtualKeyStore {
+    conn: Option<Mutex<Connection>>,
+    keys: RwLock<Vec<VirtualKey>>,
+    /// Static key names that virtual keys may not take
+    reserved_names: HashSet<String>,
+    /// IDs of keys whose counters changed since the last flush
+    dirty: Mutex<HashSet<String>>,
+}
+
+impl VirtualKeyStore {
+    /// Store that holds no keys and cannot issue any
+    pub fn disabled() -> Self {
+        Self {
+            conn: None,
+            keys: RwLock::new(Vec::new()),
+            reserved_names: HashSet::new(),
+            dirty: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Open (or create) the virtual key database
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open virtual keys database: {}", path.display()))?;
+        Self::init(conn)
+    }
+
+    /// In-memory store (for tests)
+    #[cfg(any(test, feature = "test-utils"))]
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS virtual_keys (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                key_hash TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                expires_at TEXT NOT NULL,
+                allowed_models TEXT NOT NULL DEFAULT '[]',
+                allowed_endpoints TEXT NOT NULL DEFAULT '[]',
+                max_requests INTEGER,
+                max_tokens INTEGER,
+                requests_used INTEGER NOT NULL DEFAULT 0,
+                tokens_used INTEGER NOT NULL DEFAULT 0,
+                labels TEXT NOT NULL DEFAULT '{}'
+            )",
+            [],
+        )
+        .context("Failed to create virtual_keys table")?;
+
+        // Stores created before names were unique: suffix later duplicates with their ID
+        conn.execute_batch(
+            "UPDATE virtual_keys SET name = name || '-' || id
+             WHERE rowid NOT IN (SELECT MIN(rowid) FROM virtual_keys GROUP BY name);
+             CREATE UNIQUE INDEX IF NOT EXISTS idx_virtual_keys_name ON virtual_keys (name);",
+        )
+        .context("Failed to migrate virtual_keys table")?;
+
+        let keys = load_keys(&conn)?;
+
+        Ok(Self {
+            conn: Some(Mutex::new(conn)),
+            keys: RwLock::new(keys),
+            reserved_names: HashSet::new(),
+            dirty: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Reserve the names of static client keys
+    pub fn with_reserved_names(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.reserved_names = names.into_iter().collect();
+        self
+    }
+
+    /// Check whether keys can be issued
+    pub fn is_enabled(&self) -> bool {
+        self.conn.is_some()
+    }
+
+    /// Number of live keys
+    pub fn live_count(&self) -> usize {
+        self.keys.read().map(|k| k.len()).unwrap_or(0)
+    }
+
+    /// Check whether a name is reserved or taken by a live key
+    pub fn name_in_use(&self, name: &str) -> bool {
+        is_reserved_name(name)
+            || self.reserved_names.contains(name)
+            || self
+                .keys
+                .read()
+                .map(|keys| keys.iter().any(|k| k.name == name))
+                .unwrap_or(true)
+    }
+
+    /// Issue a new key; returns the stored key and the raw secret (shown once)
+    pub fn issue(&self, params: NewVirtualKey) -> Result<(VirtualKey, String)> {
+        self.issue_at(params, Utc::now())
+    }
+
+    fn issue_at(&self, params: NewVirtualKey, now: DateTime<Utc>) -> Result<(VirtualKey, String)> {
+        let Some(ref conn) = self.conn else {
+            anyhow::bail!("Virtual keys are disabled (set VIRTUAL_KEYS_DB_FILE)");
+        };
+
+        if let Err(e) = params.validate() {
+            anyhow::bail!(e);
+        }
+        let ttl = params
+            .ttl_seconds
+            .unwrap_or(DEFAULT_VIRTUAL_KEY_TTL_SECS)
+            .min(MAX_VIRTUAL_KEY_TTL_SECS);
+        let ttl = Duration::seconds(ttl as i64);
+
+        let id = uuid::Uuid::new_v4().simple().to_string()[..12].to_string();
+        let secret = format!("{}{}", VIRTUAL_KEY_PREFIX, uuid::Uuid::new_v4().simple());
+        let key = VirtualKey {
+            name: params
+                .name
+                .filter(|n| !n.is_empty())
+                .unwrap_or_else(|| format!("vk-{}", id)),
+            id,
+            key_hash: hash_key(&secret),
+            created_at: now,
+            expires_at: now + ttl,
+            allowed_models: params.allowed_models,
+            allowed_endpoints: params.allowed_endpoints,
+            max_requests: params.max_requests,
+            max_tokens: params.max_tokens,
+            requests_used: 0,
+            tokens_used: 0,
+            labels: params.labels,
+        };
+
+        // Held across the insert so two requests cannot take the same name
+        let mut keys = self
+            .keys
+            .write()
+            .map_err(|_| anyhow::anyhow!("Virtual keys lock poisoned"))?;
+        if is_reserved_name(&key.name)
+            || self.reserved_names.contains(&key.name)
+            || keys.iter().any(|k| k.name == key.name)
+        {
+            anyhow::bail!("Virtual key name '{}' is already in use", key.name);
+        }
+
+        {
+            let conn = conn
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Virtual keys database lock poisoned"))?;
+            conn.execute(
+                "INSERT INTO virtual_keys
+                    (id, name, key_hash, created_at, expires_at, allowed_models,
+                     allowed_endpoints, max_requests, max_tokens, labels)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                rusqlite::params![
+                    key.id,
+                    key.name,
+                    key.key_hash,
+                    timestamp(key.created_at),
+                    timestamp(key.expires_at),
+                    serde_json::to_string(&key.allowed_models)?,
+                    serde_json::to_string(&key.allowed_endpoints)?,
+                    key.max_requests.map(|v| v as i64),
+                    key.max_tokens.map(|v| v as i64),
+                    serde_json::to_string(&key.labels)?,
+                ],
+            )?;
+        }
+
+        keys.push(key.clone());
+        Ok((key, secret))
+    }
+
+    /// All live keys, oldest first
+    pub fn list(&self) -> Vec<VirtualKey> {
+        let mut keys = self.keys.read().map(|k| k.clone()).unwrap_or_default();
+        keys.sort_by_key(|k| k.created_at);
+        keys
+    }
+
+    /// Revoke a key by ID; returns whether it existed
+    pub fn revoke(&self, id: &str) -> Result<bool> {
+        let Some(ref conn) = self.conn else {
+            anyhow::bail!("Virtual keys are disabled (set VIRTUAL_KEYS_DB_FILE)");
+        };
+
+        let removed = {
+            let conn = conn
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Virtual keys database lock poisoned"))?;
+            conn.execute("DELETE FROM virtual_keys WHERE id = ?1", [id])?
+        };
+
+        if let Ok(mut keys) = self.keys.write() {
+            keys.retain(|k| k.id != id);
+        }
+        Ok(removed > 0)
+    }
+
+    /// Delete expired keys; returns how many were removed
+    pub fn cleanup_expired(&self) -> usize {
+        self.cleanup_expired_at(Utc::now())
+    }
+
+    fn cleanup_expired_at(&self, now: DateTime<Utc>) -> usize {
+        let Some(ref conn) = self.conn else {
+            return 0;
+        };
+
+        let removed = match conn.lock() {
+            Ok(conn) => conn
+                .execute(
+                    "DELETE FROM virtual_keys WHERE expires_at <= ?1",
+                    [timestamp(now)],
+                )
+                .unwrap_or_else(|e| {
+                    tracing::warn!("Failed to delete expired virtual keys: {}", e);
+                    0
+                }),
+            Err(_) => 0,
+        };
+
+        if let Ok(mut keys) = self.keys.write() {
+            keys.retain(|k| !k.is_expired(now));
+        }
+        removed
+    }
+
+    /// Authenticate a presented key
+    ///
+    /// Every stored hash is compared (no early exit), as in `KeyStore`. The
+    /// request is counted later, by `record_request`, once it is authorized.
+    pub fn authenticate(&self, presented: &str) -> Result<ClientIdentity, KeyRejection> {
+        self.authenticate_at(presented, Utc::now())
+    }
+
+    fn authenticate_at(
+        &self,
+        presented: &str,
+        now: DateTime<Utc>,
+    ) -> Result<ClientIdentity, KeyRejection> {
+        if !presented.starts_with(VIRTUAL_KEY_PREFIX) {
+            return Err(KeyRejection::Unknown);
+        }
+
+        let presented_hash = normalize_hash(&hash_key(presented));
+        let keys = self.keys.read().map_err(|_| KeyRejection::Unknown)?;
+
+        let mut matched: Option<&VirtualKey> = None;
+        for key in keys.iter() {
+            let stored_hash = normalize_hash(&key.key_hash);
+            let equal: bool = stored_hash
+                .as_bytes()
+                .ct_eq(presented_hash.as_bytes())
+                .into();
+            if equal && matched.is_none() {
+                matched = Some(key);
+            }
+        }
+
+        let key = matched.ok_or(KeyRejection::Unknown)?;
+        if key.is_expired(now) {
+            return Err(KeyRejection::Expired(key.name.clone()));
+        }
+        check_caps(key)?;
+        Ok(key.identity())
+    }
+
+    /// Count an authorized request against its key's cap (no-op for other identities)
+    ///
+    /// Caps are checked again here so concurrent requests cannot overshoot them.
+    pub fn record_request(&self, identity_id: &str) -> Result<(), KeyRejection> {
+        let Some(id) = identity_id.strip_prefix(VIRTUAL_KEY_ID_PREFIX) else {
+            return Ok(());
+        };
+        let Ok(mut keys) = self.keys.write() else {
+            return Ok(());
+        };
+        let Some(key) = keys.iter_mut().find(|k| k.id == id) else {
+            return Ok(());
+        };
+
+        check_caps(key)?;
+        key.requests_used += 1;
+        self.mark_dirty(id);
+        Ok(())
+    }
+
+    /// Count tokens used by a completed request (no-op for other identities)
+    pub fn record_tokens(&self, identity_id: &str, tokens: u64) {
+        let Some(id) = identity_id.strip_prefix(VIRTUAL_KEY_ID_PREFIX) else {
+            return;
+        };
+        if tokens == 0 {
+            return;
+        }
+        let Ok(mut keys) = self.keys.write() else {
+            return;
+        };
+        if let Some(key) = keys.iter_mut().find(|k| k.id == id) {
+            key.tokens_used += tokens;
+            self.mark_dirty(id);
+        }
+    }
+
+    fn mark_dirty(&self, id: &str) {
+        if let Ok(mut dirty) = self.dirty.lock() {
+            dirty.insert(id.to_string());
+        }
+    }
+
+    /// Write changed usage counters to the database; returns how many keys were written
+    ///
+    /// Called periodically off the request path.
+    pub fn flush_counters(&self) -> usize {
+        let Some(ref conn) = self.conn else {
+            return 0;
+        };
+        let dirty = match self.dirty.lock() {
+            Ok(mut dirty) => std::mem::take(&mut *dirty),
+            Err(_) => return 0,
+        };
+        if dirty.is_empty() {
+            return 0;
+        }
+
+        let counters: Vec<(String, u64, u64)> = match self.keys.read() {
+            Ok(keys) => keys
+                .iter()
+                .filter(|k| dirty.contains(&k.id))
+                .map(|k| (k.id.clone(), k.requests_used, k.tokens_used))
+                .collect(),
+            Err(_) => return 0,
+        };
+
+        let Ok(mut conn) = conn.lock() else {
+            return 0;
+        };
+        let result = (|| -> rusqlite::Result<()> {
+            let tx = conn.transaction()?;
+            for (id, requests_used, tokens_used) in &counters {
+                tx.execute(
+                    "UPDATE virtual_keys SET requests_used = ?1, tokens_used = ?2 WHERE id = ?3",
+                    rusqlite::params![*requests_used as i64, *tokens_used as i64, id],
+                )?;
+            }
+            tx.commit()
+        })();
+
+        match result {
+            Ok(()) => counters.len(),
+            Err(e) => {
+                tracing::warn!("Failed to update virtual key usage: {}", e);
+                // Retry on the next flush
+                if let Ok(mut pending) = self.dirty.lock() {
+                    pending.extend(dirty);
+                }
+                0
+            }
+        }
+    }
+}
+
+impl Drop for VirtualKeyStore {
+    fn drop(&mut self) {
+        self.flush_counters();
+    }
+}
+
+/// Reject keys that have used up their request or token cap
+fn check_caps(key: &VirtualKey) -> Result<(), KeyRejection> {
+    if key.max_requests.is_some_and(|max| key.requests_used >= max)
+        || key.max_tokens.is_some_and(|max| key.tokens_used >= max)
+    {
+        return Err(KeyRejection::CapReached(key.name.clone()));
+    }
+    Ok(())
+}
+
+/// Fixed-width UTC timestamp, so stored values compare correctly as text
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Load live keys from the database
+fn load_keys(conn: &Connection) -> Result<Vec<VirtualKey>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, key_hash, created_at, expires_at, allowed_models, allowed_endpoints,
+                max_requests, max_tokens, requests_used, tokens_used, labels
+         FROM virtual_keys",
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, String>(5)?,
+            row.get::<_, String>(6)?,
+            row.get::<_, Option<i64>>(7)?,
+            row.get::<_, Option<i64>>(8)?,
+            row.get::<_, i64>(9)?,
+            row.get::<_, i64>(10)?,
+            row.get::<_, String>(11)?,
+        ))
+    })?;
+
+    let parse_time = |id: &str, s: &str| -> Result<DateTime<Utc>> {
+        Ok(DateTime::parse_from_rfc3339(s)
+            .with_context(|| format!("Invalid timestamp for virtual key '{}': {}", id, s))?
+            .with_timezone(&Utc))
+    };
+
+    let mut keys = Vec::new();
+    for row in rows {
+        let (
+            id,
+            name,
+            key_hash,
+            created_at,
+            expires_at,
+            models,
+            endpoints,
+            max_requests,
+            max_tokens,
+            requests_used,
+            tokens_used,
+            labels,
+        ) = row?;
+
+        keys.push(VirtualKey {
+            created_at: parse_time(&id, &created_at)?,
+            expires_at: parse_time(&id, &expires_at)?,
+            allowed_models: serde_json::from_str(&models)
+                .with_context(|| format!("Invalid allowed_models for virtual key '{}'", id))?,
+            allowed_endpoints: serde_json::from_str(&endpoints)
+                .with_context(|| format!("Invalid allowed_endpoints for virtual key '{}'", id))?,
+            max_requests: max_requests.map(|v| v as u64),
+            max_tokens: max_tokens.map(|v| v as u64),
+            requests_used: requests_used as u64,
+            tokens_used: tokens_used as u64,
+            labels: serde_json::from_str(&labels)
+                .with_context(|| format!("Invalid labels for virtual key '{}'", id))?,
+            id,
+            name,
+            key_hash,
+        });
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> NewVirtualKey {
+        NewVirtualKey {
+            name: Some("ci-run-42".to_string()),
+            ttl_seconds: Some(600),
+            allowed_models: vec!["claude-haiku-*".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_issue_and_authenticate() {
+        let store = VirtualKeyStore::open_in_memory().unwrap();
+        let (key, secret) = store.issue(params()).unwrap();
+
+        assert!(secret.starts_with(VIRTUAL_KEY_PREFIX));
+        assert_eq!(key.name, "ci-run-42");
+        assert_eq!(key.expires_at - key.created_at, Duration::seconds(600));
+
+        let identity = store.authenticate(&secret).unwrap();
+        assert_eq!(identity.name, "ci-run-42");
+        assert_eq!(identity.id, format!("vk:{}", key.id));
+        assert!(identity.allows_model("claude-haiku-4.5"));
+        assert!(!identity.allows_model("claude-opus-4.5"));
+
+        assert_eq!(store.authenticate("vk-unknown"), Err(KeyRejection::Unknown));
+
+        // Only authorized requests are counted
+        assert_eq!(store.list()[0].requests_used, 0);
+        store.record_request(&identity.id).unwrap();
+        assert_eq!(store.list()[0].requests_used, 1);
+    }
+
+    #[test]
+    fn test_request_and_token_caps() {
+        let store = VirtualKeyStore::open_in_memory().unwrap();
+        let (_, limited) = store
+            .issue(NewVirtualKey {
+                name: Some("two-requests".to_string()),
+                max_requests: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+        let (_, metered) = store
+            .issue(NewVirtualKey {
+                name: Some("hundred-tokens".to_string()),
+                max_tokens: Some(100),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let limited_id = store.authenticate(&limited).unwrap().id;
+        store.record_request(&limited_id).unwrap();
+        store.record_request(&limited_id).unwrap();
+        assert_eq!(
+            store.record_request(&limited_id),
+            Err(KeyRejection::CapReached("two-requests".to_string()))
+        );
+        assert_eq!(
+            store.authenticate(&limited),
+            Err(KeyRejection::CapReached("two-requests".to_string()))
+        );
+
+        let metered_id = store.authenticate(&metered).unwrap().id;
+        store.record_tokens(&metered_id, 60);
+        assert!(store.authenticate(&metered).is_ok());
+        store.record_tokens(&metered_id, 40);
+        assert_eq!(
+            store.authenticate(&metered),
+            Err(KeyRejection::CapReached("hundred-tokens".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_expiry_and_cleanup() {
+        let store = VirtualKeyStore::open_in_memory().unwrap();
+        let now = Utc::now();
+        let (_, secret) = store.issue_at(params(), now).unwrap();
+
+        let later = now + Duration::seconds(601);
+        assert_eq!(
+            store.authenticate_at(&secret, later),
+            Err(KeyRejection::Expired("ci-run-42".to_string()))
+        );
+
+        assert_eq!(store.cleanup_expired_at(now), 0);
+        assert_eq!(store.cleanup_expired_at(later), 1);
+        assert_eq!(store.live_count(), 0);
+        assert_eq!(store.authenticate(&secret), Err(KeyRejection::Unknown));
+    }
+
+    #[test]
+    fn test_keys_persist_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("kiro-vkeys-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("virtual_keys.db");
+
+        let (key, secret) = {
+            let store = VirtualKeyStore::open(&path).unwrap();
+            let mut p = params();
+            p.labels
+                .insert("pipeline".to_string(), "nightly".to_string());
+            p.max_requests = Some(10);
+            let issued = store.issue(p).unwrap();
+            let identity = store.authenticate(&issued.1).unwrap();
+            store.record_request(&identity.id).unwrap();
+            // Dropping the store flushes its counters
+            issued
+        };
+
+        let store = VirtualKeyStore::open(&path).unwrap();
+        let listed = store.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, key.id);
+        assert_eq!(listed[0].labels["pipeline"], "nightly");
+        assert_eq!(listed[0].requests_used, 1);
+        assert!(store.authenticate(&secret).is_ok());
+
+        assert!(store.revoke(&key.id).unwrap());
+        assert!(!store.revoke(&key.id).unwrap());
+        assert!(VirtualKeyStore::open(&path).unwrap().list().is_empty());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_names_are_unique_and_reserved_names_rejected() {
+        let store = VirtualKeyStore::open_in_memory()
+            .unwrap()
+            .with_reserved_names(vec!["ci".to_string()]);
+        store.issue(params()).unwrap();
+
+        for name in [
+            "ci-run-42",
+            "ci",
+            "default",
+            "jwt:alice",
+            "mtls:runner",
+            "vk:abc",
+        ] {
+            assert!(store.name_in_use(name), "{}", name);
+            let result = store.issue(NewVirtualKey {
+                name: Some(name.to_string()),
+                ..Default::default()
+            });
+            assert!(result.is_err(), "{}", name);
+        }
+        assert!(!store.name_in_use("ci-run-43"));
+
+        // Usage for a same-named identity never lands on the virtual key
+        store.record_tokens("ci-run-42", 50);
+        assert_eq!(store.list()[0].tokens_used, 0);
+    }
+
+    #[test]
+    fn test_validate_params() {
+        assert!(params().validate().is_ok());
+        assert!(NewVirtualKey {
+            ttl_seconds: Some(0),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(NewVirtualKey {
+            ttl_seconds: Some(MAX_VIRTUAL_KEY_TTL_SECS + 1),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(NewVirtualKey {
+            max_tokens: Some(0),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(NewVirtualKey {
+            name: Some("jwt:alice".to_string()),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_disabled_store() {
+        let store = VirtualKeyStore::disabled();
+        assert!(!store.is_enabled());
+        assert!(store.issue(params()).is_err());
+        assert_eq!(
+            store.authenticate("vk-anything"),
+            Err(KeyRejection::Unknown)
+        );
+    }
+}
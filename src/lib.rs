@@ -1,18 +1,36 @@
+pub mod adaptive_limit;
+pub mod admission;
 pub mod auth;
+pub mod backends;
 pub mod cache;
+pub mod catalog;
+pub mod circuit_breaker;
+pub mod coalescing;
 pub mod config;
 pub mod converters;
 pub mod dashboard;
+pub mod documents;
+pub mod egress;
+pub mod endpoints;
 pub mod error;
+pub mod hedging;
 pub mod http_client;
+pub mod image_fetch;
+pub mod keys;
 pub mod metrics;
 pub mod middleware;
 pub mod models;
+pub mod prompt_cache;
+pub mod regions;
 pub mod resolver;
+pub mod response_cache;
+pub mod retry;
 pub mod routes;
 pub mod streaming;
 pub mod thinking_parser;
+pub mod tls;
 pub mod tokenizer;
+pub mod usage;
 pub mod utils;
 
 #[cfg(feature = "bench")]
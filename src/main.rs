@@ -1,23 +1,41 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::collections::VecDeque;
 use std::io;
 use std::sync::{Arc, Mutex};
 
+mod adaptive_limit;
+mod admission;
 mod auth;
+mod backends;
 mod cache;
+mod catalog;
+mod circuit_breaker;
+mod coalescing;
 mod config;
 mod converters;
 mod dashboard;
+mod documents;
+mod egress;
+mod endpoints;
 mod error;
+mod hedging;
 mod http_client;
+mod image_fetch;
+mod keys;
 mod metrics;
 mod middleware;
 mod models;
+mod prompt_cache;
+mod regions;
 mod resolver;
+mod response_cache;
+mod retry;
 mod routes;
 mod streaming;
 mod thinking_parser;
+mod tls;
 mod tokenizer;
+mod usage;
 mod utils;
 
 #[tokio::main]
@@ -70,12 +88,98 @@ async fn main() -> Result<()> {
     );
     tracing::debug!("Debug mode: {:?}", config.debug_mode);
 
+    // Resolve upstream endpoints (production URLs unless overridden)
+    let endpoints = Arc::new(endpoints::EndpointResolver::from_config(&config)?);
+
+    // Outbound proxy and trust roots, shared by the API and token refresh clients
+    let egress = egress::EgressConfig::from_config(&config)?;
+    if let Some(proxy) = egress.proxy_display() {
+        tracing::info!("Using outbound proxy: {}", proxy);
+    }
+    if egress.ca_cert_count() > 0 {
+        tracing::info!(
+            "Trusting {} extra CA certificate(s) for upstream TLS",
+            egress.ca_cert_count()
+        );
+    }
+
     // Initialize authentication manager
     tracing::info!("Initializing authentication...");
-    let auth_manager = Arc::new(auth::AuthManager::new(
-        config.kiro_cli_db_file.clone(),
-        config.token_refresh_threshold,
-    )?);
+    let auth_manager = Arc::new(
+        auth::AuthManager::new(
+            config.kiro_cli_db_file.clone(),
+            config.token_refresh_threshold,
+            &egress,
+        )?
+        .with_endpoints(endpoints.clone())
+        .with_region(config.kiro_region.clone()),
+    );
+
+    let metrics = Arc::new(metrics::MetricsCollector::new());
+    tracing::info!("✅ Metrics collector initialized");
+
+    // Initialize HTTP client
+    let http_client = Arc::new(
+        http_client::KiroHttpClient::new(
+            auth_manager.clone(),
+            config.http_max_connections,
+            config.http_connect_timeout,
+            config.http_request_timeout,
+            config.http_max_retries,
+            &egress,
+        )?
+        .with_endpoints(endpoints)
+        .with_regions(regions::RegionRouter::new(
+            std::iter::once(config.kiro_region.clone())
+                .chain(config.kiro_failover_regions.iter().cloned())
+                .collect(),
+            std::time::Duration::from_secs(config.region_sticky_ttl),
+        ))
+        .with_retry_budget(
+            config.http_retry_budget_ratio,
+            config.http_retry_budget_min_per_sec,
+        )
+        .with_circuit_breaker(circuit_breaker::BreakerConfig {
+            consecutive_failures: config.circuit_breaker_failures,
+            error_rate: config.circuit_breaker_error_rate,
+            min_requests: config.circuit_breaker_min_requests,
+            open_duration: std::time::Duration::from_secs(config.circuit_breaker_open_secs),
+        })
+        .with_hedging(hedging::HedgeConfig {
+            models: config.hedge_models.clone(),
+            percentile: config.hedge_percentile,
+            min_delay: std::time::Duration::from_millis(config.hedge_min_delay_ms),
+            per_key_per_minute: config.hedge_budget_per_min,
+        })
+        .with_admission(admission::AdmissionConfig {
+            max_in_flight: config.upstream_max_in_flight,
+            adaptive: config
+                .adaptive_concurrency
+                .then_some(adaptive_limit::AdaptiveConfig {
+                    min_limit: config.adaptive_min_in_flight,
+                    max_limit: config.adaptive_max_in_flight,
+                    decrease_ratio: config.adaptive_decrease_ratio,
+                    latency_tolerance: config.adaptive_latency_tolerance,
+                }),
+            max_wait: std::time::Duration::from_millis(config.upstream_queue_max_wait_ms),
+            tier_priorities: config.upstream_tier_priorities.clone(),
+        })
+        .with_metrics(metrics.clone()),
+    );
+    tracing::info!("✅ HTTP client initialized with connection pooling");
+
+    // Verify overridden upstream endpoints before anything talks to them
+    match http_client
+        .verify_endpoints(&auth_manager.get_region().await)
+        .await
+    {
+        Ok(urls) => {
+            for url in urls {
+                tracing::info!("✅ Upstream endpoint reachable: {}", url);
+            }
+        }
+        Err(e) => anyhow::bail!("Startup failed: {}", e),
+    }
 
     // Test authentication by getting a token
     match auth_manager.get_access_token().await {
@@ -93,24 +197,92 @@ async fn main() -> Result<()> {
         }
     }
 
-    // Initialize HTTP client
-    let http_client = Arc::new(http_client::KiroHttpClient::new(
-        auth_manager.clone(),
-        config.http_max_connections,
-        config.http_connect_timeout,
-        config.http_request_timeout,
-        config.http_max_retries,
-    )?);
-    tracing::info!("✅ HTTP client initialized with connection pooling");
+    // List profiles and exit (--list-profiles)
+    if config.list_profiles {
+        let profiles = auth_manager.list_profiles().await?;
+        if profiles.is_empty() {
+            println!("No CodeWhisperer profiles available for this account.");
+        } else {
+            println!("Available CodeWhisperer profiles:");
+            for profile in &profiles {
+                println!(
+                    "  {}  {}",
+                    profile.profile_name.as_deref().unwrap_or("-"),
+                    profile.arn
+                );
+            }
+            println!();
+            println!("Select one with KIRO_PROFILE=<name or ARN> or --profile <name or ARN>");
+        }
+        return Ok(());
+    }
+
+    // Resolve the profile ARN attached to Kiro API requests
+    match auth_manager
+        .initialize_profile(config.kiro_profile.as_deref())
+        .await
+    {
+        Ok(Some(arn)) => tracing::info!("✅ Using profile: {}", arn),
+        Ok(None) => tracing::info!("No profile ARN in use"),
+        Err(e)
+            if config.kiro_profile.is_some()
+                && (!config.degraded_startup || e.is::<auth::ProfileNotFound>()) =>
+        {
+            anyhow::bail!("Failed to select configured profile: {}", e);
+        }
+        Err(e) => {
+            tracing::warn!(
+                "⚠️  Failed to resolve profile ARN, retrying in the background: {}",
+                e
+            );
+            auth::spawn_profile_task(Arc::clone(&auth_manager), config.kiro_profile.clone());
+        }
+    }
 
     // Initialize model cache
     tracing::info!("Initializing model cache...");
-    let model_cache = cache::ModelCache::new(3600); // 1 hour TTL
+    let model_cache = cache::ModelCache::new(config.model_cache_ttl);
 
-    // Load models from Kiro API at startup - fail fast on errors
+    // Load models from Kiro API at startup - fail fast on errors unless
+    // DEGRADED_STARTUP allows serving a snapshot or the hidden models
     tracing::info!("Loading models from Kiro API...");
-    let models = match load_models_from_kiro(&http_client, &auth_manager, &config).await {
-        Ok(models) => models,
+    let snapshot_file = config.model_snapshot_file.as_deref();
+    match catalog::fetch_models(&http_client, &auth_manager).await {
+        Ok(models) => {
+            tracing::info!("📊 Models from Kiro API:");
+            for model in &models {
+                tracing::info!(
+                    "{}",
+                    serde_json::to_string_pretty(model).unwrap_or_default()
+                );
+            }
+
+            catalog::apply(&model_cache, models, snapshot_file);
+            tracing::info!(
+                "✅ Loaded {} models from Kiro API",
+                model_cache.get_all_model_ids().len()
+            );
+        }
+        Err(e) if config.degraded_startup => {
+            tracing::warn!("⚠️  Failed to load models from Kiro API: {}", e);
+            match snapshot_file.map(catalog::load_snapshot) {
+                Some(Ok(models)) => {
+                    model_cache.restore(models);
+                    tracing::warn!(
+                        "⚠️  Starting degraded with {} models from the last snapshot",
+                        model_cache.get_all_model_ids().len()
+                    );
+                }
+                Some(Err(e)) => {
+                    tracing::warn!(
+                        "⚠️  Starting degraded with hidden models only (no usable snapshot: {:#})",
+                        e
+                    );
+                }
+                None => tracing::warn!("⚠️  Starting degraded with hidden models only"),
+            }
+            tracing::warn!("Model loading will keep retrying in the background");
+        }
         Err(e) => {
             tracing::error!("❌ Failed to load models from Kiro API: {}", e);
             tracing::error!("");
@@ -122,47 +294,292 @@ async fn main() -> Result<()> {
             tracing::error!("      kiro-cli logout");
             tracing::error!("      kiro-cli login");
             tracing::error!("");
+            tracing::error!(
+                "   4. Or set DEGRADED_STARTUP=true to start serving while models load"
+            );
+            tracing::error!("");
             anyhow::bail!("Startup failed: Unable to connect to CodeWhisperer API");
         }
+    }
+
+    // Add hidden models to cache
+    catalog::add_hidden_models(&model_cache);
+    tracing::info!("✅ Added hidden models to cache");
+
+    // Pick up newly released models without a restart
+    catalog::spawn_refresh_task(
+        Arc::clone(&http_client),
+        Arc::clone(&auth_manager),
+        model_cache.clone(),
+        config.model_snapshot_file.clone(),
+    );
+    tracing::info!(
+        "✅ Model catalogue refresh scheduled every {}s",
+        config.model_cache_ttl
+    );
+
+    let resolver =
+        resolver::ModelResolver::new(model_cache.clone(), std::collections::HashMap::new());
+    tracing::info!("✅ Model resolver initialized");
+
+    let key_store = match config.api_keys_file {
+        Some(ref path) => Arc::new(
+            keys::KeyStore::load(path)
+                .with_context(|| format!("Failed to load API keys from {}", path.display()))?,
+        ),
+        None => Arc::new(keys::KeyStore::empty()),
+    };
+    if key_store.is_empty() {
+        tracing::info!("✅ Client key store initialized (PROXY_API_KEY only)");
+    } else {
+        tracing::info!("✅ Client key store initialized ({} keys)", key_store.len());
+    }
+
+    let virtual_keys = match config.virtual_keys_db_file {
+        Some(ref path) => {
+            let store = keys::VirtualKeyStore::open(path)
+                .with_context(|| format!("Failed to open virtual keys {}", path.display()))?
+                .with_reserved_names(key_store.names());
+            tracing::info!(
+                "✅ Virtual keys enabled ({} live keys in {})",
+                store.live_count(),
+                path.display()
+            );
+
+            let store = Arc::new(store);
+            let cleanup_store = Arc::clone(&store);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+                loop {
+                    interval.tick().await;
+                    let store = Arc::clone(&cleanup_store);
+                    let removed = tokio::task::spawn_blocking(move || store.cleanup_expired())
+                        .await
+                        .unwrap_or(0);
+                    if removed > 0 {
+                        tracing::info!("Removed {} expired virtual keys", removed);
+                    }
+                }
+            });
+
+            // Usage counters are kept in memory; persist them off the request path
+            let flush_store = Arc::clone(&store);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(5));
+                loop {
+                    interval.tick().await;
+                    let store = Arc::clone(&flush_store);
+                    let _ = tokio::task::spawn_blocking(move || store.flush_counters()).await;
+                }
+            });
+            store
+        }
+        None => Arc::new(keys::VirtualKeyStore::disabled()),
     };
 
-    tracing::info!("📊 Models from Kiro API:");
-    for model in &models {
+    let jwt_validator = keys::JwtValidator::from_config(&config)
+        .context("Failed to initialize JWT authentication")?
+        .map(Arc::new);
+    if let Some(ref jwt) = jwt_validator {
         tracing::info!(
-            "{}",
-            serde_json::to_string_pretty(model).unwrap_or_default()
+            "✅ JWT authentication enabled ({} verification keys, API key fallback: {})",
+            jwt.key_count(),
+            jwt.api_key_fallback()
         );
     }
 
-    model_cache.update(models);
-    tracing::info!(
-        "✅ Loaded {} models from Kiro API",
-        model_cache.get_all_model_ids().len()
+    let rate_limiter = Arc::new(
+        middleware::RateLimiter::new(middleware::RateLimits {
+            requests_per_minute: config.rate_limit_rpm,
+            input_tokens_per_minute: config.rate_limit_input_tpm,
+            concurrent_streams: config.rate_limit_concurrent_streams,
+        })
+        .with_tiers(
+            jwt_validator
+                .as_ref()
+                .map(|jwt| jwt.tiers())
+                .unwrap_or_default(),
+        ),
     );
+    if rate_limiter.is_enabled() {
+        tracing::info!(
+            "✅ Rate limiting enabled ({:?}): rpm={}, input_tpm={}, streams={}",
+            config.rate_limit_by,
+            config.rate_limit_rpm,
+            config.rate_limit_input_tpm,
+            config.rate_limit_concurrent_streams
+        );
 
-    // Add hidden models to cache
-    add_hidden_models(&model_cache);
-    tracing::info!("✅ Added hidden models to cache");
+        let cleanup_limiter = Arc::clone(&rate_limiter);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                cleanup_limiter.cleanup_idle();
+            }
+        });
+    }
 
-    let resolver =
-        resolver::ModelResolver::new(model_cache.clone(), std::collections::HashMap::new());
-    tracing::info!("✅ Model resolver initialized");
+    let usage_ledger = match config.usage_db_file {
+        Some(ref path) => {
+            let ledger = usage::UsageLedger::open(path)
+                .with_context(|| format!("Failed to open usage ledger {}", path.display()))?;
+            tracing::info!("✅ Usage ledger enabled ({})", path.display());
+            Arc::new(ledger)
+        }
+        None => Arc::new(usage::UsageLedger::disabled()),
+    };
 
-    let metrics = Arc::new(metrics::MetricsCollector::new());
-    tracing::info!("✅ Metrics collector initialized");
+    let network_policy = match config.network_policy_file {
+        Some(ref path) => {
+            let policy = middleware::NetworkPolicy::load(path)?;
+            tracing::info!("✅ Network policy loaded ({})", path.display());
+            Arc::new(policy)
+        }
+        None => Arc::new(middleware::NetworkPolicy::permissive()),
+    };
+
+    let shared_config = Arc::new(config.clone());
+    let kiro_backend: Arc<dyn backends::UpstreamBackend> = Arc::new(backends::KiroBackend::new(
+        Arc::clone(&http_client),
+        Arc::clone(&auth_manager),
+        Arc::clone(&shared_config),
+    ));
+    let backends = match config.backends_file {
+        Some(ref path) => {
+            let router = backends::BackendRouter::load(
+                path,
+                kiro_backend,
+                http_client.client().clone(),
+                std::time::Duration::from_secs(config.first_token_timeout),
+            )?;
+            tracing::info!("✅ Upstream backends loaded ({})", path.display());
+            Arc::new(router)
+        }
+        None => Arc::new(backends::BackendRouter::new(kiro_backend)),
+    };
+
+    let response_cache = if config.response_cache {
+        let mut cache = response_cache::ResponseCache::new(
+            std::time::Duration::from_secs(config.response_cache_ttl),
+            config.response_cache_max_entries,
+        )
+        .with_max_bytes(config.response_cache_max_bytes)
+        .with_keys(config.response_cache_keys.clone());
+        if let Some(ref path) = config.response_cache_db_file {
+            cache = cache.with_db(
+                path,
+                config.response_cache_db_max_entries,
+                config.response_cache_db_max_bytes,
+            )?;
+        }
+        tracing::info!(
+            "✅ Response cache enabled (ttl: {}s, {} entries in memory{})",
+            config.response_cache_ttl,
+            config.response_cache_max_entries,
+            config
+                .response_cache_db_file
+                .as_ref()
+                .map(|path| format!(", SQLite: {}", path.display()))
+                .unwrap_or_default()
+        );
+        Arc::new(cache)
+    } else {
+        Arc::new(response_cache::ResponseCache::disabled())
+    };
+
+    let coalescer = Arc::new(coalescing::Coalescer::new(config.coalesce_routes.clone()));
+    if !config.coalesce_routes.is_empty() {
+        tracing::info!(
+            "✅ Request coalescing enabled for {}",
+            config.coalesce_routes.join(", ")
+        );
+    }
+
+    let image_fetcher = Arc::new(image_fetch::ImageFetcher::from_config(&config, &egress)?);
+    if !config.image_fetch_allowed_hosts.is_empty() {
+        tracing::info!(
+            "Remote images limited to {}",
+            config.image_fetch_allowed_hosts.join(", ")
+        );
+    }
+
+    let prompt_cache = Arc::new(prompt_cache::PromptCache::from_config(&config));
+    if prompt_cache.is_enabled() {
+        tracing::info!(
+            "Prompt cache accounting enabled (ttl={}s, max_entries={})",
+            config.prompt_cache_ttl,
+            config.prompt_cache_max_entries
+        );
+    }
 
     let app_state = routes::AppState {
         proxy_api_key: config.proxy_api_key.clone(),
+        key_store,
+        virtual_keys,
+        network_policy,
+        jwt_validator,
+        rate_limiter,
         model_cache: model_cache.clone(),
         auth_manager: auth_manager.clone(),
         http_client: http_client.clone(),
+        backends,
+        response_cache,
+        coalescer,
+        image_fetcher,
+        prompt_cache,
         resolver,
-        config: Arc::new(config.clone()),
+        config: shared_config,
         metrics: Arc::clone(&metrics),
+        usage_ledger,
     };
 
     let app = build_app(app_state);
 
+    let tls_config = match (&config.tls_cert_file, &config.tls_key_file) {
+        (Some(cert), Some(key)) => {
+            let certs = Arc::new(
+                tls::CertReloader::new(cert, key).context("Failed to load TLS certificate")?,
+            );
+            let tls_config = tls::server_config(
+                Arc::clone(&certs),
+                config.tls_client_ca_file.as_deref(),
+                config.tls_client_cert_required,
+            )?;
+            match config.tls_client_ca_file {
+                Some(ref ca) => tracing::info!(
+                    "✅ TLS enabled with mTLS (client CA {}, client cert {})",
+                    ca.display(),
+                    if config.tls_client_cert_required {
+                        "required"
+                    } else {
+                        "optional"
+                    }
+                ),
+                None => tracing::info!("✅ TLS enabled ({})", cert.display()),
+            }
+
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(tls::RELOAD_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    match certs.reload_if_changed() {
+                        Ok(true) => tracing::info!("🔄 Reloaded TLS certificate"),
+                        Ok(false) => {}
+                        Err(e) => tracing::warn!("Failed to reload TLS certificate: {:#}", e),
+                    }
+                }
+            });
+            Some(tls_config)
+        }
+        _ => None,
+    };
+    let scheme = if tls_config.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+
     let addr = format!("{}:{}", config.server_host, config.server_port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
 
@@ -177,7 +594,7 @@ async fn main() -> Result<()> {
         });
 
         tokio::select! {
-            result = axum::serve(listener, app).with_graceful_shutdown(shutdown_signal()) => {
+            result = serve(listener, app, tls_config) => {
                 if let Err(e) = result {
                     tracing::error!("Server error: {}", e);
                 }
@@ -188,11 +605,9 @@ async fn main() -> Result<()> {
         }
     } else {
         print_startup_banner(&config);
-        tracing::info!("🚀 Server listening on http://{}", addr);
+        tracing::info!("🚀 Server listening on {}://{}", scheme, addr);
 
-        axum::serve(listener, app)
-            .with_graceful_shutdown(shutdown_signal())
-            .await?;
+        serve(listener, app, tls_config).await?;
     }
 
     tracing::info!("👋 Server shutdown complete");
@@ -200,79 +615,20 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-/// Load models from Kiro API (no retries - fail fast during startup)
-async fn load_models_from_kiro(
-    http_client: &http_client::KiroHttpClient,
-    auth_manager: &auth::AuthManager,
-    _config: &config::Config,
-) -> anyhow::Result<Vec<serde_json::Value>> {
-    // Get access token
-    let access_token = auth_manager.get_access_token().await?;
-    let region = auth_manager.get_region().await;
-
-    // Build request to list models - use Q API endpoint, not CodeWhisperer
-    // Correct endpoint: https://q.{region}.amazonaws.com/ListAvailableModels
-    let url = format!("https://q.{}.amazonaws.com/ListAvailableModels", region);
-
-    // Build request with query parameters
-    // Note: profileArn is only needed for Kiro Desktop auth, not AWS SSO OIDC
-    let req_builder = http_client
-        .client()
-        .get(&url)
-        .query(&[("origin", "AI_EDITOR")])
-        .header("Authorization", format!("Bearer {}", access_token))
-        .header("Content-Type", "application/json");
-
-    // Add profileArn only if using Kiro Desktop auth (not AWS SSO)
-    // For AWS SSO OIDC (kiro-cli), profileArn is not needed
-    // TODO: Detect auth type and conditionally add profileArn
-
-    let req = req_builder.build()?;
-
-    // Execute request WITHOUT retries (fail fast during startup)
-    let response = http_client.request_no_retry(req).await?;
-
-    // Parse response
-    let body = response.text().await?;
-    let json: serde_json::Value = serde_json::from_str(&body)?;
-
-    // Extract models from response
-    if let Some(models) = json.get("models").and_then(|v| v.as_array()) {
-        Ok(models.clone())
-    } else {
-        Ok(vec![])
-    }
-}
-
-/// Add hidden models to cache
-fn add_hidden_models(cache: &cache::ModelCache) {
-    // Add commonly used model aliases that may not be in the API response
-    let hidden_models = vec![
-        (
-            "claude-3-5-sonnet-20241022",
-            "CLAUDE_3_5_SONNET_20241022_V2_0",
-        ),
-        (
-            "claude-3-5-sonnet-20240620",
-            "CLAUDE_3_5_SONNET_20240620_V1_0",
-        ),
-        (
-            "claude-3-5-haiku-20241022",
-            "CLAUDE_3_5_HAIKU_20241022_V1_0",
-        ),
-        ("claude-3-opus-20240229", "CLAUDE_3_OPUS_20240229_V1_0"),
-        ("claude-3-sonnet-20240229", "CLAUDE_3_SONNET_20240229_V1_0"),
-        ("claude-3-haiku-20240307", "CLAUDE_3_HAIKU_20240307_V1_0"),
-        ("claude-sonnet-4", "CLAUDE_SONNET_4_20250514_V1_0"),
-        ("claude-sonnet-4-20250514", "CLAUDE_SONNET_4_20250514_V1_0"),
-        (
-            "anthropic.claude-sonnet-4-v1",
-            "CLAUDE_SONNET_4_20250514_V1_0",
-        ),
-    ];
-
-    for (display_name, internal_id) in hidden_models {
-        cache.add_hidden_model(display_name, internal_id);
+/// Serve the app over plain HTTP, or HTTPS when TLS is configured
+async fn serve(
+    listener: tokio::net::TcpListener,
+    app: axum::Router,
+    tls_config: Option<Arc<rustls::ServerConfig>>,
+) -> anyhow::Result<()> {
+    match tls_config {
+        Some(tls_config) => tls::serve(listener, app, tls_config, shutdown_signal()).await,
+        None => Ok(axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown_signal())
+        .await?),
     }
 }
 
@@ -281,7 +637,7 @@ fn build_app(state: routes::AppState) -> axum::Router {
     use axum::Router;
 
     // Health check routes (no auth required)
-    let health_routes = routes::health_routes();
+    let health_routes = routes::health_routes(state.clone());
 
     // OpenAI API routes (with auth)
     let openai_routes = routes::openai_routes(state.clone());
@@ -289,13 +645,20 @@ fn build_app(state: routes::AppState) -> axum::Router {
     // Anthropic API routes (with auth)
     let anthropic_routes = routes::anthropic_routes(state.clone());
 
+    // Admin API routes (with admin auth)
+    let admin_routes = routes::admin_routes(state.clone());
+
     // Combine all routes
     Router::new()
         .merge(health_routes)
         .merge(openai_routes)
         .merge(anthropic_routes)
-        // Apply middleware stack: CORS → Debug → (Auth is per-route)
-        .layer(middleware::cors_layer())
+        .merge(admin_routes)
+        // Apply middleware stack: Debug → Network policy (ACL + CORS) → (Auth is per-route)
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::network_middleware,
+        ))
         .layer(axum::middleware::from_fn_with_state(
             state.clone(),
             middleware::debug_middleware,
@@ -317,10 +680,19 @@ fn print_startup_banner(config: &config::Config) {
     println!("{}", banner);
     println!("  Version:     {}", env!("CARGO_PKG_VERSION"));
     println!(
-        "  Server:      http://{}:{}",
-        config.server_host, config.server_port
+        "  Server:      {}://{}:{}",
+        if config.tls_cert_file.is_some() {
+            "https"
+        } else {
+            "http"
+        },
+        config.server_host,
+        config.server_port
     );
     println!("  Region:      {}", config.kiro_region);
+    if !config.kiro_failover_regions.is_empty() {
+        println!("  Failover:    {}", config.kiro_failover_regions.join(", "));
+    }
     println!("  Debug Mode:  {:?}", config.debug_mode);
     println!("  Log Level:   {}", config.log_level);
     println!(
@@ -4,6 +4,9 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+use crate::keys::VirtualKeyStore;
+use crate::usage::{UsageLedger, UsageRecord, STATUS_OK};
+
 /// Ring buffer capacity for samples (15 minutes at ~4 samples/sec)
 const RING_BUFFER_CAPACITY: usize = 3600;
 
@@ -42,6 +45,18 @@ impl ModelStats {
     }
 }
 
+impl ModelStats {
+    /// Copy the current counter values
+    fn snapshot(&self) -> Self {
+        Self {
+            request_count: AtomicU64::new(self.request_count.load(Ordering::Relaxed)),
+            total_latency_ms: AtomicU64::new(self.total_latency_ms.load(Ordering::Relaxed)),
+            total_input_tokens: AtomicU64::new(self.total_input_tokens.load(Ordering::Relaxed)),
+            total_output_tokens: AtomicU64::new(self.total_output_tokens.load(Ordering::Relaxed)),
+        }
+    }
+}
+
 impl Default for ModelStats {
     fn default() -> Self {
         Self::new()
@@ -56,35 +71,105 @@ impl Default for ModelStats {
 pub struct StreamingMetricsTracker {
     metrics: Arc<MetricsCollector>,
     model: String,
+    key: String,
     input_tokens: u64,
     output_tokens: Arc<AtomicU64>,
+    /// Upstream credits as `f64` bits
+    credits: Arc<AtomicU64>,
+    ledger: Option<Arc<UsageLedger>>,
+    virtual_keys: Option<Arc<VirtualKeyStore>>,
+    /// Replayed from the response cache
+    cached: bool,
+    /// Shared from another request's upstream call
+    coalesced: bool,
     start_time: Instant,
     completed: bool,
 }
 
 impl StreamingMetricsTracker {
-    pub fn new(metrics: Arc<MetricsCollector>, model: String, input_tokens: u64) -> Self {
+    pub fn new(
+        metrics: Arc<MetricsCollector>,
+        model: String,
+        key: String,
+        input_tokens: u64,
+    ) -> Self {
         metrics.record_request_start();
         Self {
             metrics,
             model,
+            key,
             input_tokens,
             output_tokens: Arc::new(AtomicU64::new(0)),
+            credits: Arc::new(AtomicU64::new(0)),
+            ledger: None,
+            virtual_keys: None,
+            cached: false,
+            coalesced: false,
             start_time: Instant::now(),
             completed: false,
         }
     }
 
+    /// Also record the finished stream in the usage ledger
+    pub fn with_ledger(mut self, ledger: Arc<UsageLedger>) -> Self {
+        self.ledger = Some(ledger);
+        self
+    }
+
+    /// Also count the stream's tokens against virtual key caps
+    pub fn with_virtual_keys(mut self, virtual_keys: Arc<VirtualKeyStore>) -> Self {
+        self.virtual_keys = Some(virtual_keys);
+        self
+    }
+
+    /// Flag the stream as a response cache replay (no upstream credits spent)
+    pub fn with_cached(mut self, cached: bool) -> Self {
+        self.cached = cached;
+        self
+    }
+
+    /// Flag the stream as joined to another request's upstream call (no upstream credits spent)
+    pub fn with_coalesced(mut self, coalesced: bool) -> Self {
+        self.coalesced = coalesced;
+        self
+    }
+
     pub fn output_tokens_handle(&self) -> Arc<AtomicU64> {
         Arc::clone(&self.output_tokens)
     }
 
+    pub fn credits_handle(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.credits)
+    }
+
     pub fn complete(&mut self) {
         if !self.completed {
             let latency_ms = self.start_time.elapsed().as_secs_f64() * 1000.0;
             let output = self.output_tokens.load(Ordering::Relaxed);
             self.metrics
                 .record_request_end(latency_ms, &self.model, self.input_tokens, output);
+            self.metrics
+                .record_key_usage(&self.key, latency_ms, self.input_tokens, output);
+            if let Some(ref ledger) = self.ledger {
+                ledger.record(&UsageRecord {
+                    key: self.key.clone(),
+                    model: self.model.clone(),
+                    input_tokens: self.input_tokens,
+                    output_tokens: output,
+                    credits: if self.cached || self.coalesced {
+                        0.0
+                    } else {
+                        f64::from_bits(self.credits.load(Ordering::Relaxed))
+                    },
+                    latency_ms,
+                    status: STATUS_OK.to_string(),
+                    cached: self.cached,
+                    coalesced: self.coalesced,
+                });
+            }
+            if let Some(ref virtual_keys) = self.virtual_keys {
+                virtual_keys.record_tokens(&self.key, self.input_tokens + output);
+            }
             self.completed = true;
         }
     }
@@ -121,6 +206,51 @@ pub struct MetricsCollector {
 
     /// Per-model statistics
     per_model_stats: DashMap<String, ModelStats>,
+
+    /// Per-client-key statistics
+    per_key_stats: DashMap<String, ModelStats>,
+
+    /// Requests rejected by network policy, keyed by `group:reason`
+    rejections: DashMap<String, AtomicU64>,
+
+    /// Upstream retries, keyed by status code or network error kind
+    retries: DashMap<String, AtomicU64>,
+
+    /// Upstream circuit breaker state (closed, open, half_open)
+    circuit_state: Mutex<&'static str>,
+
+    /// Upstream requests served, keyed by region
+    regions: DashMap<String, AtomicU64>,
+
+    /// Failovers away from a region, keyed by the region that failed
+    failovers: DashMap<String, AtomicU64>,
+
+    /// Hedged requests, keyed by outcome (launched, won, lost, budget_exhausted, no_slot)
+    hedges: DashMap<String, AtomicU64>,
+
+    /// Requests waiting in the upstream admission queue
+    queue_depth: AtomicU64,
+
+    /// Upstream calls holding an admission slot
+    upstream_in_flight: AtomicU64,
+
+    /// Current upstream in-flight limit (0 when unlimited)
+    concurrency_limit: AtomicU64,
+
+    /// Adaptive limit changes, keyed by reason
+    limit_adjustments: DashMap<String, AtomicU64>,
+
+    /// Admission queue wait samples (time, wait_ms) - ring buffer
+    queue_waits: Mutex<VecDeque<(Instant, f64)>>,
+
+    /// Requests rejected after waiting the maximum time in the admission queue
+    queue_timeouts: AtomicU64,
+
+    /// Response cache lookups, keyed by outcome (hit, miss, bypass)
+    cache_lookups: DashMap<String, AtomicU64>,
+
+    /// Requests that joined an identical in-flight upstream call
+    coalesced: AtomicU64,
 }
 
 impl MetricsCollector {
@@ -135,6 +265,21 @@ impl MetricsCollector {
             request_rate_samples: Mutex::new(VecDeque::with_capacity(RING_BUFFER_CAPACITY)),
             token_counts: Mutex::new(VecDeque::with_capacity(RING_BUFFER_CAPACITY)),
             per_model_stats: DashMap::new(),
+            per_key_stats: DashMap::new(),
+            rejections: DashMap::new(),
+            retries: DashMap::new(),
+            circuit_state: Mutex::new("closed"),
+            regions: DashMap::new(),
+            failovers: DashMap::new(),
+            hedges: DashMap::new(),
+            queue_depth: AtomicU64::new(0),
+            upstream_in_flight: AtomicU64::new(0),
+            concurrency_limit: AtomicU64::new(0),
+            limit_adjustments: DashMap::new(),
+            queue_waits: Mutex::new(VecDeque::with_capacity(RING_BUFFER_CAPACITY)),
+            queue_timeouts: AtomicU64::new(0),
+            cache_lookups: DashMap::new(),
+            coalesced: AtomicU64::new(0),
         }
     }
 
@@ -183,6 +328,20 @@ impl MetricsCollector {
             .record_request(latency_ms, input_tokens, output_tokens);
     }
 
+    /// Record a completed request against the client key that made it
+    pub fn record_key_usage(
+        &self,
+        key: &str,
+        latency_ms: f64,
+        input_tokens: u64,
+        output_tokens: u64,
+    ) {
+        self.per_key_stats
+            .entry(key.to_string())
+            .or_default()
+            .record_request(latency_ms, input_tokens, output_tokens);
+    }
+
     /// Record an error
     pub fn record_error(&self, error_type: &str) {
         self.total_errors.fetch_add(1, Ordering::Relaxed);
@@ -193,6 +352,221 @@ impl MetricsCollector {
             .fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Record a request rejected by network policy (IP ACL or origin)
+    pub fn record_rejection(&self, group: &str, reason: &str) {
+        self.rejections
+            .entry(format!("{}:{}", group, reason))
+            .or_insert_with(|| AtomicU64::new(0))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get rejection counts by `group:reason`
+    pub fn get_rejections(&self) -> Vec<(String, u64)> {
+        let mut rejections: Vec<(String, u64)> = self
+            .rejections
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+            .collect();
+        rejections.sort();
+        rejections
+    }
+
+    /// Record an upstream retry (status code such as `429`, or e.g. `timeout`)
+    pub fn record_retry(&self, reason: &str) {
+        self.retries
+            .entry(reason.to_string())
+            .or_insert_with(|| AtomicU64::new(0))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get retry counts by status code or network error kind
+    pub fn get_retries(&self) -> Vec<(String, u64)> {
+        let mut retries: Vec<(String, u64)> = self
+            .retries
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+            .collect();
+        retries.sort();
+        retries
+    }
+
+    /// Record the upstream circuit breaker state
+    pub fn set_circuit_state(&self, state: &'static str) {
+        if let Ok(mut current) = self.circuit_state.lock() {
+            *current = state;
+        }
+    }
+
+    /// Get the upstream circuit breaker state
+    pub fn get_circuit_state(&self) -> &'static str {
+        self.circuit_state
+            .lock()
+            .map(|state| *state)
+            .unwrap_or("closed")
+    }
+
+    /// Record the region that served an upstream request
+    pub fn record_region(&self, region: &str) {
+        self.regions
+            .entry(region.to_string())
+            .or_insert_with(|| AtomicU64::new(0))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get upstream request counts by serving region
+    pub fn get_regions(&self) -> Vec<(String, u64)> {
+        let mut regions: Vec<(String, u64)> = self
+            .regions
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+            .collect();
+        regions.sort();
+        regions
+    }
+
+    /// Record a failover away from a region
+    pub fn record_failover(&self, region: &str) {
+        self.failovers
+            .entry(region.to_string())
+            .or_insert_with(|| AtomicU64::new(0))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get failover counts by the region that failed
+    pub fn get_failovers(&self) -> Vec<(String, u64)> {
+        let mut failovers: Vec<(String, u64)> = self
+            .failovers
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+            .collect();
+        failovers.sort();
+        failovers
+    }
+
+    /// Record a hedging outcome
+    pub fn record_hedge(&self, outcome: &str) {
+        self.hedges
+            .entry(outcome.to_string())
+            .or_insert_with(|| AtomicU64::new(0))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get hedge counts by outcome
+    pub fn get_hedges(&self) -> Vec<(String, u64)> {
+        let mut hedges: Vec<(String, u64)> = self
+            .hedges
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+            .collect();
+        hedges.sort();
+        hedges
+    }
+
+    /// Record the admission queue depth, upstream calls in flight and their limit
+    pub fn set_admission(&self, queue_depth: u64, in_flight: u64, limit: u64) {
+        self.queue_depth.store(queue_depth, Ordering::Relaxed);
+        self.upstream_in_flight.store(in_flight, Ordering::Relaxed);
+        self.concurrency_limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// Get the admission queue depth, upstream calls in flight and their limit
+    pub fn get_admission(&self) -> (u64, u64, u64) {
+        (
+            self.queue_depth.load(Ordering::Relaxed),
+            self.upstream_in_flight.load(Ordering::Relaxed),
+            self.concurrency_limit.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Record a change of the adaptive in-flight limit
+    pub fn record_limit_adjustment(&self, reason: &str) {
+        self.limit_adjustments
+            .entry(reason.to_string())
+            .or_insert_with(|| AtomicU64::new(0))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get adaptive limit change counts by reason
+    pub fn get_limit_adjustments(&self) -> Vec<(String, u64)> {
+        let mut adjustments: Vec<(String, u64)> = self
+            .limit_adjustments
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+            .collect();
+        adjustments.sort();
+        adjustments
+    }
+
+    /// Record a response cache outcome
+    pub fn record_cache(&self, outcome: &str) {
+        self.cache_lookups
+            .entry(outcome.to_string())
+            .or_insert_with(|| AtomicU64::new(0))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get response cache lookup counts by outcome
+    pub fn get_cache(&self) -> Vec<(String, u64)> {
+        let mut lookups: Vec<(String, u64)> = self
+            .cache_lookups
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+            .collect();
+        lookups.sort();
+        lookups
+    }
+
+    /// Record a request served by another request's upstream call
+    pub fn record_coalesced(&self) {
+        self.coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of requests that joined an in-flight upstream call
+    pub fn get_coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+
+    /// Record how long a request waited for an upstream slot
+    pub fn record_queue_wait(&self, wait_ms: f64) {
+        if let Ok(mut samples) = self.queue_waits.lock() {
+            if samples.len() >= RING_BUFFER_CAPACITY {
+                samples.pop_front();
+            }
+            samples.push_back((Instant::now(), wait_ms));
+        }
+    }
+
+    /// Record a request rejected after the maximum admission queue wait
+    pub fn record_queue_timeout(&self) {
+        self.queue_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of admission queue timeouts
+    pub fn get_queue_timeouts(&self) -> u64 {
+        self.queue_timeouts.load(Ordering::Relaxed)
+    }
+
+    /// Get admission queue wait percentiles (p50, p95)
+    pub fn get_queue_wait_percentiles(&self) -> (f64, f64) {
+        let samples = match self.queue_waits.lock() {
+            Ok(s) => s,
+            Err(_) => return (0.0, 0.0),
+        };
+
+        if samples.is_empty() {
+            return (0.0, 0.0);
+        }
+
+        let mut waits: Vec<f64> = samples.iter().map(|(_, wait)| *wait).collect();
+        waits.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let len = waits.len();
+        let p50 = waits[((len as f64 * 0.50) as usize).min(len - 1)];
+        let p95 = waits[((len as f64 * 0.95) as usize).min(len - 1)];
+
+        (p50, p95)
+    }
+
     /// Get current active connections
     pub fn get_active_connections(&self) -> u64 {
         self.active_connections.load(Ordering::Relaxed)
@@ -228,23 +602,15 @@ impl MetricsCollector {
     pub fn get_model_stats(&self) -> Vec<(String, ModelStats)> {
         self.per_model_stats
             .iter()
-            .map(|entry| {
-                let model = entry.key().clone();
-                let stats = entry.value();
-                let cloned_stats = ModelStats {
-                    request_count: AtomicU64::new(stats.request_count.load(Ordering::Relaxed)),
-                    total_latency_ms: AtomicU64::new(
-                        stats.total_latency_ms.load(Ordering::Relaxed),
-                    ),
-                    total_input_tokens: AtomicU64::new(
-                        stats.total_input_tokens.load(Ordering::Relaxed),
-                    ),
-                    total_output_tokens: AtomicU64::new(
-                        stats.total_output_tokens.load(Ordering::Relaxed),
-                    ),
-                };
-                (model, cloned_stats)
-            })
+            .map(|entry| (entry.key().clone(), entry.value().snapshot()))
+            .collect()
+    }
+
+    /// Get per-client-key statistics
+    pub fn get_key_stats(&self) -> Vec<(String, ModelStats)> {
+        self.per_key_stats
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().snapshot()))
             .collect()
     }
 
@@ -264,6 +630,10 @@ impl MetricsCollector {
         if let Ok(mut counts) = self.token_counts.lock() {
             counts.retain(|(time, _, _)| *time >= cutoff);
         }
+
+        if let Ok(mut samples) = self.queue_waits.lock() {
+            samples.retain(|(time, _)| *time >= cutoff);
+        }
     }
 
     /// Get request rate history for sparkline display
@@ -339,6 +709,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_key_stats() {
+        let collector = Arc::new(MetricsCollector::new());
+
+        collector.record_key_usage("alice", 100.0, 10, 20);
+        collector.record_key_usage("alice", 50.0, 5, 5);
+
+        {
+            let mut tracker = StreamingMetricsTracker::new(
+                Arc::clone(&collector),
+                "model-a".to_string(),
+                "ci".to_string(),
+                30,
+            );
+            tracker.output_tokens_handle().store(40, Ordering::Relaxed);
+            tracker.complete();
+        }
+
+        let stats = collector.get_key_stats();
+        assert_eq!(stats.len(), 2);
+
+        let (_, alice) = stats.iter().find(|(name, _)| name == "alice").unwrap();
+        assert_eq!(alice.request_count.load(Ordering::Relaxed), 2);
+        assert_eq!(alice.total_input_tokens.load(Ordering::Relaxed), 15);
+
+        let (_, ci) = stats.iter().find(|(name, _)| name == "ci").unwrap();
+        assert_eq!(ci.total_output_tokens.load(Ordering::Relaxed), 40);
+        assert_eq!(collector.get_active_connections(), 0);
+    }
+
     #[test]
     fn test_error_recording() {
         let collector = MetricsCollector::new();
@@ -350,4 +750,81 @@ mod tests {
         assert_eq!(collector.total_errors.load(Ordering::Relaxed), 3);
         assert_eq!(collector.errors_by_type.len(), 2);
     }
+
+    #[test]
+    fn test_rejection_recording() {
+        let collector = MetricsCollector::new();
+
+        collector.record_rejection("openai", "ip_denied");
+        collector.record_rejection("admin", "origin_not_allowed");
+        collector.record_rejection("openai", "ip_denied");
+
+        assert_eq!(
+            collector.get_rejections(),
+            vec![
+                ("admin:origin_not_allowed".to_string(), 1),
+                ("openai:ip_denied".to_string(), 2),
+            ]
+        );
+        // Rejections happen before handlers and are not counted as errors
+        assert_eq!(collector.total_errors.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_retry_recording() {
+        let collector = MetricsCollector::new();
+
+        collector.record_retry("503");
+        collector.record_retry("429");
+        collector.record_retry("timeout");
+        collector.record_retry("429");
+
+        assert_eq!(
+            collector.get_retries(),
+            vec![
+                ("429".to_string(), 2),
+                ("503".to_string(), 1),
+                ("timeout".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_region_metrics() {
+        let collector = MetricsCollector::new();
+        collector.record_region("us-west-2");
+        collector.record_region("us-east-1");
+        collector.record_region("us-west-2");
+        collector.record_failover("us-east-1");
+
+        assert_eq!(
+            collector.get_regions(),
+            vec![("us-east-1".to_string(), 1), ("us-west-2".to_string(), 2)]
+        );
+        assert_eq!(
+            collector.get_failovers(),
+            vec![("us-east-1".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_admission_metrics() {
+        let collector = MetricsCollector::new();
+        assert_eq!(collector.get_queue_wait_percentiles(), (0.0, 0.0));
+
+        collector.set_admission(3, 10, 10);
+        for i in 1..=100 {
+            collector.record_queue_wait(i as f64);
+        }
+        collector.record_queue_timeout();
+        collector.record_limit_adjustment("throttled");
+
+        assert_eq!(collector.get_admission(), (3, 10, 10));
+        assert_eq!(collector.get_queue_wait_percentiles(), (51.0, 96.0));
+        assert_eq!(collector.get_queue_timeouts(), 1);
+        assert_eq!(
+            collector.get_limit_adjustments(),
+            vec![("throttled".to_string(), 1)]
+        );
+    }
 }
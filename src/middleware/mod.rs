@@ -1,58 +1,201 @@
 // Authentication, CORS, and debug logging middleware
 
 pub mod debug;
-
-use axum::{body::Body, extract::State, http::Request, middleware::Next, response::Response};
+pub mod network;
+pub mod rate_limit;
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
 use tower_http::cors::{Any, CorsLayer};
 
 use crate::error::ApiError;
+use crate::keys::{
+    looks_like_jwt, secrets_equal, ClientIdentity, KeyRejection, DEFAULT_KEY_NAME, MTLS_NAME_PREFIX,
+};
 use crate::routes::AppState;
+use crate::tls::ClientCertificate;
 
 pub use debug::debug_middleware;
 pub use debug::DEBUG_LOGGER;
+pub use network::{network_middleware, NetworkPolicy};
+pub use rate_limit::{rate_limit_middleware, RateLimiter, RateLimits, TokenCharge};
 
 /// Authentication middleware
 ///
 /// Verifies the API key in the Authorization header or x-api-key header.
-/// Expects format: "Bearer {PROXY_API_KEY}" or just the key in x-api-key.
+/// Expects format: "Bearer {key}" or just the key in x-api-key. The key may be
+/// `PROXY_API_KEY` or any named key from the key store. Connections with a
+/// verified client certificate are authenticated as `mtls:<common name>`
+/// without a key. On success the client's `ClientIdentity` is inserted into
+/// the request extensions.
 pub async fn auth_middleware(
     State(state): State<AppState>,
-    request: Request<Body>,
+    mut request: Request<Body>,
     next: Next,
 ) -> Result<Response, ApiError> {
-    if let Some(auth_header) = request.headers().get("authorization") {
-        if let Ok(auth_str) = auth_header.to_str() {
-            tracing::debug!("Received Authorization header: {}", auth_str);
-            let expected = format!("Bearer {}", state.proxy_api_key);
-            tracing::debug!("Expected: {}", expected);
-            if auth_str == expected {
-                return Ok(next.run(request).await);
-            }
+    let path = request.uri().path().to_string();
+    let method = request.method().clone();
+
+    // A verified client certificate (mTLS) stands in for an API key
+    let authenticated = match request.extensions().get::<ClientCertificate>() {
+        Some(cert) => Ok(ClientIdentity::unrestricted(&format!(
+            "{}{}",
+            MTLS_NAME_PREFIX, cert.common_name
+        ))),
+        None => authenticate_request(&state, request.headers()),
+    };
+    let identity = match authenticated {
+        Ok(identity) => identity,
+        Err(rejection) => {
+            let request_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+            tracing::warn!(
+                "[{}] Access attempt rejected ({}): {} {}",
+                request_id,
+                rejection,
+                method,
+                path
+            );
+            return Err(match rejection {
+                KeyRejection::Unknown => {
+                    ApiError::AuthError("Invalid or missing API Key".to_string())
+                }
+                KeyRejection::Disabled(_) => ApiError::AuthError("API Key is disabled".to_string()),
+                KeyRejection::Expired(_) => ApiError::AuthError("API Key has expired".to_string()),
+                KeyRejection::InvalidToken(reason) => {
+                    ApiError::AuthError(format!("Invalid JWT: {}", reason))
+                }
+                KeyRejection::CapReached(_) => {
+                    ApiError::Forbidden("API Key has reached its request or token cap".to_string())
+                }
+                KeyRejection::NoMatchingRule(_) => ApiError::Forbidden(
+                    "JWT claims do not grant access to this gateway".to_string(),
+                ),
+            });
         }
-    }
+    };
 
-    if let Some(api_key_header) = request.headers().get("x-api-key") {
-        if let Ok(key_str) = api_key_header.to_str() {
-            tracing::debug!("Received x-api-key header: {}", key_str);
-            tracing::debug!("Expected: {}", state.proxy_api_key);
-            if key_str == state.proxy_api_key {
-                return Ok(next.run(request).await);
-            }
-        }
+    if !identity.allows_endpoint(&path) {
+        tracing::warn!(
+            "Client key '{}' is not allowed to access {} {}",
+            identity.name,
+            method,
+            path
+        );
+        return Err(ApiError::Forbidden(format!(
+            "API Key is not allowed to access {}",
+            path
+        )));
     }
 
-    let path = request.uri().path();
-    let method = request.method();
-    let request_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
-    tracing::warn!(
-        "[{}] Access attempt with invalid or missing API key: {} {}",
-        request_id,
+    tracing::debug!(
+        "Authenticated client key '{}': {} {}",
+        identity.name,
         method,
         path
     );
-    Err(ApiError::AuthError(
-        "Invalid or missing API Key".to_string(),
-    ))
+    DEBUG_LOGGER
+        .log_app_message(
+            "INFO",
+            "auth",
+            &format!("Client key '{}': {} {}", identity.name, method, path),
+        )
+        .await;
+
+    request.extensions_mut().insert(identity);
+    Ok(next.run(request).await)
+}
+
+/// Authenticate the keys presented in the request headers
+///
+/// When JWT mode is on, a Bearer JWT is verified and its claims decide access;
+/// API keys are only accepted with `JWT_API_KEY_FALLBACK`. Keys are checked
+/// against `PROXY_API_KEY`, the key store, then virtual keys issued through the
+/// admin API. Returns the most specific rejection (disabled/expired/cap
+/// reached) if no key matched.
+fn authenticate_request(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<ClientIdentity, KeyRejection> {
+    let bearer = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let api_key = headers.get("x-api-key").and_then(|v| v.to_str().ok());
+
+    if let Some(ref jwt) = state.jwt_validator {
+        if let Some(token) = bearer.filter(|t| looks_like_jwt(t)) {
+            return jwt.validate(token);
+        }
+        if !jwt.api_key_fallback() {
+            return Err(KeyRejection::InvalidToken(
+                "missing bearer token".to_string(),
+            ));
+        }
+    }
+
+    let mut rejection = KeyRejection::Unknown;
+    for presented in bearer.into_iter().chain(api_key) {
+        if !state.proxy_api_key.is_empty() && secrets_equal(presented, &state.proxy_api_key) {
+            return Ok(ClientIdentity::unrestricted(DEFAULT_KEY_NAME));
+        }
+
+        let result = match state.key_store.authenticate(presented) {
+            Err(KeyRejection::Unknown) => state.virtual_keys.authenticate(presented),
+            result => result,
+        };
+        match result {
+            Ok(identity) => return Ok(identity),
+            Err(KeyRejection::Unknown) => {}
+            Err(other) => rejection = other,
+        }
+    }
+
+    Err(rejection)
+}
+
+/// Admin authentication middleware
+///
+/// Admin routes accept only `ADMIN_API_KEY` (Bearer or x-api-key), never client
+/// keys or `PROXY_API_KEY`. The admin API is disabled when the key is unset.
+pub async fn admin_auth_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let Some(ref admin_key) = state.config.admin_api_key else {
+        return Err(ApiError::Forbidden(
+            "Admin API is disabled (set ADMIN_API_KEY)".to_string(),
+        ));
+    };
+
+    let headers = request.headers();
+    let bearer = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let api_key = headers.get("x-api-key").and_then(|v| v.to_str().ok());
+
+    let authorized = bearer
+        .into_iter()
+        .chain(api_key)
+        .any(|presented| secrets_equal(presented, admin_key));
+    if !authorized {
+        tracing::warn!(
+            "Admin access rejected: {} {}",
+            request.method(),
+            request.uri().path()
+        );
+        return Err(ApiError::AuthError(
+            "Invalid or missing admin API Key".to_string(),
+        ));
+    }
+
+    Ok(next.run(request).await)
 }
 
 /// Create CORS middleware layer
@@ -70,14 +213,20 @@ pub fn cors_layer() -> CorsLayer {
 mod tests {
     use super::*;
     use crate::{
-        auth::AuthManager, cache::ModelCache, config::Config, http_client::KiroHttpClient,
+        auth::AuthManager,
+        cache::ModelCache,
+        config::Config,
+        egress::EgressConfig,
+        http_client::KiroHttpClient,
+        keys::{hash_key, ClientKey, KeyStore},
+        middleware::RateLimits,
         resolver::ModelResolver,
     };
     use axum::{
         body::Body,
         http::{Request, StatusCode},
         routing::get,
-        Router,
+        Extension, Router,
     };
     use std::collections::HashMap;
     use std::sync::Arc;
@@ -89,8 +238,17 @@ mod tests {
             AuthManager::new_for_testing("test-token".to_string(), "us-east-1".to_string(), 300)
                 .unwrap(),
         );
-        let http_client =
-            Arc::new(KiroHttpClient::new(auth_manager.clone(), 20, 30, 300, 3).unwrap());
+        let http_client = Arc::new(
+            KiroHttpClient::new(
+                auth_manager.clone(),
+                20,
+                30,
+                300,
+                3,
+                &EgressConfig::default(),
+            )
+            .unwrap(),
+        );
         let resolver = ModelResolver::new(cache.clone(), HashMap::new());
         let config = Arc::new(Config {
             server_host: "0.0.0.0".to_string(),
@@ -112,18 +270,115 @@ mod tests {
             fake_reasoning_max_tokens: 10000,
             fake_reasoning_handling: crate::config::FakeReasoningHandling::AsReasoningContent,
             dashboard: false,
+            kiro_profile: None,
+            list_profiles: false,
+            api_keys_file: None,
+            rate_limit_rpm: 0,
+            rate_limit_input_tpm: 0,
+            rate_limit_concurrent_streams: 0,
+            rate_limit_by: crate::config::RateLimitKey::ApiKey,
+            max_request_body_bytes: 32 * 1024 * 1024,
+            usage_db_file: None,
+            admin_api_key: None,
+            virtual_keys_db_file: None,
+            jwt_secret: None,
+            jwt_jwks_file: None,
+            jwt_issuer: None,
+            jwt_audience: vec![],
+            jwt_claims_file: None,
+            jwt_api_key_fallback: false,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_client_ca_file: None,
+            tls_client_cert_required: false,
+            network_policy_file: None,
+            backends_file: None,
+            upstream_base_url: None,
+            upstream_endpoints: HashMap::new(),
+            http_retry_budget_ratio: 0.2,
+            http_retry_budget_min_per_sec: 1,
+            circuit_breaker_failures: 5,
+            circuit_breaker_error_rate: 0.5,
+            circuit_breaker_min_requests: 20,
+            circuit_breaker_open_secs: 30,
+            kiro_proxy_url: None,
+            kiro_proxy_username: None,
+            kiro_proxy_password: None,
+            no_proxy: None,
+            kiro_ca_bundle: None,
+            kiro_failover_regions: vec![],
+            region_sticky_ttl: 3600,
+            hedge_models: vec![],
+            hedge_percentile: 95.0,
+            hedge_min_delay_ms: 500,
+            hedge_budget_per_min: 10,
+            upstream_max_in_flight: 0,
+            upstream_queue_max_wait_ms: 10_000,
+            upstream_tier_priorities: HashMap::new(),
+            adaptive_concurrency: false,
+            adaptive_min_in_flight: 2,
+            adaptive_max_in_flight: 64,
+            adaptive_decrease_ratio: 0.7,
+            adaptive_latency_tolerance: 2.0,
+            model_cache_ttl: 3600,
+            model_snapshot_file: None,
+            degraded_startup: false,
+            response_cache: false,
+            response_cache_ttl: 3600,
+            response_cache_max_entries: 1000,
+            response_cache_max_bytes: 64 * 1024 * 1024,
+            response_cache_db_file: None,
+            response_cache_db_max_entries: 10_000,
+            response_cache_db_max_bytes: 1024 * 1024 * 1024,
+            response_cache_keys: Vec::new(),
+            coalesce_routes: Vec::new(),
+            image_fetch_allowed_hosts: Vec::new(),
+            image_fetch_max_bytes: 5 * 1024 * 1024,
+            image_fetch_timeout: 10,
+            image_fetch_cache_entries: 100,
+            image_fetch_max_total_bytes: 20 * 1024 * 1024,
+            image_max_dimension: 1568,
+            image_max_bytes: 3_750_000,
+            image_max_count: 20,
+            document_max_bytes: 32 * 1024 * 1024,
+            document_max_pages: 100,
+            document_max_chars: 200_000,
+            prompt_cache_ttl: 300,
+            prompt_cache_max_entries: 10_000,
         });
 
         let metrics = Arc::new(crate::metrics::MetricsCollector::new());
 
+        let backends = Arc::new(crate::backends::BackendRouter::new(Arc::new(
+            crate::backends::KiroBackend::new(
+                http_client.clone(),
+                auth_manager.clone(),
+                config.clone(),
+            ),
+        )));
+
         AppState {
             proxy_api_key: "test-key-123".to_string(),
+            key_store: Arc::new(KeyStore::empty()),
+            virtual_keys: Arc::new(crate::keys::VirtualKeyStore::disabled()),
+            network_policy: Arc::new(NetworkPolicy::permissive()),
+            jwt_validator: None,
+            rate_limiter: Arc::new(RateLimiter::new(RateLimits::default())),
             model_cache: cache,
             auth_manager,
             http_client,
+            backends,
+            response_cache: Arc::new(crate::response_cache::ResponseCache::disabled()),
+            coalescer: Arc::new(crate::coalescing::Coalescer::new(Vec::new())),
+            image_fetcher: Arc::new(
+                crate::image_fetch::ImageFetcher::from_config(&config, &EgressConfig::default())
+                    .unwrap(),
+            ),
+            prompt_cache: Arc::new(crate::prompt_cache::PromptCache::from_config(&config)),
             resolver,
             config,
             metrics,
+            usage_ledger: Arc::new(crate::usage::UsageLedger::disabled()),
         }
     }
 
@@ -233,6 +488,135 @@ mod tests {
         assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
     }
 
+    fn create_keyed_state() -> AppState {
+        let mut state = create_test_state();
+        let key = |name: &str, secret: &str| ClientKey {
+            name: name.to_string(),
+            key_hash: hash_key(secret),
+            enabled: true,
+            expires_at: None,
+            allowed_models: vec![],
+            allowed_endpoints: vec![],
+        };
+
+        let mut disabled = key("old", "old-key");
+        disabled.enabled = false;
+
+        let mut expired = key("temp", "temp-key");
+        expired.expires_at = Some(chrono::Utc::now() - chrono::Duration::hours(1));
+
+        let mut scoped = key("ci", "ci-key");
+        scoped.allowed_endpoints = vec!["/other".to_string()];
+
+        state.key_store = Arc::new(KeyStore::from_keys(vec![
+            key("alice", "alice-key"),
+            disabled,
+            expired,
+            scoped,
+        ]));
+        state
+    }
+
+    async fn identity_handler(Extension(identity): Extension<ClientIdentity>) -> String {
+        identity.name
+    }
+
+    fn create_identity_app(state: AppState) -> Router {
+        Router::new()
+            .route("/test", get(identity_handler))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            ))
+            .with_state(state)
+    }
+
+    async fn body_string(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_named_key_sets_identity() {
+        let app = create_identity_app(create_keyed_state());
+
+        let request = Request::builder()
+            .uri("/test")
+            .header("authorization", "Bearer alice-key")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "alice");
+
+        // The proxy key still works and is attributed to the default key
+        let request = Request::builder()
+            .uri("/test")
+            .header("x-api-key", "test-key-123")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(body_string(response).await, DEFAULT_KEY_NAME);
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_rejects_disabled_and_expired_keys() {
+        let app = create_identity_app(create_keyed_state());
+
+        for secret in ["old-key", "temp-key"] {
+            let request = Request::builder()
+                .uri("/test")
+                .header("x-api-key", secret)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_enforces_endpoint_scope() {
+        let app = create_identity_app(create_keyed_state());
+
+        let request = Request::builder()
+            .uri("/test")
+            .header("x-api-key", "ci-key")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_empty_proxy_key_is_disabled() {
+        let mut state = create_keyed_state();
+        state.proxy_api_key = String::new();
+        let app = create_identity_app(state);
+
+        let request = Request::builder()
+            .uri("/test")
+            .header("authorization", "Bearer ")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_auth_middleware_accepts_client_certificate() {
+        let app = create_identity_app(create_keyed_state());
+
+        let mut request = Request::builder().uri("/test").body(Body::empty()).unwrap();
+        request.extensions_mut().insert(ClientCertificate {
+            common_name: "ci-runner".to_string(),
+        });
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "mtls:ci-runner");
+    }
+
     // CORS middleware tests
 
     #[tokio::test]
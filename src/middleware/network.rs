@@ -0,0 +1,524 @@
+// Network ACLs and CORS policies per route group
+//
+// Requests are classified into a route group by path. Each group has its own
+// CIDR allow/deny lists and CORS allow-list; groups without a section in the
+// policy file use `default`, and sections without `cors` use its CORS policy
+// (or refuse cross-origin requests if it has none). Without a policy file
+// everything is allowed and CORS stays permissive.
+
+use anyhow::{Context, Result};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use tower::{Layer, ServiceExt};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+
+use crate::error::ApiError;
+use crate::routes::AppState;
+
+/// Route groups that can carry their own policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    Health,
+    OpenAi,
+    Anthropic,
+    Admin,
+    Other,
+}
+
+impl RouteGroup {
+    /// Classify a request path
+    pub fn from_path(path: &str) -> Self {
+        if path == "/" || path == "/health" {
+            RouteGroup::Health
+        } else if path == "/admin" || path.starts_with("/admin/") {
+            RouteGroup::Admin
+        } else if path.starts_with("/v1/messages") {
+            RouteGroup::Anthropic
+        } else if path.starts_with("/v1/") {
+            RouteGroup::OpenAi
+        } else {
+            RouteGroup::Other
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RouteGroup::Health => "health",
+            RouteGroup::OpenAi => "openai",
+            RouteGroup::Anthropic => "anthropic",
+            RouteGroup::Admin => "admin",
+            RouteGroup::Other => "other",
+        }
+    }
+}
+
+/// Client IP after `X-Forwarded-For` resolution (request extension)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientIp(pub IpAddr);
+
+/// CORS allow-list; `*` allows any value
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsPolicy {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+}
+
+impl Default for CorsPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            allowed_headers: vec![
+                "authorization".to_string(),
+                "content-type".to_string(),
+                "x-api-key".to_string(),
+                "anthropic-version".to_string(),
+            ],
+        }
+    }
+}
+
+impl CorsPolicy {
+    fn layer(&self) -> Result<CorsLayer> {
+        let wildcard = |values: &[String]| values.iter().any(|v| v == "*");
+
+        let origins = if wildcard(&self.allowed_origins) {
+            AllowOrigin::from(Any)
+        } else {
+            AllowOrigin::list(
+                self.allowed_origins
+                    .iter()
+                    .map(|o| {
+                        HeaderValue::from_str(o).with_context(|| format!("Invalid origin: {}", o))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        };
+        let methods = if wildcard(&self.allowed_methods) {
+            AllowMethods::from(Any)
+        } else {
+            AllowMethods::list(
+                self.allowed_methods
+                    .iter()
+                    .map(|m| {
+                        Method::from_bytes(m.to_uppercase().as_bytes())
+                            .with_context(|| format!("Invalid method: {}", m))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        };
+        let headers = if wildcard(&self.allowed_headers) {
+            AllowHeaders::from(Any)
+        } else {
+            AllowHeaders::list(
+                self.allowed_headers
+                    .iter()
+                    .map(|h| {
+                        HeaderName::from_bytes(h.to_lowercase().as_bytes())
+                            .with_context(|| format!("Invalid header name: {}", h))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        };
+
+        Ok(CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers))
+    }
+}
+
+/// One route group's section in the policy file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RoutePolicyFile {
+    allow: Vec<String>,
+    deny: Vec<String>,
+    cors: Option<CorsPolicy>,
+}
+
+/// Policy file layout (`NETWORK_POLICY_FILE`)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PolicyFile {
+    trusted_proxies: Vec<String>,
+    default: RoutePolicyFile,
+    health: Option<RoutePolicyFile>,
+    openai: Option<RoutePolicyFile>,
+    anthropic: Option<RoutePolicyFile>,
+    admin: Option<RoutePolicyFile>,
+}
+
+/// Compiled policy for a route group
+struct RoutePolicy {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+    /// Allowed origins; `None` accepts any
+    origins: Option<Vec<String>>,
+    cors: CorsLayer,
+}
+
+impl RoutePolicy {
+    fn permissive() -> Self {
+        Self {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            origins: None,
+            cors: super::cors_layer(),
+        }
+    }
+
+    /// Compile a section; without its own `cors` it uses `fallback_cors`,
+    /// then the restrictive default (no origins)
+    fn compile(file: &RoutePolicyFile, fallback_cors: Option<&CorsPolicy>) -> Result<Self> {
+        let cors = file
+            .cors
+            .as_ref()
+            .or(fallback_cors)
+            .cloned()
+            .unwrap_or_default();
+        let origins = if cors.allowed_origins.iter().any(|o| o == "*") {
+            None
+        } else {
+            Some(cors.allowed_origins.clone())
+        };
+        let cors = cors.layer()?;
+
+        Ok(Self {
+            allow: parse_nets(&file.allow)?,
+            deny: parse_nets(&file.deny)?,
+            origins,
+            cors,
+        })
+    }
+
+    /// Check the client IP against the deny and allow lists
+    ///
+    /// Returns the rejection reason. Unknown IPs only pass without an allow list.
+    fn check_ip(&self, ip: Option<IpAddr>) -> Result<(), &'static str> {
+        if let Some(ip) = ip {
+            if self.deny.iter().any(|net| net.contains(&ip)) {
+                return Err("ip_denied");
+            }
+        }
+        if !self.allow.is_empty()
+            && !ip.is_some_and(|ip| self.allow.iter().any(|net| net.contains(&ip)))
+        {
+            return Err("ip_not_allowed");
+        }
+        Ok(())
+    }
+
+    fn allows_origin(&self, origin: &HeaderValue) -> bool {
+        match self.origins {
+            Some(ref origins) => origin
+                .to_str()
+                .is_ok_and(|origin| origins.iter().any(|o| o.eq_ignore_ascii_case(origin))),
+            None => true,
+        }
+    }
+}
+
+/// Network ACLs and CORS policies for all route groups
+pub struct NetworkPolicy {
+    trusted_proxies: Vec<IpNet>,
+    default: RoutePolicy,
+    groups: HashMap<RouteGroup, RoutePolicy>,
+}
+
+impl NetworkPolicy {
+    /// Allow every client and any CORS origin
+    pub fn permissive() -> Self {
+        Self {
+            trusted_proxies: Vec::new(),
+            default: RoutePolicy::permissive(),
+            groups: HashMap::new(),
+        }
+    }
+
+    /// Load the policy from a JSON file
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read network policy: {}", path.display()))?;
+        Self::from_json(&content)
+            .with_context(|| format!("Failed to parse network policy: {}", path.display()))
+    }
+
+    fn from_json(content: &str) -> Result<Self> {
+        let file: PolicyFile = serde_json::from_str(content)?;
+
+        let mut groups = HashMap::new();
+        for (group, section) in [
+            (RouteGroup::Health, &file.health),
+            (RouteGroup::OpenAi, &file.openai),
+            (RouteGroup::Anthropic, &file.anthropic),
+            (RouteGroup::Admin, &file.admin),
+        ] {
+            if let Some(section) = section {
+                groups.insert(
+                    group,
+                    RoutePolicy::compile(section, file.default.cors.as_ref())?,
+                );
+            }
+        }
+
+        Ok(Self {
+            trusted_proxies: parse_nets(&file.trusted_proxies)?,
+            default: RoutePolicy::compile(&file.default, None)?,
+            groups,
+        })
+    }
+
+    fn route(&self, group: RouteGroup) -> &RoutePolicy {
+        self.groups.get(&group).unwrap_or(&self.default)
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(&ip))
+    }
+
+    /// Resolve the client IP from the peer address and `X-Forwarded-For`
+    ///
+    /// The header is only honored when the peer is a trusted proxy. Hops are
+    /// walked from the nearest one; the first untrusted address is the client.
+    pub fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let peer = peer?.to_canonical();
+        if !self.is_trusted(peer) {
+            return Some(peer);
+        }
+
+        let hops: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect();
+
+        let mut client = peer;
+        for hop in hops.iter().rev() {
+            // Stop at garbage rather than trusting anything further out
+            let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = ip.to_canonical();
+            if !self.is_trusted(client) {
+                break;
+            }
+        }
+        Some(client)
+    }
+}
+
+/// Parse CIDRs, accepting bare addresses as single-host networks
+fn parse_nets(values: &[String]) -> Result<Vec<IpNet>> {
+    values
+        .iter()
+        .map(|v| {
+            v.parse::<IpNet>()
+                .or_else(|_| v.parse::<IpAddr>().map(IpNet::from))
+                .with_context(|| format!("Invalid CIDR or IP address: {}", v))
+        })
+        .collect()
+}
+
+/// Network policy middleware
+///
+/// Resolves the client IP (inserted as `ClientIp`), enforces the route group's
+/// IP allow/deny lists and origin allow-list, then applies its CORS headers.
+/// Rejections return 403 and are counted in `MetricsCollector`.
+pub async fn network_middleware(
+    State(state): State<AppState>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let group = RouteGroup::from_path(request.uri().path());
+    let policy = &state.network_policy;
+    let route = policy.route(group);
+
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let client_ip = policy.client_ip(peer, request.headers());
+    if let Some(ip) = client_ip {
+        request.extensions_mut().insert(ClientIp(ip));
+    }
+
+    let rejection = match route.check_ip(client_ip) {
+        Err(reason) => Some((reason, "Client IP address is not allowed")),
+        Ok(()) => match request.headers().get(header::ORIGIN) {
+            Some(origin) if !route.allows_origin(origin) => {
+                Some(("origin_not_allowed", "Origin is not allowed"))
+            }
+            _ => None,
+        },
+    };
+    if let Some((reason, message)) = rejection {
+        tracing::warn!(
+            "Rejected {} {} from {} ({} policy): {}",
+            request.method(),
+            request.uri().path(),
+            client_ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string()),
+            group.as_str(),
+            reason
+        );
+        state.metrics.record_rejection(group.as_str(), reason);
+        return ApiError::Forbidden(message.to_string()).into_response();
+    }
+
+    match route.cors.clone().layer(next).oneshot(request).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn forwarded(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_route_groups() {
+        assert_eq!(RouteGroup::from_path("/"), RouteGroup::Health);
+        assert_eq!(RouteGroup::from_path("/health"), RouteGroup::Health);
+        assert_eq!(RouteGroup::from_path("/v1/models"), RouteGroup::OpenAi);
+        assert_eq!(
+            RouteGroup::from_path("/v1/chat/completions"),
+            RouteGroup::OpenAi
+        );
+        assert_eq!(RouteGroup::from_path("/v1/messages"), RouteGroup::Anthropic);
+        assert_eq!(RouteGroup::from_path("/admin/usage"), RouteGroup::Admin);
+        assert_eq!(RouteGroup::from_path("/metrics"), RouteGroup::Other);
+    }
+
+    #[test]
+    fn test_allow_and_deny_lists() {
+        let policy = NetworkPolicy::from_json(
+            r#"{
+                "default": {"allow": ["10.0.0.0/8", "192.168.1.5"], "deny": ["10.0.66.0/24"]},
+                "health": {}
+            }"#,
+        )
+        .unwrap();
+
+        let openai = policy.route(RouteGroup::OpenAi);
+        assert!(openai.check_ip(Some(ip("10.1.2.3"))).is_ok());
+        assert!(openai.check_ip(Some(ip("192.168.1.5"))).is_ok());
+        assert_eq!(openai.check_ip(Some(ip("10.0.66.7"))), Err("ip_denied"));
+        assert_eq!(openai.check_ip(Some(ip("8.8.8.8"))), Err("ip_not_allowed"));
+        assert_eq!(openai.check_ip(None), Err("ip_not_allowed"));
+
+        // The health section replaces the default: open to everyone
+        let health = policy.route(RouteGroup::Health);
+        assert!(health.check_ip(Some(ip("8.8.8.8"))).is_ok());
+        assert!(health.check_ip(None).is_ok());
+    }
+
+    #[test]
+    fn test_forwarded_for_only_from_trusted_proxies() {
+        let policy =
+            NetworkPolicy::from_json(r#"{"trusted_proxies": ["10.0.0.0/8", "::1"]}"#).unwrap();
+
+        // Untrusted peers cannot spoof their address
+        assert_eq!(
+            policy.client_ip(Some(ip("203.0.113.9")), &forwarded("1.2.3.4")),
+            Some(ip("203.0.113.9"))
+        );
+
+        // Walk back through trusted hops to the first untrusted address
+        assert_eq!(
+            policy.client_ip(
+                Some(ip("10.0.0.1")),
+                &forwarded("6.6.6.6, 198.51.100.7, 10.0.0.2")
+            ),
+            Some(ip("198.51.100.7"))
+        );
+
+        // All hops trusted: the leftmost one is the client
+        assert_eq!(
+            policy.client_ip(Some(ip("::1")), &forwarded("10.9.9.9")),
+            Some(ip("10.9.9.9"))
+        );
+
+        // Garbage stops the walk
+        assert_eq!(
+            policy.client_ip(Some(ip("10.0.0.1")), &forwarded("1.2.3.4, junk")),
+            Some(ip("10.0.0.1"))
+        );
+
+        // IPv4-mapped IPv6 peers match IPv4 networks
+        assert_eq!(
+            policy.client_ip(Some(ip("::ffff:10.0.0.1")), &forwarded("5.5.5.5")),
+            Some(ip("5.5.5.5"))
+        );
+    }
+
+    #[test]
+    fn test_origin_allow_list() {
+        let policy = NetworkPolicy::from_json(
+            r#"{
+                "openai": {"cors": {"allowed_origins": ["https://app.example.com"]}},
+                "admin": {"cors": {"allowed_origins": ["*"]}}
+            }"#,
+        )
+        .unwrap();
+
+        let openai = policy.route(RouteGroup::OpenAi);
+        assert!(openai.allows_origin(&HeaderValue::from_static("https://app.example.com")));
+        assert!(!openai.allows_origin(&HeaderValue::from_static("https://evil.example.com")));
+        assert!(policy
+            .route(RouteGroup::Admin)
+            .allows_origin(&HeaderValue::from_static("https://evil.example.com")));
+        // No `cors` anywhere: cross-origin requests are refused
+        assert!(!policy
+            .route(RouteGroup::Anthropic)
+            .allows_origin(&HeaderValue::from_static("https://evil.example.com")));
+    }
+
+    #[test]
+    fn test_sections_without_cors_use_default_cors() {
+        let policy = NetworkPolicy::from_json(
+            r#"{
+                "default": {"cors": {"allowed_origins": ["https://app.example.com"]}},
+                "admin": {"allow": ["10.0.5.0/24"]}
+            }"#,
+        )
+        .unwrap();
+
+        let admin = policy.route(RouteGroup::Admin);
+        assert!(admin.allows_origin(&HeaderValue::from_static("https://app.example.com")));
+        assert!(!admin.allows_origin(&HeaderValue::from_static("https://evil.example.com")));
+    }
+
+    #[test]
+    fn test_invalid_policy_rejected() {
+        assert!(NetworkPolicy::from_json(r#"{"default": {"allow": ["10.0.0.0/33"]}}"#).is_err());
+        assert!(NetworkPolicy::from_json(r#"{"trusted_proxies": ["proxy.local"]}"#).is_err());
+        assert!(NetworkPolicy::from_json(r#"{"openai": {"alow": []}}"#).is_err());
+        assert!(NetworkPolicy::from_json(
+            r#"{"default": {"cors": {"allowed_methods": ["GET POST"]}}}"#
+        )
+        .is_err());
+    }
+}
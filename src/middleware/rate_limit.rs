@@ -0,0 +1,829 @@
+// Per-client rate limiting: requests/min, input tokens/min, concurrent streams
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use chrono::Utc;
+use dashmap::DashMap;
+use futures::StreamExt;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::RateLimitKey;
+use crate::error::ApiError;
+use crate::keys::ClientIdentity;
+use crate::models::anthropic::AnthropicMessagesRequest;
+use crate::models::openai::ChatCompletionRequest;
+use crate::routes::AppState;
+use crate::tokenizer::{count_anthropic_message_tokens, count_message_tokens, count_tools_tokens};
+
+use super::network::ClientIp;
+
+/// Length of the sliding window for per-minute limits
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Retry-After suggested when the concurrent stream limit is hit
+const STREAM_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Limits applied to each client (0 = unlimited)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RateLimits {
+    pub requests_per_minute: u32,
+    pub input_tokens_per_minute: u64,
+    pub concurrent_streams: u32,
+}
+
+impl RateLimits {
+    /// Check whether any limit is configured
+    pub fn is_enabled(&self) -> bool {
+        self.requests_per_minute > 0
+            || self.input_tokens_per_minute > 0
+            || self.concurrent_streams > 0
+    }
+
+    fn has_window(&self) -> bool {
+        self.requests_per_minute > 0 || self.input_tokens_per_minute > 0
+    }
+}
+
+/// Requests admitted in the last minute: (admitted at, input tokens)
+#[derive(Default)]
+struct Window {
+    entries: VecDeque<(Instant, u64)>,
+    tokens: u64,
+}
+
+impl Window {
+    /// Drop entries older than the window
+    fn prune(&mut self, now: Instant) {
+        while let Some(&(at, tokens)) = self.entries.front() {
+            if now.duration_since(at) < WINDOW {
+                break;
+            }
+            self.entries.pop_front();
+            self.tokens -= tokens;
+        }
+    }
+
+    /// Time until the entry admitted at `at` leaves the window
+    fn expires_in(at: Instant, now: Instant) -> Duration {
+        (at + WINDOW).saturating_duration_since(now)
+    }
+
+    /// Time until the window is empty again
+    fn reset_in(&self, now: Instant) -> Duration {
+        self.entries
+            .back()
+            .map(|&(at, _)| Self::expires_in(at, now))
+            .unwrap_or_default()
+    }
+}
+
+/// Current limit state for a client, reported in response headers
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitStatus {
+    pub requests_remaining: u32,
+    pub tokens_remaining: u64,
+    pub reset: Duration,
+}
+
+/// A throttled request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Throttled {
+    pub reason: &'static str,
+    pub limit: u64,
+    pub retry_after: Duration,
+    pub status: RateLimitStatus,
+}
+
+/// Held for the lifetime of a streaming response
+pub struct StreamPermit {
+    active: Arc<AtomicU32>,
+}
+
+impl Drop for StreamPermit {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Charges an admitted request's window with input tokens counted later
+///
+/// Inserted into the request by `rate_limit_middleware` when an input token
+/// limit applies. Images and documents are only measured once the handler has
+/// prepared them, so it adds their tokens here.
+#[derive(Clone)]
+pub struct TokenCharge {
+    limiter: Arc<RateLimiter>,
+    client: String,
+    admitted_at: Instant,
+}
+
+impl TokenCharge {
+    pub fn add_input_tokens(&self, tokens: u64) {
+        self.limiter
+            .add_input_tokens(&self.client, self.admitted_at, tokens);
+    }
+}
+
+/// Sliding-window rate limiter keyed by client
+///
+/// Clients use the default limits unless their identity names a rate-limit
+/// tier, in which case the tier's limits apply instead.
+pub struct RateLimiter {
+    limits: RateLimits,
+    tiers: HashMap<String, RateLimits>,
+    windows: DashMap<String, Window>,
+    streams: DashMap<String, Arc<AtomicU32>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            tiers: HashMap::new(),
+            windows: DashMap::new(),
+            streams: DashMap::new(),
+        }
+    }
+
+    /// Add named rate-limit tiers
+    pub fn with_tiers(mut self, tiers: HashMap<String, RateLimits>) -> Self {
+        self.tiers = tiers;
+        self
+    }
+
+    /// Limits for a client in the given tier (unknown tiers get the defaults)
+    pub fn limits_for(&self, tier: Option<&str>) -> RateLimits {
+        tier.and_then(|t| self.tiers.get(t))
+            .copied()
+            .unwrap_or(self.limits)
+    }
+
+    /// Check whether any limit (default or tier) is configured
+    pub fn is_enabled(&self) -> bool {
+        self.limits.is_enabled() || self.tiers.values().any(|t| t.is_enabled())
+    }
+
+    /// Admit a request with the given input token estimate, or throttle it
+    pub fn check(
+        &self,
+        client: &str,
+        tier: Option<&str>,
+        input_tokens: u64,
+        now: Instant,
+    ) -> Result<RateLimitStatus, Throttled> {
+        let limits = self.limits_for(tier);
+        if !limits.has_window() {
+            return Ok(status(&limits, &Window::default(), now));
+        }
+
+        let mut window = self.windows.entry(client.to_string()).or_default();
+        window.prune(now);
+
+        let rpm = limits.requests_per_minute;
+        if rpm > 0 && window.entries.len() as u64 >= rpm as u64 {
+            // The oldest request has to leave the window first
+            let retry_after = window
+                .entries
+                .front()
+                .map(|&(at, _)| Window::expires_in(at, now))
+                .unwrap_or(WINDOW);
+            return Err(Throttled {
+                reason: "requests per minute",
+                limit: rpm as u64,
+                retry_after,
+                status: status(&limits, &window, now),
+            });
+        }
+
+        // A single request larger than the whole budget is admitted once the
+        // window is empty, otherwise it could never succeed
+        let tpm = limits.input_tokens_per_minute;
+        if tpm > 0 && window.tokens > 0 && window.tokens + input_tokens > tpm {
+            let mut used = window.tokens;
+            let mut retry_after = WINDOW;
+            for &(at, tokens) in &window.entries {
+                used -= tokens;
+                if used == 0 || used + input_tokens <= tpm {
+                    retry_after = Window::expires_in(at, now);
+                    break;
+                }
+            }
+            return Err(Throttled {
+                reason: "input tokens per minute",
+                limit: tpm,
+                retry_after,
+                status: status(&limits, &window, now),
+            });
+        }
+
+        window.entries.push_back((now, input_tokens));
+        window.tokens += input_tokens;
+
+        Ok(status(&limits, &window, now))
+    }
+
+    /// Add tokens to the request admitted at `admitted_at`, if still in the window
+    pub fn add_input_tokens(&self, client: &str, admitted_at: Instant, tokens: u64) {
+        let Some(mut window) = self.windows.get_mut(client) else {
+            return;
+        };
+        let window = &mut *window;
+        if let Some(entry) = window.entries.iter_mut().find(|(at, _)| *at == admitted_at) {
+            entry.1 += tokens;
+            window.tokens += tokens;
+        }
+    }
+
+    /// Reserve a concurrent stream slot for the client
+    pub fn acquire_stream(
+        &self,
+        client: &str,
+        tier: Option<&str>,
+    ) -> Result<Option<StreamPermit>, Throttled> {
+        let limits = self.limits_for(tier);
+        let max = limits.concurrent_streams;
+        if max == 0 {
+            return Ok(None);
+        }
+
+        let active = Arc::clone(
+            self.streams
+                .entry(client.to_string())
+                .or_insert_with(|| Arc::new(AtomicU32::new(0)))
+                .value(),
+        );
+
+        let acquired = active
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .is_ok();
+
+        if !acquired {
+            let now = Instant::now();
+            let status = match self.windows.get_mut(client) {
+                Some(mut window) => {
+                    window.prune(now);
+                    status(&limits, &window, now)
+                }
+                None => status(&limits, &Window::default(), now),
+            };
+            return Err(Throttled {
+                reason: "concurrent streams",
+                limit: max as u64,
+                retry_after: STREAM_RETRY_AFTER,
+                status,
+            });
+        }
+
+        Ok(Some(StreamPermit { active }))
+    }
+
+    /// Remove state for clients with no recent requests or open streams
+    pub fn cleanup_idle(&self) {
+        let now = Instant::now();
+        self.windows.retain(|_, window| {
+            window.prune(now);
+            !window.entries.is_empty()
+        });
+        self.streams
+            .retain(|_, active| active.load(Ordering::Relaxed) > 0);
+    }
+}
+
+/// Remaining allowance for a window under the given limits
+fn status(limits: &RateLimits, window: &Window, now: Instant) -> RateLimitStatus {
+    RateLimitStatus {
+        requests_remaining: limits
+            .requests_per_minute
+            .saturating_sub(window.entries.len() as u32),
+        tokens_remaining: limits.input_tokens_per_minute.saturating_sub(window.tokens),
+        reset: window.reset_in(now),
+    }
+}
+
+/// Which API flavor of rate-limit headers to emit
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HeaderStyle {
+    OpenAI,
+    Anthropic,
+}
+
+/// What the limiter needs to know about a request body
+#[derive(Debug, Default, PartialEq)]
+struct RequestEstimate {
+    stream: bool,
+    input_tokens: u64,
+}
+
+/// Whether reading a body failed because it exceeded the size limit
+fn is_length_limit(err: &axum::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(err) = source {
+        if err.is::<http_body_util::LengthLimitError>() {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Rate limiting middleware
+///
+/// Must run after `auth_middleware` so the client identity is available.
+/// Buffers the request body (up to `MAX_REQUEST_BODY_BYTES`, 413 beyond) to
+/// estimate input tokens with the tokenizer and to detect streaming requests
+/// before they are dispatched. Attachment tokens are added by the handler
+/// through `TokenCharge`.
+pub async fn rate_limit_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let limiter = Arc::clone(&state.rate_limiter);
+    let tier = request
+        .extensions()
+        .get::<ClientIdentity>()
+        .and_then(|identity| identity.rate_limit_tier.clone());
+    let limits = limiter.limits_for(tier.as_deref());
+    if !limits.is_enabled() {
+        return next.run(request).await;
+    }
+
+    let path = request.uri().path().to_string();
+    let style = if path == "/v1/messages" {
+        HeaderStyle::Anthropic
+    } else {
+        HeaderStyle::OpenAI
+    };
+    let client = client_key(state.config.rate_limit_by, &request);
+
+    let (parts, body) = request.into_parts();
+    let max_body = state.config.max_request_body_bytes;
+    let body_bytes = match axum::body::to_bytes(body, max_body).await {
+        Ok(bytes) => bytes,
+        Err(e) if is_length_limit(&e) => {
+            return ApiError::PayloadTooLarge(format!(
+                "Request body exceeds the maximum of {} bytes",
+                max_body
+            ))
+            .into_response();
+        }
+        Err(e) => {
+            tracing::warn!("Failed to read request body: {}", e);
+            return ApiError::ValidationError("Failed to read request body".to_string())
+                .into_response();
+        }
+    };
+    let estimate = estimate_request(&path, &body_bytes, limits);
+    let request = Request::from_parts(parts, Body::from(body_bytes));
+
+    let permit = if estimate.stream {
+        match limiter.acquire_stream(&client, tier.as_deref()) {
+            Ok(permit) => permit,
+            Err(throttled) => return throttled_response(&state, &client, throttled, style, limits),
+        }
+    } else {
+        None
+    };
+
+    let admitted_at = Instant::now();
+    let status = match limiter.check(&client, tier.as_deref(), estimate.input_tokens, admitted_at) {
+        Ok(status) => status,
+        Err(throttled) => return throttled_response(&state, &client, throttled, style, limits),
+    };
+
+    let mut request = request;
+    if limits.input_tokens_per_minute > 0 {
+        request.extensions_mut().insert(TokenCharge {
+            limiter: Arc::clone(&limiter),
+            client: client.clone(),
+            admitted_at,
+        });
+    }
+    let mut response = next.run(request).await;
+    apply_headers(response.headers_mut(), &status, style, limits);
+
+    match permit {
+        // Keep the stream slot until the response body is finished or dropped
+        Some(permit) => {
+            let (parts, body) = response.into_parts();
+            let stream = body.into_data_stream().map(move |chunk| {
+                let _permit = &permit;
+                chunk
+            });
+            Response::from_parts(parts, Body::from_stream(stream))
+        }
+        None => response,
+    }
+}
+
+/// Build the client key used to bucket requests
+fn client_key(key_by: RateLimitKey, request: &Request<Body>) -> String {
+    // Prefer the address resolved through trusted proxies
+    let ip = match request.extensions().get::<ClientIp>() {
+        Some(ClientIp(ip)) => Some(ip.to_string()),
+        None => request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string()),
+    };
+
+    match key_by {
+        RateLimitKey::ApiKey => match request.extensions().get::<ClientIdentity>() {
+            Some(identity) => format!("key:{}", identity.id),
+            None => format!("ip:{}", ip.unwrap_or_else(|| "unknown".to_string())),
+        },
+        RateLimitKey::ClientIp => format!("ip:{}", ip.unwrap_or_else(|| "unknown".to_string())),
+    }
+}
+
+/// Detect streaming and estimate input tokens from the request body
+fn estimate_request(path: &str, body: &Bytes, limits: RateLimits) -> RequestEstimate {
+    if body.is_empty() {
+        return RequestEstimate::default();
+    }
+
+    // Malformed bodies are left for the handler to reject
+    let stream = serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v.get("stream").and_then(|s| s.as_bool()))
+        .unwrap_or(false);
+
+    let input_tokens = if limits.input_tokens_per_minute == 0 {
+        0
+    } else if path == "/v1/messages" {
+        serde_json::from_slice::<AnthropicMessagesRequest>(body)
+            .map(|r| {
+                count_anthropic_message_tokens(&r.messages, r.system.as_ref(), r.tools.as_ref())
+            })
+            .unwrap_or(0)
+    } else {
+        serde_json::from_slice::<ChatCompletionRequest>(body)
+            .map(|r| {
+                count_message_tokens(&r.messages, false)
+                    + count_tools_tokens(r.tools.as_ref(), false)
+            })
+            .unwrap_or(0)
+    };
+
+    RequestEstimate {
+        stream,
+        input_tokens: input_tokens.max(0) as u64,
+    }
+}
+
+/// Build the 429 response for a throttled request
+fn throttled_response(
+    state: &AppState,
+    client: &str,
+    throttled: Throttled,
+    style: HeaderStyle,
+    limits: RateLimits,
+) -> Response {
+    // Round up so clients never retry too early
+    let retry_after_secs =
+        throttled.retry_after.as_secs() + u64::from(throttled.retry_after.subsec_nanos() > 0);
+    let retry_after_secs = retry_after_secs.max(1);
+
+    tracing::warn!(
+        "Rate limit exceeded for {} ({} limit {}), retry after {}s",
+        client,
+        throttled.reason,
+        throttled.limit,
+        retry_after_secs
+    );
+    state.metrics.record_error("rate_limited");
+
+    let mut response = ApiError::RateLimited {
+        message: format!(
+            "Rate limit exceeded: {} (limit {}). Retry after {} seconds.",
+            throttled.reason, throttled.limit, retry_after_secs
+        ),
+        retry_after: retry_after_secs,
+    }
+    .into_response();
+    apply_headers(response.headers_mut(), &throttled.status, style, limits);
+    response
+}
+
+/// Add rate-limit headers for the configured limits
+fn apply_headers(
+    headers: &mut HeaderMap,
+    status: &RateLimitStatus,
+    style: HeaderStyle,
+    limits: RateLimits,
+) {
+    let mut set = |name: &'static str, value: String| {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    };
+
+    match style {
+        HeaderStyle::OpenAI => {
+            let reset = format_reset_duration(status.reset);
+            if limits.requests_per_minute > 0 {
+                set(
+                    "x-ratelimit-limit-requests",
+                    limits.requests_per_minute.to_string(),
+                );
+                set(
+                    "x-ratelimit-remaining-requests",
+                    status.requests_remaining.to_string(),
+                );
+                set("x-ratelimit-reset-requests", reset.clone());
+            }
+            if limits.input_tokens_per_minute > 0 {
+                set(
+                    "x-ratelimit-limit-tokens",
+                    limits.input_tokens_per_minute.to_string(),
+                );
+                set(
+                    "x-ratelimit-remaining-tokens",
+                    status.tokens_remaining.to_string(),
+                );
+                set("x-ratelimit-reset-tokens", reset);
+            }
+        }
+        HeaderStyle::Anthropic => {
+            let reset = chrono::Duration::from_std(status.reset)
+                .map(|d| Utc::now() + d)
+                .unwrap_or_else(|_| Utc::now())
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+            if limits.requests_per_minute > 0 {
+                set(
+                    "anthropic-ratelimit-requests-limit",
+                    limits.requests_per_minute.to_string(),
+                );
+                set(
+                    "anthropic-ratelimit-requests-remaining",
+                    status.requests_remaining.to_string(),
+                );
+                set("anthropic-ratelimit-requests-reset", reset.clone());
+            }
+            if limits.input_tokens_per_minute > 0 {
+                set(
+                    "anthropic-ratelimit-input-tokens-limit",
+                    limits.input_tokens_per_minute.to_string(),
+                );
+                set(
+                    "anthropic-ratelimit-input-tokens-remaining",
+                    status.tokens_remaining.to_string(),
+                );
+                set("anthropic-ratelimit-input-tokens-reset", reset);
+            }
+        }
+    }
+}
+
+/// Format a reset duration the way OpenAI does (`1m30s`, `12s`, `250ms`)
+fn format_reset_duration(d: Duration) -> String {
+    let total_ms = d.as_millis() as u64;
+    if total_ms < 1000 {
+        return format!("{}ms", total_ms);
+    }
+
+    let mins = total_ms / 60_000;
+    let secs = (total_ms % 60_000) as f64 / 1000.0;
+    let secs = format!("{:.3}", secs)
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string();
+
+    if mins > 0 {
+        format!("{}m{}s", mins, secs)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(rpm: u32, tpm: u64, streams: u32) -> RateLimits {
+        RateLimits {
+            requests_per_minute: rpm,
+            input_tokens_per_minute: tpm,
+            concurrent_streams: streams,
+        }
+    }
+
+    #[test]
+    fn test_requests_per_minute() {
+        let limiter = RateLimiter::new(limits(2, 0, 0));
+        let start = Instant::now();
+
+        let status = limiter.check("a", None, 0, start).unwrap();
+        assert_eq!(status.requests_remaining, 1);
+        assert!(limiter
+            .check("a", None, 0, start + Duration::from_secs(10))
+            .is_ok());
+
+        let throttled = limiter
+            .check("a", None, 0, start + Duration::from_secs(20))
+            .unwrap_err();
+        assert_eq!(throttled.reason, "requests per minute");
+        assert_eq!(throttled.retry_after, Duration::from_secs(40));
+        assert_eq!(throttled.status.requests_remaining, 0);
+
+        // Other clients have their own budget
+        assert!(limiter.check("b", None, 0, start).is_ok());
+
+        // The first request leaves the window after a minute
+        assert!(limiter
+            .check("a", None, 0, start + Duration::from_secs(60))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_input_tokens_per_minute() {
+        let limiter = RateLimiter::new(limits(0, 1000, 0));
+        let start = Instant::now();
+
+        assert!(limiter.check("a", None, 400, start).is_ok());
+        let status = limiter
+            .check("a", None, 500, start + Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(status.tokens_remaining, 100);
+
+        // 900 used, 300 more does not fit until the first 400 expire
+        let throttled = limiter
+            .check("a", None, 300, start + Duration::from_secs(30))
+            .unwrap_err();
+        assert_eq!(throttled.reason, "input tokens per minute");
+        assert_eq!(throttled.retry_after, Duration::from_secs(30));
+
+        assert!(limiter
+            .check("a", None, 300, start + Duration::from_secs(60))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_tokens_added_after_admission() {
+        let limiter = RateLimiter::new(limits(0, 1000, 0));
+        let start = Instant::now();
+
+        assert!(limiter.check("a", None, 100, start).is_ok());
+        limiter.add_input_tokens("a", start, 800);
+        let throttled = limiter
+            .check("a", None, 200, start + Duration::from_secs(1))
+            .unwrap_err();
+        assert_eq!(throttled.status.tokens_remaining, 100);
+
+        // The added tokens leave the window with their request
+        assert!(limiter
+            .check("a", None, 200, start + Duration::from_secs(60))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_oversized_request_admitted_when_window_empty() {
+        let limiter = RateLimiter::new(limits(0, 100, 0));
+        let start = Instant::now();
+
+        assert!(limiter.check("a", None, 500, start).is_ok());
+        assert!(limiter
+            .check("a", None, 1, start + Duration::from_secs(1))
+            .is_err());
+    }
+
+    #[test]
+    fn test_concurrent_streams() {
+        let limiter = RateLimiter::new(limits(0, 0, 1));
+
+        let permit = limiter.acquire_stream("a", None).unwrap();
+        assert!(permit.is_some());
+
+        let throttled = limiter.acquire_stream("a", None).err().unwrap();
+        assert_eq!(throttled.reason, "concurrent streams");
+        assert!(limiter.acquire_stream("b", None).is_ok());
+
+        drop(permit);
+        assert!(limiter.acquire_stream("a", None).is_ok());
+    }
+
+    #[test]
+    fn test_tier_limits() {
+        let limiter = RateLimiter::new(limits(1, 0, 0))
+            .with_tiers(HashMap::from([("premium".to_string(), limits(3, 0, 2))]));
+        let now = Instant::now();
+
+        assert!(limiter.check("a", None, 0, now).is_ok());
+        assert!(limiter.check("a", None, 0, now).is_err());
+
+        for _ in 0..3 {
+            assert!(limiter.check("b", Some("premium"), 0, now).is_ok());
+        }
+        assert!(limiter.check("b", Some("premium"), 0, now).is_err());
+        assert!(limiter
+            .acquire_stream("b", Some("premium"))
+            .unwrap()
+            .is_some());
+
+        // Unknown tiers fall back to the defaults
+        assert_eq!(limiter.limits_for(Some("gold")), limits(1, 0, 0));
+    }
+
+    #[test]
+    fn test_cleanup_idle() {
+        let limiter = RateLimiter::new(limits(10, 0, 1));
+        let long_ago = Instant::now() - Duration::from_secs(120);
+
+        limiter.check("old", None, 0, long_ago).unwrap();
+        limiter.check("new", None, 0, Instant::now()).unwrap();
+        let permit = limiter.acquire_stream("streaming", None).unwrap();
+
+        limiter.cleanup_idle();
+        assert!(!limiter.windows.contains_key("old"));
+        assert!(limiter.windows.contains_key("new"));
+        assert!(limiter.streams.contains_key("streaming"));
+
+        drop(permit);
+        limiter.cleanup_idle();
+        assert!(!limiter.streams.contains_key("streaming"));
+    }
+
+    #[test]
+    fn test_estimate_request() {
+        let body = Bytes::from(
+            r#"{"model":"claude-sonnet-4","stream":true,"messages":[{"role":"user","content":"Hello there, how are you?"}]}"#,
+        );
+
+        let estimate = estimate_request("/v1/chat/completions", &body, limits(0, 1000, 0));
+        assert!(estimate.stream);
+        assert!(estimate.input_tokens > 0);
+
+        // Token counting is skipped when no token limit is configured
+        let estimate = estimate_request("/v1/chat/completions", &body, limits(10, 0, 0));
+        assert_eq!(estimate.input_tokens, 0);
+
+        let body = Bytes::from(
+            r#"{"model":"claude-sonnet-4","max_tokens":10,"messages":[{"role":"user","content":"Hello"}]}"#,
+        );
+        let estimate = estimate_request("/v1/messages", &body, limits(0, 1000, 0));
+        assert!(!estimate.stream);
+        assert!(estimate.input_tokens > 0);
+
+        assert_eq!(
+            estimate_request("/v1/messages", &Bytes::from("not json"), limits(0, 1000, 0)),
+            RequestEstimate::default()
+        );
+    }
+
+    #[test]
+    fn test_headers_by_style() {
+        let status = RateLimitStatus {
+            requests_remaining: 9,
+            tokens_remaining: 900,
+            reset: Duration::from_secs(30),
+        };
+
+        let mut headers = HeaderMap::new();
+        apply_headers(
+            &mut headers,
+            &status,
+            HeaderStyle::OpenAI,
+            limits(10, 1000, 0),
+        );
+        assert_eq!(headers["x-ratelimit-limit-requests"], "10");
+        assert_eq!(headers["x-ratelimit-remaining-requests"], "9");
+        assert_eq!(headers["x-ratelimit-reset-requests"], "30s");
+        assert_eq!(headers["x-ratelimit-remaining-tokens"], "900");
+        assert!(!headers.contains_key("anthropic-ratelimit-requests-limit"));
+
+        let mut headers = HeaderMap::new();
+        apply_headers(
+            &mut headers,
+            &status,
+            HeaderStyle::Anthropic,
+            limits(10, 0, 0),
+        );
+        assert_eq!(headers["anthropic-ratelimit-requests-limit"], "10");
+        assert_eq!(headers["anthropic-ratelimit-requests-remaining"], "9");
+        assert!(headers.contains_key("anthropic-ratelimit-requests-reset"));
+        assert!(!headers.contains_key("anthropic-ratelimit-input-tokens-limit"));
+        assert!(!headers.contains_key("x-ratelimit-limit-requests"));
+    }
+
+    #[test]
+    fn test_format_reset_duration() {
+        assert_eq!(format_reset_duration(Duration::from_millis(250)), "250ms");
+        assert_eq!(format_reset_duration(Duration::from_secs(12)), "12s");
+        assert_eq!(format_reset_duration(Duration::from_millis(1500)), "1.5s");
+        assert_eq!(format_reset_duration(Duration::from_secs(90)), "1m30s");
+    }
+}
@@ -126,6 +126,10 @@ pub struct AnthropicMessagesRequest {
 pub struct AnthropicUsage {
     pub input_tokens: i32,
     pub output_tokens: i32,
+    #[serde(default)]
+    pub cache_creation_input_tokens: i32,
+    #[serde(default)]
+    pub cache_read_input_tokens: i32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,633 @@
+// Local prompt cache accounting
+//
+// Upstream backends do not report prompt caching, yet clients budget on
+// `cache_creation_input_tokens`/`cache_read_input_tokens` (Anthropic) and
+// `prompt_tokens_details.cached_tokens` (OpenAI). Prompt prefixes are tracked
+// here the way the real APIs cache them: the prefix up to each `cache_control`
+// breakpoint is hashed and remembered for its TTL, and a later request
+// starting with a remembered prefix reads it instead of writing it again.
+// OpenAI requests carry no markers; their whole prompt is the breakpoint.
+//
+// Only token accounting is affected - nothing is cached upstream.
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::models::anthropic::AnthropicMessagesRequest;
+use crate::models::openai::ChatCompletionRequest;
+use crate::tokenizer::{count_anthropic_message_tokens, count_message_tokens, count_tools_tokens};
+
+/// Shortest prefix that is cached
+const MIN_CACHEABLE_TOKENS: i32 = 1024;
+
+/// Content blocks before a breakpoint checked for an earlier cached prefix
+const LOOKBACK_BLOCKS: usize = 20;
+
+/// Cache tokens of one request's prompt
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PromptCacheUsage {
+    /// Tokens written to the cache by this request (Anthropic only; OpenAI
+    /// usage has no field for it)
+    pub creation_tokens: i32,
+    /// Tokens read from a prefix cached by an earlier request
+    pub read_tokens: i32,
+}
+
+impl PromptCacheUsage {
+    /// Prompt tokens neither written to nor read from the cache
+    pub fn uncached(&self, input_tokens: i32) -> i32 {
+        (input_tokens - self.creation_tokens - self.read_tokens).max(0)
+    }
+}
+
+/// End of a prompt prefix
+#[derive(Debug, Clone, Copy)]
+enum Prefix {
+    /// System blocks up to this one
+    System(usize),
+    /// Messages up to this one, optionally cut after one of its content blocks
+    Message(usize, Option<usize>),
+}
+
+/// Block boundary in a prompt
+struct Boundary {
+    /// Hash of the prompt up to here
+    hash: [u8; 32],
+    /// TTL of an explicit breakpoint at this boundary
+    breakpoint: Option<Duration>,
+    prefix: Prefix,
+}
+
+struct Entry {
+    expires: Instant,
+    ttl: Duration,
+    last_used: u64,
+}
+
+/// Remembered prefixes, evicting the least recently used one
+#[derive(Default)]
+struct Entries {
+    entries: HashMap<[u8; 32], Entry>,
+    /// `last_used` tick -> hash
+    order: BTreeMap<u64, [u8; 32]>,
+    tick: u64,
+}
+
+impl Entries {
+    fn is_live(&mut self, hash: &[u8; 32], now: Instant) -> bool {
+        match self.entries.get(hash) {
+            Some(entry) if entry.expires > now => true,
+            Some(entry) => {
+                self.order.remove(&entry.last_used);
+                self.entries.remove(hash);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Remember a prefix for `ttl`, or extend it if it is already known
+    fn touch(&mut self, hash: [u8; 32], ttl: Duration, now: Instant, max_entries: usize) {
+        let ttl = match self.entries.remove(&hash) {
+            Some(old) => {
+                self.order.remove(&old.last_used);
+                ttl.max(old.ttl)
+            }
+            None => ttl,
+        };
+        self.tick += 1;
+        self.order.insert(self.tick, hash);
+        self.entries.insert(
+            hash,
+            Entry {
+                expires: now + ttl,
+                ttl,
+                last_used: self.tick,
+            },
+        );
+        while self.entries.len() > max_entries {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+/// Tracker of cached prompt prefixes
+///
+/// Disabled (every request uncached) when the TTL is zero.
+pub struct PromptCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<Entries>,
+}
+
+impl PromptCache {
+    /// Tracker remembering up to `max_entries` prefixes, by default for `ttl`
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            Duration::from_secs(config.prompt_cache_ttl),
+            config.prompt_cache_max_entries,
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    /// Account an Anthropic request whose prefixes are cached by client `scope`
+    ///
+    /// Tools, system blocks and messages are hashed in that order; blocks
+    /// carrying `cache_control` are breakpoints.
+    pub fn anthropic_usage(
+        &self,
+        scope: &str,
+        request: &AnthropicMessagesRequest,
+    ) -> PromptCacheUsage {
+        if !self.is_enabled() {
+            return PromptCacheUsage::default();
+        }
+
+        let mut hasher = Sha256::new();
+        hash_part(&mut hasher, scope.as_bytes());
+        hash_part(&mut hasher, request.model.as_bytes());
+        if let Some(ref tools) = request.tools {
+            hash_part(&mut hasher, &serde_json::to_vec(tools).unwrap_or_default());
+        }
+
+        let mut boundaries = Vec::new();
+        let system_blocks = match request.system {
+            Some(Value::Array(ref blocks)) => blocks.clone(),
+            Some(ref system) => vec![system.clone()],
+            None => Vec::new(),
+        };
+        for (index, block) in system_blocks.iter().enumerate() {
+            boundaries.push(self.block_boundary(&mut hasher, block, Prefix::System(index)));
+        }
+        for (index, message) in request.messages.iter().enumerate() {
+            hash_part(&mut hasher, message.role.as_bytes());
+            match message.content {
+                Value::Array(ref blocks) => {
+                    for (block_index, block) in blocks.iter().enumerate() {
+                        let prefix = Prefix::Message(index, Some(block_index));
+                        boundaries.push(self.block_boundary(&mut hasher, block, prefix));
+                    }
+                }
+                ref content => {
+                    boundaries.push(self.block_boundary(
+                        &mut hasher,
+                        content,
+                        Prefix::Message(index, None),
+                    ));
+                }
+            }
+        }
+
+        let tools = request.tools.as_ref();
+        self.record(&boundaries, LOOKBACK_BLOCKS, |prefix| match prefix {
+            Prefix::System(index) => {
+                let system = match request.system {
+                    Some(Value::Array(ref blocks)) => Value::Array(blocks[..=index].to_vec()),
+                    ref system => system.clone().unwrap_or(Value::Null),
+                };
+                count_anthropic_message_tokens(&[], Some(&system), tools)
+            }
+            Prefix::Message(index, block) => {
+                let mut messages = request.messages[..=index].to_vec();
+                if let (Some(block), Value::Array(blocks)) = (block, &mut messages[index].content) {
+                    blocks.truncate(block + 1);
+                }
+                count_anthropic_message_tokens(&messages, request.system.as_ref(), tools)
+            }
+        })
+    }
+
+    /// Account an OpenAI request whose prefixes are cached by client `scope`
+    ///
+    /// The whole prompt is cached; any earlier prompt it extends at a message
+    /// boundary is read. Only `read_tokens` is reported to OpenAI clients, as
+    /// `prompt_tokens_details.cached_tokens`; `creation_tokens` goes unused.
+    pub fn openai_usage(&self, scope: &str, request: &ChatCompletionRequest) -> PromptCacheUsage {
+        if !self.is_enabled() {
+            return PromptCacheUsage::default();
+        }
+
+        let mut hasher = Sha256::new();
+        hash_part(&mut hasher, scope.as_bytes());
+        hash_part(&mut hasher, request.model.as_bytes());
+        if let Some(ref tools) = request.tools {
+            hash_part(&mut hasher, &serde_json::to_vec(tools).unwrap_or_default());
+        }
+
+        let mut boundaries: Vec<Boundary> = request
+            .messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| {
+                hash_part(
+                    &mut hasher,
+                    &serde_json::to_vec(message).unwrap_or_default(),
+                );
+                Boundary {
+                    hash: hasher.clone().finalize().into(),
+                    breakpoint: None,
+                    prefix: Prefix::Message(index, None),
+                }
+            })
+            .collect();
+        if let Some(last) = boundaries.last_mut() {
+            last.breakpoint = Some(self.ttl);
+        }
+
+        let lookback = boundaries.len();
+        let tools_tokens = count_tools_tokens(request.tools.as_ref(), false);
+        self.record(&boundaries, lookback, |prefix| match prefix {
+            Prefix::Message(index, _) => {
+                count_message_tokens(&request.messages[..=index], false) + tools_tokens
+            }
+            Prefix::System(_) => tools_tokens,
+        })
+    }
+
+    /// Hash one content block (without its `cache_control` marker, so moving
+    /// a breakpoint keeps earlier prefixes intact)
+    fn block_boundary(&self, hasher: &mut Sha256, block: &Value, prefix: Prefix) -> Boundary {
+        let marker = block.get("cache_control");
+        let breakpoint = marker.map(|marker| {
+            marker
+                .get("ttl")
+                .and_then(|ttl| ttl.as_str())
+                .and_then(parse_ttl)
+                .unwrap_or(self.ttl)
+        });
+        let bytes = match (marker, block) {
+            (Some(_), Value::Object(fields)) => {
+                let mut fields = fields.clone();
+                fields.remove("cache_control");
+                serde_json::to_vec(&fields)
+            }
+            _ => serde_json::to_vec(block),
+        };
+        hash_part(hasher, &bytes.unwrap_or_default());
+        Boundary {
+            hash: hasher.clone().finalize().into(),
+            breakpoint,
+            prefix,
+        }
+    }
+
+    /// Read the longest cached prefix within `lookback` boundaries of a
+    /// breakpoint and write every breakpoint beyond it
+    fn record(
+        &self,
+        boundaries: &[Boundary],
+        lookback: usize,
+        count: impl Fn(Prefix) -> i32,
+    ) -> PromptCacheUsage {
+        let breakpoints: Vec<usize> = (0..boundaries.len())
+            .filter(|&i| boundaries[i].breakpoint.is_some())
+            .collect();
+        if breakpoints.is_empty() {
+            return PromptCacheUsage::default();
+        }
+
+        let now = Instant::now();
+        let Ok(mut entries) = self.entries.lock() else {
+            return PromptCacheUsage::default();
+        };
+
+        let mut hit = None;
+        for &breakpoint in breakpoints.iter().rev() {
+            let start = breakpoint.saturating_sub(lookback);
+            if let Some(found) = (start..=breakpoint)
+                .rev()
+                .find(|&i| entries.is_live(&boundaries[i].hash, now))
+            {
+                hit = Some(found);
+                break;
+            }
+        }
+        let read_tokens = hit.map_or(0, |i| count(boundaries[i].prefix));
+        if let Some(i) = hit {
+            let ttl = boundaries[i].breakpoint.unwrap_or(self.ttl);
+            entries.touch(boundaries[i].hash, ttl, now, self.max_entries);
+        }
+
+        let mut cached_tokens = read_tokens;
+        for &breakpoint in &breakpoints {
+            let boundary = &boundaries[breakpoint];
+            if hit.is_some_and(|hit| breakpoint <= hit) {
+                if entries.is_live(&boundary.hash, now) {
+                    let ttl = boundary.breakpoint.unwrap_or(self.ttl);
+                    entries.touch(boundary.hash, ttl, now, self.max_entries);
+                }
+                continue;
+            }
+            let tokens = count(boundary.prefix);
+            if tokens < MIN_CACHEABLE_TOKENS {
+                continue;
+            }
+            let ttl = boundary.breakpoint.unwrap_or(self.ttl);
+            entries.touch(boundary.hash, ttl, now, self.max_entries);
+            cached_tokens = cached_tokens.max(tokens);
+        }
+
+        let usage = PromptCacheUsage {
+            creation_tokens: cached_tokens - read_tokens,
+            read_tokens,
+        };
+        tracing::debug!(
+            "Prompt cache: {} tokens written, {} tokens read",
+            usage.creation_tokens,
+            usage.read_tokens
+        );
+        usage
+    }
+}
+
+/// Feed one length-prefixed part to a prefix hash
+fn hash_part(hasher: &mut Sha256, bytes: &[u8]) {
+    hasher.update((bytes.len() as u64).to_le_bytes());
+    hasher.update(bytes);
+}
+
+/// Parse a `cache_control` TTL such as `5m` or `1h`
+fn parse_ttl(ttl: &str) -> Option<Duration> {
+    let ttl = ttl.trim();
+    let (value, unit) = ttl.split_at(ttl.find(|c: char| !c.is_ascii_digit())?);
+    let value: u64 = value.parse().ok()?;
+    match unit {
+        "s" => Some(Duration::from_secs(value)),
+        "m" => Some(Duration::from_secs(value * 60)),
+        "h" => Some(Duration::from_secs(value * 3600)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cache() -> PromptCache {
+        PromptCache::new(Duration::from_secs(300), 100)
+    }
+
+    /// Text of roughly `tokens` tokens
+    fn long_text(tokens: usize) -> String {
+        "hello ".repeat(tokens)
+    }
+
+    fn anthropic(system: Value, messages: Value) -> AnthropicMessagesRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 100,
+            "system": system,
+            "messages": messages,
+        }))
+        .unwrap()
+    }
+
+    fn cached_system() -> Value {
+        json!([{
+            "type": "text",
+            "text": long_text(2000),
+            "cache_control": {"type": "ephemeral"}
+        }])
+    }
+
+    fn openai(messages: Value) -> ChatCompletionRequest {
+        serde_json::from_value(json!({"model": "gpt-4", "messages": messages})).unwrap()
+    }
+
+    #[test]
+    fn test_parse_ttl() {
+        assert_eq!(parse_ttl("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_ttl("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_ttl("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_ttl("1d"), None);
+        assert_eq!(parse_ttl("m"), None);
+        assert_eq!(parse_ttl("60"), None);
+    }
+
+    #[test]
+    fn test_uncached_tokens() {
+        let usage = PromptCacheUsage {
+            creation_tokens: 100,
+            read_tokens: 50,
+        };
+        assert_eq!(usage.uncached(200), 50);
+        assert_eq!(usage.uncached(100), 0);
+    }
+
+    #[test]
+    fn test_breakpoint_is_written_then_read() {
+        let cache = cache();
+        let request = anthropic(cached_system(), json!([{"role": "user", "content": "Hi"}]));
+        let total = count_anthropic_message_tokens(
+            &request.messages,
+            request.system.as_ref(),
+            request.tools.as_ref(),
+        );
+
+        let first = cache.anthropic_usage("key", &request);
+        assert!(first.creation_tokens >= 2000);
+        assert_eq!(first.read_tokens, 0);
+        assert!(first.creation_tokens < total);
+
+        let second = cache.anthropic_usage("key", &request);
+        assert_eq!(second.creation_tokens, 0);
+        assert_eq!(second.read_tokens, first.creation_tokens);
+    }
+
+    #[test]
+    fn test_no_breakpoint_is_uncached() {
+        let cache = cache();
+        let request = anthropic(
+            json!(long_text(2000)),
+            json!([{"role": "user", "content": "Hi"}]),
+        );
+        cache.anthropic_usage("key", &request);
+        assert_eq!(
+            cache.anthropic_usage("key", &request),
+            PromptCacheUsage::default()
+        );
+    }
+
+    #[test]
+    fn test_short_prefix_is_not_cached() {
+        let cache = cache();
+        let request = anthropic(
+            json!([{"type": "text", "text": "Short", "cache_control": {"type": "ephemeral"}}]),
+            json!([{"role": "user", "content": "Hi"}]),
+        );
+        cache.anthropic_usage("key", &request);
+        assert_eq!(
+            cache.anthropic_usage("key", &request),
+            PromptCacheUsage::default()
+        );
+    }
+
+    #[test]
+    fn test_prefixes_are_scoped_per_client_and_model() {
+        let cache = cache();
+        let request = anthropic(cached_system(), json!([{"role": "user", "content": "Hi"}]));
+        cache.anthropic_usage("alice", &request);
+
+        assert_eq!(cache.anthropic_usage("bob", &request).read_tokens, 0);
+
+        let mut other_model = request.clone();
+        other_model.model = "claude-opus-4".to_string();
+        assert_eq!(cache.anthropic_usage("alice", &other_model).read_tokens, 0);
+    }
+
+    #[test]
+    fn test_changed_prefix_misses() {
+        let cache = cache();
+        let request = anthropic(cached_system(), json!([{"role": "user", "content": "Hi"}]));
+        cache.anthropic_usage("key", &request);
+
+        let changed = anthropic(
+            json!([{
+                "type": "text",
+                "text": format!("{} changed", long_text(2000)),
+                "cache_control": {"type": "ephemeral"}
+            }]),
+            json!([{"role": "user", "content": "Hi"}]),
+        );
+        let usage = cache.anthropic_usage("key", &changed);
+        assert_eq!(usage.read_tokens, 0);
+        assert!(usage.creation_tokens > 0);
+    }
+
+    #[test]
+    fn test_moved_breakpoint_reads_earlier_prefix() {
+        let cache = cache();
+        let first = anthropic(
+            json!(long_text(2000)),
+            json!([{"role": "user", "content": [
+                {"type": "text", "text": "Question one", "cache_control": {"type": "ephemeral"}}
+            ]}]),
+        );
+        let written = cache.anthropic_usage("key", &first).creation_tokens;
+        assert!(written > 2000);
+
+        // Next turn: the marker moves to the new last message
+        let second = anthropic(
+            json!(long_text(2000)),
+            json!([
+                {"role": "user", "content": [{"type": "text", "text": "Question one"}]},
+                {"role": "assistant", "content": [{"type": "text", "text": "Answer one"}]},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "Question two", "cache_control": {"type": "ephemeral"}}
+                ]}
+            ]),
+        );
+        let usage = cache.anthropic_usage("key", &second);
+        assert_eq!(usage.read_tokens, written);
+        assert!(usage.creation_tokens > 0);
+    }
+
+    #[test]
+    fn test_expired_prefix_is_written_again() {
+        let cache = PromptCache::new(Duration::from_millis(50), 100);
+        let request = anthropic(cached_system(), json!([{"role": "user", "content": "Hi"}]));
+        cache.anthropic_usage("key", &request);
+        std::thread::sleep(Duration::from_millis(100));
+
+        let usage = cache.anthropic_usage("key", &request);
+        assert_eq!(usage.read_tokens, 0);
+        assert!(usage.creation_tokens > 0);
+    }
+
+    #[test]
+    fn test_marker_ttl_overrides_default() {
+        let cache = PromptCache::new(Duration::from_millis(50), 100);
+        let request = anthropic(
+            json!([{
+                "type": "text",
+                "text": long_text(2000),
+                "cache_control": {"type": "ephemeral", "ttl": "1h"}
+            }]),
+            json!([{"role": "user", "content": "Hi"}]),
+        );
+        cache.anthropic_usage("key", &request);
+        std::thread::sleep(Duration::from_millis(100));
+
+        assert!(cache.anthropic_usage("key", &request).read_tokens > 0);
+    }
+
+    #[test]
+    fn test_least_recently_used_prefix_is_evicted() {
+        let cache = PromptCache::new(Duration::from_secs(300), 1);
+        let first = anthropic(cached_system(), json!([{"role": "user", "content": "Hi"}]));
+        cache.anthropic_usage("alice", &first);
+        cache.anthropic_usage("bob", &first);
+
+        assert_eq!(cache.anthropic_usage("alice", &first).read_tokens, 0);
+    }
+
+    #[test]
+    fn test_disabled_cache() {
+        let cache = PromptCache::new(Duration::ZERO, 100);
+        let request = anthropic(cached_system(), json!([{"role": "user", "content": "Hi"}]));
+        cache.anthropic_usage("key", &request);
+        assert!(!cache.is_enabled());
+        assert_eq!(
+            cache.anthropic_usage("key", &request),
+            PromptCacheUsage::default()
+        );
+    }
+
+    #[test]
+    fn test_openai_prompt_is_read_by_next_turn() {
+        let cache = cache();
+        let first = openai(json!([
+            {"role": "system", "content": long_text(2000)},
+            {"role": "user", "content": "Question one"}
+        ]));
+        let written = cache.openai_usage("key", &first);
+        assert_eq!(written.read_tokens, 0);
+        assert_eq!(
+            written.creation_tokens,
+            count_message_tokens(&first.messages, false)
+        );
+
+        let second = openai(json!([
+            {"role": "system", "content": long_text(2000)},
+            {"role": "user", "content": "Question one"},
+            {"role": "assistant", "content": "Answer one"},
+            {"role": "user", "content": "Question two"}
+        ]));
+        let usage = cache.openai_usage("key", &second);
+        assert_eq!(usage.read_tokens, written.creation_tokens);
+        assert_eq!(
+            usage.read_tokens + usage.creation_tokens,
+            count_message_tokens(&second.messages, false)
+        );
+    }
+
+    #[test]
+    fn test_openai_short_prompt_is_not_cached() {
+        let cache = cache();
+        let request = openai(json!([{"role": "user", "content": "Hi"}]));
+        cache.openai_usage("key", &request);
+        assert_eq!(
+            cache.openai_usage("key", &request),
+            PromptCacheUsage::default()
+        );
+    }
+}
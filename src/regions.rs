@@ -0,0 +1,165 @@
+// Multi-region routing: ordered failover list and per-conversation stickiness
+
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Region used when none is configured (matches the credentials default)
+pub const DEFAULT_REGION: &str = "us-east-1";
+
+/// Upper bound on remembered conversations; expired entries are dropped first
+const MAX_STICKY_CONVERSATIONS: usize = 100_000;
+
+/// Stable key for a conversation
+///
+/// Derived from the client and the opening of the conversation (system prompt
+/// and first user message), which clients resend unchanged on every turn.
+pub fn conversation_key(client: &str, opening: &impl Serialize) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    client.hash(&mut hasher);
+    serde_json::to_string(opening)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Ordered list of upstream regions with conversation stickiness
+///
+/// Requests try the regions in order (primary first). Once a region has served
+/// a conversation, later turns of that conversation try it first, so a
+/// conversation doesn't bounce between regions after a failover.
+pub struct RegionRouter {
+    regions: Vec<String>,
+    sticky_ttl: Duration,
+    /// Conversation key -> (region, last used)
+    sticky: Mutex<HashMap<u64, (String, Instant)>>,
+}
+
+impl RegionRouter {
+    /// Create a router; duplicate and empty regions are dropped
+    pub fn new(regions: Vec<String>, sticky_ttl: Duration) -> Self {
+        let mut unique: Vec<String> = Vec::new();
+        for region in regions {
+            let region = region.trim().to_string();
+            if !region.is_empty() && !unique.contains(&region) {
+                unique.push(region);
+            }
+        }
+        if unique.is_empty() {
+            unique.push(DEFAULT_REGION.to_string());
+        }
+
+        Self {
+            regions: unique,
+            sticky_ttl,
+            sticky: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Configured regions, primary first
+    pub fn regions(&self) -> &[String] {
+        &self.regions
+    }
+
+    /// Primary region
+    pub fn primary(&self) -> &str {
+        &self.regions[0]
+    }
+
+    /// Regions to try for a request, in order
+    pub fn candidates(&self, conversation: Option<u64>, now: Instant) -> Vec<String> {
+        let sticky = conversation.and_then(|key| {
+            let sticky = self.sticky.lock().unwrap();
+            sticky
+                .get(&key)
+                .filter(|(_, used)| now.duration_since(*used) < self.sticky_ttl)
+                .map(|(region, _)| region.clone())
+        });
+
+        match sticky {
+            Some(region) if self.regions.contains(&region) => std::iter::once(region.clone())
+                .chain(self.regions.iter().filter(|r| **r != region).cloned())
+                .collect(),
+            _ => self.regions.clone(),
+        }
+    }
+
+    /// Remember the region that served a conversation
+    pub fn record(&self, conversation: u64, region: &str, now: Instant) {
+        if self.regions.len() < 2 || self.sticky_ttl.is_zero() {
+            return;
+        }
+
+        let mut sticky = self.sticky.lock().unwrap();
+        if sticky.len() >= MAX_STICKY_CONVERSATIONS && !sticky.contains_key(&conversation) {
+            let ttl = self.sticky_ttl;
+            sticky.retain(|_, (_, used)| now.duration_since(*used) < ttl);
+            if sticky.len() >= MAX_STICKY_CONVERSATIONS {
+                return;
+            }
+        }
+        sticky.insert(conversation, (region.to_string(), now));
+    }
+}
+
+impl Default for RegionRouter {
+    fn default() -> Self {
+        Self::new(vec![DEFAULT_REGION.to_string()], Duration::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn router() -> RegionRouter {
+        RegionRouter::new(
+            vec![
+                "us-east-1".to_string(),
+                "us-west-2".to_string(),
+                " us-east-1 ".to_string(),
+                "eu-west-1".to_string(),
+            ],
+            Duration::from_secs(60),
+        )
+    }
+
+    #[test]
+    fn test_regions_in_order() {
+        let router = router();
+        assert_eq!(router.primary(), "us-east-1");
+        assert_eq!(router.regions(), ["us-east-1", "us-west-2", "eu-west-1"]);
+        assert_eq!(router.candidates(None, Instant::now()), router.regions());
+
+        assert_eq!(
+            RegionRouter::new(vec![], Duration::ZERO).primary(),
+            DEFAULT_REGION
+        );
+    }
+
+    #[test]
+    fn test_conversation_stickiness() {
+        let router = router();
+        let now = Instant::now();
+        let conversation = conversation_key("ci", &json!([{"role": "user", "content": "hi"}]));
+
+        router.record(conversation, "eu-west-1", now);
+        assert_eq!(
+            router.candidates(Some(conversation), now),
+            ["eu-west-1", "us-east-1", "us-west-2"]
+        );
+
+        // Other conversations and other clients keep the configured order
+        let other = conversation_key("other", &json!([{"role": "user", "content": "hi"}]));
+        assert_ne!(other, conversation);
+        assert_eq!(router.candidates(Some(other), now)[0], "us-east-1");
+
+        // Stickiness expires
+        let later = now + Duration::from_secs(60);
+        assert_eq!(router.candidates(Some(conversation), later)[0], "us-east-1");
+    }
+}
@@ -0,0 +1,733 @@
+// Exact-match response cache (in-memory LRU with an optional SQLite tier)
+//
+// Entries are keyed on the request fingerprint (`UpstreamRequest::fingerprint`)
+// and hold the upstream event stream of a completed response. A hit is replayed through
+// the usual converters, so one entry serves OpenAI and Anthropic clients,
+// streaming or not.
+
+use anyhow::{Context, Result};
+use axum::http::{header, HeaderMap};
+use chrono::Utc;
+use futures::stream::{self, StreamExt};
+use rusqlite::{Connection, OptionalExtension};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::keys::matches_pattern;
+use crate::streaming::{KiroEvent, KiroEventStream};
+
+/// Response header reporting the cache outcome (`HIT`, `MISS`, `BYPASS`)
+pub const CACHE_HEADER: &str = "x-cache";
+
+/// Cache outcome for one request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+    /// Not looked up: excluded key or `Cache-Control: no-cache`/`no-store`
+    Bypass,
+}
+
+impl CacheStatus {
+    /// Value of the `x-cache` header
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Bypass => "BYPASS",
+        }
+    }
+
+    /// Metrics label
+    pub fn label(&self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Miss => "miss",
+            CacheStatus::Bypass => "bypass",
+        }
+    }
+}
+
+/// `Cache-Control` request directives
+///
+/// `no-cache` skips the lookup but stores the fresh response; `no-store`
+/// skips both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub no_cache: bool,
+    pub no_store: bool,
+}
+
+impl CacheControl {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut control = Self::default();
+        for value in headers.get_all(header::CACHE_CONTROL) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            for directive in value.split(',') {
+                match directive.trim().to_ascii_lowercase().as_str() {
+                    "no-cache" => control.no_cache = true,
+                    "no-store" => control.no_store = true,
+                    _ => {}
+                }
+            }
+        }
+        control
+    }
+}
+
+/// How often the SQLite tier drops expired rows and applies its caps
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Entry {
+    events: Arc<Vec<KiroEvent>>,
+    /// Serialized size in bytes
+    size: usize,
+    /// Unix seconds
+    stored_at: i64,
+    last_used: u64,
+}
+
+/// In-memory tier, evicting the least recently used entries
+#[derive(Default)]
+struct MemoryTier {
+    entries: HashMap<String, Entry>,
+    /// `last_used` tick -> key
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+}
+
+impl MemoryTier {
+    fn get(&mut self, key: &str, oldest: i64) -> Option<Arc<Vec<KiroEvent>>> {
+        let entry = self.entries.get_mut(key)?;
+        if entry.stored_at <= oldest {
+            self.remove(key);
+            return None;
+        }
+        self.order.remove(&entry.last_used);
+        self.tick += 1;
+        entry.last_used = self.tick;
+        self.order.insert(self.tick, key.to_string());
+        Some(Arc::clone(&entry.events))
+    }
+
+    fn insert(
+        &mut self,
+        key: String,
+        events: Arc<Vec<KiroEvent>>,
+        size: usize,
+        stored_at: i64,
+        max_entries: usize,
+        max_bytes: usize,
+    ) {
+        self.remove(&key);
+        if size > max_bytes {
+            return;
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.bytes += size;
+        self.entries.insert(
+            key,
+            Entry {
+                events,
+                size,
+                stored_at,
+                last_used: self.tick,
+            },
+        );
+        while self.entries.len() > max_entries || self.bytes > max_bytes {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.bytes -= entry.size;
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(old) = self.entries.remove(key) {
+            self.order.remove(&old.last_used);
+            self.bytes -= old.size;
+        }
+    }
+}
+
+/// Size limits and expiry applied to the SQLite tier
+#[derive(Clone, Copy)]
+struct DbLimits {
+    ttl: Duration,
+    max_entries: usize,
+    max_bytes: usize,
+}
+
+enum WriterMessage {
+    Put {
+        key: String,
+        events: String,
+        stored_at: i64,
+    },
+    /// Record a hit for LRU eviction
+    Touch(String, i64),
+    /// Answered once every earlier message has been handled
+    #[cfg_attr(not(test), allow(dead_code))]
+    Flush(mpsc::Sender<()>),
+}
+
+/// Exact-match cache of completed upstream responses
+///
+/// Disabled (no-op) unless `RESPONSE_CACHE` is set. SQLite reads run on the
+/// blocking pool and writes on a dedicated thread, which also prunes the
+/// table every `PRUNE_INTERVAL`.
+pub struct ResponseCache {
+    enabled: bool,
+    ttl: Duration,
+    max_entries: usize,
+    max_bytes: usize,
+    /// Key name patterns using the cache (all keys when empty)
+    keys: Vec<String>,
+    memory: Mutex<MemoryTier>,
+    db: Option<Arc<Mutex<Connection>>>,
+    writer: Option<mpsc::Sender<WriterMessage>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl ResponseCache {
+    /// Cache that never stores anything
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ttl: Duration::ZERO,
+            max_entries: 0,
+            max_bytes: 0,
+            keys: Vec::new(),
+            memory: Mutex::new(MemoryTier::default()),
+            db: None,
+            writer: None,
+            worker: None,
+        }
+    }
+
+    /// In-memory cache holding up to `max_entries` responses for `ttl`
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        let mut cache = Self::disabled();
+        cache.enabled = true;
+        cache.ttl = ttl;
+        cache.max_entries = max_entries;
+        cache.max_bytes = usize::MAX;
+        cache
+    }
+
+    /// Cap the serialized size of the in-memory tier
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Only cache requests from keys matching these patterns
+    pub fn with_keys(mut self, keys: Vec<String>) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// Back the memory tier with a SQLite database holding up to
+    /// `max_entries` responses and `max_bytes` of serialized events
+    pub fn with_db(mut self, path: &Path, max_entries: usize, max_bytes: usize) -> Result<Self> {
+        let conn = Connection::open(path).with_context(|| {
+            format!("Failed to open response cache database: {}", path.display())
+        })?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS response_cache (
+                key TEXT PRIMARY KEY,
+                stored_at INTEGER NOT NULL,
+                last_used INTEGER NOT NULL,
+                events TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS idx_response_cache_last_used
+                ON response_cache (last_used);",
+        )
+        .context("Failed to initialize response cache database")?;
+
+        let conn = Arc::new(Mutex::new(conn));
+        let limits = DbLimits {
+            ttl: self.ttl,
+            max_entries,
+            max_bytes,
+        };
+        let (tx, rx) = mpsc::channel();
+        let writer_conn = Arc::clone(&conn);
+        let worker = std::thread::Builder::new()
+            .name("response-cache".to_string())
+            .spawn(move || run_writer(rx, &writer_conn, limits))
+            .context("Failed to start response cache writer")?;
+        self.db = Some(conn);
+        self.writer = Some(tx);
+        self.worker = Some(worker);
+        Ok(self)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Check whether requests from this key use the cache
+    pub fn applies_to(&self, key_name: &str) -> bool {
+        self.enabled
+            && (self.keys.is_empty()
+                || self
+                    .keys
+                    .iter()
+                    .any(|pattern| matches_pattern(pattern, key_name)))
+    }
+
+    /// Look up a cached response
+    pub async fn get(self: &Arc<Self>, key: &str) -> Option<Arc<Vec<KiroEvent>>> {
+        let now = Utc::now().timestamp();
+        if let Some(events) = self.memory_get(key, now) {
+            return Some(events);
+        }
+        self.db.as_ref()?;
+        let cache = Arc::clone(self);
+        let key = key.to_string();
+        tokio::task::spawn_blocking(move || cache.db_get(&key, now))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    #[cfg(test)]
+    fn get_at(&self, key: &str, now: i64) -> Option<Arc<Vec<KiroEvent>>> {
+        self.memory_get(key, now).or_else(|| self.db_get(key, now))
+    }
+
+    fn memory_get(&self, key: &str, now: i64) -> Option<Arc<Vec<KiroEvent>>> {
+        if !self.enabled {
+            return None;
+        }
+        self.memory
+            .lock()
+            .ok()?
+            .get(key, now - self.ttl.as_secs() as i64)
+    }
+
+    /// Read from SQLite (blocking) and promote hits into memory
+    fn db_get(&self, key: &str, now: i64) -> Option<Arc<Vec<KiroEvent>>> {
+        let conn = self.db.as_ref()?.lock().ok()?;
+        let row: Option<(String, i64)> = conn
+            .prepare_cached(
+                "SELECT events, stored_at FROM response_cache WHERE key = ?1 AND stored_at > ?2",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_row(
+                    rusqlite::params![key, now - self.ttl.as_secs() as i64],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()
+            })
+            .inspect_err(|e| tracing::warn!("Response cache lookup failed: {}", e))
+            .ok()?;
+        drop(conn);
+        let (json, stored_at) = row?;
+        let events = Arc::new(serde_json::from_str::<Vec<KiroEvent>>(&json).ok()?);
+
+        self.send(WriterMessage::Touch(key.to_string(), now));
+        if let Ok(mut memory) = self.memory.lock() {
+            memory.insert(
+                key.to_string(),
+                Arc::clone(&events),
+                json.len(),
+                stored_at,
+                self.max_entries,
+                self.max_bytes,
+            );
+        }
+        Some(events)
+    }
+
+    /// Store a completed response
+    ///
+    /// Empty responses and responses with error events are not stored.
+    pub fn put(&self, key: &str, events: Vec<KiroEvent>) {
+        self.put_at(key, events, Utc::now().timestamp());
+    }
+
+    fn put_at(&self, key: &str, events: Vec<KiroEvent>, now: i64) {
+        if !self.enabled || events.is_empty() || events.iter().any(|e| e.event_type == "error") {
+            return;
+        }
+        let Ok(json) = serde_json::to_string(&events) else {
+            return;
+        };
+
+        if let Ok(mut memory) = self.memory.lock() {
+            memory.insert(
+                key.to_string(),
+                Arc::new(events),
+                json.len(),
+                now,
+                self.max_entries,
+                self.max_bytes,
+            );
+        }
+        self.send(WriterMessage::Put {
+            key: key.to_string(),
+            events: json,
+            stored_at: now,
+        });
+    }
+
+    fn send(&self, message: WriterMessage) {
+        if let Some(ref writer) = self.writer {
+            if writer.send(message).is_err() {
+                tracing::warn!("Response cache writer stopped; not persisting response");
+            }
+        }
+    }
+
+    /// Wait until every write queued so far has been applied
+    #[cfg(test)]
+    fn flush(&self) {
+        let (tx, rx) = mpsc::channel();
+        if let Some(ref writer) = self.writer {
+            if writer.send(WriterMessage::Flush(tx)).is_ok() {
+                let _ = rx.recv();
+            }
+        }
+    }
+
+    /// Pass an upstream event stream through, storing it once it completes
+    ///
+    /// Streams that fail or are dropped early are not stored.
+    pub fn record(self: &Arc<Self>, key: String, events: KiroEventStream) -> KiroEventStream {
+        let cache = Arc::clone(self);
+        stream::unfold(
+            (events, Vec::new(), Some((cache, key))),
+            |(mut events, mut recorded, store)| async move {
+                match events.next().await {
+                    Some(Ok(event)) => {
+                        if store.is_some() {
+                            recorded.push(event.clone());
+                        }
+                        Some((Ok(event), (events, recorded, store)))
+                    }
+                    Some(Err(e)) => Some((Err(e), (events, Vec::new(), None))),
+                    None => {
+                        if let Some((cache, key)) = store {
+                            cache.put(&key, recorded);
+                        }
+                        None
+                    }
+                }
+            },
+        )
+        .boxed()
+    }
+}
+
+impl Drop for ResponseCache {
+    fn drop(&mut self) {
+        // Closing the channel lets the writer drain what is queued and exit
+        self.writer.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run_writer(rx: mpsc::Receiver<WriterMessage>, conn: &Mutex<Connection>, limits: DbLimits) {
+    let mut next_prune = Instant::now() + PRUNE_INTERVAL;
+    loop {
+        match rx.recv_timeout(next_prune.saturating_duration_since(Instant::now())) {
+            Ok(first) => {
+                let mut batch = vec![first];
+                batch.extend(rx.try_iter());
+                write_batch(conn, batch);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        if Instant::now() >= next_prune {
+            if let Ok(conn) = conn.lock() {
+                if let Err(e) = prune(&conn, Utc::now().timestamp(), limits) {
+                    tracing::warn!("Failed to prune response cache database: {}", e);
+                }
+            }
+            next_prune = Instant::now() + PRUNE_INTERVAL;
+        }
+    }
+}
+
+fn write_batch(conn: &Mutex<Connection>, batch: Vec<WriterMessage>) {
+    let mut acks = Vec::new();
+    if let Ok(mut conn) = conn.lock() {
+        let result = conn.transaction().and_then(|tx| {
+            for message in batch {
+                match message {
+                    WriterMessage::Put {
+                        key,
+                        events,
+                        stored_at,
+                    } => {
+                        tx.prepare_cached(
+                            "INSERT OR REPLACE INTO response_cache (key, stored_at, last_used, events)
+                             VALUES (?1, ?2, ?2, ?3)",
+                        )?
+                        .execute(rusqlite::params![key, stored_at, events])?;
+                    }
+                    WriterMessage::Touch(key, now) => {
+                        tx.prepare_cached("UPDATE response_cache SET last_used = ?2 WHERE key = ?1")?
+                            .execute(rusqlite::params![key, now])?;
+                    }
+                    WriterMessage::Flush(ack) => acks.push(ack),
+                }
+            }
+            tx.commit()
+        });
+        if let Err(e) = result {
+            tracing::warn!("Failed to store responses in cache database: {}", e);
+        }
+    }
+
+    for ack in acks {
+        let _ = ack.send(());
+    }
+}
+
+/// Drop expired rows, then the least recently used beyond either cap
+fn prune(conn: &Connection, now: i64, limits: DbLimits) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM response_cache WHERE stored_at <= ?1",
+        [now - limits.ttl.as_secs() as i64],
+    )?;
+    conn.execute(
+        "DELETE FROM response_cache WHERE key IN (
+            SELECT key FROM (
+                SELECT key,
+                       ROW_NUMBER() OVER recent AS n,
+                       SUM(length(events)) OVER recent AS total
+                FROM response_cache
+                WINDOW recent AS (ORDER BY last_used DESC, stored_at DESC
+                                  ROWS UNBOUNDED PRECEDING)
+            ) WHERE n > ?1 OR total > ?2
+         )",
+        rusqlite::params![
+            limits.max_entries as i64,
+            i64::try_from(limits.max_bytes).unwrap_or(i64::MAX)
+        ],
+    )?;
+    Ok(())
+}
+
+/// Replay a cached response as an event stream
+pub fn replay(events: Arc<Vec<KiroEvent>>) -> KiroEventStream {
+    stream::iter(events.as_ref().clone().into_iter().map(Ok)).boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ApiError;
+
+    fn event(event_type: &str, content: Option<&str>) -> KiroEvent {
+        KiroEvent {
+            event_type: event_type.to_string(),
+            content: content.map(str::to_string),
+            thinking_content: None,
+            tool_use: None,
+            usage: None,
+            context_usage_percentage: None,
+            is_first_thinking_chunk: false,
+            is_last_thinking_chunk: false,
+        }
+    }
+
+    fn content(text: &str) -> KiroEvent {
+        event("content", Some(text))
+    }
+
+    #[test]
+    fn test_cache_control() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            CacheControl::from_headers(&headers),
+            CacheControl::default()
+        );
+
+        headers.insert(
+            header::CACHE_CONTROL,
+            "max-age=0, No-Cache".parse().unwrap(),
+        );
+        let control = CacheControl::from_headers(&headers);
+        assert!(control.no_cache);
+        assert!(!control.no_store);
+
+        headers.insert(header::CACHE_CONTROL, "no-store".parse().unwrap());
+        assert!(CacheControl::from_headers(&headers).no_store);
+    }
+
+    #[test]
+    fn test_ttl_and_lru_eviction() {
+        let cache = ResponseCache::new(Duration::from_secs(60), 2);
+        cache.put_at("a", vec![content("A")], 1000);
+        cache.put_at("b", vec![content("B")], 1000);
+        assert!(cache.get_at("a", 1010).is_some());
+
+        // "b" is least recently used
+        cache.put_at("c", vec![content("C")], 1020);
+        assert!(cache.get_at("b", 1020).is_none());
+        assert_eq!(
+            cache.get_at("a", 1020).unwrap()[0].content.as_deref(),
+            Some("A")
+        );
+        assert!(cache.get_at("a", 1060).is_none());
+        assert!(cache.get_at("c", 1060).is_some());
+
+        // Incomplete answers are not stored
+        cache.put_at("d", Vec::new(), 1000);
+        cache.put_at("e", vec![event("error", None)], 1000);
+        assert!(cache.get_at("d", 1000).is_none());
+        assert!(cache.get_at("e", 1000).is_none());
+    }
+
+    #[test]
+    fn test_key_patterns() {
+        assert!(!ResponseCache::disabled().applies_to("ci"));
+        let cache = ResponseCache::new(Duration::from_secs(60), 10);
+        assert!(cache.applies_to("anyone"));
+        let cache = cache.with_keys(vec!["ci-*".to_string()]);
+        assert!(cache.applies_to("ci-nightly"));
+        assert!(!cache.applies_to("alice"));
+    }
+
+    #[test]
+    fn test_memory_byte_cap() {
+        let size = serde_json::to_string(&vec![content("A")]).unwrap().len();
+        let cache = ResponseCache::new(Duration::from_secs(60), 10).with_max_bytes(size * 2);
+        cache.put_at("a", vec![content("A")], 1000);
+        cache.put_at("b", vec![content("B")], 1000);
+        cache.put_at("c", vec![content("C")], 1000);
+        assert!(cache.get_at("a", 1000).is_none());
+        assert!(cache.get_at("b", 1000).is_some());
+        assert!(cache.get_at("c", 1000).is_some());
+
+        // Larger than the whole tier
+        cache.put_at("d", vec![content(&"x".repeat(size * 2))], 1000);
+        assert!(cache.get_at("d", 1000).is_none());
+        assert!(cache.get_at("c", 1000).is_some());
+    }
+
+    #[test]
+    fn test_sqlite_tier_survives_restart() {
+        let path = std::env::temp_dir().join(format!("kiro-cache-{}.db", uuid::Uuid::new_v4()));
+        let open = || {
+            ResponseCache::new(Duration::from_secs(60), 1)
+                .with_db(&path, 2, usize::MAX)
+                .unwrap()
+        };
+
+        let cache = open();
+        cache.put_at("a", vec![content("A")], 1000);
+        cache.put_at("b", vec![content("B")], 1001);
+        cache.flush();
+        // Evicted from memory, still in SQLite
+        assert_eq!(
+            cache.get_at("a", 1002).unwrap()[0].content.as_deref(),
+            Some("A")
+        );
+        drop(cache);
+
+        let cache = open();
+        assert!(cache.get_at("b", 1010).is_some());
+        assert!(cache.get_at("b", 1061).is_none());
+        drop(cache);
+
+        for suffix in ["", "-wal", "-shm"] {
+            std::fs::remove_file(format!("{}{}", path.display(), suffix)).ok();
+        }
+    }
+
+    #[test]
+    fn test_sqlite_prune() {
+        let path = std::env::temp_dir().join(format!("kiro-cache-{}.db", uuid::Uuid::new_v4()));
+        let cache = ResponseCache::new(Duration::from_secs(60), 10)
+            .with_db(&path, 3, usize::MAX)
+            .unwrap();
+        for (i, key) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            cache.put_at(key, vec![content(&key.repeat(100))], 1000 + i as i64);
+        }
+        cache.flush();
+        // A hit makes "a" the most recently used
+        *cache.memory.lock().unwrap() = MemoryTier::default();
+        cache.get_at("a", 1010).unwrap();
+        cache.flush();
+
+        let conn = cache.db.as_ref().unwrap().lock().unwrap();
+        let keys = |conn: &Connection| -> Vec<String> {
+            conn.prepare("SELECT key FROM response_cache ORDER BY key")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap()
+        };
+        assert_eq!(keys(&conn).len(), 4);
+
+        let mut limits = DbLimits {
+            ttl: Duration::from_secs(60),
+            max_entries: 3,
+            max_bytes: usize::MAX,
+        };
+        prune(&conn, 1020, limits).unwrap();
+        assert_eq!(keys(&conn), ["a", "c", "d"]);
+
+        // Room for two responses
+        let size: i64 = conn
+            .query_row(
+                "SELECT length(events) FROM response_cache WHERE key = 'a'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        limits.max_bytes = size as usize * 2 + 1;
+        prune(&conn, 1020, limits).unwrap();
+        assert_eq!(keys(&conn), ["a", "d"]);
+
+        prune(&conn, 1063, limits).unwrap();
+        assert!(keys(&conn).is_empty());
+        drop(conn);
+        drop(cache);
+
+        for suffix in ["", "-wal", "-shm"] {
+            std::fs::remove_file(format!("{}{}", path.display(), suffix)).ok();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_stores_completed_streams_only() {
+        let cache = Arc::new(ResponseCache::new(Duration::from_secs(60), 10));
+
+        let events = stream::iter(vec![Ok(content("Hel")), Ok(content("lo"))]).boxed();
+        let replayed: Vec<_> = cache.record("ok".to_string(), events).collect().await;
+        assert_eq!(replayed.len(), 2);
+        let cached = cache.get("ok").await.unwrap();
+        assert_eq!(cached.len(), 2);
+        assert_eq!(replay(cached).count().await, 2);
+
+        let events = stream::iter(vec![
+            Ok(content("Hel")),
+            Err(ApiError::Internal(anyhow::anyhow!("stream reset"))),
+        ])
+        .boxed();
+        let _: Vec<_> = cache.record("failed".to_string(), events).collect().await;
+        assert!(cache.get("failed").await.is_none());
+    }
+}
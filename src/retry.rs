@@ -0,0 +1,297 @@
+// Upstream retry policy: Retry-After parsing, jittered backoff, retry budget
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sliding window over which the retry budget is computed
+const BUDGET_WINDOW: Duration = Duration::from_secs(10);
+
+/// Upper bound for a single retry delay (backoff or Retry-After)
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Delay requested by the upstream via `Retry-After`
+///
+/// Supports both the delta-seconds (`Retry-After: 120`) and the HTTP-date
+/// (`Retry-After: Wed, 21 Oct 2015 07:28:00 GMT`) forms. Dates in the past
+/// yield a zero delay.
+pub fn parse_retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Decorrelated jitter backoff
+///
+/// Each delay is drawn uniformly from `[base, previous * 3]` and capped, so
+/// clients that were throttled together spread out instead of retrying in
+/// lockstep.
+pub struct Backoff {
+    base: Duration,
+    cap: Duration,
+    previous: Duration,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, cap: Duration) -> Self {
+        Self {
+            base,
+            cap,
+            previous: base,
+        }
+    }
+
+    /// Next delay, using `sample` in `[0, 1)` as the random draw
+    pub fn next_with(&mut self, sample: f64) -> Duration {
+        let low = self.base.as_secs_f64();
+        let high = (self.previous.as_secs_f64() * 3.0).max(low);
+        let delay = Duration::from_secs_f64(low + (high - low) * sample).min(self.cap);
+        self.previous = delay;
+        delay
+    }
+
+    /// Next delay with a random draw
+    pub fn next_delay(&mut self) -> Duration {
+        self.next_with(rand::random())
+    }
+}
+
+/// Process-wide retry budget
+///
+/// Retries are allowed while the retries in the last 10 seconds stay within
+/// `min_per_sec * 10 + ratio * requests`. When the upstream is struggling
+/// this caps the extra load retries add, instead of multiplying it by the
+/// retry count.
+pub struct RetryBudget {
+    ratio: f64,
+    min_per_sec: u32,
+    history: Mutex<BudgetHistory>,
+}
+
+#[derive(Default)]
+struct BudgetHistory {
+    requests: VecDeque<Instant>,
+    retries: VecDeque<Instant>,
+}
+
+impl BudgetHistory {
+    fn prune(&mut self, now: Instant) {
+        for entries in [&mut self.requests, &mut self.retries] {
+            while let Some(&at) = entries.front() {
+                if now.duration_since(at) < BUDGET_WINDOW {
+                    break;
+                }
+                entries.pop_front();
+            }
+        }
+    }
+}
+
+impl RetryBudget {
+    pub fn new(ratio: f64, min_per_sec: u32) -> Self {
+        Self {
+            ratio: ratio.max(0.0),
+            min_per_sec,
+            history: Mutex::new(BudgetHistory::default()),
+        }
+    }
+
+    /// Record an original (non-retry) request
+    pub fn record_request(&self, now: Instant) {
+        let mut history = self.history.lock().unwrap();
+        history.prune(now);
+        history.requests.push_back(now);
+    }
+
+    /// Take one retry from the budget, returning false if it is exhausted
+    pub fn try_acquire(&self, now: Instant) -> bool {
+        let mut history = self.history.lock().unwrap();
+        history.prune(now);
+
+        let allowed = self.min_per_sec as f64 * BUDGET_WINDOW.as_secs_f64()
+            + self.ratio * history.requests.len() as f64;
+        if (history.retries.len() + 1) as f64 <= allowed {
+            history.retries.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Network errors worth retrying, with the label used in metrics
+///
+/// Timeouts, refused or reset connections and connections closed mid-response
+/// are transient. Everything else (invalid requests, redirect loops, decode
+/// and certificate errors) fails the same way on every attempt.
+pub fn retryable_error_kind(error: &reqwest::Error) -> Option<&'static str> {
+    if error.is_timeout() {
+        return Some("timeout");
+    }
+
+    let mut source = std::error::Error::source(error);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            use std::io::ErrorKind;
+            match io.kind() {
+                ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::BrokenPipe
+                | ErrorKind::UnexpectedEof => return Some("connection_reset"),
+                ErrorKind::TimedOut => return Some("timeout"),
+                ErrorKind::ConnectionRefused => return Some("connection_refused"),
+                _ => {}
+            }
+        }
+        if let Some(hyper_err) = err.downcast_ref::<hyper::Error>() {
+            if hyper_err.is_incomplete_message() || hyper_err.is_closed() {
+                return Some("connection_reset");
+            }
+        }
+        source = err.source();
+    }
+
+    // Connect errors without a recognizable cause (e.g. DNS lookup failures)
+    if error.is_connect() && !is_certificate_error(error) {
+        return Some("connection_failed");
+    }
+
+    None
+}
+
+/// Check whether a connect error was caused by TLS certificate validation
+fn is_certificate_error(error: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(error);
+    while let Some(err) = source {
+        if err.is::<rustls::Error>() {
+            return true;
+        }
+        // rustls errors reach reqwest wrapped in an io::Error
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            if io
+                .get_ref()
+                .is_some_and(|inner| inner.is::<rustls::Error>())
+            {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+// Simple random number generation for jitter
+mod rand {
+    use std::collections::hash_map::RandomState;
+    use std::hash::BuildHasher;
+
+    pub fn random() -> f64 {
+        let state = RandomState::new();
+        (state.hash_one(std::time::SystemTime::now()) % 1000) as f64 / 1000.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn retry_after(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:27:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(
+            parse_retry_after(&retry_after("120"), now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after(&retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), now),
+            Some(Duration::from_secs(60))
+        );
+        // Dates in the past mean "retry now"
+        assert_eq!(
+            parse_retry_after(&retry_after("Wed, 21 Oct 2015 07:00:00 GMT"), now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after(&retry_after("soon"), now), None);
+        assert_eq!(parse_retry_after(&HeaderMap::new(), now), None);
+    }
+
+    #[test]
+    fn test_decorrelated_jitter() {
+        let base = Duration::from_millis(1000);
+        let mut backoff = Backoff::new(base, MAX_RETRY_DELAY);
+
+        // Lowest draw stays at the base delay
+        assert_eq!(backoff.next_with(0.0), base);
+
+        // Highest draw triples the previous delay
+        assert_eq!(backoff.next_with(0.999_999).as_millis(), 2999);
+        let third = backoff.next_with(0.5);
+        assert!(third > base && third < Duration::from_secs(9));
+
+        // Delays never exceed the cap
+        for _ in 0..20 {
+            assert!(backoff.next_with(0.999) <= MAX_RETRY_DELAY);
+        }
+
+        // Random draws stay within [base, cap]
+        let mut backoff = Backoff::new(base, MAX_RETRY_DELAY);
+        for _ in 0..20 {
+            let delay = backoff.next_delay();
+            assert!(delay >= base && delay <= MAX_RETRY_DELAY);
+        }
+    }
+
+    #[test]
+    fn test_retry_budget() {
+        let budget = RetryBudget::new(0.1, 0);
+        let start = Instant::now();
+
+        // No traffic, no floor: nothing to retry
+        assert!(!budget.try_acquire(start));
+
+        // 10% of 20 requests = 2 retries
+        for _ in 0..20 {
+            budget.record_request(start);
+        }
+        assert!(budget.try_acquire(start));
+        assert!(budget.try_acquire(start));
+        assert!(!budget.try_acquire(start));
+
+        // The window slides: old requests and retries no longer count
+        let later = start + BUDGET_WINDOW;
+        budget.record_request(later);
+        assert!(!budget.try_acquire(later));
+        for _ in 0..9 {
+            budget.record_request(later);
+        }
+        assert!(budget.try_acquire(later));
+
+        // The floor allows retries at low traffic
+        let budget = RetryBudget::new(0.0, 1);
+        for _ in 0..10 {
+            assert!(budget.try_acquire(start));
+        }
+        assert!(!budget.try_acquire(start));
+    }
+}
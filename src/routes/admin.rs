@@ -0,0 +1,263 @@
+// Admin API: usage reporting, budget management, virtual keys and model refresh
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    middleware::{self as axum_middleware},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use super::AppState;
+use crate::catalog;
+use crate::error::ApiError;
+use crate::keys::NewVirtualKey;
+use crate::middleware;
+use crate::usage::{self, Budget, GroupBy, UsageQuery};
+
+/// Admin routes (require `ADMIN_API_KEY`)
+pub fn admin_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/admin/usage", get(get_usage_handler))
+        .route("/admin/budgets", get(list_budgets_handler))
+        .route(
+            "/admin/budgets/:key",
+            put(put_budget_handler).delete(delete_budget_handler),
+        )
+        .route(
+            "/admin/keys",
+            get(list_keys_handler).post(create_key_handler),
+        )
+        .route("/admin/keys/:id", delete(revoke_key_handler))
+        .route("/admin/models/refresh", post(refresh_models_handler))
+        .route_layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            middleware::admin_auth_middleware,
+        ))
+        .with_state(state)
+}
+
+/// Query parameters for GET /admin/usage
+#[derive(Debug, Default, Deserialize)]
+struct UsageParams {
+    group_by: Option<String>,
+    key: Option<String>,
+    model: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    format: Option<String>,
+}
+
+/// Fail with a clear message when no usage database is configured
+fn require_ledger(state: &AppState) -> Result<(), ApiError> {
+    if state.usage_ledger.is_enabled() {
+        Ok(())
+    } else {
+        Err(ApiError::ConfigError(
+            "Usage ledger is disabled (set USAGE_DB_FILE)".to_string(),
+        ))
+    }
+}
+
+/// Run synchronous SQLite work on the blocking pool
+async fn blocking<T, F>(work: F) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(anyhow::Error::from)?
+        .map_err(ApiError::from)
+}
+
+/// Validate a `YYYY-MM-DD` day filter
+fn parse_day(name: &str, value: Option<String>) -> Result<Option<String>, ApiError> {
+    match value.filter(|v| !v.is_empty()) {
+        Some(v) => chrono::NaiveDate::parse_from_str(&v, "%Y-%m-%d")
+            .map(|_| Some(v.clone()))
+            .map_err(|_| {
+                ApiError::ValidationError(format!("'{}' must be a date (YYYY-MM-DD): {}", name, v))
+            }),
+        None => Ok(None),
+    }
+}
+
+/// GET /admin/usage - Aggregated usage from the ledger
+///
+/// Supports `group_by=key,model,day`, `key`/`model` filters, an inclusive
+/// `from`/`to` day range and `format=json|csv`.
+async fn get_usage_handler(
+    State(state): State<AppState>,
+    Query(params): Query<UsageParams>,
+) -> Result<Response, ApiError> {
+    require_ledger(&state)?;
+
+    let group_by = GroupBy::parse_list(params.group_by.as_deref().unwrap_or_default())
+        .map_err(ApiError::ValidationError)?;
+    let query = UsageQuery {
+        group_by,
+        key: params.key.filter(|v| !v.is_empty()),
+        model: params.model.filter(|v| !v.is_empty()),
+        from: parse_day("from", params.from)?,
+        to: parse_day("to", params.to)?,
+    };
+
+    let group_by = query.group_by.clone();
+    let ledger = Arc::clone(&state.usage_ledger);
+    let rows = blocking(move || ledger.query(&query)).await?;
+
+    match params.format.as_deref().unwrap_or("json") {
+        "json" => Ok(Json(json!({ "data": rows })).into_response()),
+        "csv" => Ok((
+            [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+            usage::to_csv(&rows, &group_by),
+        )
+            .into_response()),
+        other => Err(ApiError::ValidationError(format!(
+            "Invalid format '{}' (expected json or csv)",
+            other
+        ))),
+    }
+}
+
+/// GET /admin/budgets - List configured budgets
+async fn list_budgets_handler(State(state): State<AppState>) -> Result<Response, ApiError> {
+    require_ledger(&state)?;
+    Ok(Json(json!({ "budgets": state.usage_ledger.list_budgets() })).into_response())
+}
+
+/// PUT /admin/budgets/:key - Set the daily/monthly budget for a key
+async fn put_budget_handler(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Json(budget): Json<Budget>,
+) -> Result<Response, ApiError> {
+    require_ledger(&state)?;
+    tracing::info!("Setting usage budget for key '{}': {:?}", key, budget);
+    let body = json!({ "key": key, "budget": budget });
+    let ledger = Arc::clone(&state.usage_ledger);
+    blocking(move || ledger.set_budget(&key, budget)).await?;
+    Ok(Json(body).into_response())
+}
+
+/// DELETE /admin/budgets/:key - Remove the budget for a key
+async fn delete_budget_handler(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Response, ApiError> {
+    require_ledger(&state)?;
+    let ledger = Arc::clone(&state.usage_ledger);
+    let budget_key = key.clone();
+    if blocking(move || ledger.delete_budget(&budget_key)).await? {
+        tracing::info!("Removed usage budget for key '{}'", key);
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Err(ApiError::NotFound(format!(
+            "No budget configured for key '{}'",
+            key
+        )))
+    }
+}
+
+/// Fail with a clear message when no virtual key database is configured
+fn require_virtual_keys(state: &AppState) -> Result<(), ApiError> {
+    if state.virtual_keys.is_enabled() {
+        Ok(())
+    } else {
+        Err(ApiError::ConfigError(
+            "Virtual keys are disabled (set VIRTUAL_KEYS_DB_FILE)".to_string(),
+        ))
+    }
+}
+
+/// POST /admin/keys - Issue an ephemeral virtual key
+///
+/// The raw key is returned only in this response.
+async fn create_key_handler(
+    State(state): State<AppState>,
+    Json(params): Json<NewVirtualKey>,
+) -> Result<Response, ApiError> {
+    require_virtual_keys(&state)?;
+    params.validate().map_err(ApiError::ValidationError)?;
+    if let Some(name) = params
+        .name
+        .as_deref()
+        .filter(|n| state.virtual_keys.name_in_use(n))
+    {
+        return Err(ApiError::ValidationError(format!(
+            "Virtual key name '{}' is already in use",
+            name
+        )));
+    }
+
+    let store = Arc::clone(&state.virtual_keys);
+    let (key, secret) = blocking(move || store.issue(params)).await?;
+    tracing::info!(
+        "Issued virtual key '{}' (id {}), expires at {}",
+        key.name,
+        key.id,
+        key.expires_at.to_rfc3339()
+    );
+
+    let mut body = serde_json::to_value(&key).map_err(anyhow::Error::from)?;
+    body["key"] = json!(secret);
+    Ok((StatusCode::CREATED, Json(body)).into_response())
+}
+
+/// GET /admin/keys - List live virtual keys (without secrets)
+async fn list_keys_handler(State(state): State<AppState>) -> Result<Response, ApiError> {
+    require_virtual_keys(&state)?;
+    Ok(Json(json!({ "keys": state.virtual_keys.list() })).into_response())
+}
+
+/// DELETE /admin/keys/:id - Revoke a virtual key
+async fn revoke_key_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    require_virtual_keys(&state)?;
+    let store = Arc::clone(&state.virtual_keys);
+    let key_id = id.clone();
+    if blocking(move || store.revoke(&key_id)).await? {
+        tracing::info!("Revoked virtual key {}", id);
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Err(ApiError::NotFound(format!(
+            "No virtual key with id '{}'",
+            id
+        )))
+    }
+}
+
+/// POST /admin/models/refresh - Reload the model catalogue now
+///
+/// On failure the previously cached catalogue keeps being served.
+async fn refresh_models_handler(State(state): State<AppState>) -> Result<Response, ApiError> {
+    let diff = catalog::refresh(
+        &state.http_client,
+        &state.auth_manager,
+        &state.model_cache,
+        state.config.model_snapshot_file.as_deref(),
+    )
+    .await
+    .map_err(|e| ApiError::KiroApiError {
+        status: 502,
+        message: format!(
+            "Model refresh failed, keeping the cached catalogue: {:#}",
+            e
+        ),
+    })?;
+
+    Ok(Json(json!({
+        "models": state.model_cache.get_all_model_ids().len(),
+        "added": diff.added,
+        "removed": diff.removed,
+        "updated": diff.updated,
+    }))
+    .into_response())
+}
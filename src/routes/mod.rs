@@ -1,76 +1,148 @@
+mod admin;
+
 use axum::{
     body::Body,
-    extract::State,
+    extract::{DefaultBodyLimit, State},
+    http::HeaderValue,
     middleware::{self as axum_middleware},
     response::{IntoResponse, Response},
     routing::{get, post},
-    Json, Router,
+    Extension, Json, Router,
 };
 use bytes::Bytes;
 use chrono::Utc;
 use futures::stream::StreamExt;
 use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use uuid::Uuid;
 
+use crate::admission::{Priority, PRIORITY_HEADER};
 use crate::auth::AuthManager;
+use crate::backends::{BackendRouter, Route, UpstreamRequest, UpstreamResponse};
 use crate::cache::ModelCache;
+use crate::coalescing::Coalescer;
 use crate::config::Config;
-use crate::converters::anthropic_to_kiro::build_kiro_payload as build_kiro_payload_anthropic;
-use crate::converters::openai_to_kiro::build_kiro_payload;
-use crate::error::ApiError;
+use crate::converters::core::{normalize_images, ImageLimits, UnifiedMessage};
+use crate::documents::{has_documents, render_documents, DocumentLimits};
+use crate::error::{AnthropicApiError, ApiError};
 use crate::http_client::KiroHttpClient;
+use crate::image_fetch::ImageFetcher;
+use crate::keys::{ClientIdentity, JwtValidator, KeyStore, VirtualKeyStore, DEFAULT_KEY_NAME};
 use crate::metrics::MetricsCollector;
 use crate::middleware;
-use crate::middleware::DEBUG_LOGGER;
+use crate::middleware::{NetworkPolicy, RateLimiter, TokenCharge, DEBUG_LOGGER};
 use crate::models::anthropic::AnthropicMessagesRequest;
 use crate::models::openai::{ChatCompletionRequest, ModelList, OpenAIModel};
+use crate::prompt_cache::PromptCache;
+use crate::regions::conversation_key;
 use crate::resolver::ModelResolver;
-use crate::tokenizer::{count_anthropic_message_tokens, count_message_tokens, count_tools_tokens};
+use crate::response_cache::{replay, CacheControl, CacheStatus, ResponseCache, CACHE_HEADER};
+use crate::tokenizer::{
+    count_anthropic_message_tokens, count_images_tokens, count_message_tokens, count_tools_tokens,
+};
+use crate::usage::{UsageLedger, UsageRecord, STATUS_ERROR, STATUS_OK};
 use std::time::Instant;
 
+pub use admin::admin_routes;
+
 /// Application version from Cargo.toml
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Response header naming the upstream region that served the request
+pub const REGION_HEADER: &str = "x-kiro-region";
+
 /// Application state shared across handlers
 #[derive(Clone)]
 pub struct AppState {
     pub proxy_api_key: String,
+    pub key_store: Arc<KeyStore>,
+    pub virtual_keys: Arc<VirtualKeyStore>,
+    pub network_policy: Arc<NetworkPolicy>,
+    pub jwt_validator: Option<Arc<JwtValidator>>,
+    pub rate_limiter: Arc<RateLimiter>,
     pub model_cache: ModelCache,
     pub auth_manager: Arc<AuthManager>,
     pub http_client: Arc<KiroHttpClient>,
+    pub backends: Arc<BackendRouter>,
+    pub response_cache: Arc<ResponseCache>,
+    pub coalescer: Arc<Coalescer>,
+    pub image_fetcher: Arc<ImageFetcher>,
+    pub prompt_cache: Arc<PromptCache>,
     pub resolver: ModelResolver,
     pub config: Arc<Config>,
     pub metrics: Arc<MetricsCollector>,
+    pub usage_ledger: Arc<UsageLedger>,
 }
 
 /// Guard to ensure active connections are decremented on drop
+///
+/// Failed requests (dropped without `complete`) are recorded in the usage ledger
+/// with an error status, unless the request was handed off to a stream tracker.
 struct RequestGuard {
     metrics: Arc<MetricsCollector>,
+    ledger: Arc<UsageLedger>,
+    virtual_keys: Arc<VirtualKeyStore>,
     start_time: Instant,
     model: String,
+    key: String,
+    /// Served from the response cache
+    cached: bool,
+    /// Joined another request's upstream call
+    coalesced: bool,
     completed: bool,
+    handed_off: bool,
 }
 
 impl RequestGuard {
-    fn new(metrics: Arc<MetricsCollector>, model: String) -> Self {
-        metrics.record_request_start();
+    fn new(state: &AppState, model: String, key: String) -> Self {
+        state.metrics.record_request_start();
         Self {
-            metrics,
+            metrics: Arc::clone(&state.metrics),
+            ledger: Arc::clone(&state.usage_ledger),
+            virtual_keys: Arc::clone(&state.virtual_keys),
             start_time: Instant::now(),
             model,
+            key,
+            cached: false,
+            coalesced: false,
             completed: false,
+            handed_off: false,
         }
     }
 
-    fn complete(&mut self, input_tokens: u64, output_tokens: u64) {
+    fn complete(&mut self, input_tokens: u64, output_tokens: u64, credits: f64) {
         if !self.completed {
             let latency_ms = self.start_time.elapsed().as_secs_f64() * 1000.0;
             self.metrics
                 .record_request_end(latency_ms, &self.model, input_tokens, output_tokens);
+            self.metrics
+                .record_key_usage(&self.key, latency_ms, input_tokens, output_tokens);
+            self.ledger.record(&UsageRecord {
+                key: self.key.clone(),
+                model: self.model.clone(),
+                input_tokens,
+                output_tokens,
+                // Cached and coalesced responses spend no upstream credits of their own
+                credits: if self.cached || self.coalesced {
+                    0.0
+                } else {
+                    credits
+                },
+                latency_ms,
+                status: STATUS_OK.to_string(),
+                cached: self.cached,
+                coalesced: self.coalesced,
+            });
+            self.virtual_keys
+                .record_tokens(&self.key, input_tokens + output_tokens);
             self.completed = true;
         }
     }
+
+    /// Release the guard once a `StreamingMetricsTracker` has taken over accounting
+    fn hand_off(mut self) {
+        self.handed_off = true;
+    }
 }
 
 impl Drop for RequestGuard {
@@ -80,6 +152,20 @@ impl Drop for RequestGuard {
             self.metrics
                 .active_connections
                 .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+
+            if !self.handed_off {
+                self.ledger.record(&UsageRecord {
+                    key: self.key.clone(),
+                    model: self.model.clone(),
+                    input_tokens: 0,
+                    output_tokens: 0,
+                    credits: 0.0,
+                    latency_ms: self.start_time.elapsed().as_secs_f64() * 1000.0,
+                    status: STATUS_ERROR.to_string(),
+                    cached: self.cached,
+                    coalesced: self.coalesced,
+                });
+            }
         }
     }
 }
@@ -87,41 +173,240 @@ impl Drop for RequestGuard {
 fn error_type_from_api_error(err: &ApiError) -> &'static str {
     match err {
         ApiError::AuthError(_) => "auth",
+        ApiError::Forbidden(_) => "forbidden",
+        ApiError::NotFound(_) => "not_found",
+        ApiError::RateLimited { .. } => "rate_limited",
+        ApiError::BudgetExceeded { .. } => "budget_exceeded",
         ApiError::ValidationError(_) => "validation",
+        ApiError::PayloadTooLarge(_) => "validation",
         ApiError::KiroApiError { .. } => "upstream",
+        ApiError::Overloaded { .. } => "overloaded",
         ApiError::Internal(_) => "internal",
         ApiError::InvalidModel(_) => "validation",
         ApiError::ConfigError(_) => "config",
     }
 }
 
+/// Identity of the calling client, as inserted by `auth_middleware`
+fn client_identity(identity: Option<Extension<ClientIdentity>>) -> ClientIdentity {
+    identity
+        .map(|Extension(identity)| identity)
+        .unwrap_or_else(|| ClientIdentity::unrestricted(DEFAULT_KEY_NAME))
+}
+
+/// Reject models outside the client key's allowed list
+fn check_model_allowed(
+    state: &AppState,
+    identity: &ClientIdentity,
+    requested: &str,
+    model_id: &str,
+) -> Result<(), ApiError> {
+    if identity.allows_model(model_id) {
+        return Ok(());
+    }
+
+    tracing::warn!(
+        "Client key '{}' is not allowed to use model {} ({})",
+        identity.name,
+        requested,
+        model_id
+    );
+    let err = ApiError::Forbidden(format!(
+        "API Key is not allowed to use model '{}'",
+        requested
+    ));
+    state.metrics.record_error(error_type_from_api_error(&err));
+    Err(err)
+}
+
+/// Reject requests from keys that have used up their usage budget
+fn check_budget(state: &AppState, identity: &ClientIdentity) -> Result<(), ApiError> {
+    let Err(exceeded) = state.usage_ledger.check_budget(&identity.id) else {
+        return Ok(());
+    };
+
+    tracing::warn!("{}", exceeded.message);
+    let retry_after = (exceeded.resets_at - Utc::now()).num_seconds().max(1) as u64;
+    let err = ApiError::BudgetExceeded {
+        message: exceeded.message,
+        retry_after,
+    };
+    state.metrics.record_error(error_type_from_api_error(&err));
+    Err(err)
+}
+
+/// Count an authorized request against the client's virtual key cap
+fn record_key_request(state: &AppState, identity: &ClientIdentity) -> Result<(), ApiError> {
+    if let Err(rejection) = state.virtual_keys.record_request(&identity.id) {
+        tracing::warn!("Request rejected: {}", rejection);
+        let err = ApiError::Forbidden("API Key has reached its request or token cap".to_string());
+        state.metrics.record_error(error_type_from_api_error(&err));
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Admission priority: set by the key's tier, optionally lowered by the client
+fn request_priority(
+    state: &AppState,
+    identity: &ClientIdentity,
+    headers: &axum::http::HeaderMap,
+) -> Priority {
+    let requested = headers
+        .get(PRIORITY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(Priority::parse);
+    state
+        .http_client
+        .admission_priority(identity.rate_limit_tier.as_deref(), requested)
+}
+
+/// Inline remote images and documents, render documents as text and bring
+/// every image within the configured limits
+///
+/// Returns the input tokens of the attachments as sent upstream, which the
+/// client's text count leaves out.
+async fn prepare_content(
+    state: &AppState,
+    messages: &mut Vec<UnifiedMessage>,
+) -> Result<i32, ApiError> {
+    let documents = DocumentLimits::from_config(&state.config);
+    state.image_fetcher.inline_remote_images(messages).await?;
+    state
+        .image_fetcher
+        .inline_remote_documents(messages, documents.max_bytes)
+        .await?;
+    if !messages.iter().any(|m| m.images.is_some()) && !has_documents(messages) {
+        return Ok(0);
+    }
+
+    // PDF extraction and image re-encoding are CPU bound; keep them off the async workers
+    let images = ImageLimits::from_config(&state.config);
+    let mut owned = std::mem::take(messages);
+    let (owned, result) = tokio::task::spawn_blocking(move || {
+        let result = render_documents(&mut owned, &documents).and_then(|document_tokens| {
+            normalize_images(&mut owned, &images)?;
+            Ok(document_tokens + count_images_tokens(&owned, images.max_dimension))
+        });
+        (owned, result)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(anyhow::anyhow!("Content processing failed: {}", e)))?;
+    *messages = owned;
+    result.map_err(ApiError::ValidationError)
+}
+
+/// Send a request to its backend, going through the response cache and,
+/// on coalescing routes, joining an identical request already in flight
+///
+/// The cache status is `None` when the response cache is off. The flag is
+/// set when the response was shared from another request's upstream call.
+async fn send_upstream(
+    state: &AppState,
+    identity: &ClientIdentity,
+    headers: &axum::http::HeaderMap,
+    path: &str,
+    route: Route<'_>,
+    upstream: UpstreamRequest,
+) -> Result<(UpstreamResponse, Option<CacheStatus>, bool), ApiError> {
+    let cache = &state.response_cache;
+    let coalesce = state.coalescer.applies_to(path);
+    if !cache.is_enabled() && !coalesce {
+        return Ok((route.backend.send(upstream).await?, None, false));
+    }
+
+    let control = CacheControl::from_headers(headers);
+    let store = cache.applies_to(&identity.name) && !control.no_store;
+    let fingerprint = if store || coalesce {
+        upstream.fingerprint(route.backend.as_ref())
+    } else {
+        None
+    };
+    let status = match fingerprint {
+        _ if !cache.is_enabled() => None,
+        Some(ref key) if store && !control.no_cache => {
+            if let Some(events) = cache.get(key).await {
+                tracing::debug!("Response cache hit ({})", key);
+                state.metrics.record_cache(CacheStatus::Hit.label());
+                let response = UpstreamResponse {
+                    events: replay(events),
+                    region: None,
+                };
+                return Ok((response, Some(CacheStatus::Hit), false));
+            }
+            Some(CacheStatus::Miss)
+        }
+        _ => Some(CacheStatus::Bypass),
+    };
+    if let Some(status) = status {
+        state.metrics.record_cache(status.label());
+    }
+
+    let backend = Arc::clone(route.backend);
+    let cache = Arc::clone(cache);
+    let cache_key = fingerprint.clone().filter(|_| store);
+    let send = async move {
+        let mut response = backend.send(upstream).await?;
+        if let Some(key) = cache_key {
+            response.events = cache.record(key, response.events);
+        }
+        Ok(response)
+    };
+
+    let (response, joined) = match fingerprint.filter(|_| coalesce) {
+        Some(fingerprint) => {
+            let (response, joined) = state.coalescer.run(fingerprint, send).await?;
+            if joined {
+                tracing::debug!("Joined an identical in-flight upstream request");
+                state.metrics.record_coalesced();
+            }
+            (response, joined)
+        }
+        None => (send.await?, false),
+    };
+    Ok((response, status, joined))
+}
+
 /// Health check routes (no authentication required)
-pub fn health_routes() -> Router {
+pub fn health_routes(state: AppState) -> Router {
     Router::new()
         .route("/", get(root_handler))
         .route("/health", get(health_handler))
+        .with_state(state)
 }
 
-/// OpenAI API routes (require authentication)
+/// OpenAI API routes (require authentication, rate limited per client)
 pub fn openai_routes(state: AppState) -> Router {
+    let body_limit = DefaultBodyLimit::max(state.config.max_request_body_bytes);
     Router::new()
         .route("/v1/models", get(get_models_handler))
         .route("/v1/chat/completions", post(chat_completions_handler))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            middleware::rate_limit_middleware,
+        ))
         .layer(axum_middleware::from_fn_with_state(
             state.clone(),
             middleware::auth_middleware,
         ))
+        .layer(body_limit)
         .with_state(state)
 }
 
-/// Anthropic API routes (require authentication)
+/// Anthropic API routes (require authentication, rate limited per client)
 pub fn anthropic_routes(state: AppState) -> Router {
+    let body_limit = DefaultBodyLimit::max(state.config.max_request_body_bytes);
     Router::new()
         .route("/v1/messages", post(anthropic_messages_handler))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            middleware::rate_limit_middleware,
+        ))
         .layer(axum_middleware::from_fn_with_state(
             state.clone(),
             middleware::auth_middleware,
         ))
+        .layer(body_limit)
         .with_state(state)
 }
 
@@ -139,29 +424,58 @@ async fn root_handler() -> Json<Value> {
 
 /// GET /health - Detailed health check
 ///
-/// Returns detailed health information including timestamp.
+/// Returns detailed health information including timestamp and the state of
+/// the upstream circuit breakers (primary region, and each configured region).
+/// The status is `degraded` while the model catalogue is stale, e.g. after a
+/// degraded startup served from a snapshot.
 /// This endpoint does not require authentication (for load balancers).
-async fn health_handler() -> Json<Value> {
+async fn health_handler(State(state): State<AppState>) -> Json<Value> {
+    let now = Instant::now();
+    let circuit_breaker = state.http_client.circuit_breaker().snapshot(now);
+    let regions: serde_json::Map<String, Value> = state
+        .http_client
+        .regions()
+        .regions()
+        .iter()
+        .map(|region| {
+            let snapshot = state.http_client.circuit_breaker_for(region).snapshot(now);
+            (region.clone(), json!({ "circuit_breaker": snapshot }))
+        })
+        .collect();
+    let catalogue_fresh = !state.model_cache.is_stale();
     Json(json!({
-        "status": "healthy",
+        "status": if catalogue_fresh { "healthy" } else { "degraded" },
         "timestamp": Utc::now().to_rfc3339(),
-        "version": VERSION
+        "version": VERSION,
+        "circuit_breaker": circuit_breaker,
+        "regions": regions,
+        "models": {
+            "count": state.model_cache.get_all_model_ids().len(),
+            "fresh": catalogue_fresh
+        }
     }))
 }
 
 /// GET /v1/models - List available models
 ///
 /// Returns a list of available models in OpenAI format.
-/// Models are loaded from the cache (populated at startup).
-async fn get_models_handler(State(state): State<AppState>) -> Result<Json<ModelList>, ApiError> {
-    tracing::info!("Request to /v1/models");
+/// Models are loaded from the cache (populated at startup) and filtered
+/// to those the client key is allowed to use.
+async fn get_models_handler(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+) -> Result<Json<ModelList>, ApiError> {
+    let identity = client_identity(identity);
+    tracing::info!("Request to /v1/models (key: {})", identity.name);
+    record_key_request(&state, &identity)?;
 
     // Get all model IDs from cache
     let model_ids = state.model_cache.get_all_model_ids();
 
     // Build OpenAI-compatible model list
-    let models: Vec<OpenAIModel> = model_ids
+    let mut models: Vec<OpenAIModel> = model_ids
         .into_iter()
+        .filter(|id| identity.allows_model(id))
         .map(|id| {
             let mut model = OpenAIModel::new(id);
             model.description = Some("Claude model via Kiro API".to_string());
@@ -169,6 +483,16 @@ async fn get_models_handler(State(state): State<AppState>) -> Result<Json<ModelL
         })
         .collect();
 
+    // Models routed to other backends by name
+    for id in state.backends.listed_models() {
+        if identity.allows_model(&id) && !models.iter().any(|m| m.id == id) {
+            let backend = state.backends.route(&id).backend.name().to_string();
+            let mut model = OpenAIModel::new(id);
+            model.description = Some(format!("Routed to backend '{}'", backend));
+            models.push(model);
+        }
+    }
+
     Ok(Json(ModelList::new(models)))
 }
 
@@ -178,13 +502,18 @@ async fn get_models_handler(State(state): State<AppState>) -> Result<Json<ModelL
 /// Converts OpenAI format to Kiro format, makes the request, and converts back.
 async fn chat_completions_handler(
     State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    charge: Option<Extension<TokenCharge>>,
+    headers: axum::http::HeaderMap,
     Json(request): Json<ChatCompletionRequest>,
 ) -> Result<Response, ApiError> {
+    let identity = client_identity(identity);
     tracing::info!(
-        "Request to /v1/chat/completions: model={}, stream={}, messages={}",
+        "Request to /v1/chat/completions: model={}, stream={}, messages={}, key={}",
         request.model,
         request.stream,
-        request.messages.len()
+        request.messages.len(),
+        identity.name
     );
 
     // Validate request
@@ -198,7 +527,11 @@ async fn chat_completions_handler(
     let resolution = state.resolver.resolve(&request.model);
     let model_id = resolution.internal_id.clone();
 
-    let mut guard = RequestGuard::new(Arc::clone(&state.metrics), model_id.clone());
+    check_model_allowed(&state, &identity, &request.model, &model_id)?;
+    check_budget(&state, &identity)?;
+    record_key_request(&state, &identity)?;
+
+    let mut guard = RequestGuard::new(&state, model_id.clone(), identity.id.clone());
 
     tracing::debug!(
         "Model resolution: {} -> {} (source: {}, verified: {})",
@@ -208,81 +541,63 @@ async fn chat_completions_handler(
         resolution.is_verified
     );
 
-    // Generate conversation ID
-    let conversation_id = Uuid::new_v4().to_string();
-
-    // Get profile ARN
-    let profile_arn = state
-        .auth_manager
-        .get_profile_arn()
-        .await
-        .unwrap_or_default();
-
-    // Convert OpenAI request to Kiro format
-    let kiro_payload_result =
-        build_kiro_payload(&request, &conversation_id, &profile_arn, &state.config).map_err(
-            |e| {
-                let err = ApiError::ValidationError(e);
-                state.metrics.record_error(error_type_from_api_error(&err));
-                err
-            },
-        )?;
-
-    let kiro_payload = kiro_payload_result.payload;
-
-    tracing::debug!(
-        "Kiro payload: {}",
-        serde_json::to_string_pretty(&kiro_payload).unwrap_or_default()
+    // Region stickiness: messages up to the first user turn identify the conversation
+    let opening = request
+        .messages
+        .iter()
+        .position(|m| m.role == "user")
+        .map_or(&request.messages[..], |i| &request.messages[..=i]);
+    let conversation = conversation_key(&identity.name, &opening);
+
+    // Pick the backend; a routing rule may also rewrite the upstream model
+    let route = state.backends.route(&request.model);
+    let mut upstream = UpstreamRequest::from_openai(&request, model_id.clone()).with_client(
+        &identity.name,
+        conversation,
+        request_priority(&state, &identity, &headers),
     );
-
-    // Log Kiro request body for debugging
-    if let Ok(kiro_body_json) = serde_json::to_vec_pretty(&kiro_payload) {
-        DEBUG_LOGGER
-            .log_kiro_request_body(Bytes::from(kiro_body_json))
-            .await;
+    if let Some(model) = route.model {
+        upstream.model = model.to_string();
     }
-
-    // Get access token
-    let access_token = state.auth_manager.get_access_token().await.map_err(|e| {
-        let err = ApiError::AuthError(format!("Failed to get access token: {}", e));
-        state.metrics.record_error(error_type_from_api_error(&err));
-        err
-    })?;
-
-    // Get region
-    let region = state.auth_manager.get_region().await;
-
-    // Build Kiro API URL - use /v1/chat/completions endpoint
-    let kiro_api_url = format!(
-        "https://codewhisperer.{}.amazonaws.com/generateAssistantResponse",
-        region
+    tracing::debug!(
+        "Backend: {} (upstream model: {})",
+        route.backend.name(),
+        upstream.model
     );
 
-    // Build request
-    let req = state
-        .http_client
-        .client()
-        .post(&kiro_api_url)
-        .header("Authorization", format!("Bearer {}", access_token))
-        .header("Content-Type", "application/json")
-        .json(&kiro_payload)
-        .build()
-        .map_err(|e| {
-            let err = ApiError::Internal(anyhow::anyhow!("Failed to build request: {}", e));
-            state.metrics.record_error(error_type_from_api_error(&err));
-            err
-        })?;
-
-    let response = state
-        .http_client
-        .request_with_retry(req)
+    // Inline remote content, render documents and normalize images; anything
+    // that cannot be used fails the request
+    let attachment_tokens = prepare_content(&state, &mut upstream.messages)
         .await
         .inspect_err(|e| {
             state.metrics.record_error(error_type_from_api_error(e));
         })?;
+    if let Some(Extension(ref charge)) = charge {
+        charge.add_input_tokens(attachment_tokens as u64);
+    }
+
+    // Returns once the response has started; admission slots and the like are
+    // held by the event stream until it has been consumed
+    let (UpstreamResponse { events, region }, cache_status, coalesced) = send_upstream(
+        &state,
+        &identity,
+        &headers,
+        "/v1/chat/completions",
+        route,
+        upstream,
+    )
+    .await
+    .inspect_err(|e| {
+        state.metrics.record_error(error_type_from_api_error(e));
+    })?;
+    let cached = cache_status == Some(CacheStatus::Hit);
+    guard.cached = cached;
+    guard.coalesced = coalesced;
 
     let input_tokens = count_message_tokens(&request.messages, false)
-        + count_tools_tokens(request.tools.as_ref(), false);
+        + count_tools_tokens(request.tools.as_ref(), false)
+        + attachment_tokens;
+    let prompt_cache = state.prompt_cache.openai_usage(&identity.id, &request);
 
     // Extract include_usage from stream_options
     // Default to true for better compatibility with OpenCode and other clients
@@ -311,23 +626,26 @@ async fn chat_completions_handler(
         let streaming_tracker = StreamingMetricsTracker::new(
             Arc::clone(&state.metrics),
             model_id.clone(),
+            identity.id.clone(),
             input_tokens as u64,
-        );
+        )
+        .with_ledger(Arc::clone(&state.usage_ledger))
+        .with_virtual_keys(Arc::clone(&state.virtual_keys))
+        .with_cached(cached)
+        .with_coalesced(coalesced);
         let output_tokens_handle = streaming_tracker.output_tokens_handle();
+        let credits_handle = streaming_tracker.credits_handle();
 
         // Use proper streaming conversion from streaming module
-        let openai_stream = crate::streaming::stream_kiro_to_openai(
-            response,
+        let openai_stream = crate::streaming::stream_events_to_openai(
+            events,
             &request.model,
-            15,
             input_tokens,
+            prompt_cache,
             Some(output_tokens_handle),
+            Some(credits_handle),
             include_usage,
-        )
-        .await
-        .inspect_err(|e| {
-            state.metrics.record_error(error_type_from_api_error(e));
-        })?;
+        );
 
         // Convert Result<String, ApiError> stream to bytes stream for SSE
         use bytes::Bytes;
@@ -339,35 +657,40 @@ async fn chat_completions_handler(
         });
 
         // Return as SSE response with proper headers
-        let response = Response::builder()
+        let mut builder = Response::builder()
             .status(200)
             .header("Content-Type", "text/event-stream")
             .header("Cache-Control", "no-cache")
-            .header("Connection", "keep-alive")
-            .body(Body::from_stream(byte_stream))
-            .map_err(|e| {
-                let err = ApiError::Internal(anyhow::anyhow!("Failed to build response: {}", e));
-                state.metrics.record_error(error_type_from_api_error(&err));
-                err
-            })?;
+            .header("Connection", "keep-alive");
+        if let Some(ref region) = region {
+            builder = builder.header(REGION_HEADER, region);
+        }
+        if let Some(status) = cache_status {
+            builder = builder.header(CACHE_HEADER, status.as_str());
+        }
+        let response = builder.body(Body::from_stream(byte_stream)).map_err(|e| {
+            let err = ApiError::Internal(anyhow::anyhow!("Failed to build response: {}", e));
+            state.metrics.record_error(error_type_from_api_error(&err));
+            err
+        })?;
 
-        std::mem::drop(guard);
+        guard.hand_off();
 
         DEBUG_LOGGER.discard_buffers().await;
 
         Ok(response)
     } else {
         // Non-streaming response
-        // Kiro API always returns AWS Event Stream format, even for non-streaming requests.
-        // We use collect_openai_response to parse the stream and aggregate into a single response.
+        // Backends always stream; collect_openai_events aggregates the events into a single response.
         tracing::debug!("Handling non-streaming response (collecting stream)");
 
-        let first_token_timeout = state.config.first_token_timeout;
-        let openai_response = crate::streaming::collect_openai_response(
-            response,
+        let credits = Arc::new(AtomicU64::new(0));
+        let openai_response = crate::streaming::collect_openai_events(
+            events,
             &request.model,
-            first_token_timeout,
             input_tokens,
+            prompt_cache,
+            Some(Arc::clone(&credits)),
         )
         .await
         .inspect_err(|e| {
@@ -380,11 +703,24 @@ async fn chat_completions_handler(
             .and_then(|t| t.as_u64())
             .unwrap_or(0);
 
-        guard.complete(input_tokens as u64, output_tokens);
+        guard.complete(
+            input_tokens as u64,
+            output_tokens,
+            f64::from_bits(credits.load(Ordering::Relaxed)),
+        );
 
         DEBUG_LOGGER.discard_buffers().await;
 
-        Ok(Json(openai_response).into_response())
+        let mut response = Json(openai_response).into_response();
+        if let Some(value) = region.and_then(|r| HeaderValue::from_str(&r).ok()) {
+            response.headers_mut().insert(REGION_HEADER, value);
+        }
+        if let Some(status) = cache_status {
+            response
+                .headers_mut()
+                .insert(CACHE_HEADER, HeaderValue::from_static(status.as_str()));
+        }
+        Ok(response)
     }
 }
 
@@ -394,14 +730,18 @@ async fn chat_completions_handler(
 /// Converts Anthropic format to Kiro format, makes the request, and converts back.
 async fn anthropic_messages_handler(
     State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    charge: Option<Extension<TokenCharge>>,
     headers: axum::http::HeaderMap,
     Json(request): Json<AnthropicMessagesRequest>,
-) -> Result<Response, ApiError> {
+) -> Result<Response, AnthropicApiError> {
+    let identity = client_identity(identity);
     tracing::info!(
-        "Request to /v1/messages: model={}, stream={}, messages={}",
+        "Request to /v1/messages: model={}, stream={}, messages={}, key={}",
         request.model,
         request.stream,
-        request.messages.len()
+        request.messages.len(),
+        identity.name
     );
 
     // Check anthropic-version header (optional, for compatibility logging)
@@ -417,20 +757,24 @@ async fn anthropic_messages_handler(
     if request.messages.is_empty() {
         let err = ApiError::ValidationError("messages cannot be empty".to_string());
         state.metrics.record_error(error_type_from_api_error(&err));
-        return Err(err);
+        return Err(err.into());
     }
 
     if request.max_tokens <= 0 {
         let err = ApiError::ValidationError("max_tokens must be positive".to_string());
         state.metrics.record_error(error_type_from_api_error(&err));
-        return Err(err);
+        return Err(err.into());
     }
 
     // Resolve model name
     let resolution = state.resolver.resolve(&request.model);
     let model_id = resolution.internal_id.clone();
 
-    let mut guard = RequestGuard::new(Arc::clone(&state.metrics), model_id.clone());
+    check_model_allowed(&state, &identity, &request.model, &model_id)?;
+    check_budget(&state, &identity)?;
+    record_key_request(&state, &identity)?;
+
+    let mut guard = RequestGuard::new(&state, model_id.clone(), identity.id.clone());
 
     tracing::debug!(
         "Model resolution: {} -> {} (source: {}, verified: {})",
@@ -440,83 +784,54 @@ async fn anthropic_messages_handler(
         resolution.is_verified
     );
 
-    // Generate conversation ID
-    let conversation_id = Uuid::new_v4().to_string();
-
-    // Get profile ARN
-    let profile_arn = state
-        .auth_manager
-        .get_profile_arn()
-        .await
-        .unwrap_or_default();
-
-    // Convert Anthropic request to Kiro format
-    let kiro_payload_result =
-        build_kiro_payload_anthropic(&request, &conversation_id, &profile_arn, &state.config)
-            .map_err(|e| {
-                let err = ApiError::ValidationError(e);
-                state.metrics.record_error(error_type_from_api_error(&err));
-                err
-            })?;
+    // Region stickiness: system prompt and first message identify the conversation
+    let conversation = conversation_key(&identity.name, &(&request.system, &request.messages[..1]));
 
-    let kiro_payload = kiro_payload_result.payload;
-
-    tracing::debug!(
-        "Kiro payload: {}",
-        serde_json::to_string_pretty(&kiro_payload).unwrap_or_default()
+    // Pick the backend; a routing rule may also rewrite the upstream model
+    let route = state.backends.route(&request.model);
+    let mut upstream = UpstreamRequest::from_anthropic(&request, model_id.clone()).with_client(
+        &identity.name,
+        conversation,
+        request_priority(&state, &identity, &headers),
     );
-
-    // Log Kiro request body for debugging
-    if let Ok(kiro_body_json) = serde_json::to_vec_pretty(&kiro_payload) {
-        DEBUG_LOGGER
-            .log_kiro_request_body(Bytes::from(kiro_body_json))
-            .await;
+    if let Some(model) = route.model {
+        upstream.model = model.to_string();
     }
-
-    // Get access token
-    let access_token = state.auth_manager.get_access_token().await.map_err(|e| {
-        let err = ApiError::AuthError(format!("Failed to get access token: {}", e));
-        state.metrics.record_error(error_type_from_api_error(&err));
-        err
-    })?;
-
-    // Get region
-    let region = state.auth_manager.get_region().await;
-
-    // Build Kiro API URL - use /v1/messages endpoint
-    let kiro_api_url = format!(
-        "https://codewhisperer.{}.amazonaws.com/generateAssistantResponse",
-        region
+    tracing::debug!(
+        "Backend: {} (upstream model: {})",
+        route.backend.name(),
+        upstream.model
     );
 
-    // Build request
-    let req = state
-        .http_client
-        .client()
-        .post(&kiro_api_url)
-        .header("Authorization", format!("Bearer {}", access_token))
-        .header("Content-Type", "application/json")
-        .json(&kiro_payload)
-        .build()
-        .map_err(|e| {
-            let err = ApiError::Internal(anyhow::anyhow!("Failed to build request: {}", e));
-            state.metrics.record_error(error_type_from_api_error(&err));
-            err
-        })?;
-
-    let response = state
-        .http_client
-        .request_with_retry(req)
+    // Inline remote content, render documents and normalize images; anything
+    // that cannot be used fails the request
+    let attachment_tokens = prepare_content(&state, &mut upstream.messages)
         .await
         .inspect_err(|e| {
             state.metrics.record_error(error_type_from_api_error(e));
         })?;
+    if let Some(Extension(ref charge)) = charge {
+        charge.add_input_tokens(attachment_tokens as u64);
+    }
+
+    // Returns once the response has started; admission slots and the like are
+    // held by the event stream until it has been consumed
+    let (UpstreamResponse { events, region }, cache_status, coalesced) =
+        send_upstream(&state, &identity, &headers, "/v1/messages", route, upstream)
+            .await
+            .inspect_err(|e| {
+                state.metrics.record_error(error_type_from_api_error(e));
+            })?;
+    let cached = cache_status == Some(CacheStatus::Hit);
+    guard.cached = cached;
+    guard.coalesced = coalesced;
 
     let input_tokens = count_anthropic_message_tokens(
         &request.messages,
         request.system.as_ref(),
         request.tools.as_ref(),
-    );
+    ) + attachment_tokens;
+    let prompt_cache = state.prompt_cache.anthropic_usage(&identity.id, &request);
 
     // Handle streaming vs non-streaming
     if request.stream {
@@ -528,23 +843,25 @@ async fn anthropic_messages_handler(
         let streaming_tracker = StreamingMetricsTracker::new(
             Arc::clone(&state.metrics),
             model_id.clone(),
+            identity.id.clone(),
             input_tokens as u64,
-        );
+        )
+        .with_ledger(Arc::clone(&state.usage_ledger))
+        .with_virtual_keys(Arc::clone(&state.virtual_keys))
+        .with_cached(cached)
+        .with_coalesced(coalesced);
         let output_tokens_handle = streaming_tracker.output_tokens_handle();
+        let credits_handle = streaming_tracker.credits_handle();
 
         // Convert response to Anthropic SSE stream
-        let first_token_timeout = state.config.first_token_timeout;
-        let anthropic_stream = crate::streaming::stream_kiro_to_anthropic(
-            response,
+        let anthropic_stream = crate::streaming::stream_events_to_anthropic(
+            events,
             &request.model,
-            first_token_timeout,
             input_tokens,
+            prompt_cache,
             Some(output_tokens_handle),
-        )
-        .await
-        .inspect_err(|e| {
-            state.metrics.record_error(error_type_from_api_error(e));
-        })?;
+            Some(credits_handle),
+        );
 
         // Convert to raw SSE response (stream already contains properly formatted SSE events)
         // Don't use Axum's Sse wrapper as it would double-wrap the events
@@ -555,35 +872,40 @@ async fn anthropic_messages_handler(
                 .map_err(|e| std::io::Error::other(e.to_string()))
         });
 
-        let response = Response::builder()
+        let mut builder = Response::builder()
             .status(200)
             .header("Content-Type", "text/event-stream")
             .header("Cache-Control", "no-cache")
-            .header("Connection", "keep-alive")
-            .body(Body::from_stream(byte_stream))
-            .map_err(|e| {
-                let err = ApiError::Internal(anyhow::anyhow!("Failed to build response: {}", e));
-                state.metrics.record_error(error_type_from_api_error(&err));
-                err
-            })?;
+            .header("Connection", "keep-alive");
+        if let Some(ref region) = region {
+            builder = builder.header(REGION_HEADER, region);
+        }
+        if let Some(status) = cache_status {
+            builder = builder.header(CACHE_HEADER, status.as_str());
+        }
+        let response = builder.body(Body::from_stream(byte_stream)).map_err(|e| {
+            let err = ApiError::Internal(anyhow::anyhow!("Failed to build response: {}", e));
+            state.metrics.record_error(error_type_from_api_error(&err));
+            err
+        })?;
 
-        std::mem::drop(guard);
+        guard.hand_off();
 
         DEBUG_LOGGER.discard_buffers().await;
 
         Ok(response)
     } else {
         // Non-streaming response
-        // Kiro API always returns AWS Event Stream format, even for non-streaming requests.
-        // We use collect_anthropic_response to parse the stream and aggregate into a single response.
+        // Backends always stream; collect_anthropic_events aggregates the events into a single response.
         tracing::debug!("Handling non-streaming response (collecting stream)");
 
-        let first_token_timeout = state.config.first_token_timeout;
-        let anthropic_response = crate::streaming::collect_anthropic_response(
-            response,
+        let credits = Arc::new(AtomicU64::new(0));
+        let anthropic_response = crate::streaming::collect_anthropic_events(
+            events,
             &request.model,
-            first_token_timeout,
             input_tokens,
+            prompt_cache,
+            Some(Arc::clone(&credits)),
         )
         .await
         .inspect_err(|e| {
@@ -596,17 +918,33 @@ async fn anthropic_messages_handler(
             .and_then(|t| t.as_u64())
             .unwrap_or(0);
 
-        guard.complete(input_tokens as u64, output_tokens);
+        guard.complete(
+            input_tokens as u64,
+            output_tokens,
+            f64::from_bits(credits.load(Ordering::Relaxed)),
+        );
 
         DEBUG_LOGGER.discard_buffers().await;
 
-        Ok(Json(anthropic_response).into_response())
+        let mut response = Json(anthropic_response).into_response();
+        if let Some(value) = region.and_then(|r| HeaderValue::from_str(&r).ok()) {
+            response.headers_mut().insert(REGION_HEADER, value);
+        }
+        if let Some(status) = cache_status {
+            response
+                .headers_mut()
+                .insert(CACHE_HEADER, HeaderValue::from_static(status.as_str()));
+        }
+        Ok(response)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backends::KiroBackend;
+    use crate::egress::EgressConfig;
+    use crate::middleware::RateLimits;
     use std::collections::HashMap;
 
     fn create_test_state() -> AppState {
@@ -627,8 +965,17 @@ mod tests {
                 .unwrap(),
         );
 
-        let http_client =
-            Arc::new(KiroHttpClient::new(auth_manager.clone(), 20, 30, 300, 3).unwrap());
+        let http_client = Arc::new(
+            KiroHttpClient::new(
+                auth_manager.clone(),
+                20,
+                30,
+                300,
+                3,
+                &EgressConfig::default(),
+            )
+            .unwrap(),
+        );
 
         let resolver = ModelResolver::new(cache.clone(), HashMap::new());
 
@@ -652,18 +999,112 @@ mod tests {
             fake_reasoning_max_tokens: 10000,
             fake_reasoning_handling: crate::config::FakeReasoningHandling::AsReasoningContent,
             dashboard: false,
+            kiro_profile: None,
+            list_profiles: false,
+            api_keys_file: None,
+            rate_limit_rpm: 0,
+            rate_limit_input_tpm: 0,
+            rate_limit_concurrent_streams: 0,
+            rate_limit_by: crate::config::RateLimitKey::ApiKey,
+            max_request_body_bytes: 32 * 1024 * 1024,
+            usage_db_file: None,
+            admin_api_key: None,
+            virtual_keys_db_file: None,
+            jwt_secret: None,
+            jwt_jwks_file: None,
+            jwt_issuer: None,
+            jwt_audience: vec![],
+            jwt_claims_file: None,
+            jwt_api_key_fallback: false,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_client_ca_file: None,
+            tls_client_cert_required: false,
+            network_policy_file: None,
+            backends_file: None,
+            upstream_base_url: None,
+            upstream_endpoints: HashMap::new(),
+            http_retry_budget_ratio: 0.2,
+            http_retry_budget_min_per_sec: 1,
+            circuit_breaker_failures: 5,
+            circuit_breaker_error_rate: 0.5,
+            circuit_breaker_min_requests: 20,
+            circuit_breaker_open_secs: 30,
+            kiro_proxy_url: None,
+            kiro_proxy_username: None,
+            kiro_proxy_password: None,
+            no_proxy: None,
+            kiro_ca_bundle: None,
+            kiro_failover_regions: vec![],
+            region_sticky_ttl: 3600,
+            hedge_models: vec![],
+            hedge_percentile: 95.0,
+            hedge_min_delay_ms: 500,
+            hedge_budget_per_min: 10,
+            upstream_max_in_flight: 0,
+            upstream_queue_max_wait_ms: 10_000,
+            upstream_tier_priorities: HashMap::new(),
+            adaptive_concurrency: false,
+            adaptive_min_in_flight: 2,
+            adaptive_max_in_flight: 64,
+            adaptive_decrease_ratio: 0.7,
+            adaptive_latency_tolerance: 2.0,
+            model_cache_ttl: 3600,
+            model_snapshot_file: None,
+            degraded_startup: false,
+            response_cache: false,
+            response_cache_ttl: 3600,
+            response_cache_max_entries: 1000,
+            response_cache_max_bytes: 64 * 1024 * 1024,
+            response_cache_db_file: None,
+            response_cache_db_max_entries: 10_000,
+            response_cache_db_max_bytes: 1024 * 1024 * 1024,
+            response_cache_keys: Vec::new(),
+            coalesce_routes: Vec::new(),
+            image_fetch_allowed_hosts: Vec::new(),
+            image_fetch_max_bytes: 5 * 1024 * 1024,
+            image_fetch_timeout: 10,
+            image_fetch_cache_entries: 100,
+            image_fetch_max_total_bytes: 20 * 1024 * 1024,
+            image_max_dimension: 1568,
+            image_max_bytes: 3_750_000,
+            image_max_count: 20,
+            document_max_bytes: 32 * 1024 * 1024,
+            document_max_pages: 100,
+            document_max_chars: 200_000,
+            prompt_cache_ttl: 300,
+            prompt_cache_max_entries: 10_000,
         });
 
         let metrics = Arc::new(crate::metrics::MetricsCollector::new());
 
+        let backends = Arc::new(BackendRouter::new(Arc::new(KiroBackend::new(
+            http_client.clone(),
+            auth_manager.clone(),
+            config.clone(),
+        ))));
+
         AppState {
             proxy_api_key: "test-key".to_string(),
+            key_store: Arc::new(KeyStore::empty()),
+            virtual_keys: Arc::new(VirtualKeyStore::disabled()),
+            network_policy: Arc::new(NetworkPolicy::permissive()),
+            jwt_validator: None,
+            rate_limiter: Arc::new(RateLimiter::new(RateLimits::default())),
             model_cache: cache,
             auth_manager,
             http_client,
+            backends,
+            response_cache: Arc::new(ResponseCache::disabled()),
+            coalescer: Arc::new(Coalescer::new(Vec::new())),
+            image_fetcher: Arc::new(
+                ImageFetcher::from_config(&config, &EgressConfig::default()).unwrap(),
+            ),
+            prompt_cache: Arc::new(PromptCache::from_config(&config)),
             resolver,
             config,
             metrics,
+            usage_ledger: Arc::new(UsageLedger::disabled()),
         }
     }
 
@@ -679,12 +1120,51 @@ mod tests {
 
     #[tokio::test]
     async fn test_health_handler() {
-        let json = health_handler().await;
+        let state = create_test_state();
+        let json = health_handler(State(state.clone())).await;
         let value = json.0;
 
         assert_eq!(value["status"], "healthy");
         assert!(value["timestamp"].is_string());
         assert_eq!(value["version"], VERSION);
+        assert_eq!(value["circuit_breaker"]["state"], "closed");
+        assert!(value["circuit_breaker"].get("retry_after").is_none());
+
+        for _ in 0..5 {
+            state
+                .http_client
+                .circuit_breaker()
+                .record_failure(Instant::now());
+        }
+        let value = health_handler(State(state)).await.0;
+        assert_eq!(value["circuit_breaker"]["state"], "open");
+        assert_eq!(value["circuit_breaker"]["trips"], 1);
+        assert!(value["circuit_breaker"]["retry_after"].is_u64());
+        assert_eq!(
+            value["regions"]["us-east-1"]["circuit_breaker"]["state"],
+            "open"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_handler_degraded_until_catalogue_fresh() {
+        let mut state = create_test_state();
+        state.model_cache = ModelCache::new(3600);
+        state
+            .model_cache
+            .restore(vec![json!({"modelId": "claude-sonnet-4"})]);
+
+        let value = health_handler(State(state.clone())).await.0;
+        assert_eq!(value["status"], "degraded");
+        assert_eq!(value["models"]["count"], 1);
+        assert_eq!(value["models"]["fresh"], false);
+
+        state
+            .model_cache
+            .update(vec![json!({"modelId": "claude-sonnet-4"})]);
+        let value = health_handler(State(state)).await.0;
+        assert_eq!(value["status"], "healthy");
+        assert_eq!(value["models"]["fresh"], true);
     }
 
     #[tokio::test]
@@ -692,7 +1172,7 @@ mod tests {
         let state = create_test_state();
 
         // Call handler
-        let result = get_models_handler(State(state)).await;
+        let result = get_models_handler(State(state), None).await;
         assert!(result.is_ok());
 
         let model_list = result.unwrap().0;
@@ -740,12 +1220,13 @@ mod tests {
 
         // Call handler - will fail later when trying to call Kiro API,
         // but should NOT fail due to missing anthropic-version header
-        let result = anthropic_messages_handler(State(state), headers, Json(request)).await;
+        let result =
+            anthropic_messages_handler(State(state), None, None, headers, Json(request)).await;
 
         // The request should proceed past header validation
         // It will fail on the actual API call, but that's expected in tests
         match result {
-            Err(ApiError::ValidationError(msg)) => {
+            Err(AnthropicApiError(ApiError::ValidationError(msg))) => {
                 // Should NOT be about anthropic-version
                 assert!(
                     !msg.contains("anthropic-version"),
@@ -784,14 +1265,83 @@ mod tests {
         headers.insert("anthropic-version", "2023-06-01".parse().unwrap());
 
         // Call handler - should fail due to empty messages
-        let result = anthropic_messages_handler(State(state), headers, Json(request)).await;
+        let result =
+            anthropic_messages_handler(State(state), None, None, headers, Json(request)).await;
 
         assert!(result.is_err());
         match result {
-            Err(ApiError::ValidationError(msg)) => {
+            Err(AnthropicApiError(ApiError::ValidationError(msg))) => {
                 assert!(msg.contains("messages"));
             }
             _ => panic!("Expected ValidationError for empty messages"),
         }
     }
+
+    #[tokio::test]
+    async fn test_region_header_after_failover() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/us-east-1/generateAssistantResponse")
+            .with_status(503)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/us-west-2/generateAssistantResponse")
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let mut state = create_test_state();
+        let endpoints = crate::endpoints::EndpointResolver::new(
+            None,
+            HashMap::from([(
+                crate::endpoints::Endpoint::GenerateAssistantResponse,
+                format!("{}/{{region}}/generateAssistantResponse", server.url()),
+            )]),
+        )
+        .unwrap();
+        state.http_client = Arc::new(
+            KiroHttpClient::new(
+                state.auth_manager.clone(),
+                20,
+                30,
+                300,
+                0,
+                &EgressConfig::default(),
+            )
+            .unwrap()
+            .with_endpoints(Arc::new(endpoints))
+            .with_regions(crate::regions::RegionRouter::new(
+                vec!["us-east-1".to_string(), "us-west-2".to_string()],
+                std::time::Duration::from_secs(3600),
+            ))
+            .with_metrics(state.metrics.clone()),
+        );
+        state.backends = Arc::new(BackendRouter::new(Arc::new(KiroBackend::new(
+            state.http_client.clone(),
+            state.auth_manager.clone(),
+            state.config.clone(),
+        ))));
+
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4.5",
+            "messages": [{"role": "user", "content": "Hello"}]
+        }))
+        .unwrap();
+        let response = chat_completions_handler(
+            State(state.clone()),
+            None,
+            None,
+            axum::http::HeaderMap::new(),
+            Json(request),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.headers()[REGION_HEADER], "us-west-2");
+        assert_eq!(
+            state.metrics.get_regions(),
+            vec![("us-west-2".to_string(), 1)]
+        );
+    }
 }
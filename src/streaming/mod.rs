@@ -1,6 +1,8 @@
 use futures::stream::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use tokio::time::{timeout, Duration};
 use tracing::warn;
 
@@ -63,6 +65,17 @@ pub struct ToolUse {
 pub struct Usage {
     pub input_tokens: i32,
     pub output_tokens: i32,
+
+    /// Kiro usage credits (from numeric `usage` events)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credits: Option<f64>,
+}
+
+/// Store Kiro usage credits in a tracker (as `f64` bits)
+fn track_credits(tracker: Option<&Arc<AtomicU64>>, usage: &Usage) {
+    if let (Some(tracker), Some(credits)) = (tracker, usage.credits) {
+        tracker.store(credits.to_bits(), Ordering::Relaxed);
+    }
 }
 
 /// Result of collecting a complete stream response
@@ -132,7 +145,7 @@ pub fn deduplicate_tool_calls(tool_calls: Vec<ToolUse>) -> Vec<ToolUse> {
     let mut seen: HashSet<String> = HashSet::new();
     let mut unique: Vec<ToolUse> = Vec::new();
 
-    for tc in result_with_id.into_iter().chain(without_id.into_iter()) {
+    for tc in result_with_id.into_iter().chain(without_id) {
         let args_str = serde_json::to_string(&tc.input).unwrap_or_default();
         let key = format!("{}-{}", tc.name, args_str);
 
@@ -640,6 +653,7 @@ pub fn parse_kiro_event_with_accumulator(
                 usage: Some(Usage {
                     input_tokens: 0,
                     output_tokens: (usage_num * 1000.0) as i32, // Approximate conversion
+                    credits: Some(usage_num),
                 }),
                 context_usage_percentage: None,
                 is_first_thinking_chunk: false,
@@ -663,6 +677,7 @@ pub fn parse_kiro_event_with_accumulator(
                 usage: Some(Usage {
                     input_tokens,
                     output_tokens,
+                    credits: None,
                 }),
                 context_usage_percentage: None,
                 is_first_thinking_chunk: false,
@@ -757,6 +772,7 @@ pub fn parse_kiro_event_with_accumulator(
                 usage: Some(Usage {
                     input_tokens,
                     output_tokens,
+                    credits: None,
                 }),
                 context_usage_percentage: None,
                 is_first_thinking_chunk: false,
@@ -795,32 +811,53 @@ pub async fn parse_kiro_stream(
     parse_kiro_stream_with_thinking(response, first_token_timeout_secs, true).await
 }
 
+/// Rest of a Kiro response body after the first chunk
+pub type ByteStream = futures::stream::BoxStream<'static, Result<bytes::Bytes, reqwest::Error>>;
+
+/// Unified events from any upstream backend
+pub type KiroEventStream = futures::stream::BoxStream<'static, Result<KiroEvent, ApiError>>;
+
+/// Wait for the first chunk of a Kiro response body.
+///
+/// Returns the first chunk (None for an empty body) together with the rest of
+/// the body. Fails if nothing arrives within `first_token_timeout`.
+pub async fn wait_for_first_chunk(
+    response: reqwest::Response,
+    first_token_timeout: Duration,
+) -> Result<(Option<bytes::Bytes>, ByteStream), ApiError> {
+    let mut byte_stream = response.bytes_stream().boxed();
+
+    let first_chunk = timeout(first_token_timeout, byte_stream.next())
+        .await
+        .map_err(|_| {
+            warn!(
+                "[FirstTokenTimeout] Model did not respond within {}s",
+                first_token_timeout.as_secs_f64()
+            );
+            ApiError::Internal(anyhow::anyhow!("First token timeout"))
+        })?;
+
+    match first_chunk {
+        Some(Ok(chunk)) => Ok((Some(chunk), byte_stream)),
+        Some(Err(e)) => Err(ApiError::Internal(anyhow::anyhow!("Stream error: {}", e))),
+        None => Ok((None, byte_stream)),
+    }
+}
+
 /// Parse a Kiro SSE stream with optional thinking parser.
 pub async fn parse_kiro_stream_with_thinking(
     response: reqwest::Response,
     first_token_timeout_secs: u64,
     enable_thinking_parser: bool,
 ) -> Result<impl Stream<Item = Result<KiroEvent, ApiError>>, ApiError> {
-    let mut byte_stream = response.bytes_stream();
     let mut parser = SseParser::new();
 
     // Wait for first chunk with timeout
-    let first_chunk = timeout(
-        Duration::from_secs(first_token_timeout_secs),
-        byte_stream.next(),
-    )
-    .await
-    .map_err(|_| {
-        warn!(
-            "[FirstTokenTimeout] Model did not respond within {}s",
-            first_token_timeout_secs
-        );
-        ApiError::Internal(anyhow::anyhow!("First token timeout"))
-    })?;
+    let (first_chunk, byte_stream) =
+        wait_for_first_chunk(response, Duration::from_secs(first_token_timeout_secs)).await?;
 
     let first_chunk = match first_chunk {
-        Some(Ok(chunk)) => chunk,
-        Some(Err(e)) => return Err(ApiError::Internal(anyhow::anyhow!("Stream error: {}", e))),
+        Some(chunk) => chunk,
         None => {
             return Ok(futures::stream::empty().boxed());
         }
@@ -1112,1499 +1149,1507 @@ impl Clone for SseParser {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_sse_parser_basic() {
-        let mut parser = SseParser::new();
-
-        // Use a pattern that matches EVENT_PATTERNS
-        let chunk = b"{\"content\": \"Hello, world!\"}\n\n";
-        let events = parser.feed(chunk).unwrap();
-
-        assert_eq!(events.len(), 1);
-        assert_eq!(events[0]["content"], "Hello, world!");
-    }
-
-    #[test]
-    fn test_sse_parser_done_marker() {
-        let mut parser = SseParser::new();
+// ==================================================================================================
+// OpenAI Streaming
+// ==================================================================================================
 
-        let chunk = b"data: [DONE]\n\n";
-        let events = parser.feed(chunk).unwrap();
+use crate::models::openai::{
+    ChatCompletionChunk, ChatCompletionChunkChoice, ChatCompletionChunkDelta, ChatCompletionUsage,
+    FunctionCallDelta, PromptTokensDetails, ToolCallDelta,
+};
+use crate::prompt_cache::PromptCacheUsage;
+use futures::stream::BoxStream;
+use uuid::Uuid;
 
-        assert_eq!(events.len(), 0);
-    }
+/// Generates a unique completion ID in OpenAI format.
+fn generate_completion_id() -> String {
+    format!("chatcmpl-{}", &Uuid::new_v4().simple().to_string()[..24])
+}
 
-    #[test]
-    fn test_sse_parser_aws_format() {
-        let mut parser = SseParser::new();
+/// Converts a backend event stream to OpenAI SSE format.
+///
+/// This function takes the unified events of an upstream backend and converts
+/// them to OpenAI's chat.completion.chunk format with SSE encoding.
+/// `input_tokens` covers the whole prompt, including `prompt_cache` tokens.
+pub fn stream_events_to_openai(
+    kiro_stream: KiroEventStream,
+    model: &str,
+    input_tokens: i32,
+    prompt_cache: PromptCacheUsage,
+    output_tokens_tracker: Option<std::sync::Arc<std::sync::atomic::AtomicU64>>,
+    credits_tracker: Option<std::sync::Arc<std::sync::atomic::AtomicU64>>,
+    include_usage: bool,
+) -> BoxStream<'static, Result<String, ApiError>> {
+    let completion_id = generate_completion_id();
+    let created_time = chrono::Utc::now().timestamp();
+    let model = model.to_string();
 
-        // Use a pattern that matches EVENT_PATTERNS
-        let chunk =
-            b":event-type: content\n:content-type: application/json\n{\"content\": \"Hello\"}\n\n";
-        let events = parser.feed(chunk).unwrap();
+    // Use scan to maintain state across stream items
+    use std::sync::Arc;
+    use std::sync::Mutex;
 
-        assert_eq!(events.len(), 1);
-        assert_eq!(events[0]["content"], "Hello");
+    #[derive(Default)]
+    struct StreamState {
+        first_chunk: bool,
+        tool_calls: Vec<ToolUse>,
+        usage: Option<Usage>,
+        accumulated_text: String, // Accumulate all text for accurate token counting
     }
 
-    #[test]
-    fn test_parse_kiro_event_content() {
-        let json = serde_json::json!({
-            "contentBlockDelta": {
-                "delta": {
-                    "text": "Hello, world!"
-                }
-            }
-        });
+    let state = Arc::new(Mutex::new(StreamState {
+        first_chunk: true,
+        tool_calls: Vec::new(),
+        usage: None,
+        accumulated_text: String::new(),
+    }));
 
-        let event = parse_kiro_event(&json).unwrap();
-        assert_eq!(event.event_type, "content");
-        assert_eq!(event.content, Some("Hello, world!".to_string()));
-    }
+    let completion_id_clone = completion_id.clone();
+    let model_clone = model.clone();
 
-    #[test]
-    fn test_parse_kiro_event_tool_use() {
-        let json = serde_json::json!({
-            "contentBlockDelta": {
-                "delta": {
-                    "toolUse": {
-                        "toolUseId": "call_123",
-                        "name": "get_weather",
-                        "input": {"location": "SF"}
-                    }
-                }
-            }
-        });
+    // Clone state for use in final stream
+    let state_for_final = state.clone();
 
-        let event = parse_kiro_event(&json).unwrap();
-        assert_eq!(event.event_type, "tool_use");
-        assert!(event.tool_use.is_some());
-        let tool_use = event.tool_use.unwrap();
-        assert_eq!(tool_use.tool_use_id, "call_123");
-        assert_eq!(tool_use.name, "get_weather");
-    }
+    // Clone tracker for stream processing
+    let tracker_for_stream = output_tokens_tracker.clone();
 
-    #[test]
-    fn test_parse_kiro_event_usage() {
-        let json = serde_json::json!({
-            "metadata": {
-                "usage": {
-                    "inputTokens": 100,
-                    "outputTokens": 50
-                }
-            }
-        });
+    // Convert to OpenAI chunks
+    let openai_stream = kiro_stream.filter_map(move |event_result| {
+        let completion_id = completion_id_clone.clone();
+        let model = model_clone.clone();
+        let state = state.clone();
+        let tracker = tracker_for_stream.clone();
+        let credits_tracker = credits_tracker.clone();
 
-        let event = parse_kiro_event(&json).unwrap();
-        assert_eq!(event.event_type, "usage");
-        assert!(event.usage.is_some());
-        let usage = event.usage.unwrap();
-        assert_eq!(usage.input_tokens, 100);
-        assert_eq!(usage.output_tokens, 50);
-    }
+        async move {
+            match event_result {
+                Ok(event) => {
+                    let mut state = state.lock().unwrap();
 
-    // ==================== Tool Call Accumulator Tests ====================
+                    match event.event_type.as_str() {
+                        "content" => {
+                            if let Some(content) = event.content {
+                                // Accumulate text for accurate token counting
+                                state.accumulated_text.push_str(&content);
 
-    #[test]
-    fn test_tool_call_accumulator_simple() {
-        let mut acc = ToolCallAccumulator::new();
+                                let delta = ChatCompletionChunkDelta {
+                                    role: if state.first_chunk {
+                                        Some("assistant".to_string())
+                                    } else {
+                                        None
+                                    },
+                                    content: Some(content),
+                                    tool_calls: None,
+                                    reasoning_content: None,
+                                };
 
-        // Tool start
-        let start = serde_json::json!({
-            "name": "get_weather",
-            "toolUseId": "call_123"
-        });
-        let result = acc.process_event(&start);
-        assert!(result.is_none()); // Not complete yet
+                                state.first_chunk = false;
 
-        // Tool input
-        let input = serde_json::json!({
-            "input": "{\"location\": \"SF\"}"
-        });
-        let result = acc.process_event(&input);
-        assert!(result.is_none()); // Not complete yet
+                                let chunk = ChatCompletionChunk {
+                                    id: completion_id,
+                                    object: "chat.completion.chunk".to_string(),
+                                    created: created_time,
+                                    model,
+                                    choices: vec![ChatCompletionChunkChoice {
+                                        index: 0,
+                                        delta,
+                                        finish_reason: None,
+                                        logprobs: None,
+                                    }],
+                                    usage: None,
+                                    system_fingerprint: None,
+                                };
 
-        // Tool stop
-        let stop = serde_json::json!({
-            "stop": true
-        });
-        let result = acc.process_event(&stop);
-        assert!(result.is_some());
+                                let json = serde_json::to_string(&chunk)
+                                    .unwrap_or_else(|_| "{}".to_string());
+                                Some(Ok(format!("data: {}\n\n", json)))
+                            } else {
+                                None
+                            }
+                        }
+                        "thinking" => {
+                            if let Some(thinking) = event.thinking_content {
+                                // Accumulate text for accurate token counting
+                                state.accumulated_text.push_str(&thinking);
 
-        let tool = result.unwrap();
-        assert_eq!(tool.name, "get_weather");
-        assert_eq!(tool.tool_use_id, "call_123");
-    }
+                                let delta = ChatCompletionChunkDelta {
+                                    role: if state.first_chunk {
+                                        Some("assistant".to_string())
+                                    } else {
+                                        None
+                                    },
+                                    content: None,
+                                    tool_calls: None,
+                                    reasoning_content: Some(thinking),
+                                };
 
-    #[test]
-    fn test_tool_call_accumulator_with_input_in_start() {
-        let mut acc = ToolCallAccumulator::new();
+                                state.first_chunk = false;
 
-        // Tool start with input
-        let start = serde_json::json!({
-            "name": "bash",
-            "toolUseId": "call_456",
-            "input": "{\"command\": \"ls\"}"
-        });
-        let result = acc.process_event(&start);
-        assert!(result.is_none());
+                                let chunk = ChatCompletionChunk {
+                                    id: completion_id,
+                                    object: "chat.completion.chunk".to_string(),
+                                    created: created_time,
+                                    model,
+                                    choices: vec![ChatCompletionChunkChoice {
+                                        index: 0,
+                                        delta,
+                                        finish_reason: None,
+                                        logprobs: None,
+                                    }],
+                                    usage: None,
+                                    system_fingerprint: None,
+                                };
 
-        // Tool stop
-        let stop = serde_json::json!({
-            "stop": true
-        });
-        let result = acc.process_event(&stop);
-        assert!(result.is_some());
-
-        let tool = result.unwrap();
-        assert_eq!(tool.name, "bash");
-        assert_eq!(tool.input["command"], "ls");
-    }
-
-    #[test]
-    fn test_tool_call_accumulator_continuation_same_id() {
-        let mut acc = ToolCallAccumulator::new();
-
-        // First chunk with name and toolUseId
-        let chunk1 = serde_json::json!({
-            "name": "bash",
-            "toolUseId": "call_789",
-            "input": "{\"comm"
-        });
-        acc.process_event(&chunk1);
+                                let json = serde_json::to_string(&chunk)
+                                    .unwrap_or_else(|_| "{}".to_string());
+                                Some(Ok(format!("data: {}\n\n", json)))
+                            } else {
+                                None
+                            }
+                        }
+                        "tool_use" => {
+                            if let Some(tool_use) = event.tool_use {
+                                state.tool_calls.push(tool_use);
+                            }
+                            None
+                        }
+                        "usage" => {
+                            if let Some(u) = event.usage {
+                                track_credits(credits_tracker.as_ref(), &u);
+                                state.usage = Some(u.clone());
+                                if let Some(ref t) = tracker {
+                                    t.store(
+                                        u.output_tokens as u64,
+                                        std::sync::atomic::Ordering::Relaxed,
+                                    );
+                                }
+                            }
+                            None
+                        }
+                        _ => None,
+                    }
+                }
+                Err(e) => Some(Err(e)),
+            }
+        }
+    });
 
-        // Continuation with same toolUseId (Kiro sends name in every chunk)
-        let chunk2 = serde_json::json!({
-            "name": "bash",
-            "toolUseId": "call_789",
-            "input": "and\": \"ls -la\"}"
-        });
-        acc.process_event(&chunk2);
+    // Add final chunk with tool calls, usage, and [DONE]
+    let completion_id_for_final = completion_id.clone();
+    let model_for_final = model.clone();
+    let tracker_for_final = output_tokens_tracker.clone();
+    let final_chunks_stream = futures::stream::unfold(
+        Some((
+            state_for_final,
+            completion_id_for_final,
+            model_for_final,
+            created_time,
+            input_tokens,
+            tracker_for_final,
+        )),
+        move |state_opt| async move {
+            let (state_arc, completion_id, model, created_time, input_tokens, tracker) = state_opt?;
+            let state = state_arc.lock().unwrap();
+            let mut final_chunks = Vec::new();
 
-        // Stop
-        let stop = serde_json::json!({
-            "stop": true
-        });
-        let result = acc.process_event(&stop);
-        assert!(result.is_some());
+            // Deduplicate tool calls before sending
+            let deduped_tool_calls = deduplicate_tool_calls(state.tool_calls.clone());
 
-        let tool = result.unwrap();
-        assert_eq!(tool.name, "bash");
-        assert_eq!(tool.input["command"], "ls -la");
-    }
+            // Send tool calls if present
+            if !deduped_tool_calls.is_empty() {
+                let tool_call_deltas: Vec<ToolCallDelta> = deduped_tool_calls
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, tc)| ToolCallDelta {
+                        index: idx as i32,
+                        id: Some(tc.tool_use_id.clone()),
+                        tool_type: Some("function".to_string()),
+                        function: Some(FunctionCallDelta {
+                            name: Some(tc.name.clone()),
+                            arguments: Some(
+                                serde_json::to_string(&tc.input)
+                                    .unwrap_or_else(|_| "{}".to_string()),
+                            ),
+                        }),
+                    })
+                    .collect();
 
-    #[test]
-    fn test_tool_call_accumulator_finalize() {
-        let mut acc = ToolCallAccumulator::new();
+                let tool_chunk = ChatCompletionChunk {
+                    id: completion_id.clone(),
+                    object: "chat.completion.chunk".to_string(),
+                    created: created_time,
+                    model: model.clone(),
+                    choices: vec![ChatCompletionChunkChoice {
+                        index: 0,
+                        delta: ChatCompletionChunkDelta {
+                            role: None,
+                            content: None,
+                            tool_calls: Some(tool_call_deltas),
+                            reasoning_content: None,
+                        },
+                        finish_reason: None,
+                        logprobs: None,
+                    }],
+                    usage: None,
+                    system_fingerprint: None,
+                };
 
-        // Start a tool but don't stop it
-        let start = serde_json::json!({
-            "name": "test_tool",
-            "toolUseId": "call_999",
-            "input": "{\"key\": \"value\"}"
-        });
-        acc.process_event(&start);
+                let json = serde_json::to_string(&tool_chunk).unwrap_or_else(|_| "{}".to_string());
+                final_chunks.push(Ok(format!("data: {}\n\n", json)));
+            }
 
-        // Finalize should complete the tool
-        let result = acc.finalize();
-        assert!(result.is_some());
+            // Determine finish_reason
+            let finish_reason = if !deduped_tool_calls.is_empty() {
+                "tool_calls"
+            } else {
+                "stop"
+            };
 
-        let tool = result.unwrap();
-        assert_eq!(tool.name, "test_tool");
-    }
+            // Calculate usage - use our calculated input_tokens, output from Kiro
+            // Only include usage if explicitly requested via stream_options.include_usage
+            let usage_obj = if include_usage {
+                if let Some(ref u) = state.usage {
+                    tracing::info!(
+                        "Including usage in final chunk: prompt_tokens={}, completion_tokens={}, total_tokens={}",
+                        input_tokens,
+                        u.output_tokens,
+                        input_tokens + u.output_tokens
+                    );
+                    Some(ChatCompletionUsage {
+                        prompt_tokens: input_tokens,
+                        completion_tokens: u.output_tokens,
+                        total_tokens: input_tokens + u.output_tokens,
+                        prompt_tokens_details: Some(PromptTokensDetails {
+                            cached_tokens: prompt_cache.read_tokens,
+                        }),
+                        credits_used: None,
+                    })
+                } else {
+                    // Fallback: Count output tokens using tiktoken (same method as input tokens)
+                    let output_tokens = crate::tokenizer::count_tokens(&state.accumulated_text, false);
 
-    // ==================== Deduplicate Tool Calls Tests ====================
+                    if output_tokens > 0 {
+                        tracing::info!(
+                            "No usage data from Kiro API - using tiktoken count: prompt_tokens={}, completion_tokens={} (counted from {} chars), total_tokens={}",
+                            input_tokens,
+                            output_tokens,
+                            state.accumulated_text.len(),
+                            input_tokens + output_tokens
+                        );
 
-    #[test]
-    fn test_deduplicate_tool_calls_empty() {
-        let result = deduplicate_tool_calls(vec![]);
-        assert!(result.is_empty());
-    }
+                        // Update metrics tracker with counted tokens
+                        if let Some(ref t) = tracker {
+                            t.store(output_tokens as u64, std::sync::atomic::Ordering::Relaxed);
+                        }
 
-    #[test]
-    fn test_deduplicate_tool_calls_by_id() {
-        let tools = vec![
-            ToolUse {
-                tool_use_id: "call_1".to_string(),
-                name: "test".to_string(),
-                input: serde_json::json!({}),
-            },
-            ToolUse {
-                tool_use_id: "call_1".to_string(),
-                name: "test".to_string(),
-                input: serde_json::json!({"key": "value"}),
-            },
-        ];
+                        Some(ChatCompletionUsage {
+                            prompt_tokens: input_tokens,
+                            completion_tokens: output_tokens,
+                            total_tokens: input_tokens + output_tokens,
+                            prompt_tokens_details: Some(PromptTokensDetails {
+                                cached_tokens: prompt_cache.read_tokens,
+                            }),
+                            credits_used: None,
+                        })
+                    } else {
+                        tracing::warn!("include_usage=true but no usage data received from Kiro API and no content to count from");
+                        None
+                    }
+                }
+            } else {
+                tracing::debug!("Excluding usage from final chunk (include_usage=false)");
+                None
+            };
 
-        let result = deduplicate_tool_calls(tools);
-        assert_eq!(result.len(), 1);
-        // Should keep the one with more arguments
-        assert_eq!(result[0].input["key"], "value");
-    }
+            // Final chunk with finish_reason and usage
+            let final_chunk = ChatCompletionChunk {
+                id: completion_id.clone(),
+                object: "chat.completion.chunk".to_string(),
+                created: created_time,
+                model: model.clone(),
+                choices: vec![ChatCompletionChunkChoice {
+                    index: 0,
+                    delta: ChatCompletionChunkDelta {
+                        role: None,
+                        content: None,
+                        tool_calls: None,
+                        reasoning_content: None,
+                    },
+                    finish_reason: Some(finish_reason.to_string()),
+                    logprobs: None,
+                }],
+                usage: usage_obj,
+                system_fingerprint: None,
+            };
 
-    #[test]
-    fn test_deduplicate_tool_calls_by_name_args() {
-        let tools = vec![
-            ToolUse {
-                tool_use_id: "call_1".to_string(),
-                name: "test".to_string(),
-                input: serde_json::json!({"key": "value"}),
-            },
-            ToolUse {
-                tool_use_id: "call_2".to_string(),
-                name: "test".to_string(),
-                input: serde_json::json!({"key": "value"}),
-            },
-        ];
+            let json = serde_json::to_string(&final_chunk).unwrap_or_else(|_| "{}".to_string());
+            final_chunks.push(Ok(format!("data: {}\n\n", json)));
 
-        let result = deduplicate_tool_calls(tools);
-        assert_eq!(result.len(), 1);
-    }
+            // [DONE] marker
+            final_chunks.push(Ok("data: [DONE]\n\n".to_string()));
 
-    #[test]
-    fn test_deduplicate_tool_calls_different_tools() {
-        let tools = vec![
-            ToolUse {
-                tool_use_id: "call_1".to_string(),
-                name: "tool_a".to_string(),
-                input: serde_json::json!({"key": "value1"}),
-            },
-            ToolUse {
-                tool_use_id: "call_2".to_string(),
-                name: "tool_b".to_string(),
-                input: serde_json::json!({"key": "value2"}),
-            },
-        ];
+            Some((futures::stream::iter(final_chunks), None))
+        },
+    )
+    .flatten();
 
-        let result = deduplicate_tool_calls(tools);
-        assert_eq!(result.len(), 2);
-    }
+    let final_stream = openai_stream.chain(final_chunks_stream);
 
-    // ==================== SSE Parser Additional Tests ====================
+    final_stream.boxed()
+}
 
-    #[test]
-    fn test_sse_parser_multiple_events() {
-        let mut parser = SseParser::new();
+// ==================================================================================================
+// Anthropic Streaming
+// ==================================================================================================
 
-        let chunk = b"{\"content\": \"Hello\"}{\"content\": \"World\"}";
-        let events = parser.feed(chunk).unwrap();
+/// Formats data as Anthropic SSE event.
+///
+/// Anthropic SSE format:
+/// ```text
+/// event: {event_type}
+/// data: {json_data}
+///
+/// ```
+fn format_anthropic_sse_event(event_type: &str, data: &Value) -> String {
+    format!(
+        "event: {}\ndata: {}\n\n",
+        event_type,
+        serde_json::to_string(data).unwrap_or_else(|_| "{}".to_string())
+    )
+}
 
-        assert_eq!(events.len(), 2);
-        assert_eq!(events[0]["content"], "Hello");
-        assert_eq!(events[1]["content"], "World");
-    }
+/// Generates a unique message ID in Anthropic format.
+fn generate_anthropic_message_id() -> String {
+    format!("msg_{}", &Uuid::new_v4().simple().to_string()[..24])
+}
 
-    #[test]
-    fn test_sse_parser_partial_json() {
-        let mut parser = SseParser::new();
+/// Converts a backend event stream to Anthropic SSE format.
+///
+/// This function takes the unified events of an upstream backend and converts
+/// them to Anthropic's Messages API streaming format with SSE encoding.
+/// `input_tokens` covers the whole prompt, including `prompt_cache` tokens.
+pub fn stream_events_to_anthropic(
+    kiro_stream: KiroEventStream,
+    model: &str,
+    input_tokens: i32,
+    prompt_cache: PromptCacheUsage,
+    output_tokens_tracker: Option<std::sync::Arc<std::sync::atomic::AtomicU64>>,
+    credits_tracker: Option<std::sync::Arc<std::sync::atomic::AtomicU64>>,
+) -> BoxStream<'static, Result<String, ApiError>> {
+    let message_id = generate_anthropic_message_id();
+    let model = model.to_string();
 
-        // First chunk - incomplete JSON
-        let chunk1 = b"{\"content\": \"Hel";
-        let events1 = parser.feed(chunk1).unwrap();
-        assert_eq!(events1.len(), 0);
+    // Use state to track blocks
+    use std::sync::Arc;
+    use std::sync::Mutex;
 
-        // Second chunk - completes the JSON
-        let chunk2 = b"lo\"}";
-        let events2 = parser.feed(chunk2).unwrap();
-        assert_eq!(events2.len(), 1);
-        assert_eq!(events2[0]["content"], "Hello");
+    #[derive(Default)]
+    struct StreamState {
+        text_block_started: bool,
+        text_block_index: i32,
+        thinking_block_started: bool,
+        thinking_block_index: i32,
+        current_block_index: i32,
+        tool_calls: Vec<ToolUse>,
+        usage: Option<Usage>,
+        accumulated_text: String, // Accumulate all text for accurate token counting
     }
 
-    #[test]
-    fn test_sse_parser_nested_json() {
-        let mut parser = SseParser::new();
-
-        let chunk = b"{\"content\": \"{\\\"nested\\\": true}\"}";
-        let events = parser.feed(chunk).unwrap();
+    let state = Arc::new(Mutex::new(StreamState::default()));
 
-        assert_eq!(events.len(), 1);
-        assert_eq!(events[0]["content"], "{\"nested\": true}");
-    }
+    // Send message_start event first
+    let message_start = serde_json::json!({
+        "type": "message_start",
+        "message": {
+            "id": message_id,
+            "type": "message",
+            "role": "assistant",
+            "content": [],
+            "model": model,
+            "stop_reason": null,
+            "stop_sequence": null,
+            "usage": {
+                "input_tokens": prompt_cache.uncached(input_tokens),
+                "cache_creation_input_tokens": prompt_cache.creation_tokens,
+                "cache_read_input_tokens": prompt_cache.read_tokens,
+                "output_tokens": 0
+            }
+        }
+    });
 
-    #[test]
-    fn test_sse_parser_usage_event() {
-        let mut parser = SseParser::new();
+    let start_event = format_anthropic_sse_event("message_start", &message_start);
 
-        let chunk = b"{\"usage\": {\"inputTokens\": 100, \"outputTokens\": 50}}";
-        let events = parser.feed(chunk).unwrap();
+    let _model_clone = model.clone();
 
-        assert_eq!(events.len(), 1);
-        assert_eq!(events[0]["usage"]["inputTokens"], 100);
-    }
+    // Clone state for use in final stream
+    let state_for_final = state.clone();
 
-    #[test]
-    fn test_sse_parser_context_usage() {
-        let mut parser = SseParser::new();
+    // Clone tracker for final events before it gets moved
+    let tracker_for_final = output_tokens_tracker.clone();
 
-        let chunk = b"{\"contextUsagePercentage\": 45.5}";
-        let events = parser.feed(chunk).unwrap();
+    // Convert Kiro events to Anthropic events
+    let anthropic_stream = kiro_stream.filter_map(move |event_result| {
+        let _model = _model_clone.clone();
+        let state = state.clone();
+        let tracker = output_tokens_tracker.clone();
+        let credits_tracker = credits_tracker.clone();
 
-        assert_eq!(events.len(), 1);
-        assert_eq!(events[0]["contextUsagePercentage"], 45.5);
-    }
+        async move {
+            match event_result {
+                Ok(event) => {
+                    let mut state = state.lock().unwrap();
 
-    // ==================== Parse Kiro Event Additional Tests ====================
+                    match event.event_type.as_str() {
+                        "content" => {
+                            if let Some(content) = event.content {
+                                // Accumulate text for accurate token counting
+                                state.accumulated_text.push_str(&content);
 
-    #[test]
-    fn test_parse_kiro_event_direct_content() {
-        let json = serde_json::json!({
-            "content": "Direct content"
-        });
+                                // Start text block if not started
+                                if !state.text_block_started {
+                                    state.text_block_index = state.current_block_index;
+                                    state.current_block_index += 1;
+                                    state.text_block_started = true;
 
-        let event = parse_kiro_event(&json).unwrap();
-        assert_eq!(event.event_type, "content");
-        assert_eq!(event.content, Some("Direct content".to_string()));
-    }
+                                    let block_start = serde_json::json!({
+                                        "type": "content_block_start",
+                                        "index": state.text_block_index,
+                                        "content_block": {
+                                            "type": "text",
+                                            "text": ""
+                                        }
+                                    });
 
-    #[test]
-    fn test_parse_kiro_event_direct_usage_number() {
-        let json = serde_json::json!({
-            "usage": 1.5
-        });
+                                    let start_event = format_anthropic_sse_event(
+                                        "content_block_start",
+                                        &block_start,
+                                    );
 
-        let event = parse_kiro_event(&json).unwrap();
-        assert_eq!(event.event_type, "usage");
-        assert!(event.usage.is_some());
-    }
+                                    // Send both start and delta
+                                    let delta = serde_json::json!({
+                                        "type": "content_block_delta",
+                                        "index": state.text_block_index,
+                                        "delta": {
+                                            "type": "text_delta",
+                                            "text": content
+                                        }
+                                    });
 
-    #[test]
-    fn test_parse_kiro_event_direct_usage_object() {
-        let json = serde_json::json!({
-            "usage": {
-                "inputTokens": 200,
-                "outputTokens": 100
-            }
-        });
+                                    let delta_event =
+                                        format_anthropic_sse_event("content_block_delta", &delta);
 
-        let event = parse_kiro_event(&json).unwrap();
-        assert_eq!(event.event_type, "usage");
-        let usage = event.usage.unwrap();
-        assert_eq!(usage.input_tokens, 200);
-        assert_eq!(usage.output_tokens, 100);
-    }
+                                    return Some(Ok(format!("{}{}", start_event, delta_event)));
+                                } else {
+                                    // Send delta only
+                                    let delta = serde_json::json!({
+                                        "type": "content_block_delta",
+                                        "index": state.text_block_index,
+                                        "delta": {
+                                            "type": "text_delta",
+                                            "text": content
+                                        }
+                                    });
 
-    #[test]
-    fn test_parse_kiro_event_context_usage() {
-        let json = serde_json::json!({
-            "contextUsagePercentage": 75.0
-        });
+                                    return Some(Ok(format_anthropic_sse_event(
+                                        "content_block_delta",
+                                        &delta,
+                                    )));
+                                }
+                            }
+                            None
+                        }
+                        "thinking" => {
+                            if let Some(thinking) = event.thinking_content {
+                                // Accumulate text for accurate token counting
+                                state.accumulated_text.push_str(&thinking);
 
-        let event = parse_kiro_event(&json).unwrap();
-        assert_eq!(event.event_type, "context_usage");
-        assert_eq!(event.context_usage_percentage, Some(75.0));
-    }
+                                // Start thinking block if not started
+                                if !state.thinking_block_started {
+                                    state.thinking_block_index = state.current_block_index;
+                                    state.current_block_index += 1;
+                                    state.thinking_block_started = true;
 
-    #[test]
-    fn test_parse_kiro_event_followup_prompt_ignored() {
-        let json = serde_json::json!({
-            "followupPrompt": "Some prompt"
-        });
+                                    let block_start = serde_json::json!({
+                                        "type": "content_block_start",
+                                        "index": state.thinking_block_index,
+                                        "content_block": {
+                                            "type": "thinking",
+                                            "thinking": ""
+                                        }
+                                    });
 
-        let event = parse_kiro_event(&json);
-        assert!(event.is_none());
-    }
+                                    let start_event = format_anthropic_sse_event(
+                                        "content_block_start",
+                                        &block_start,
+                                    );
 
-    #[test]
-    fn test_parse_kiro_event_message_stop_ignored() {
-        let json = serde_json::json!({
-            "messageStop": {}
-        });
+                                    // Send both start and delta
+                                    let delta = serde_json::json!({
+                                        "type": "content_block_delta",
+                                        "index": state.thinking_block_index,
+                                        "delta": {
+                                            "type": "thinking_delta",
+                                            "thinking": thinking
+                                        }
+                                    });
 
-        let event = parse_kiro_event(&json);
-        assert!(event.is_none());
-    }
+                                    let delta_event =
+                                        format_anthropic_sse_event("content_block_delta", &delta);
 
-    // ==================== KiroEvent Tests ====================
+                                    return Some(Ok(format!("{}{}", start_event, delta_event)));
+                                } else {
+                                    // Send delta only
+                                    let delta = serde_json::json!({
+                                        "type": "content_block_delta",
+                                        "index": state.thinking_block_index,
+                                        "delta": {
+                                            "type": "thinking_delta",
+                                            "thinking": thinking
+                                        }
+                                    });
 
-    #[test]
-    fn test_kiro_event_default() {
-        let event = KiroEvent {
-            event_type: "content".to_string(),
-            content: Some("test".to_string()),
-            thinking_content: None,
-            tool_use: None,
-            usage: None,
-            context_usage_percentage: None,
-            is_first_thinking_chunk: false,
-            is_last_thinking_chunk: false,
-        };
+                                    return Some(Ok(format_anthropic_sse_event(
+                                        "content_block_delta",
+                                        &delta,
+                                    )));
+                                }
+                            }
+                            None
+                        }
+                        "tool_use" => {
+                            if let Some(tool_use) = event.tool_use {
+                                tracing::info!(
+                                    "Received tool_use event: name={}, id={}, total_collected={}",
+                                    tool_use.name,
+                                    tool_use.tool_use_id,
+                                    state.tool_calls.len() + 1
+                                );
+                                state.tool_calls.push(tool_use);
+                            }
+                            None
+                        }
+                        "usage" => {
+                            if let Some(u) = event.usage {
+                                track_credits(credits_tracker.as_ref(), &u);
+                                state.usage = Some(u.clone());
+                                if let Some(ref t) = tracker {
+                                    t.store(
+                                        u.output_tokens as u64,
+                                        std::sync::atomic::Ordering::Relaxed,
+                                    );
+                                }
+                            }
+                            None
+                        }
+                        _ => None,
+                    }
+                }
+                Err(e) => Some(Err(e)),
+            }
+        }
+    });
 
-        assert_eq!(event.event_type, "content");
-        assert!(!event.is_first_thinking_chunk);
-        assert!(!event.is_last_thinking_chunk);
-    }
+    // Add final events
+    let final_events_stream = futures::stream::unfold(Some(state_for_final), move |state_opt| {
+        let tracker = tracker_for_final.clone();
+        async move {
+            let state_arc = state_opt?;
+            let state = state_arc.lock().unwrap();
+        let mut final_events = Vec::new();
 
-    #[test]
-    fn test_tool_use_serialization() {
-        let tool_use = ToolUse {
-            tool_use_id: "call_123".to_string(),
-            name: "test_tool".to_string(),
-            input: serde_json::json!({"key": "value"}),
-        };
+        // Close thinking block if open
+        if state.thinking_block_started {
+            let block_stop = serde_json::json!({
+                "type": "content_block_stop",
+                "index": state.thinking_block_index
+            });
+            final_events.push(Ok(format_anthropic_sse_event("content_block_stop", &block_stop)));
+        }
 
-        let json = serde_json::to_string(&tool_use).unwrap();
-        assert!(json.contains("call_123"));
-        assert!(json.contains("test_tool"));
-    }
+        // Close text block if open
+        if state.text_block_started {
+            let block_stop = serde_json::json!({
+                "type": "content_block_stop",
+                "index": state.text_block_index
+            });
+            final_events.push(Ok(format_anthropic_sse_event("content_block_stop", &block_stop)));
+        }
 
-    #[test]
-    fn test_usage_serialization() {
-        let usage = Usage {
-            input_tokens: 100,
-            output_tokens: 50,
-        };
+        // Deduplicate tool calls before sending
+        let deduped_tool_calls = deduplicate_tool_calls(state.tool_calls.clone());
 
-        let json = serde_json::to_string(&usage).unwrap();
-        let parsed: Usage = serde_json::from_str(&json).unwrap();
-        assert_eq!(parsed.input_tokens, 100);
-        assert_eq!(parsed.output_tokens, 50);
-    }
+        // Log tool calls for debugging
+        if !deduped_tool_calls.is_empty() {
+            tracing::info!(
+                "Emitting {} tool calls (before dedup: {}): {:?}",
+                deduped_tool_calls.len(),
+                state.tool_calls.len(),
+                deduped_tool_calls.iter().map(|t| format!("{}:{}", t.name, &t.tool_use_id)).collect::<Vec<_>>()
+            );
+        }
 
-    // ==================== Finalize Double-Call Prevention Tests ====================
+        // Send tool use blocks if present
+        for (tool_index, tool_use) in (state.current_block_index..).zip(deduped_tool_calls.iter()) {
 
-    #[test]
-    fn test_tool_call_accumulator_finalize_prevents_double() {
-        let mut acc = ToolCallAccumulator::new();
+            let block_start = serde_json::json!({
+                "type": "content_block_start",
+                "index": tool_index,
+                "content_block": {
+                    "type": "tool_use",
+                    "id": tool_use.tool_use_id,
+                    "name": tool_use.name,
+                    "input": {}
+                }
+            });
+            final_events.push(Ok(format_anthropic_sse_event("content_block_start", &block_start)));
 
-        // Start a tool but don't stop it
-        let start = serde_json::json!({
-            "name": "write",
-            "toolUseId": "call_double_test",
-            "input": "{\"filePath\": \"/test/path.txt\"}"
-        });
-        acc.process_event(&start);
+            let delta = serde_json::json!({
+                "type": "content_block_delta",
+                "index": tool_index,
+                "delta": {
+                    "type": "input_json_delta",
+                    "partial_json": serde_json::to_string(&tool_use.input).unwrap_or_else(|_| "{}".to_string())
+                }
+            });
+            final_events.push(Ok(format_anthropic_sse_event("content_block_delta", &delta)));
+
+            let block_stop = serde_json::json!({
+                "type": "content_block_stop",
+                "index": tool_index
+            });
+            final_events.push(Ok(format_anthropic_sse_event("content_block_stop", &block_stop)));
+        }
 
-        // First finalize should return the tool
-        let result1 = acc.finalize();
-        assert!(result1.is_some());
-        let tool = result1.unwrap();
-        assert_eq!(tool.name, "write");
-        assert_eq!(tool.tool_use_id, "call_double_test");
+        // Determine stop_reason
+        let stop_reason = if !deduped_tool_calls.is_empty() {
+            "tool_use"
+        } else {
+            "end_turn"
+        };
 
-        // Second finalize should return None (prevents double-finalization)
-        let result2 = acc.finalize();
-        assert!(result2.is_none());
+        // Calculate usage
+        let output_tokens = if let Some(ref u) = state.usage {
+            u.output_tokens
+        } else {
+            // Fallback: Count output tokens using tiktoken (same method as input tokens)
+            let tokens = crate::tokenizer::count_tokens(&state.accumulated_text, false);
+            if tokens > 0 {
+                tracing::info!(
+                    "No usage data from Kiro API - using tiktoken count: output_tokens={} (counted from {} chars)",
+                    tokens,
+                    state.accumulated_text.len()
+                );
 
-        // Third finalize should also return None
-        let result3 = acc.finalize();
-        assert!(result3.is_none());
-    }
+                // Update metrics tracker with counted tokens
+                if let Some(ref t) = tracker {
+                    t.store(tokens as u64, std::sync::atomic::Ordering::Relaxed);
+                }
+            }
+            tokens
+        };
 
-    #[test]
-    fn test_tool_call_accumulator_truncated_json() {
-        let mut acc = ToolCallAccumulator::new();
+        // Send message_delta with stop_reason
+        let message_delta = serde_json::json!({
+            "type": "message_delta",
+            "delta": {
+                "stop_reason": stop_reason,
+                "stop_sequence": null
+            },
+            "usage": {
+                "output_tokens": output_tokens
+            }
+        });
+        final_events.push(Ok(format_anthropic_sse_event("message_delta", &message_delta)));
 
-        // Simulate truncated JSON input (stream terminated mid-tool-call)
-        let start = serde_json::json!({
-            "name": "write",
-            "toolUseId": "call_truncated",
-            "input": "{\"filePath\": \"/Users/test/docs/api.yaml\""
+        // Send message_stop
+        let message_stop = serde_json::json!({
+            "type": "message_stop"
         });
-        // Note: The input JSON is missing the closing "}"
-        acc.process_event(&start);
+        final_events.push(Ok(format_anthropic_sse_event("message_stop", &message_stop)));
 
-        // Finalize should still return the tool with whatever data we have
-        let result = acc.finalize();
-        assert!(result.is_some());
+        Some((futures::stream::iter(final_events), None))
+    }})
+    .flatten();
 
-        let tool = result.unwrap();
-        assert_eq!(tool.name, "write");
-        assert_eq!(tool.tool_use_id, "call_truncated");
-        // The input should be an empty object since the truncated JSON fails to parse
-        assert!(tool.input.is_object());
-    }
+    let final_stream = anthropic_stream.chain(final_events_stream);
+
+    // Prepend message_start event
+    let complete_stream = futures::stream::once(async move { Ok(start_event) }).chain(final_stream);
+
+    complete_stream.boxed()
 }
 
 // ==================================================================================================
-// OpenAI Streaming
+// Non-Streaming Response Collection (like Python's collect_stream_response)
 // ==================================================================================================
 
-use crate::models::openai::{
-    ChatCompletionChunk, ChatCompletionChunkChoice, ChatCompletionChunkDelta, ChatCompletionUsage,
-    FunctionCallDelta, ToolCallDelta,
-};
-use futures::stream::BoxStream;
-use uuid::Uuid;
-
-/// Generates a unique completion ID in OpenAI format.
-fn generate_completion_id() -> String {
-    format!("chatcmpl-{}", &Uuid::new_v4().simple().to_string()[..24])
-}
-
-/// Converts Kiro stream to OpenAI SSE format.
+/// Collects a complete OpenAI response from a backend event stream.
 ///
-/// This function takes a Kiro API response stream and converts it to OpenAI's
-/// chat.completion.chunk format with SSE encoding.
-pub async fn stream_kiro_to_openai(
-    response: reqwest::Response,
+/// This is used for non-streaming mode - it processes the stream internally
+/// and returns a single complete response. This matches Python's collect_stream_response().
+///
+/// Backends always stream (Kiro returns AWS Event Stream format even for
+/// non-streaming requests), so the events are aggregated here.
+pub async fn collect_openai_events(
+    mut kiro_stream: KiroEventStream,
     model: &str,
-    first_token_timeout_secs: u64,
     input_tokens: i32,
-    output_tokens_tracker: Option<std::sync::Arc<std::sync::atomic::AtomicU64>>,
-    include_usage: bool,
-) -> Result<BoxStream<'static, Result<String, ApiError>>, ApiError> {
+    prompt_cache: PromptCacheUsage,
+    credits_tracker: Option<Arc<AtomicU64>>,
+) -> Result<Value, ApiError> {
     let completion_id = generate_completion_id();
     let created_time = chrono::Utc::now().timestamp();
-    let model = model.to_string();
-
-    // Parse Kiro stream and collect all events
-    let kiro_stream = parse_kiro_stream(response, first_token_timeout_secs).await?;
 
-    // Use scan to maintain state across stream items
-    use std::sync::Arc;
-    use std::sync::Mutex;
+    // Collect all content
+    let mut full_content = String::new();
+    let mut full_reasoning_content = String::new();
+    let mut tool_calls: Vec<ToolUse> = Vec::new();
+    let mut usage: Option<Usage> = None;
 
-    #[derive(Default)]
-    struct StreamState {
-        first_chunk: bool,
-        tool_calls: Vec<ToolUse>,
-        usage: Option<Usage>,
-        accumulated_text: String, // Accumulate all text for accurate token counting
+    while let Some(event_result) = kiro_stream.next().await {
+        match event_result {
+            Ok(event) => match event.event_type.as_str() {
+                "content" => {
+                    if let Some(content) = event.content {
+                        full_content.push_str(&content);
+                    }
+                }
+                "thinking" => {
+                    if let Some(thinking) = event.thinking_content {
+                        full_reasoning_content.push_str(&thinking);
+                    }
+                }
+                "tool_use" => {
+                    if let Some(tool_use) = event.tool_use {
+                        tool_calls.push(tool_use);
+                    }
+                }
+                "usage" => {
+                    if let Some(u) = event.usage {
+                        track_credits(credits_tracker.as_ref(), &u);
+                        usage = Some(u);
+                    }
+                }
+                _ => {}
+            },
+            Err(e) => {
+                tracing::warn!("Error in stream: {:?}", e);
+            }
+        }
     }
 
-    let state = Arc::new(Mutex::new(StreamState {
-        first_chunk: true,
-        tool_calls: Vec::new(),
-        usage: None,
-        accumulated_text: String::new(),
-    }));
-
-    let completion_id_clone = completion_id.clone();
-    let model_clone = model.clone();
-
-    // Clone state for use in final stream
-    let state_for_final = state.clone();
-
-    // Clone tracker for stream processing
-    let tracker_for_stream = output_tokens_tracker.clone();
+    // Deduplicate tool calls
+    let tool_calls = deduplicate_tool_calls(tool_calls);
 
-    // Convert to OpenAI chunks
-    let openai_stream = kiro_stream.filter_map(move |event_result| {
-        let completion_id = completion_id_clone.clone();
-        let model = model_clone.clone();
-        let state = state.clone();
-        let tracker = tracker_for_stream.clone();
+    // Build message
+    let mut message = serde_json::json!({
+        "role": "assistant",
+        "content": full_content
+    });
 
-        async move {
-            match event_result {
-                Ok(event) => {
-                    let mut state = state.lock().unwrap();
+    if !full_reasoning_content.is_empty() {
+        message["reasoning_content"] = serde_json::json!(full_reasoning_content);
+    }
 
-                    match event.event_type.as_str() {
-                        "content" => {
-                            if let Some(content) = event.content {
-                                // Accumulate text for accurate token counting
-                                state.accumulated_text.push_str(&content);
+    if !tool_calls.is_empty() {
+        let tool_calls_json: Vec<Value> = tool_calls
+            .iter()
+            .map(|tc| {
+                serde_json::json!({
+                    "id": tc.tool_use_id,
+                    "type": "function",
+                    "function": {
+                        "name": tc.name,
+                        "arguments": serde_json::to_string(&tc.input).unwrap_or_else(|_| "{}".to_string())
+                    }
+                })
+            })
+            .collect();
+        message["tool_calls"] = serde_json::json!(tool_calls_json);
+    }
 
-                                let delta = ChatCompletionChunkDelta {
-                                    role: if state.first_chunk {
-                                        Some("assistant".to_string())
-                                    } else {
-                                        None
-                                    },
-                                    content: Some(content),
-                                    tool_calls: None,
-                                    reasoning_content: None,
-                                };
+    // Determine finish_reason
+    let finish_reason = if !tool_calls.is_empty() {
+        "tool_calls"
+    } else {
+        "stop"
+    };
 
-                                state.first_chunk = false;
+    // Build usage - use our calculated input_tokens, output from Kiro
+    let output_tokens = if let Some(u) = usage {
+        u.output_tokens
+    } else {
+        // Fallback: Count output tokens using tiktoken (same method as input tokens)
+        let mut accumulated_text = full_content.clone();
+        accumulated_text.push_str(&full_reasoning_content);
+        let tokens = crate::tokenizer::count_tokens(&accumulated_text, false);
+        if tokens > 0 {
+            tracing::info!(
+                "No usage data from Kiro API - using tiktoken count: output_tokens={} (counted from {} chars)",
+                tokens,
+                accumulated_text.len()
+            );
+        }
+        tokens
+    };
 
-                                let chunk = ChatCompletionChunk {
-                                    id: completion_id,
-                                    object: "chat.completion.chunk".to_string(),
-                                    created: created_time,
-                                    model,
-                                    choices: vec![ChatCompletionChunkChoice {
-                                        index: 0,
-                                        delta,
-                                        finish_reason: None,
-                                        logprobs: None,
-                                    }],
-                                    usage: None,
-                                    system_fingerprint: None,
-                                };
+    let usage_json = serde_json::json!({
+        "prompt_tokens": input_tokens,
+        "completion_tokens": output_tokens,
+        "total_tokens": input_tokens + output_tokens,
+        "prompt_tokens_details": {
+            "cached_tokens": prompt_cache.read_tokens
+        }
+    });
 
-                                let json = serde_json::to_string(&chunk)
-                                    .unwrap_or_else(|_| "{}".to_string());
-                                Some(Ok(format!("data: {}\n\n", json)))
-                            } else {
-                                None
-                            }
-                        }
-                        "thinking" => {
-                            if let Some(thinking) = event.thinking_content {
-                                // Accumulate text for accurate token counting
-                                state.accumulated_text.push_str(&thinking);
+    // Build complete response
+    let response = serde_json::json!({
+        "id": completion_id,
+        "object": "chat.completion",
+        "created": created_time,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason
+        }],
+        "usage": usage_json
+    });
 
-                                let delta = ChatCompletionChunkDelta {
-                                    role: if state.first_chunk {
-                                        Some("assistant".to_string())
-                                    } else {
-                                        None
-                                    },
-                                    content: None,
-                                    tool_calls: None,
-                                    reasoning_content: Some(thinking),
-                                };
+    Ok(response)
+}
 
-                                state.first_chunk = false;
+/// Collects a complete Anthropic response from a backend event stream.
+///
+/// This is used for non-streaming mode - it processes the stream internally
+/// and returns a single complete response.
+pub async fn collect_anthropic_events(
+    mut kiro_stream: KiroEventStream,
+    model: &str,
+    input_tokens: i32,
+    prompt_cache: PromptCacheUsage,
+    credits_tracker: Option<Arc<AtomicU64>>,
+) -> Result<Value, ApiError> {
+    let message_id = generate_anthropic_message_id();
 
-                                let chunk = ChatCompletionChunk {
-                                    id: completion_id,
-                                    object: "chat.completion.chunk".to_string(),
-                                    created: created_time,
-                                    model,
-                                    choices: vec![ChatCompletionChunkChoice {
-                                        index: 0,
-                                        delta,
-                                        finish_reason: None,
-                                        logprobs: None,
-                                    }],
-                                    usage: None,
-                                    system_fingerprint: None,
-                                };
+    // Collect all content
+    let mut full_content = String::new();
+    let mut full_thinking_content = String::new();
+    let mut tool_calls: Vec<ToolUse> = Vec::new();
+    let mut usage: Option<Usage> = None;
 
-                                let json = serde_json::to_string(&chunk)
-                                    .unwrap_or_else(|_| "{}".to_string());
-                                Some(Ok(format!("data: {}\n\n", json)))
-                            } else {
-                                None
-                            }
-                        }
-                        "tool_use" => {
-                            if let Some(tool_use) = event.tool_use {
-                                state.tool_calls.push(tool_use);
-                            }
-                            None
-                        }
-                        "usage" => {
-                            if let Some(u) = event.usage {
-                                state.usage = Some(u.clone());
-                                if let Some(ref t) = tracker {
-                                    t.store(
-                                        u.output_tokens as u64,
-                                        std::sync::atomic::Ordering::Relaxed,
-                                    );
-                                }
-                            }
-                            None
-                        }
-                        _ => None,
+    while let Some(event_result) = kiro_stream.next().await {
+        match event_result {
+            Ok(event) => match event.event_type.as_str() {
+                "content" => {
+                    if let Some(content) = event.content {
+                        full_content.push_str(&content);
                     }
                 }
-                Err(e) => Some(Err(e)),
+                "thinking" => {
+                    if let Some(thinking) = event.thinking_content {
+                        full_thinking_content.push_str(&thinking);
+                    }
+                }
+                "tool_use" => {
+                    if let Some(tool_use) = event.tool_use {
+                        tool_calls.push(tool_use);
+                    }
+                }
+                "usage" => {
+                    if let Some(u) = event.usage {
+                        track_credits(credits_tracker.as_ref(), &u);
+                        usage = Some(u);
+                    }
+                }
+                _ => {}
+            },
+            Err(e) => {
+                tracing::warn!("Error in stream: {:?}", e);
             }
         }
-    });
-
-    // Add final chunk with tool calls, usage, and [DONE]
-    let completion_id_for_final = completion_id.clone();
-    let model_for_final = model.clone();
-    let tracker_for_final = output_tokens_tracker.clone();
-    let final_chunks_stream = futures::stream::unfold(
-        Some((
-            state_for_final,
-            completion_id_for_final,
-            model_for_final,
-            created_time,
-            input_tokens,
-            tracker_for_final,
-        )),
-        move |state_opt| async move {
-            let (state_arc, completion_id, model, created_time, input_tokens, tracker) = state_opt?;
-            let state = state_arc.lock().unwrap();
-            let mut final_chunks = Vec::new();
-
-            // Deduplicate tool calls before sending
-            let deduped_tool_calls = deduplicate_tool_calls(state.tool_calls.clone());
+    }
 
-            // Send tool calls if present
-            if !deduped_tool_calls.is_empty() {
-                let tool_call_deltas: Vec<ToolCallDelta> = deduped_tool_calls
-                    .iter()
-                    .enumerate()
-                    .map(|(idx, tc)| ToolCallDelta {
-                        index: idx as i32,
-                        id: Some(tc.tool_use_id.clone()),
-                        tool_type: Some("function".to_string()),
-                        function: Some(FunctionCallDelta {
-                            name: Some(tc.name.clone()),
-                            arguments: Some(
-                                serde_json::to_string(&tc.input)
-                                    .unwrap_or_else(|_| "{}".to_string()),
-                            ),
-                        }),
-                    })
-                    .collect();
+    // Deduplicate tool calls
+    let tool_calls = deduplicate_tool_calls(tool_calls);
 
-                let tool_chunk = ChatCompletionChunk {
-                    id: completion_id.clone(),
-                    object: "chat.completion.chunk".to_string(),
-                    created: created_time,
-                    model: model.clone(),
-                    choices: vec![ChatCompletionChunkChoice {
-                        index: 0,
-                        delta: ChatCompletionChunkDelta {
-                            role: None,
-                            content: None,
-                            tool_calls: Some(tool_call_deltas),
-                            reasoning_content: None,
-                        },
-                        finish_reason: None,
-                        logprobs: None,
-                    }],
-                    usage: None,
-                    system_fingerprint: None,
-                };
+    // Build content blocks
+    let mut content_blocks: Vec<Value> = Vec::new();
 
-                let json = serde_json::to_string(&tool_chunk).unwrap_or_else(|_| "{}".to_string());
-                final_chunks.push(Ok(format!("data: {}\n\n", json)));
-            }
+    // Add thinking block if present
+    if !full_thinking_content.is_empty() {
+        content_blocks.push(serde_json::json!({
+            "type": "thinking",
+            "thinking": full_thinking_content
+        }));
+    }
 
-            // Determine finish_reason
-            let finish_reason = if !deduped_tool_calls.is_empty() {
-                "tool_calls"
-            } else {
-                "stop"
-            };
+    // Add text block if present
+    if !full_content.is_empty() {
+        content_blocks.push(serde_json::json!({
+            "type": "text",
+            "text": full_content
+        }));
+    }
 
-            // Calculate usage - use our calculated input_tokens, output from Kiro
-            // Only include usage if explicitly requested via stream_options.include_usage
-            let usage_obj = if include_usage {
-                if let Some(ref u) = state.usage {
-                    tracing::info!(
-                        "Including usage in final chunk: prompt_tokens={}, completion_tokens={}, total_tokens={}",
-                        input_tokens,
-                        u.output_tokens,
-                        input_tokens + u.output_tokens
-                    );
-                    Some(ChatCompletionUsage {
-                        prompt_tokens: input_tokens,
-                        completion_tokens: u.output_tokens,
-                        total_tokens: input_tokens + u.output_tokens,
-                        credits_used: None,
-                    })
-                } else {
-                    // Fallback: Count output tokens using tiktoken (same method as input tokens)
-                    let output_tokens = crate::tokenizer::count_tokens(&state.accumulated_text, false);
+    // Add tool use blocks
+    for tool_use in &tool_calls {
+        content_blocks.push(serde_json::json!({
+            "type": "tool_use",
+            "id": tool_use.tool_use_id,
+            "name": tool_use.name,
+            "input": tool_use.input
+        }));
+    }
 
-                    if output_tokens > 0 {
-                        tracing::info!(
-                            "No usage data from Kiro API - using tiktoken count: prompt_tokens={}, completion_tokens={} (counted from {} chars), total_tokens={}",
-                            input_tokens,
-                            output_tokens,
-                            state.accumulated_text.len(),
-                            input_tokens + output_tokens
-                        );
+    // Determine stop_reason
+    let stop_reason = if !tool_calls.is_empty() {
+        "tool_use"
+    } else {
+        "end_turn"
+    };
 
-                        // Update metrics tracker with counted tokens
-                        if let Some(ref t) = tracker {
-                            t.store(output_tokens as u64, std::sync::atomic::Ordering::Relaxed);
-                        }
+    // Build usage - use passed input_tokens, get output_tokens from stream
+    let output_tokens = if let Some(u) = usage {
+        u.output_tokens
+    } else {
+        // Fallback: Count output tokens using tiktoken (same method as input tokens)
+        let mut accumulated_text = full_content.clone();
+        accumulated_text.push_str(&full_thinking_content);
+        let tokens = crate::tokenizer::count_tokens(&accumulated_text, false);
+        if tokens > 0 {
+            tracing::info!(
+                "No usage data from Kiro API - using tiktoken count: output_tokens={} (counted from {} chars)",
+                tokens,
+                accumulated_text.len()
+            );
+        }
+        tokens
+    };
 
-                        Some(ChatCompletionUsage {
-                            prompt_tokens: input_tokens,
-                            completion_tokens: output_tokens,
-                            total_tokens: input_tokens + output_tokens,
-                            credits_used: None,
-                        })
-                    } else {
-                        tracing::warn!("include_usage=true but no usage data received from Kiro API and no content to count from");
-                        None
-                    }
-                }
-            } else {
-                tracing::debug!("Excluding usage from final chunk (include_usage=false)");
-                None
-            };
+    // Build complete response
+    let response = serde_json::json!({
+        "id": message_id,
+        "type": "message",
+        "role": "assistant",
+        "content": content_blocks,
+        "model": model,
+        "stop_reason": stop_reason,
+        "stop_sequence": null,
+        "usage": {
+            "input_tokens": prompt_cache.uncached(input_tokens),
+            "cache_creation_input_tokens": prompt_cache.creation_tokens,
+            "cache_read_input_tokens": prompt_cache.read_tokens,
+            "output_tokens": output_tokens
+        }
+    });
 
-            // Final chunk with finish_reason and usage
-            let final_chunk = ChatCompletionChunk {
-                id: completion_id.clone(),
-                object: "chat.completion.chunk".to_string(),
-                created: created_time,
-                model: model.clone(),
-                choices: vec![ChatCompletionChunkChoice {
-                    index: 0,
-                    delta: ChatCompletionChunkDelta {
-                        role: None,
-                        content: None,
-                        tool_calls: None,
-                        reasoning_content: None,
-                    },
-                    finish_reason: Some(finish_reason.to_string()),
-                    logprobs: None,
-                }],
-                usage: usage_obj,
-                system_fingerprint: None,
-            };
+    Ok(response)
+}
 
-            let json = serde_json::to_string(&final_chunk).unwrap_or_else(|_| "{}".to_string());
-            final_chunks.push(Ok(format!("data: {}\n\n", json)));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            // [DONE] marker
-            final_chunks.push(Ok("data: [DONE]\n\n".to_string()));
+    #[test]
+    fn test_sse_parser_basic() {
+        let mut parser = SseParser::new();
 
-            Some((futures::stream::iter(final_chunks), None))
-        },
-    )
-    .flatten();
+        // Use a pattern that matches EVENT_PATTERNS
+        let chunk = b"{\"content\": \"Hello, world!\"}\n\n";
+        let events = parser.feed(chunk).unwrap();
 
-    let final_stream = openai_stream.chain(final_chunks_stream);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["content"], "Hello, world!");
+    }
 
-    Ok(final_stream.boxed())
-}
+    #[test]
+    fn test_sse_parser_done_marker() {
+        let mut parser = SseParser::new();
 
-// ==================================================================================================
-// Anthropic Streaming
-// ==================================================================================================
+        let chunk = b"data: [DONE]\n\n";
+        let events = parser.feed(chunk).unwrap();
 
-/// Formats data as Anthropic SSE event.
-///
-/// Anthropic SSE format:
-/// ```text
-/// event: {event_type}
-/// data: {json_data}
-///
-/// ```
-fn format_anthropic_sse_event(event_type: &str, data: &Value) -> String {
-    format!(
-        "event: {}\ndata: {}\n\n",
-        event_type,
-        serde_json::to_string(data).unwrap_or_else(|_| "{}".to_string())
-    )
-}
+        assert_eq!(events.len(), 0);
+    }
 
-/// Generates a unique message ID in Anthropic format.
-fn generate_anthropic_message_id() -> String {
-    format!("msg_{}", &Uuid::new_v4().simple().to_string()[..24])
-}
+    #[test]
+    fn test_sse_parser_aws_format() {
+        let mut parser = SseParser::new();
 
-/// Converts Kiro stream to Anthropic SSE format.
-///
-/// This function takes a Kiro API response stream and converts it to Anthropic's
-/// Messages API streaming format with SSE encoding.
-pub async fn stream_kiro_to_anthropic(
-    response: reqwest::Response,
-    model: &str,
-    first_token_timeout_secs: u64,
-    input_tokens: i32,
-    output_tokens_tracker: Option<std::sync::Arc<std::sync::atomic::AtomicU64>>,
-) -> Result<BoxStream<'static, Result<String, ApiError>>, ApiError> {
-    let message_id = generate_anthropic_message_id();
-    let model = model.to_string();
+        // Use a pattern that matches EVENT_PATTERNS
+        let chunk =
+            b":event-type: content\n:content-type: application/json\n{\"content\": \"Hello\"}\n\n";
+        let events = parser.feed(chunk).unwrap();
 
-    // Parse Kiro stream
-    let kiro_stream = parse_kiro_stream(response, first_token_timeout_secs).await?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["content"], "Hello");
+    }
 
-    // Use state to track blocks
-    use std::sync::Arc;
-    use std::sync::Mutex;
+    #[test]
+    fn test_parse_kiro_event_content() {
+        let json = serde_json::json!({
+            "contentBlockDelta": {
+                "delta": {
+                    "text": "Hello, world!"
+                }
+            }
+        });
 
-    #[derive(Default)]
-    struct StreamState {
-        text_block_started: bool,
-        text_block_index: i32,
-        thinking_block_started: bool,
-        thinking_block_index: i32,
-        current_block_index: i32,
-        tool_calls: Vec<ToolUse>,
-        usage: Option<Usage>,
-        accumulated_text: String, // Accumulate all text for accurate token counting
+        let event = parse_kiro_event(&json).unwrap();
+        assert_eq!(event.event_type, "content");
+        assert_eq!(event.content, Some("Hello, world!".to_string()));
     }
 
-    let state = Arc::new(Mutex::new(StreamState::default()));
+    #[test]
+    fn test_parse_kiro_event_tool_use() {
+        let json = serde_json::json!({
+            "contentBlockDelta": {
+                "delta": {
+                    "toolUse": {
+                        "toolUseId": "call_123",
+                        "name": "get_weather",
+                        "input": {"location": "SF"}
+                    }
+                }
+            }
+        });
 
-    // Send message_start event first
-    let message_start = serde_json::json!({
-        "type": "message_start",
-        "message": {
-            "id": message_id,
-            "type": "message",
-            "role": "assistant",
-            "content": [],
-            "model": model,
-            "stop_reason": null,
-            "stop_sequence": null,
-            "usage": {
-                "input_tokens": input_tokens,
-                "output_tokens": 0
+        let event = parse_kiro_event(&json).unwrap();
+        assert_eq!(event.event_type, "tool_use");
+        assert!(event.tool_use.is_some());
+        let tool_use = event.tool_use.unwrap();
+        assert_eq!(tool_use.tool_use_id, "call_123");
+        assert_eq!(tool_use.name, "get_weather");
+    }
+
+    #[test]
+    fn test_parse_kiro_event_usage() {
+        let json = serde_json::json!({
+            "metadata": {
+                "usage": {
+                    "inputTokens": 100,
+                    "outputTokens": 50
+                }
             }
-        }
-    });
+        });
 
-    let start_event = format_anthropic_sse_event("message_start", &message_start);
+        let event = parse_kiro_event(&json).unwrap();
+        assert_eq!(event.event_type, "usage");
+        assert!(event.usage.is_some());
+        let usage = event.usage.unwrap();
+        assert_eq!(usage.input_tokens, 100);
+        assert_eq!(usage.output_tokens, 50);
+    }
 
-    let _model_clone = model.clone();
+    // ==================== Tool Call Accumulator Tests ====================
 
-    // Clone state for use in final stream
-    let state_for_final = state.clone();
+    #[test]
+    fn test_tool_call_accumulator_simple() {
+        let mut acc = ToolCallAccumulator::new();
 
-    // Clone tracker for final events before it gets moved
-    let tracker_for_final = output_tokens_tracker.clone();
+        // Tool start
+        let start = serde_json::json!({
+            "name": "get_weather",
+            "toolUseId": "call_123"
+        });
+        let result = acc.process_event(&start);
+        assert!(result.is_none()); // Not complete yet
 
-    // Convert Kiro events to Anthropic events
-    let anthropic_stream = kiro_stream.filter_map(move |event_result| {
-        let _model = _model_clone.clone();
-        let state = state.clone();
-        let tracker = output_tokens_tracker.clone();
+        // Tool input
+        let input = serde_json::json!({
+            "input": "{\"location\": \"SF\"}"
+        });
+        let result = acc.process_event(&input);
+        assert!(result.is_none()); // Not complete yet
 
-        async move {
-            match event_result {
-                Ok(event) => {
-                    let mut state = state.lock().unwrap();
+        // Tool stop
+        let stop = serde_json::json!({
+            "stop": true
+        });
+        let result = acc.process_event(&stop);
+        assert!(result.is_some());
 
-                    match event.event_type.as_str() {
-                        "content" => {
-                            if let Some(content) = event.content {
-                                // Accumulate text for accurate token counting
-                                state.accumulated_text.push_str(&content);
+        let tool = result.unwrap();
+        assert_eq!(tool.name, "get_weather");
+        assert_eq!(tool.tool_use_id, "call_123");
+    }
+
+    #[test]
+    fn test_tool_call_accumulator_with_input_in_start() {
+        let mut acc = ToolCallAccumulator::new();
 
-                                // Start text block if not started
-                                if !state.text_block_started {
-                                    state.text_block_index = state.current_block_index;
-                                    state.current_block_index += 1;
-                                    state.text_block_started = true;
+        // Tool start with input
+        let start = serde_json::json!({
+            "name": "bash",
+            "toolUseId": "call_456",
+            "input": "{\"command\": \"ls\"}"
+        });
+        let result = acc.process_event(&start);
+        assert!(result.is_none());
 
-                                    let block_start = serde_json::json!({
-                                        "type": "content_block_start",
-                                        "index": state.text_block_index,
-                                        "content_block": {
-                                            "type": "text",
-                                            "text": ""
-                                        }
-                                    });
+        // Tool stop
+        let stop = serde_json::json!({
+            "stop": true
+        });
+        let result = acc.process_event(&stop);
+        assert!(result.is_some());
 
-                                    let start_event = format_anthropic_sse_event(
-                                        "content_block_start",
-                                        &block_start,
-                                    );
+        let tool = result.unwrap();
+        assert_eq!(tool.name, "bash");
+        assert_eq!(tool.input["command"], "ls");
+    }
 
-                                    // Send both start and delta
-                                    let delta = serde_json::json!({
-                                        "type": "content_block_delta",
-                                        "index": state.text_block_index,
-                                        "delta": {
-                                            "type": "text_delta",
-                                            "text": content
-                                        }
-                                    });
+    #[test]
+    fn test_tool_call_accumulator_continuation_same_id() {
+        let mut acc = ToolCallAccumulator::new();
 
-                                    let delta_event =
-                                        format_anthropic_sse_event("content_block_delta", &delta);
+        // First chunk with name and toolUseId
+        let chunk1 = serde_json::json!({
+            "name": "bash",
+            "toolUseId": "call_789",
+            "input": "{\"comm"
+        });
+        acc.process_event(&chunk1);
 
-                                    return Some(Ok(format!("{}{}", start_event, delta_event)));
-                                } else {
-                                    // Send delta only
-                                    let delta = serde_json::json!({
-                                        "type": "content_block_delta",
-                                        "index": state.text_block_index,
-                                        "delta": {
-                                            "type": "text_delta",
-                                            "text": content
-                                        }
-                                    });
+        // Continuation with same toolUseId (Kiro sends name in every chunk)
+        let chunk2 = serde_json::json!({
+            "name": "bash",
+            "toolUseId": "call_789",
+            "input": "and\": \"ls -la\"}"
+        });
+        acc.process_event(&chunk2);
 
-                                    return Some(Ok(format_anthropic_sse_event(
-                                        "content_block_delta",
-                                        &delta,
-                                    )));
-                                }
-                            }
-                            None
-                        }
-                        "thinking" => {
-                            if let Some(thinking) = event.thinking_content {
-                                // Accumulate text for accurate token counting
-                                state.accumulated_text.push_str(&thinking);
+        // Stop
+        let stop = serde_json::json!({
+            "stop": true
+        });
+        let result = acc.process_event(&stop);
+        assert!(result.is_some());
 
-                                // Start thinking block if not started
-                                if !state.thinking_block_started {
-                                    state.thinking_block_index = state.current_block_index;
-                                    state.current_block_index += 1;
-                                    state.thinking_block_started = true;
+        let tool = result.unwrap();
+        assert_eq!(tool.name, "bash");
+        assert_eq!(tool.input["command"], "ls -la");
+    }
 
-                                    let block_start = serde_json::json!({
-                                        "type": "content_block_start",
-                                        "index": state.thinking_block_index,
-                                        "content_block": {
-                                            "type": "thinking",
-                                            "thinking": ""
-                                        }
-                                    });
+    #[test]
+    fn test_tool_call_accumulator_finalize() {
+        let mut acc = ToolCallAccumulator::new();
 
-                                    let start_event = format_anthropic_sse_event(
-                                        "content_block_start",
-                                        &block_start,
-                                    );
+        // Start a tool but don't stop it
+        let start = serde_json::json!({
+            "name": "test_tool",
+            "toolUseId": "call_999",
+            "input": "{\"key\": \"value\"}"
+        });
+        acc.process_event(&start);
 
-                                    // Send both start and delta
-                                    let delta = serde_json::json!({
-                                        "type": "content_block_delta",
-                                        "index": state.thinking_block_index,
-                                        "delta": {
-                                            "type": "thinking_delta",
-                                            "thinking": thinking
-                                        }
-                                    });
+        // Finalize should complete the tool
+        let result = acc.finalize();
+        assert!(result.is_some());
 
-                                    let delta_event =
-                                        format_anthropic_sse_event("content_block_delta", &delta);
+        let tool = result.unwrap();
+        assert_eq!(tool.name, "test_tool");
+    }
 
-                                    return Some(Ok(format!("{}{}", start_event, delta_event)));
-                                } else {
-                                    // Send delta only
-                                    let delta = serde_json::json!({
-                                        "type": "content_block_delta",
-                                        "index": state.thinking_block_index,
-                                        "delta": {
-                                            "type": "thinking_delta",
-                                            "thinking": thinking
-                                        }
-                                    });
+    // ==================== Deduplicate Tool Calls Tests ====================
 
-                                    return Some(Ok(format_anthropic_sse_event(
-                                        "content_block_delta",
-                                        &delta,
-                                    )));
-                                }
-                            }
-                            None
-                        }
-                        "tool_use" => {
-                            if let Some(tool_use) = event.tool_use {
-                                tracing::info!(
-                                    "Received tool_use event: name={}, id={}, total_collected={}",
-                                    tool_use.name,
-                                    tool_use.tool_use_id,
-                                    state.tool_calls.len() + 1
-                                );
-                                state.tool_calls.push(tool_use);
-                            }
-                            None
-                        }
-                        "usage" => {
-                            if let Some(u) = event.usage {
-                                state.usage = Some(u.clone());
-                                if let Some(ref t) = tracker {
-                                    t.store(
-                                        u.output_tokens as u64,
-                                        std::sync::atomic::Ordering::Relaxed,
-                                    );
-                                }
-                            }
-                            None
-                        }
-                        _ => None,
-                    }
-                }
-                Err(e) => Some(Err(e)),
-            }
-        }
-    });
+    #[test]
+    fn test_deduplicate_tool_calls_empty() {
+        let result = deduplicate_tool_calls(vec![]);
+        assert!(result.is_empty());
+    }
 
-    // Add final events
-    let final_events_stream = futures::stream::unfold(Some(state_for_final), move |state_opt| {
-        let tracker = tracker_for_final.clone();
-        async move {
-            let state_arc = state_opt?;
-            let state = state_arc.lock().unwrap();
-        let mut final_events = Vec::new();
+    #[test]
+    fn test_deduplicate_tool_calls_by_id() {
+        let tools = vec![
+            ToolUse {
+                tool_use_id: "call_1".to_string(),
+                name: "test".to_string(),
+                input: serde_json::json!({}),
+            },
+            ToolUse {
+                tool_use_id: "call_1".to_string(),
+                name: "test".to_string(),
+                input: serde_json::json!({"key": "value"}),
+            },
+        ];
 
-        // Close thinking block if open
-        if state.thinking_block_started {
-            let block_stop = serde_json::json!({
-                "type": "content_block_stop",
-                "index": state.thinking_block_index
-            });
-            final_events.push(Ok(format_anthropic_sse_event("content_block_stop", &block_stop)));
-        }
+        let result = deduplicate_tool_calls(tools);
+        assert_eq!(result.len(), 1);
+        // Should keep the one with more arguments
+        assert_eq!(result[0].input["key"], "value");
+    }
 
-        // Close text block if open
-        if state.text_block_started {
-            let block_stop = serde_json::json!({
-                "type": "content_block_stop",
-                "index": state.text_block_index
-            });
-            final_events.push(Ok(format_anthropic_sse_event("content_block_stop", &block_stop)));
-        }
+    #[test]
+    fn test_deduplicate_tool_calls_by_name_args() {
+        let tools = vec![
+            ToolUse {
+                tool_use_id: "call_1".to_string(),
+                name: "test".to_string(),
+                input: serde_json::json!({"key": "value"}),
+            },
+            ToolUse {
+                tool_use_id: "call_2".to_string(),
+                name: "test".to_string(),
+                input: serde_json::json!({"key": "value"}),
+            },
+        ];
+
+        let result = deduplicate_tool_calls(tools);
+        assert_eq!(result.len(), 1);
+    }
 
-        // Deduplicate tool calls before sending
-        let deduped_tool_calls = deduplicate_tool_calls(state.tool_calls.clone());
+    #[test]
+    fn test_deduplicate_tool_calls_different_tools() {
+        let tools = vec![
+            ToolUse {
+                tool_use_id: "call_1".to_string(),
+                name: "tool_a".to_string(),
+                input: serde_json::json!({"key": "value1"}),
+            },
+            ToolUse {
+                tool_use_id: "call_2".to_string(),
+                name: "tool_b".to_string(),
+                input: serde_json::json!({"key": "value2"}),
+            },
+        ];
 
-        // Log tool calls for debugging
-        if !deduped_tool_calls.is_empty() {
-            tracing::info!(
-                "Emitting {} tool calls (before dedup: {}): {:?}",
-                deduped_tool_calls.len(),
-                state.tool_calls.len(),
-                deduped_tool_calls.iter().map(|t| format!("{}:{}", t.name, &t.tool_use_id)).collect::<Vec<_>>()
-            );
-        }
+        let result = deduplicate_tool_calls(tools);
+        assert_eq!(result.len(), 2);
+    }
 
-        // Send tool use blocks if present
-        let mut tool_block_index = state.current_block_index;
-        for tool_use in &deduped_tool_calls {
-            let tool_index = tool_block_index;
-            tool_block_index += 1;  // Increment for each tool
+    // ==================== SSE Parser Additional Tests ====================
 
-            let block_start = serde_json::json!({
-                "type": "content_block_start",
-                "index": tool_index,
-                "content_block": {
-                    "type": "tool_use",
-                    "id": tool_use.tool_use_id,
-                    "name": tool_use.name,
-                    "input": {}
-                }
-            });
-            final_events.push(Ok(format_anthropic_sse_event("content_block_start", &block_start)));
+    #[test]
+    fn test_sse_parser_multiple_events() {
+        let mut parser = SseParser::new();
 
-            let delta = serde_json::json!({
-                "type": "content_block_delta",
-                "index": tool_index,
-                "delta": {
-                    "type": "input_json_delta",
-                    "partial_json": serde_json::to_string(&tool_use.input).unwrap_or_else(|_| "{}".to_string())
-                }
-            });
-            final_events.push(Ok(format_anthropic_sse_event("content_block_delta", &delta)));
+        let chunk = b"{\"content\": \"Hello\"}{\"content\": \"World\"}";
+        let events = parser.feed(chunk).unwrap();
 
-            let block_stop = serde_json::json!({
-                "type": "content_block_stop",
-                "index": tool_index
-            });
-            final_events.push(Ok(format_anthropic_sse_event("content_block_stop", &block_stop)));
-        }
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["content"], "Hello");
+        assert_eq!(events[1]["content"], "World");
+    }
 
-        // Determine stop_reason
-        let stop_reason = if !deduped_tool_calls.is_empty() {
-            "tool_use"
-        } else {
-            "end_turn"
-        };
+    #[test]
+    fn test_sse_parser_partial_json() {
+        let mut parser = SseParser::new();
 
-        // Calculate usage
-        let output_tokens = if let Some(ref u) = state.usage {
-            u.output_tokens
-        } else {
-            // Fallback: Count output tokens using tiktoken (same method as input tokens)
-            let tokens = crate::tokenizer::count_tokens(&state.accumulated_text, false);
-            if tokens > 0 {
-                tracing::info!(
-                    "No usage data from Kiro API - using tiktoken count: output_tokens={} (counted from {} chars)",
-                    tokens,
-                    state.accumulated_text.len()
-                );
+        // First chunk - incomplete JSON
+        let chunk1 = b"{\"content\": \"Hel";
+        let events1 = parser.feed(chunk1).unwrap();
+        assert_eq!(events1.len(), 0);
 
-                // Update metrics tracker with counted tokens
-                if let Some(ref t) = tracker {
-                    t.store(tokens as u64, std::sync::atomic::Ordering::Relaxed);
-                }
-            }
-            tokens
-        };
+        // Second chunk - completes the JSON
+        let chunk2 = b"lo\"}";
+        let events2 = parser.feed(chunk2).unwrap();
+        assert_eq!(events2.len(), 1);
+        assert_eq!(events2[0]["content"], "Hello");
+    }
 
-        // Send message_delta with stop_reason
-        let message_delta = serde_json::json!({
-            "type": "message_delta",
-            "delta": {
-                "stop_reason": stop_reason,
-                "stop_sequence": null
-            },
-            "usage": {
-                "output_tokens": output_tokens
-            }
-        });
-        final_events.push(Ok(format_anthropic_sse_event("message_delta", &message_delta)));
+    #[test]
+    fn test_sse_parser_nested_json() {
+        let mut parser = SseParser::new();
 
-        // Send message_stop
-        let message_stop = serde_json::json!({
-            "type": "message_stop"
-        });
-        final_events.push(Ok(format_anthropic_sse_event("message_stop", &message_stop)));
+        let chunk = b"{\"content\": \"{\\\"nested\\\": true}\"}";
+        let events = parser.feed(chunk).unwrap();
 
-        Some((futures::stream::iter(final_events), None))
-    }})
-    .flatten();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["content"], "{\"nested\": true}");
+    }
 
-    let final_stream = anthropic_stream.chain(final_events_stream);
+    #[test]
+    fn test_sse_parser_usage_event() {
+        let mut parser = SseParser::new();
 
-    // Prepend message_start event
-    let complete_stream = futures::stream::once(async move { Ok(start_event) }).chain(final_stream);
+        let chunk = b"{\"usage\": {\"inputTokens\": 100, \"outputTokens\": 50}}";
+        let events = parser.feed(chunk).unwrap();
 
-    Ok(complete_stream.boxed())
-}
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["usage"]["inputTokens"], 100);
+    }
 
-// ==================================================================================================
-// Non-Streaming Response Collection (like Python's collect_stream_response)
-// ==================================================================================================
+    #[test]
+    fn test_sse_parser_context_usage() {
+        let mut parser = SseParser::new();
 
-/// Collects a complete OpenAI response from a Kiro stream.
-///
-/// This is used for non-streaming mode - it processes the stream internally
-/// and returns a single complete response. This matches Python's collect_stream_response().
-///
-/// Kiro API always returns AWS Event Stream format, even for non-streaming requests.
-/// This function parses that stream and aggregates all content into a single response.
-pub async fn collect_openai_response(
-    response: reqwest::Response,
-    model: &str,
-    first_token_timeout_secs: u64,
-    input_tokens: i32,
-) -> Result<Value, ApiError> {
-    use futures::StreamExt;
+        let chunk = b"{\"contextUsagePercentage\": 45.5}";
+        let events = parser.feed(chunk).unwrap();
 
-    let completion_id = generate_completion_id();
-    let created_time = chrono::Utc::now().timestamp();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["contextUsagePercentage"], 45.5);
+    }
 
-    // Parse Kiro stream
-    let mut kiro_stream = parse_kiro_stream(response, first_token_timeout_secs).await?;
+    // ==================== Parse Kiro Event Additional Tests ====================
 
-    // Collect all content
-    let mut full_content = String::new();
-    let mut full_reasoning_content = String::new();
-    let mut tool_calls: Vec<ToolUse> = Vec::new();
-    let mut usage: Option<Usage> = None;
+    #[test]
+    fn test_parse_kiro_event_direct_content() {
+        let json = serde_json::json!({
+            "content": "Direct content"
+        });
 
-    while let Some(event_result) = kiro_stream.next().await {
-        match event_result {
-            Ok(event) => match event.event_type.as_str() {
-                "content" => {
-                    if let Some(content) = event.content {
-                        full_content.push_str(&content);
-                    }
-                }
-                "thinking" => {
-                    if let Some(thinking) = event.thinking_content {
-                        full_reasoning_content.push_str(&thinking);
-                    }
-                }
-                "tool_use" => {
-                    if let Some(tool_use) = event.tool_use {
-                        tool_calls.push(tool_use);
-                    }
-                }
-                "usage" => {
-                    if let Some(u) = event.usage {
-                        usage = Some(u);
-                    }
-                }
-                _ => {}
-            },
-            Err(e) => {
-                tracing::warn!("Error in stream: {:?}", e);
-            }
-        }
+        let event = parse_kiro_event(&json).unwrap();
+        assert_eq!(event.event_type, "content");
+        assert_eq!(event.content, Some("Direct content".to_string()));
     }
 
-    // Deduplicate tool calls
-    let tool_calls = deduplicate_tool_calls(tool_calls);
+    #[test]
+    fn test_parse_kiro_event_direct_usage_number() {
+        let json = serde_json::json!({
+            "usage": 1.5
+        });
 
-    // Build message
-    let mut message = serde_json::json!({
-        "role": "assistant",
-        "content": full_content
-    });
+        let event = parse_kiro_event(&json).unwrap();
+        assert_eq!(event.event_type, "usage");
+        assert!(event.usage.is_some());
+    }
 
-    if !full_reasoning_content.is_empty() {
-        message["reasoning_content"] = serde_json::json!(full_reasoning_content);
+    #[test]
+    fn test_parse_kiro_event_direct_usage_object() {
+        let json = serde_json::json!({
+            "usage": {
+                "inputTokens": 200,
+                "outputTokens": 100
+            }
+        });
+
+        let event = parse_kiro_event(&json).unwrap();
+        assert_eq!(event.event_type, "usage");
+        let usage = event.usage.unwrap();
+        assert_eq!(usage.input_tokens, 200);
+        assert_eq!(usage.output_tokens, 100);
     }
 
-    if !tool_calls.is_empty() {
-        let tool_calls_json: Vec<Value> = tool_calls
-            .iter()
-            .map(|tc| {
-                serde_json::json!({
-                    "id": tc.tool_use_id,
-                    "type": "function",
-                    "function": {
-                        "name": tc.name,
-                        "arguments": serde_json::to_string(&tc.input).unwrap_or_else(|_| "{}".to_string())
-                    }
-                })
-            })
-            .collect();
-        message["tool_calls"] = serde_json::json!(tool_calls_json);
+    #[test]
+    fn test_parse_kiro_event_context_usage() {
+        let json = serde_json::json!({
+            "contextUsagePercentage": 75.0
+        });
+
+        let event = parse_kiro_event(&json).unwrap();
+        assert_eq!(event.event_type, "context_usage");
+        assert_eq!(event.context_usage_percentage, Some(75.0));
     }
 
-    // Determine finish_reason
-    let finish_reason = if !tool_calls.is_empty() {
-        "tool_calls"
-    } else {
-        "stop"
-    };
+    #[test]
+    fn test_parse_kiro_event_followup_prompt_ignored() {
+        let json = serde_json::json!({
+            "followupPrompt": "Some prompt"
+        });
 
-    // Build usage - use our calculated input_tokens, output from Kiro
-    let output_tokens = if let Some(u) = usage {
-        u.output_tokens
-    } else {
-        // Fallback: Count output tokens using tiktoken (same method as input tokens)
-        let mut accumulated_text = full_content.clone();
-        accumulated_text.push_str(&full_reasoning_content);
-        let tokens = crate::tokenizer::count_tokens(&accumulated_text, false);
-        if tokens > 0 {
-            tracing::info!(
-                "No usage data from Kiro API - using tiktoken count: output_tokens={} (counted from {} chars)",
-                tokens,
-                accumulated_text.len()
-            );
-        }
-        tokens
-    };
+        let event = parse_kiro_event(&json);
+        assert!(event.is_none());
+    }
 
-    let usage_json = serde_json::json!({
-        "prompt_tokens": input_tokens,
-        "completion_tokens": output_tokens,
-        "total_tokens": input_tokens + output_tokens
-    });
+    #[test]
+    fn test_parse_kiro_event_message_stop_ignored() {
+        let json = serde_json::json!({
+            "messageStop": {}
+        });
 
-    // Build complete response
-    let response = serde_json::json!({
-        "id": completion_id,
-        "object": "chat.completion",
-        "created": created_time,
-        "model": model,
-        "choices": [{
-            "index": 0,
-            "message": message,
-            "finish_reason": finish_reason
-        }],
-        "usage": usage_json
-    });
+        let event = parse_kiro_event(&json);
+        assert!(event.is_none());
+    }
 
-    Ok(response)
-}
+    // ==================== KiroEvent Tests ====================
 
-/// Collects a complete Anthropic response from a Kiro stream.
-///
-/// This is used for non-streaming mode - it processes the stream internally
-/// and returns a single complete response.
-pub async fn collect_anthropic_response(
-    response: reqwest::Response,
-    model: &str,
-    first_token_timeout_secs: u64,
-    input_tokens: i32,
-) -> Result<Value, ApiError> {
-    use futures::StreamExt;
+    #[test]
+    fn test_kiro_event_default() {
+        let event = KiroEvent {
+            event_type: "content".to_string(),
+            content: Some("test".to_string()),
+            thinking_content: None,
+            tool_use: None,
+            usage: None,
+            context_usage_percentage: None,
+            is_first_thinking_chunk: false,
+            is_last_thinking_chunk: false,
+        };
 
-    let message_id = generate_anthropic_message_id();
+        assert_eq!(event.event_type, "content");
+        assert!(!event.is_first_thinking_chunk);
+        assert!(!event.is_last_thinking_chunk);
+    }
 
-    // Parse Kiro stream
-    let mut kiro_stream = parse_kiro_stream(response, first_token_timeout_secs).await?;
+    #[test]
+    fn test_tool_use_serialization() {
+        let tool_use = ToolUse {
+            tool_use_id: "call_123".to_string(),
+            name: "test_tool".to_string(),
+            input: serde_json::json!({"key": "value"}),
+        };
 
-    // Collect all content
-    let mut full_content = String::new();
-    let mut full_thinking_content = String::new();
-    let mut tool_calls: Vec<ToolUse> = Vec::new();
-    let mut usage: Option<Usage> = None;
+        let json = serde_json::to_string(&tool_use).unwrap();
+        assert!(json.contains("call_123"));
+        assert!(json.contains("test_tool"));
+    }
 
-    while let Some(event_result) = kiro_stream.next().await {
-        match event_result {
-            Ok(event) => match event.event_type.as_str() {
-                "content" => {
-                    if let Some(content) = event.content {
-                        full_content.push_str(&content);
-                    }
-                }
-                "thinking" => {
-                    if let Some(thinking) = event.thinking_content {
-                        full_thinking_content.push_str(&thinking);
-                    }
-                }
-                "tool_use" => {
-                    if let Some(tool_use) = event.tool_use {
-                        tool_calls.push(tool_use);
-                    }
-                }
-                "usage" => {
-                    if let Some(u) = event.usage {
-                        usage = Some(u);
-                    }
-                }
-                _ => {}
-            },
-            Err(e) => {
-                tracing::warn!("Error in stream: {:?}", e);
-            }
-        }
+    #[test]
+    fn test_usage_serialization() {
+        let usage = Usage {
+            input_tokens: 100,
+            output_tokens: 50,
+            credits: None,
+        };
+
+        let json = serde_json::to_string(&usage).unwrap();
+        let parsed: Usage = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.input_tokens, 100);
+        assert_eq!(parsed.output_tokens, 50);
     }
 
-    // Deduplicate tool calls
-    let tool_calls = deduplicate_tool_calls(tool_calls);
+    // ==================== Finalize Double-Call Prevention Tests ====================
 
-    // Build content blocks
-    let mut content_blocks: Vec<Value> = Vec::new();
+    #[test]
+    fn test_tool_call_accumulator_finalize_prevents_double() {
+        let mut acc = ToolCallAccumulator::new();
 
-    // Add thinking block if present
-    if !full_thinking_content.is_empty() {
-        content_blocks.push(serde_json::json!({
-            "type": "thinking",
-            "thinking": full_thinking_content
-        }));
-    }
+        // Start a tool but don't stop it
+        let start = serde_json::json!({
+            "name": "write",
+            "toolUseId": "call_double_test",
+            "input": "{\"filePath\": \"/test/path.txt\"}"
+        });
+        acc.process_event(&start);
 
-    // Add text block if present
-    if !full_content.is_empty() {
-        content_blocks.push(serde_json::json!({
-            "type": "text",
-            "text": full_content
-        }));
-    }
+        // First finalize should return the tool
+        let result1 = acc.finalize();
+        assert!(result1.is_some());
+        let tool = result1.unwrap();
+        assert_eq!(tool.name, "write");
+        assert_eq!(tool.tool_use_id, "call_double_test");
 
-    // Add tool use blocks
-    for tool_use in &tool_calls {
-        content_blocks.push(serde_json::json!({
-            "type": "tool_use",
-            "id": tool_use.tool_use_id,
-            "name": tool_use.name,
-            "input": tool_use.input
-        }));
+        // Second finalize should return None (prevents double-finalization)
+        let result2 = acc.finalize();
+        assert!(result2.is_none());
+
+        // Third finalize should also return None
+        let result3 = acc.finalize();
+        assert!(result3.is_none());
     }
 
-    // Determine stop_reason
-    let stop_reason = if !tool_calls.is_empty() {
-        "tool_use"
-    } else {
-        "end_turn"
-    };
+    #[test]
+    fn test_tool_call_accumulator_truncated_json() {
+        let mut acc = ToolCallAccumulator::new();
 
-    // Build usage - use passed input_tokens, get output_tokens from stream
-    let output_tokens = if let Some(u) = usage {
-        u.output_tokens
-    } else {
-        // Fallback: Count output tokens using tiktoken (same method as input tokens)
-        let mut accumulated_text = full_content.clone();
-        accumulated_text.push_str(&full_thinking_content);
-        let tokens = crate::tokenizer::count_tokens(&accumulated_text, false);
-        if tokens > 0 {
-            tracing::info!(
-                "No usage data from Kiro API - using tiktoken count: output_tokens={} (counted from {} chars)",
-                tokens,
-                accumulated_text.len()
-            );
-        }
-        tokens
-    };
+        // Simulate truncated JSON input (stream terminated mid-tool-call)
+        let start = serde_json::json!({
+            "name": "write",
+            "toolUseId": "call_truncated",
+            "input": "{\"filePath\": \"/Users/test/docs/api.yaml\""
+        });
+        // Note: The input JSON is missing the closing "}"
+        acc.process_event(&start);
 
-    // Build complete response
-    let response = serde_json::json!({
-        "id": message_id,
-        "type": "message",
-        "role": "assistant",
-        "content": content_blocks,
-        "model": model,
-        "stop_reason": stop_reason,
-        "stop_sequence": null,
-        "usage": {
-            "input_tokens": input_tokens,
-            "output_tokens": output_tokens
-        }
-    });
+        // Finalize should still return the tool with whatever data we have
+        let result = acc.finalize();
+        assert!(result.is_some());
 
-    Ok(response)
+        let tool = result.unwrap();
+        assert_eq!(tool.name, "write");
+        assert_eq!(tool.tool_use_id, "call_truncated");
+        // The input should be an empty object since the truncated JSON fails to parse
+        assert!(tool.input.is_object());
+    }
 }
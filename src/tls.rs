@@ -0,0 +1,366 @@
+// TLS termination: rustls server config with certificate hot reload and optional mTLS
+
+use anyhow::{Context, Result};
+use axum::extract::{ConnectInfo, Request};
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig, ServerConnection};
+use std::future::Future;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+
+/// How often certificate files are checked for changes
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Handshakes that take longer than this are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long in-flight connections may finish after shutdown is requested
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+
+/// Identity from a verified client certificate
+///
+/// Inserted into request extensions for mTLS connections; `auth_middleware`
+/// accepts it in place of an API key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientCertificate {
+    /// Subject common name (used for attribution)
+    pub common_name: String,
+}
+
+/// Serves the current certificate and reloads it when the PEM files change
+#[derive(Debug)]
+pub struct CertReloader {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+    modified: Mutex<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+impl CertReloader {
+    /// Load the certificate chain and private key
+    pub fn new(cert_path: &Path, key_path: &Path) -> Result<Self> {
+        let modified = (modified_at(cert_path), modified_at(key_path));
+        let key = load_certified_key(cert_path, key_path)?;
+        Ok(Self {
+            cert_path: cert_path.to_path_buf(),
+            key_path: key_path.to_path_buf(),
+            current: RwLock::new(Arc::new(key)),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    /// Reload the certificate if either file changed; returns whether it did
+    ///
+    /// A broken pair (e.g. the cert was replaced but not the key yet) keeps the
+    /// previous certificate and is retried on the next call.
+    pub fn reload_if_changed(&self) -> Result<bool> {
+        let modified = (modified_at(&self.cert_path), modified_at(&self.key_path));
+        {
+            let last = self
+                .modified
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Certificate reload lock poisoned"))?;
+            if *last == modified {
+                return Ok(false);
+            }
+        }
+
+        let key = load_certified_key(&self.cert_path, &self.key_path)?;
+        if let Ok(mut current) = self.current.write() {
+            *current = Arc::new(key);
+        }
+        if let Ok(mut last) = self.modified.lock() {
+            *last = modified;
+        }
+        Ok(true)
+    }
+}
+
+impl ResolvesServerCert for CertReloader {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.current.read().ok().map(|key| Arc::clone(&key))
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open certificate file: {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse certificates: {}", path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {}", path.display());
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open private key file: {}", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse private key: {}", path.display()))?
+        .with_context(|| format!("No private key found in {}", path.display()))
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey> {
+    let certs = load_certs(cert_path)?;
+    let key = load_private_key(key_path)?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)
+        .context("Unsupported private key type")?;
+
+    let certified = CertifiedKey::new(certs, signing_key);
+    certified
+        .keys_match()
+        .context("TLS certificate does not match the private key")?;
+    Ok(certified)
+}
+
+/// Build the rustls server config; a client CA bundle enables mTLS
+pub fn server_config(
+    certs: Arc<CertReloader>,
+    client_ca: Option<&Path>,
+    client_cert_required: bool,
+) -> Result<Arc<ServerConfig>> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .context("Failed to configure TLS protocol versions")?;
+
+    let builder = match client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(path)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("Invalid client CA in {}", path.display()))?;
+            }
+
+            let mut verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            if !client_cert_required {
+                verifier = verifier.allow_unauthenticated();
+            }
+            builder.with_client_cert_verifier(
+                verifier
+                    .build()
+                    .context("Failed to build client certificate verifier")?,
+            )
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder.with_cert_resolver(certs);
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(server_config))
+}
+
+/// Extract the identity from the connection's verified client certificate
+fn client_certificate(conn: &ServerConnection) -> Option<ClientCertificate> {
+    let cert = conn.peer_certificates()?.first()?;
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let common_name = parsed
+        .subject()
+        .iter_common_name()
+        .next()?
+        .as_str()
+        .ok()?
+        .to_string();
+    Some(ClientCertificate { common_name })
+}
+
+/// Serve the app over TLS until `shutdown` resolves
+///
+/// Requests carry `ConnectInfo<SocketAddr>` like `axum::serve` with connect
+/// info, plus a `ClientCertificate` when the client presented a verified cert.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    tls: Arc<ServerConfig>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let acceptor = TlsAcceptor::from(tls);
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let (stream, addr) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    tracing::warn!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let tls_stream =
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(tls_stream)) => tls_stream,
+                    Ok(Err(e)) => {
+                        tracing::debug!("TLS handshake with {} failed: {}", addr, e);
+                        return;
+                    }
+                    Err(_) => {
+                        tracing::debug!("TLS handshake with {} timed out", addr);
+                        return;
+                    }
+                };
+            let peer = client_certificate(tls_stream.get_ref().1);
+
+            let service = hyper::service::service_fn(move |mut request: Request<Incoming>| {
+                request
+                    .extensions_mut()
+                    .insert(ConnectInfo::<SocketAddr>(addr));
+                if let Some(ref peer) = peer {
+                    request.extensions_mut().insert(peer.clone());
+                }
+                app.clone().oneshot(request)
+            });
+
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let conn = builder.serve_connection(TokioIo::new(tls_stream), service);
+            if let Err(e) = watcher.watch(conn).await {
+                tracing::debug!("Connection from {} closed with error: {}", addr, e);
+            }
+        });
+    }
+
+    tokio::select! {
+        _ = graceful.shutdown() => {}
+        _ = tokio::time::sleep(SHUTDOWN_GRACE) => {
+            tracing::warn!("Timed out waiting for connections to close");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::Extension;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(FIXTURES).join(name)
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kiro-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn leaf(reloader: &CertReloader) -> Vec<u8> {
+        reloader.current.read().unwrap().cert[0].as_ref().to_vec()
+    }
+
+    #[test]
+    fn test_reload_on_change() {
+        let dir = temp_dir();
+        let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+        std::fs::copy(fixture("server.pem"), &cert).unwrap();
+        std::fs::copy(fixture("server_key.pem"), &key).unwrap();
+
+        let reloader = CertReloader::new(&cert, &key).unwrap();
+        let original = leaf(&reloader);
+        assert!(!reloader.reload_if_changed().unwrap());
+
+        // Only the cert replaced: mismatched pair keeps serving the old one
+        std::thread::sleep(Duration::from_millis(20));
+        std::fs::copy(fixture("server2.pem"), &cert).unwrap();
+        assert!(reloader.reload_if_changed().is_err());
+        assert_eq!(leaf(&reloader), original);
+
+        std::fs::copy(fixture("server2_key.pem"), &key).unwrap();
+        assert!(reloader.reload_if_changed().unwrap());
+        assert_ne!(leaf(&reloader), original);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rejects_missing_or_mismatched_files() {
+        assert!(CertReloader::new(&fixture("missing.pem"), &fixture("server_key.pem")).is_err());
+        assert!(CertReloader::new(&fixture("server.pem"), &fixture("client_key.pem")).is_err());
+    }
+
+    /// Start a TLS server on an ephemeral port that echoes the client identity
+    async fn start_server(client_cert_required: bool) -> SocketAddr {
+        let certs = Arc::new(
+            CertReloader::new(&fixture("server.pem"), &fixture("server_key.pem")).unwrap(),
+        );
+        let tls = server_config(certs, Some(&fixture("ca.pem")), client_cert_required).unwrap();
+
+        let app = Router::new().route(
+            "/whoami",
+            get(|cert: Option<Extension<ClientCertificate>>| async move {
+                cert.map(|Extension(c)| c.common_name)
+                    .unwrap_or_else(|| "anonymous".to_string())
+            }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, app, tls, std::future::pending()));
+        addr
+    }
+
+    fn client(with_identity: bool) -> reqwest::Client {
+        let ca = std::fs::read(fixture("ca.pem")).unwrap();
+        let mut builder = reqwest::Client::builder()
+            .use_rustls_tls()
+            .add_root_certificate(reqwest::Certificate::from_pem(&ca).unwrap());
+        if with_identity {
+            let mut pem = std::fs::read(fixture("client.pem")).unwrap();
+            pem.extend(std::fs::read(fixture("client_key.pem")).unwrap());
+            builder = builder.identity(reqwest::Identity::from_pem(&pem).unwrap());
+        }
+        builder.build().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_serve_with_optional_client_cert() {
+        let addr = start_server(false).await;
+        let url = format!("https://localhost:{}/whoami", addr.port());
+
+        let body = client(true).get(&url).send().await.unwrap().text().await;
+        assert_eq!(body.unwrap(), "ci-runner");
+
+        let body = client(false).get(&url).send().await.unwrap().text().await;
+        assert_eq!(body.unwrap(), "anonymous");
+    }
+
+    #[tokio::test]
+    async fn test_serve_with_required_client_cert() {
+        let addr = start_server(true).await;
+        let url = format!("https://localhost:{}/whoami", addr.port());
+
+        assert!(client(false).get(&url).send().await.is_err());
+        let body = client(true).get(&url).send().await.unwrap().text().await;
+        assert_eq!(body.unwrap(), "ci-runner");
+    }
+}
@@ -1,5 +1,7 @@
+use crate::converters::core::{parse_data_url, UnifiedMessage};
 use crate::models::anthropic::AnthropicTool;
 use crate::models::openai::{ChatMessage, Tool};
+use base64::Engine;
 use serde_json::Value;
 use std::sync::OnceLock;
 use tiktoken_rs::CoreBPE;
@@ -20,8 +22,8 @@ const TOKENS_PER_TOOL_CALL: i32 = 4;
 /// Final service tokens added to request
 const FINAL_SERVICE_TOKENS: i32 = 3;
 
-/// Approximate tokens per image
-const TOKENS_PER_IMAGE: i32 = 100;
+/// Pixels per image token
+const PIXELS_PER_IMAGE_TOKEN: f64 = 750.0;
 
 /// Global tiktoken encoding (lazily initialized)
 static ENCODING: OnceLock<CoreBPE> = OnceLock::new();
@@ -58,11 +60,50 @@ pub fn count_tokens(text: &str, apply_claude_correction: bool) -> i32 {
     }
 }
 
+/// Counts the tokens of an image from its dimensions (~width * height / 750).
+///
+/// Images beyond `max_edge` are counted at the size they are scaled down to.
+pub fn count_image_tokens(width: u32, height: u32, max_edge: u32) -> i32 {
+    let longest = width.max(height);
+    if longest == 0 {
+        return 0;
+    }
+    let scale = (max_edge as f64 / longest as f64).min(1.0);
+    let pixels = (width as f64 * scale).round() * (height as f64 * scale).round();
+    ((pixels / PIXELS_PER_IMAGE_TOKEN).ceil() as i32).max(1)
+}
+
+/// Counts the tokens of the images attached to prepared messages.
+///
+/// Runs on the images as sent upstream (inlined and normalized), so remote
+/// images are measured too; `max_edge` is the configured
+/// `IMAGE_MAX_DIMENSION`. Unreadable images count nothing.
+pub fn count_images_tokens(messages: &[UnifiedMessage], max_edge: u32) -> i32 {
+    messages
+        .iter()
+        .filter_map(|message| message.images.as_ref())
+        .flatten()
+        .filter_map(|image| {
+            let data = parse_data_url(&image.data).map_or_else(|| image.data.clone(), |(_, d)| d);
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(data.trim())
+                .ok()?;
+            image::ImageReader::new(std::io::Cursor::new(bytes))
+                .with_guessed_format()
+                .ok()?
+                .into_dimensions()
+                .ok()
+        })
+        .map(|(width, height)| count_image_tokens(width, height, max_edge))
+        .sum()
+}
+
 /// Counts tokens in a list of OpenAI chat messages.
 ///
 /// Accounts for message structure:
 /// - role: tokens for role string
-/// - content: text tokens or multimodal content
+/// - content: text tokens; images and documents are counted once the
+///   request has been prepared (`count_images_tokens`, `render_documents`)
 /// - tool_calls: function name and arguments
 /// - tool_call_id: for tool response messages
 /// - Service tokens per message: ~4 tokens
@@ -95,17 +136,9 @@ pub fn count_message_tokens(messages: &[ChatMessage], apply_claude_correction: b
                 }
                 Value::Array(arr) => {
                     for item in arr {
-                        if let Some(obj) = item.as_object() {
-                            match obj.get("type").and_then(|t| t.as_str()) {
-                                Some("text") => {
-                                    if let Some(text) = obj.get("text").and_then(|t| t.as_str()) {
-                                        total_tokens += count_tokens(text, false);
-                                    }
-                                }
-                                Some("image_url") | Some("image") => {
-                                    total_tokens += TOKENS_PER_IMAGE;
-                                }
-                                _ => {}
+                        if item.get("type").and_then(|t| t.as_str()) == Some("text") {
+                            if let Some(text) = item.get("text").and_then(|t| t.as_str()) {
+                                total_tokens += count_tokens(text, false);
                             }
                         }
                     }
@@ -198,7 +231,8 @@ pub fn count_tools_tokens(tools: Option<&Vec<Tool>>, apply_claude_correction: bo
 ///
 /// Accounts for message structure:
 /// - role: ~1 token
-/// - content: text tokens
+/// - content: text tokens; images and documents are counted once the
+///   request has been prepared (`count_images_tokens`, `render_documents`)
 /// - Service tokens between messages: ~3-4 tokens
 ///
 /// # Arguments
@@ -259,10 +293,6 @@ pub fn count_anthropic_message_tokens(
                                     total_tokens += count_tokens(text, false);
                                 }
                             }
-                            Some("image") | Some("image_url") => {
-                                // Images take ~85-170 tokens depending on size
-                                total_tokens += 100;
-                            }
                             Some("tool_use") => {
                                 total_tokens += 4; // Service tokens
                                 if let Some(name) = obj.get("name").and_then(|n| n.as_str()) {
@@ -338,6 +368,7 @@ pub fn count_anthropic_message_tokens(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::converters::core::{MessageContent, UnifiedImage};
     use crate::models::anthropic::AnthropicMessage;
     use crate::models::openai::{FunctionCall, ToolCall, ToolFunction};
     use serde_json::json;
@@ -429,9 +460,69 @@ mod tests {
             tool_calls: None,
             tool_call_id: None,
         }];
-        let tokens = count_message_tokens(&messages, false);
-        // Should include at least TOKENS_PER_IMAGE (100)
-        assert!(tokens >= 100);
+        let text_only = vec![ChatMessage {
+            content: Some(json!([{"type": "text", "text": "What's in this image?"}])),
+            ..messages[0].clone()
+        }];
+        // Images are counted from the prepared request
+        assert_eq!(
+            count_message_tokens(&messages, false),
+            count_message_tokens(&text_only, false)
+        );
+    }
+
+    fn png_base64(width: u32, height: u32) -> String {
+        let mut bytes = Vec::new();
+        image::RgbImage::new(width, height)
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    }
+
+    #[test]
+    fn test_count_image_tokens() {
+        assert_eq!(count_image_tokens(750, 1, 1568), 1);
+        assert_eq!(count_image_tokens(1000, 1000, 1568), 1334);
+        // Scaled down to the maximum edge first
+        assert_eq!(
+            count_image_tokens(3136, 3136, 1568),
+            count_image_tokens(1568, 1568, 1568)
+        );
+        assert_eq!(
+            count_image_tokens(1000, 1000, 500),
+            count_image_tokens(500, 500, 500)
+        );
+        assert_eq!(count_image_tokens(0, 0, 1568), 0);
+    }
+
+    #[test]
+    fn test_images_tokens_use_prepared_images() {
+        let message = |images: Vec<UnifiedImage>| UnifiedMessage {
+            role: "user".to_string(),
+            content: MessageContent::Text(String::new()),
+            tool_calls: None,
+            tool_results: None,
+            images: Some(images),
+        };
+        let png = |width, height| UnifiedImage {
+            media_type: "image/png".to_string(),
+            data: png_base64(width, height),
+        };
+        let messages = vec![
+            message(vec![png(100, 100)]),
+            message(vec![
+                png(1000, 1000),
+                UnifiedImage {
+                    media_type: "image/png".to_string(),
+                    data: format!("data:image/png;base64,{}", png_base64(100, 100)),
+                },
+            ]),
+        ];
+        assert_eq!(count_images_tokens(&messages, 1568), 14 + 1334 + 14);
+        assert_eq!(count_images_tokens(&messages[1..], 500), 334 + 14);
     }
 
     #[test]
@@ -591,7 +682,14 @@ mod tests {
                 {"type": "image", "source": {"type": "base64", "data": "..."}}
             ]),
         }];
-        let tokens = count_anthropic_message_tokens(&messages, None, None);
-        assert!(tokens >= 100); // At least image tokens
+        let text_only = vec![AnthropicMessage {
+            role: "user".to_string(),
+            content: json!([{"type": "text", "text": "What's in this image?"}]),
+        }];
+        // Images are counted from the prepared request
+        assert_eq!(
+            count_anthropic_message_tokens(&messages, None, None),
+            count_anthropic_message_tokens(&text_only, None, None)
+        );
     }
 }
@@ -0,0 +1,466 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use super::{UsageLedger, UsageRecord};
+
+/// Daily and monthly limits for a key (tokens = input + output)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_credits: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_credits: Option<f64>,
+}
+
+impl Budget {
+    fn is_empty(&self) -> bool {
+        self.daily_tokens.is_none()
+            && self.monthly_tokens.is_none()
+            && self.daily_credits.is_none()
+            && self.monthly_credits.is_none()
+    }
+}
+
+/// A budget limit that has been reached
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetExceeded {
+    pub message: String,
+    /// When the exhausted budget period resets
+    pub resets_at: DateTime<Utc>,
+}
+
+pub(super) fn load_budgets(conn: &Connection) -> Result<HashMap<String, Budget>> {
+    let mut stmt = conn.prepare(
+        "SELECT key, daily_tokens, monthly_tokens, daily_credits, monthly_credits FROM usage_budgets",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            Budget {
+                daily_tokens: row.get::<_, Option<i64>>(1)?.map(|v| v as u64),
+                monthly_tokens: row.get::<_, Option<i64>>(2)?.map(|v| v as u64),
+                daily_credits: row.get(3)?,
+                monthly_credits: row.get(4)?,
+            },
+        ))
+    })?;
+
+    let mut budgets = HashMap::new();
+    for row in rows {
+        let (key, budget) = row?;
+        budgets.insert(key, budget);
+    }
+    Ok(budgets)
+}
+
+/// Tokens (input + output) and credits used by one key on one day
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(super) struct DayUsage {
+    tokens: u64,
+    credits: f64,
+    records: u64,
+}
+
+type KeyDays = HashMap<String, BTreeMap<NaiveDate, DayUsage>>;
+
+/// In-memory usage totals that budget checks are served from
+///
+/// `flushed` mirrors the database (rebuilt on each reconcile); `pending` holds
+/// records still queued for the writer thread.
+#[derive(Debug, Default)]
+pub(super) struct UsageCounters {
+    flushed: KeyDays,
+    pending: KeyDays,
+}
+
+impl UsageCounters {
+    pub(super) fn add_pending(&mut self, record: &UsageRecord, day: NaiveDate) {
+        let entry = self
+            .pending
+            .entry(record.key.clone())
+            .or_default()
+            .entry(day)
+            .or_default();
+        entry.tokens += record.input_tokens + record.output_tokens;
+        entry.credits += record.credits;
+        entry.records += 1;
+    }
+
+    /// Move a record the writer has handled from `pending` to `flushed`
+    pub(super) fn mark_flushed(&mut self, record: &UsageRecord, day: NaiveDate) {
+        let tokens = record.input_tokens + record.output_tokens;
+        if let Some(days) = self.pending.get_mut(&record.key) {
+            if let Some(entry) = days.get_mut(&day) {
+                entry.records = entry.records.saturating_sub(1);
+                if entry.records == 0 {
+                    days.remove(&day);
+                } else {
+                    entry.tokens = entry.tokens.saturating_sub(tokens);
+                    entry.credits -= record.credits;
+                }
+            }
+            if days.is_empty() {
+                self.pending.remove(&record.key);
+            }
+        }
+
+        let entry = self
+            .flushed
+            .entry(record.key.clone())
+            .or_default()
+            .entry(day)
+            .or_default();
+        entry.tokens += tokens;
+        entry.credits += record.credits;
+        entry.records += 1;
+    }
+
+    pub(super) fn replace_flushed(&mut self, flushed: KeyDays) {
+        self.flushed = flushed;
+    }
+
+    /// Tokens and credits used by a key on or after `from`
+    fn usage_since(&self, key: &str, from: NaiveDate) -> (u64, f64) {
+        let mut total = (0, 0.0);
+        for map in [&self.flushed, &self.pending] {
+            if let Some(days) = map.get(key) {
+                for usage in days.range(from..).map(|(_, usage)| usage) {
+                    total.0 += usage.tokens;
+                    total.1 += usage.credits;
+                }
+            }
+        }
+        total
+    }
+}
+
+/// Per-key, per-day totals from the start of `now`'s month onwards
+pub(super) fn load_month_usage(conn: &Connection, now: DateTime<Utc>) -> Result<KeyDays> {
+    let today = now.date_naive();
+    let month_start = NaiveDate::from_ymd_opt(today.year(), today.month(), 1).unwrap_or(today);
+
+    let mut stmt = conn.prepare(
+        "SELECT key, day, COALESCE(SUM(input_tokens + output_tokens), 0), COALESCE(SUM(credits), 0), COUNT(*)
+         FROM usage_ledger WHERE day >= ?1 GROUP BY key, day",
+    )?;
+    let rows = stmt.query_map([month_start.format("%Y-%m-%d").to_string()], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            DayUsage {
+                tokens: row.get::<_, i64>(2)? as u64,
+                credits: row.get(3)?,
+                records: row.get::<_, i64>(4)? as u64,
+            },
+        ))
+    })?;
+
+    let mut usage = KeyDays::new();
+    for row in rows {
+        let (key, day, totals) = row?;
+        let Ok(day) = NaiveDate::parse_from_str(&day, "%Y-%m-%d") else {
+            continue;
+        };
+        usage.entry(key).or_default().insert(day, totals);
+    }
+    Ok(usage)
+}
+
+impl UsageLedger {
+    /// Set (or replace) the budget for a key
+    pub fn set_budget(&self, key: &str, budget: Budget) -> Result<()> {
+        let Some(ref conn) = self.conn else {
+            anyhow::bail!("Usage ledger is disabled (set USAGE_DB_FILE)");
+        };
+        if budget.is_empty() {
+            return self.delete_budget(key).map(|_| ());
+        }
+
+        {
+            let conn = conn
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Usage database lock poisoned"))?;
+            conn.execute(
+                "INSERT OR REPLACE INTO usage_budgets
+                    (key, daily_tokens, monthly_tokens, daily_credits, monthly_credits)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![
+                    key,
+                    budget.daily_tokens.map(|v| v as i64),
+                    budget.monthly_tokens.map(|v| v as i64),
+                    budget.daily_credits,
+                    budget.monthly_credits,
+                ],
+            )?;
+        }
+
+        if let Ok(mut budgets) = self.budgets.write() {
+            budgets.insert(key.to_string(), budget);
+        }
+        Ok(())
+    }
+
+    /// Remove the budget for a key; returns whether one existed
+    pub fn delete_budget(&self, key: &str) -> Result<bool> {
+        let Some(ref conn) = self.conn else {
+            anyhow::bail!("Usage ledger is disabled (set USAGE_DB_FILE)");
+        };
+
+        let removed = {
+            let conn = conn
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Usage database lock poisoned"))?;
+            conn.execute("DELETE FROM usage_budgets WHERE key = ?1", [key])?
+        };
+
+        if let Ok(mut budgets) = self.budgets.write() {
+            budgets.remove(key);
+        }
+        Ok(removed > 0)
+    }
+
+    /// All configured budgets
+    pub fn list_budgets(&self) -> HashMap<String, Budget> {
+        self.budgets.read().map(|b| b.clone()).unwrap_or_default()
+    }
+
+    /// Check whether a key still has budget left
+    pub fn check_budget(&self, key: &str) -> Result<(), BudgetExceeded> {
+        self.check_budget_at(key, Utc::now())
+    }
+
+    pub(super) fn check_budget_at(
+        &self,
+        key: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(), BudgetExceeded> {
+        let Some(budget) = self.budgets.read().ok().and_then(|b| b.get(key).cloned()) else {
+            return Ok(());
+        };
+
+        let today = now.date_naive();
+        let month_start = NaiveDate::from_ymd_opt(today.year(), today.month(), 1).unwrap_or(today);
+        let day_reset = start_of(today + Duration::days(1));
+        let month_reset = start_of(next_month(month_start));
+
+        // Served from memory: budget checks sit on the request path
+        let (daily, monthly) = match self.counters.lock() {
+            Ok(counters) => (
+                counters.usage_since(key, today),
+                counters.usage_since(key, month_start),
+            ),
+            Err(_) => return Ok(()),
+        };
+
+        let exceeded = |period: &str, unit: &str, used: String, limit: String, resets_at| {
+            Err(BudgetExceeded {
+                message: format!(
+                    "{} {} budget exceeded for key '{}': {} / {} {} used. Resets at {}",
+                    period,
+                    unit,
+                    key,
+                    used,
+                    limit,
+                    unit,
+                    DateTime::<Utc>::to_rfc3339(&resets_at)
+                ),
+                resets_at,
+            })
+        };
+
+        if let Some(limit) = budget.daily_tokens {
+            if daily.0 >= limit {
+                return exceeded(
+                    "Daily",
+                    "token",
+                    daily.0.to_string(),
+                    limit.to_string(),
+                    day_reset,
+                );
+            }
+        }
+        if let Some(limit) = budget.daily_credits {
+            if daily.1 >= limit {
+                return exceeded(
+                    "Daily",
+                    "credit",
+                    daily.1.to_string(),
+                    limit.to_string(),
+                    day_reset,
+                );
+            }
+        }
+        if let Some(limit) = budget.monthly_tokens {
+            if monthly.0 >= limit {
+                return exceeded(
+                    "Monthly",
+                    "token",
+                    monthly.0.to_string(),
+                    limit.to_string(),
+                    month_reset,
+                );
+            }
+        }
+        if let Some(limit) = budget.monthly_credits {
+            if monthly.1 >= limit {
+                return exceeded(
+                    "Monthly",
+                    "credit",
+                    monthly.1.to_string(),
+                    limit.to_string(),
+                    month_reset,
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn start_of(day: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&day.and_hms_opt(0, 0, 0).unwrap_or_default())
+}
+
+fn next_month(month_start: NaiveDate) -> NaiveDate {
+    let (year, month) = if month_start.month() == 12 {
+        (month_start.year() + 1, 1)
+    } else {
+        (month_start.year(), month_start.month() + 1)
+    };
+    NaiveDate::from_ymd_opt(year, month, 1).unwrap_or(month_start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{UsageRecord, STATUS_OK};
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn usage(key: &str, tokens: u64, credits: f64) -> UsageRecord {
+        UsageRecord {
+            key: key.to_string(),
+            model: "claude-sonnet-4".to_string(),
+            input_tokens: tokens,
+            output_tokens: 0,
+            credits,
+            latency_ms: 10.0,
+            status: STATUS_OK.to_string(),
+            cached: false,
+            coalesced: false,
+        }
+    }
+
+    #[test]
+    fn test_daily_token_budget() {
+        let ledger = UsageLedger::open_in_memory().unwrap();
+        ledger
+            .set_budget(
+                "ci",
+                Budget {
+                    daily_tokens: Some(100),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let now = at("2026-10-18T10:00:00Z");
+        ledger.record_at(&usage("ci", 60, 0.0), now);
+        assert!(ledger.check_budget_at("ci", now).is_ok());
+
+        ledger.record_at(&usage("ci", 40, 0.0), now);
+        let err = ledger.check_budget_at("ci", now).unwrap_err();
+        assert_eq!(err.resets_at, at("2026-10-19T00:00:00Z"));
+        assert!(err
+            .message
+            .contains("Daily token budget exceeded for key 'ci'"));
+        assert!(err.message.contains("100 / 100"));
+
+        // Next day the budget is fresh; other keys are unaffected
+        assert!(ledger
+            .check_budget_at("ci", at("2026-10-19T00:00:01Z"))
+            .is_ok());
+        assert!(ledger.check_budget_at("alice", now).is_ok());
+    }
+
+    #[test]
+    fn test_monthly_credit_budget_resets_next_month() {
+        let ledger = UsageLedger::open_in_memory().unwrap();
+        ledger
+            .set_budget(
+                "alice",
+                Budget {
+                    monthly_credits: Some(1.0),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        ledger.record_at(&usage("alice", 1, 0.6), at("2026-12-01T08:00:00Z"));
+        ledger.record_at(&usage("alice", 1, 0.5), at("2026-12-20T08:00:00Z"));
+
+        let err = ledger
+            .check_budget_at("alice", at("2026-12-31T23:00:00Z"))
+            .unwrap_err();
+        assert_eq!(err.resets_at, at("2027-01-01T00:00:00Z"));
+        assert!(ledger
+            .check_budget_at("alice", at("2027-01-01T00:00:00Z"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_counters_survive_reconcile() {
+        let ledger = UsageLedger::open_in_memory().unwrap();
+        let now = at("2026-10-18T10:00:00Z");
+        let today = now.date_naive();
+        let record = usage("ci", 30, 0.25);
+
+        let mut counters = UsageCounters::default();
+        counters.add_pending(&record, today);
+        assert_eq!(counters.usage_since("ci", today), (30, 0.25));
+
+        // Written by the writer, then rebuilt from the database: still counted once
+        counters.mark_flushed(&record, today);
+        assert_eq!(counters.usage_since("ci", today), (30, 0.25));
+        ledger.record_at(&record, now);
+        ledger.flush();
+        let conn = ledger.conn.as_ref().unwrap().lock().unwrap();
+        counters.replace_flushed(load_month_usage(&conn, now).unwrap());
+        assert_eq!(counters.usage_since("ci", today), (30, 0.25));
+        assert_eq!(counters.usage_since("alice", today), (0, 0.0));
+    }
+
+    #[test]
+    fn test_budgets_persist_and_delete() {
+        let dir = std::env::temp_dir().join(format!("kiro-usage-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("usage.db");
+
+        let budget = Budget {
+            daily_tokens: Some(10),
+            monthly_credits: Some(2.5),
+            ..Default::default()
+        };
+        UsageLedger::open(&path)
+            .unwrap()
+            .set_budget("ci", budget.clone())
+            .unwrap();
+
+        let ledger = UsageLedger::open(&path).unwrap();
+        assert_eq!(ledger.list_budgets().get("ci"), Some(&budget));
+        assert!(ledger.delete_budget("ci").unwrap());
+        assert!(!ledger.delete_budget("ci").unwrap());
+        assert!(ledger.list_budgets().is_empty());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
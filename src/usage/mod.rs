@@ -0,0 +1,569 @@
+// Persistent usage ledger and per-key budgets (SQLite)
+
+pub mod budgets;
+mod writer;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::JoinHandle;
+
+pub use budgets::Budget;
+use budgets::UsageCounters;
+use writer::WriterMessage;
+
+/// Request status recorded in the ledger
+pub const STATUS_OK: &str = "ok";
+pub const STATUS_ERROR: &str = "error";
+
+/// One completed (or failed) request
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageRecord {
+    pub key: String,
+    pub model: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub credits: f64,
+    pub latency_ms: f64,
+    pub status: String,
+    /// Served from the response cache (no upstream call)
+    pub cached: bool,
+    /// Joined another request's upstream call (no upstream call of its own)
+    pub coalesced: bool,
+}
+
+/// Columns usage can be grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GroupBy {
+    Key,
+    Model,
+    Day,
+}
+
+impl GroupBy {
+    /// Parse a comma-separated group-by list (`key,model,day`)
+    pub fn parse_list(s: &str) -> Result<Vec<GroupBy>, String> {
+        let mut groups = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let group = match part.to_lowercase().as_str() {
+                "key" => GroupBy::Key,
+                "model" => GroupBy::Model,
+                "day" => GroupBy::Day,
+                other => {
+                    return Err(format!(
+                        "Invalid group_by value '{}' (expected key, model or day)",
+                        other
+                    ))
+                }
+            };
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+        Ok(groups)
+    }
+
+    fn column(&self) -> &'static str {
+        match self {
+            GroupBy::Key => "key",
+            GroupBy::Model => "model",
+            GroupBy::Day => "day",
+        }
+    }
+}
+
+/// Filters and grouping for a usage query
+#[derive(Debug, Clone, Default)]
+pub struct UsageQuery {
+    pub group_by: Vec<GroupBy>,
+    pub key: Option<String>,
+    pub model: Option<String>,
+    /// Inclusive start day (`YYYY-MM-DD`)
+    pub from: Option<String>,
+    /// Inclusive end day (`YYYY-MM-DD`)
+    pub to: Option<String>,
+}
+
+/// Aggregated usage row
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageRow {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day: Option<String>,
+    pub requests: u64,
+    pub errors: u64,
+    /// Requests served from the response cache
+    pub cached: u64,
+    /// Requests that joined an identical in-flight upstream call
+    pub coalesced: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Tokens of requests that called upstream themselves (not cached or coalesced)
+    pub upstream_input_tokens: u64,
+    pub upstream_output_tokens: u64,
+    pub credits: f64,
+    pub avg_latency_ms: f64,
+}
+
+/// SQLite-backed usage ledger
+///
+/// Disabled (no-op) unless `USAGE_DB_FILE` is configured. Records are written
+/// by a background thread; budget checks use in-memory counters.
+pub struct UsageLedger {
+    conn: Option<Arc<Mutex<Connection>>>,
+    writer: Option<Sender<WriterMessage>>,
+    worker: Option<JoinHandle<()>>,
+    budgets: RwLock<HashMap<String, Budget>>,
+    counters: Arc<Mutex<UsageCounters>>,
+}
+
+impl UsageLedger {
+    /// Ledger that records nothing and enforces no budgets
+    pub fn disabled() -> Self {
+        Self {
+            conn: None,
+            writer: None,
+            worker: None,
+            budgets: RwLock::new(HashMap::new()),
+            counters: Arc::new(Mutex::new(UsageCounters::default())),
+        }
+    }
+
+    /// Open (or create) the ledger database
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open usage database: {}", path.display()))?;
+        Self::init(conn)
+    }
+
+    /// In-memory ledger (for tests)
+    #[cfg(any(test, feature = "test-utils"))]
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS usage_ledger (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                day TEXT NOT NULL,
+                key TEXT NOT NULL,
+                model TEXT NOT NULL,
+                input_tokens INTEGER NOT NULL,
+                output_tokens INTEGER NOT NULL,
+                credits REAL NOT NULL DEFAULT 0,
+                latency_ms REAL NOT NULL,
+                status TEXT NOT NULL,
+                cached INTEGER NOT NULL DEFAULT 0,
+                coalesced INTEGER NOT NULL DEFAULT 0
+             );
+             CREATE INDEX IF NOT EXISTS idx_usage_ledger_key_day ON usage_ledger (key, day);
+             CREATE TABLE IF NOT EXISTS usage_budgets (
+                key TEXT PRIMARY KEY,
+                daily_tokens INTEGER,
+                monthly_tokens INTEGER,
+                daily_credits REAL,
+                monthly_credits REAL
+             );",
+        )
+        .context("Failed to initialize usage database")?;
+
+        // Ledgers created before response caching and coalescing lack these columns
+        for column in ["cached", "coalesced"] {
+            let exists: bool = conn
+                .query_row(
+                    "SELECT COUNT(*) FROM pragma_table_info('usage_ledger') WHERE name = ?1",
+                    [column],
+                    |row| row.get::<_, i64>(0),
+                )
+                .map(|n| n > 0)
+                .context("Failed to inspect usage database")?;
+            if !exists {
+                conn.execute(
+                    &format!(
+                        "ALTER TABLE usage_ledger ADD COLUMN {} INTEGER NOT NULL DEFAULT 0",
+                        column
+                    ),
+                    [],
+                )
+                .context("Failed to migrate usage database")?;
+            }
+        }
+
+        let budgets = budgets::load_budgets(&conn)?;
+        let mut counters = UsageCounters::default();
+        counters.replace_flushed(budgets::load_month_usage(&conn, Utc::now())?);
+
+        let conn = Arc::new(Mutex::new(conn));
+        let counters = Arc::new(Mutex::new(counters));
+        let (writer, worker) = writer::spawn(Arc::clone(&conn), Arc::clone(&counters))?;
+
+        Ok(Self {
+            conn: Some(conn),
+            writer: Some(writer),
+            worker: Some(worker),
+            budgets: RwLock::new(budgets),
+            counters,
+        })
+    }
+
+    /// Check whether the ledger is recording
+    pub fn is_enabled(&self) -> bool {
+        self.conn.is_some()
+    }
+
+    /// Append a request to the ledger
+    ///
+    /// Failures are logged and otherwise ignored so accounting never fails a request.
+    pub fn record(&self, record: &UsageRecord) {
+        self.record_at(record, Utc::now());
+    }
+
+    fn record_at(&self, record: &UsageRecord, now: DateTime<Utc>) {
+        let Some(ref writer) = self.writer else {
+            return;
+        };
+
+        // Count the record before queueing it so the writer never sees it first
+        if let Ok(mut counters) = self.counters.lock() {
+            counters.add_pending(record, now.date_naive());
+        }
+        if writer
+            .send(WriterMessage::Record(record.clone(), now))
+            .is_err()
+        {
+            tracing::warn!(
+                "Usage ledger writer stopped; dropping record for key '{}'",
+                record.key
+            );
+        }
+    }
+
+    /// Wait until every record queued so far has been written
+    fn flush(&self) {
+        let Some(ref writer) = self.writer else {
+            return;
+        };
+        let (tx, rx) = mpsc::channel();
+        if writer.send(WriterMessage::Flush(tx)).is_ok() {
+            let _ = rx.recv();
+        }
+    }
+
+    /// Aggregate usage, grouped by the requested columns
+    pub fn query(&self, query: &UsageQuery) -> Result<Vec<UsageRow>> {
+        let Some(ref conn) = self.conn else {
+            return Ok(Vec::new());
+        };
+        self.flush();
+        let conn = conn
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Usage database lock poisoned"))?;
+
+        let group_cols: Vec<&str> = query.group_by.iter().map(|g| g.column()).collect();
+
+        let mut conditions = Vec::new();
+        let mut params: Vec<String> = Vec::new();
+        for (column, op, value) in [
+            ("key", "=", &query.key),
+            ("model", "=", &query.model),
+            ("day", ">=", &query.from),
+            ("day", "<=", &query.to),
+        ] {
+            if let Some(value) = value {
+                params.push(value.clone());
+                conditions.push(format!("{} {} ?{}", column, op, params.len()));
+            }
+        }
+
+        let mut sql = String::from("SELECT ");
+        for col in &group_cols {
+            sql.push_str(col);
+            sql.push_str(", ");
+        }
+        sql.push_str(
+            "COUNT(*), SUM(CASE WHEN status = 'ok' THEN 0 ELSE 1 END), COALESCE(SUM(cached), 0), \
+             COALESCE(SUM(coalesced), 0), COALESCE(SUM(input_tokens), 0), \
+             COALESCE(SUM(output_tokens), 0), \
+             COALESCE(SUM(CASE WHEN cached OR coalesced THEN 0 ELSE input_tokens END), 0), \
+             COALESCE(SUM(CASE WHEN cached OR coalesced THEN 0 ELSE output_tokens END), 0), \
+             COALESCE(SUM(credits), 0), COALESCE(AVG(latency_ms), 0) FROM usage_ledger",
+        );
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        if !group_cols.is_empty() {
+            let cols = group_cols.join(", ");
+            sql.push_str(&format!(" GROUP BY {} ORDER BY {}", cols, cols));
+        }
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(params.iter()), |row| {
+            let mut usage = UsageRow::default();
+            for (i, group) in query.group_by.iter().enumerate() {
+                let value: String = row.get(i)?;
+                match group {
+                    GroupBy::Key => usage.key = Some(value),
+                    GroupBy::Model => usage.model = Some(value),
+                    GroupBy::Day => usage.day = Some(value),
+                }
+            }
+            let n = group_cols.len();
+            usage.requests = row.get::<_, i64>(n)? as u64;
+            usage.errors = row.get::<_, Option<i64>>(n + 1)?.unwrap_or(0) as u64;
+            usage.cached = row.get::<_, i64>(n + 2)? as u64;
+            usage.coalesced = row.get::<_, i64>(n + 3)? as u64;
+            usage.input_tokens = row.get::<_, i64>(n + 4)? as u64;
+            usage.output_tokens = row.get::<_, i64>(n + 5)? as u64;
+            usage.upstream_input_tokens = row.get::<_, i64>(n + 6)? as u64;
+            usage.upstream_output_tokens = row.get::<_, i64>(n + 7)? as u64;
+            usage.credits = row.get(n + 8)?;
+            usage.avg_latency_ms = row.get(n + 9)?;
+            Ok(usage)
+        })?;
+
+        let mut result = Vec::new();
+        for row in rows {
+            let row = row?;
+            // An ungrouped query over an empty ledger yields one all-zero row
+            if group_cols.is_empty() && row.requests == 0 {
+                continue;
+            }
+            result.push(row);
+        }
+        Ok(result)
+    }
+}
+
+impl Drop for UsageLedger {
+    fn drop(&mut self) {
+        // Closing the channel lets the writer drain what is queued and exit
+        self.writer.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Render usage rows as CSV
+pub fn to_csv(rows: &[UsageRow], group_by: &[GroupBy]) -> String {
+    let mut out = String::new();
+    for group in group_by {
+        out.push_str(group.column());
+        out.push(',');
+    }
+    out.push_str(
+        "requests,errors,cached,coalesced,input_tokens,output_tokens,\
+         upstream_input_tokens,upstream_output_tokens,credits,avg_latency_ms\n",
+    );
+
+    for row in rows {
+        for group in group_by {
+            let value = match group {
+                GroupBy::Key => row.key.as_deref(),
+                GroupBy::Model => row.model.as_deref(),
+                GroupBy::Day => row.day.as_deref(),
+            };
+            out.push_str(&csv_field(value.unwrap_or_default()));
+            out.push(',');
+        }
+        out.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{:.1}\n",
+            row.requests,
+            row.errors,
+            row.cached,
+            row.coalesced,
+            row.input_tokens,
+            row.output_tokens,
+            row.upstream_input_tokens,
+            row.upstream_output_tokens,
+            row.credits,
+            row.avg_latency_ms
+        ));
+    }
+    out
+}
+
+/// Quote a CSV field if needed
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(key: &str, model: &str, input: u64, output: u64, status: &str) -> UsageRecord {
+        UsageRecord {
+            key: key.to_string(),
+            model: model.to_string(),
+            input_tokens: input,
+            output_tokens: output,
+            credits: 0.5,
+            latency_ms: 100.0,
+            status: status.to_string(),
+            cached: false,
+            coalesced: false,
+        }
+    }
+
+    fn day(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&format!("{}T12:00:00Z", s))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_disabled_ledger_is_noop() {
+        let ledger = UsageLedger::disabled();
+        assert!(!ledger.is_enabled());
+        ledger.record(&record("a", "m", 1, 1, STATUS_OK));
+        assert!(ledger.query(&UsageQuery::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_query_group_by() {
+        let ledger = UsageLedger::open_in_memory().unwrap();
+        ledger.record_at(
+            &record("alice", "sonnet", 10, 20, STATUS_OK),
+            day("2026-10-01"),
+        );
+        ledger.record_at(
+            &record("alice", "haiku", 5, 5, STATUS_OK),
+            day("2026-10-01"),
+        );
+        ledger.record_at(
+            &record("alice", "sonnet", 1, 0, STATUS_ERROR),
+            day("2026-10-02"),
+        );
+        ledger.record_at(
+            &record("ci", "sonnet", 100, 200, STATUS_OK),
+            day("2026-10-02"),
+        );
+
+        let totals = ledger.query(&UsageQuery::default()).unwrap();
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].requests, 4);
+        assert_eq!(totals[0].errors, 1);
+        assert_eq!(totals[0].input_tokens, 116);
+        assert_eq!(totals[0].credits, 2.0);
+
+        let by_key = ledger
+            .query(&UsageQuery {
+                group_by: vec![GroupBy::Key],
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(by_key.len(), 2);
+        assert_eq!(by_key[0].key.as_deref(), Some("alice"));
+        assert_eq!(by_key[0].requests, 3);
+        assert_eq!(by_key[0].output_tokens, 25);
+
+        let by_model_day = ledger
+            .query(&UsageQuery {
+                group_by: vec![GroupBy::Model, GroupBy::Day],
+                key: Some("alice".to_string()),
+                from: Some("2026-10-02".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(by_model_day.len(), 1);
+        assert_eq!(by_model_day[0].model.as_deref(), Some("sonnet"));
+        assert_eq!(by_model_day[0].day.as_deref(), Some("2026-10-02"));
+        assert!(by_model_day[0].key.is_none());
+    }
+
+    #[test]
+    fn test_parse_group_by() {
+        assert_eq!(
+            GroupBy::parse_list("key, day,key").unwrap(),
+            vec![GroupBy::Key, GroupBy::Day]
+        );
+        assert!(GroupBy::parse_list("").unwrap().is_empty());
+        assert!(GroupBy::parse_list("region").is_err());
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let rows = vec![UsageRow {
+            key: Some("team, a".to_string()),
+            day: Some("2026-10-01".to_string()),
+            requests: 2,
+            errors: 0,
+            input_tokens: 10,
+            output_tokens: 20,
+            upstream_input_tokens: 10,
+            upstream_output_tokens: 20,
+            credits: 0.25,
+            avg_latency_ms: 150.0,
+            ..Default::default()
+        }];
+
+        let csv = to_csv(&rows, &[GroupBy::Key, GroupBy::Day]);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "key,day,requests,errors,cached,coalesced,input_tokens,output_tokens,\
+             upstream_input_tokens,upstream_output_tokens,credits,avg_latency_ms"
+        );
+        assert_eq!(
+            lines[1],
+            "\"team, a\",2026-10-01,2,0,0,0,10,20,10,20,0.25,150.0"
+        );
+    }
+
+    #[test]
+    fn test_cached_and_coalesced_flags_and_migration() {
+        // Ledger written before the `cached` and `coalesced` columns existed
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE usage_ledger (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                day TEXT NOT NULL,
+                key TEXT NOT NULL,
+                model TEXT NOT NULL,
+                input_tokens INTEGER NOT NULL,
+                output_tokens INTEGER NOT NULL,
+                credits REAL NOT NULL DEFAULT 0,
+                latency_ms REAL NOT NULL,
+                status TEXT NOT NULL
+             );
+             INSERT INTO usage_ledger
+                (timestamp, day, key, model, input_tokens, output_tokens, latency_ms, status)
+             VALUES ('2026-10-01T12:00:00Z', '2026-10-01', 'ci', 'sonnet', 1, 1, 10, 'ok');",
+        )
+        .unwrap();
+        let ledger = UsageLedger::init(conn).unwrap();
+
+        let mut cached = record("ci", "sonnet", 10, 20, STATUS_OK);
+        cached.cached = true;
+        ledger.record_at(&cached, day("2026-10-01"));
+        let mut coalesced = record("ci", "sonnet", 10, 20, STATUS_OK);
+        coalesced.coalesced = true;
+        ledger.record_at(&coalesced, day("2026-10-01"));
+
+        let totals = ledger.query(&UsageQuery::default()).unwrap();
+        assert_eq!(totals[0].requests, 3);
+        assert_eq!(totals[0].cached, 1);
+        assert_eq!(totals[0].coalesced, 1);
+        assert_eq!(totals[0].input_tokens, 21);
+        assert_eq!(totals[0].upstream_input_tokens, 1);
+    }
+}
@@ -0,0 +1,136 @@
+// Background writer for the usage ledger
+//
+// Requests hand their records to a dedicated thread so SQLite I/O never runs
+// on the async runtime. The thread also keeps the in-memory budget counters
+// in step with the database.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::budgets::{self, UsageCounters};
+use super::UsageRecord;
+
+/// How often the budget counters are re-read from the database
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub(super) enum WriterMessage {
+    Record(UsageRecord, DateTime<Utc>),
+    /// Answered once every earlier record has been written
+    Flush(Sender<()>),
+}
+
+/// Start the writer thread
+pub(super) fn spawn(
+    conn: Arc<Mutex<Connection>>,
+    counters: Arc<Mutex<UsageCounters>>,
+) -> Result<(Sender<WriterMessage>, JoinHandle<()>)> {
+    let (tx, rx) = mpsc::channel();
+    let handle = std::thread::Builder::new()
+        .name("usage-ledger".to_string())
+        .spawn(move || run(rx, &conn, &counters))
+        .context("Failed to start usage ledger writer")?;
+    Ok((tx, handle))
+}
+
+fn run(rx: Receiver<WriterMessage>, conn: &Mutex<Connection>, counters: &Mutex<UsageCounters>) {
+    let mut next_reconcile = Instant::now() + RECONCILE_INTERVAL;
+    loop {
+        match rx.recv_timeout(next_reconcile.saturating_duration_since(Instant::now())) {
+            Ok(first) => {
+                let mut batch = vec![first];
+                batch.extend(rx.try_iter());
+                write_batch(conn, counters, batch);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        if Instant::now() >= next_reconcile {
+            reconcile(conn, counters);
+            next_reconcile = Instant::now() + RECONCILE_INTERVAL;
+        }
+    }
+}
+
+fn write_batch(
+    conn: &Mutex<Connection>,
+    counters: &Mutex<UsageCounters>,
+    batch: Vec<WriterMessage>,
+) {
+    let mut written = Vec::new();
+    let mut acks = Vec::new();
+    for message in batch {
+        match message {
+            WriterMessage::Record(record, now) => written.push((record, now)),
+            WriterMessage::Flush(ack) => acks.push(ack),
+        }
+    }
+
+    if !written.is_empty() {
+        if let Ok(mut conn) = conn.lock() {
+            if let Err(e) = insert_records(&mut conn, &written) {
+                tracing::warn!("Failed to record usage: {}", e);
+            }
+        }
+        // Failed inserts stay counted until the next reconcile so budgets err on the safe side
+        if let Ok(mut counters) = counters.lock() {
+            for (record, now) in &written {
+                counters.mark_flushed(record, now.date_naive());
+            }
+        }
+    }
+
+    for ack in acks {
+        let _ = ack.send(());
+    }
+}
+
+fn insert_records(conn: &mut Connection, records: &[(UsageRecord, DateTime<Utc>)]) -> Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO usage_ledger
+                (timestamp, day, key, model, input_tokens, output_tokens, credits, latency_ms,
+                 status, cached, coalesced)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        )?;
+        for (record, now) in records {
+            stmt.execute(rusqlite::params![
+                now.to_rfc3339(),
+                now.format("%Y-%m-%d").to_string(),
+                record.key,
+                record.model,
+                record.input_tokens as i64,
+                record.output_tokens as i64,
+                record.credits,
+                record.latency_ms,
+                record.status,
+                record.cached,
+                record.coalesced,
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Replace the written-usage counters with the database's totals for this month
+fn reconcile(conn: &Mutex<Connection>, counters: &Mutex<UsageCounters>) {
+    let totals = match conn.lock() {
+        Ok(conn) => budgets::load_month_usage(&conn, Utc::now()),
+        Err(_) => return,
+    };
+    match totals {
+        Ok(totals) => {
+            if let Ok(mut counters) = counters.lock() {
+                counters.replace_flushed(totals);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to reconcile usage budgets: {}", e),
+    }
+}